@@ -0,0 +1,344 @@
+//! System users and groups declared by packages
+//!
+//! Packages list the accounts they need in `[users]` and `[groups]`; the
+//! transaction creates the missing ones by editing the passwd, group,
+//! shadow and (if present) gshadow files of the target root directly, so
+//! `--root` installs behave exactly like installs on the running system.
+//! Existing accounts are never changed, and accounts are never removed
+//! with their package, since files owned by them may outlive it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{Context, Result, RookpkgError};
+use crate::spec::{SystemGroup, SystemUser};
+
+/// Root-relative paths of the files accounts are kept in
+pub const ACCOUNT_FILES: [&str; 4] = ["etc/passwd", "etc/group", "etc/shadow", "etc/gshadow"];
+
+/// Range IDs are allocated from, top down (like `useradd -r`)
+const SYSTEM_ID_MIN: u32 = 100;
+const SYSTEM_ID_MAX: u32 = 999;
+
+/// Whether an account is a user or a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountKind {
+    User,
+    Group,
+}
+
+impl fmt::Display for AccountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountKind::User => write!(f, "user"),
+            AccountKind::Group => write!(f, "group"),
+        }
+    }
+}
+
+impl FromStr for AccountKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(AccountKind::User),
+            "group" => Ok(AccountKind::Group),
+            _ => Err(format!("Unknown account kind: {}", s)),
+        }
+    }
+}
+
+/// An account a package asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestedAccount {
+    pub kind: AccountKind,
+    pub name: String,
+    /// Whether the package's installation created it (false if it existed)
+    pub created: bool,
+}
+
+/// One account file, edited in memory as a list of lines
+#[derive(Debug, Clone)]
+struct AccountFile {
+    path: PathBuf,
+    lines: Vec<String>,
+    /// Permissions for the file if it has to be created
+    mode: u32,
+    changed: bool,
+}
+
+impl AccountFile {
+    fn load(path: PathBuf, mode: u32) -> Result<Self> {
+        let lines = if path.exists() {
+            fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .lines()
+                .map(str::to_string)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, lines, mode, changed: false })
+    }
+
+    /// Fields of the entry called `name`
+    fn find(&self, name: &str) -> Option<(usize, Vec<&str>)> {
+        self.lines.iter().enumerate().find_map(|(i, line)| {
+            let fields: Vec<&str> = line.split(':').collect();
+            (fields[0] == name).then_some((i, fields))
+        })
+    }
+
+    /// Whether any entry has `id` in its third field
+    fn id_taken(&self, id: u32) -> bool {
+        self.lines
+            .iter()
+            .any(|line| line.split(':').nth(2).and_then(|f| f.parse().ok()) == Some(id))
+    }
+
+    fn push(&mut self, line: String) {
+        self.lines.push(line);
+        self.changed = true;
+    }
+
+    /// Write the file via a temporary sibling and an atomic rename
+    fn save(&self) -> Result<()> {
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mode = fs::metadata(&self.path)
+            .map(|m| m.permissions().mode() & 0o7777)
+            .unwrap_or(self.mode);
+
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let staged = self.path.with_file_name(format!(".{}.rookpkg", name));
+        let mut file = fs::File::create(&staged)?;
+        for line in &self.lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.sync_all()?;
+        fs::rename(&staged, &self.path)
+            .with_context(|| format!("Failed to update {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// The account files of a root filesystem
+#[derive(Debug, Clone)]
+pub struct AccountFiles {
+    passwd: AccountFile,
+    group: AccountFile,
+    shadow: AccountFile,
+    /// Only maintained if the root already has one
+    gshadow: Option<AccountFile>,
+}
+
+impl AccountFiles {
+    /// Load the account files under `root` (missing files start out empty)
+    pub fn load(root: &Path) -> Result<Self> {
+        let [passwd, group, shadow, gshadow] = ACCOUNT_FILES.map(|path| root.join(path));
+        let gshadow = if gshadow.exists() {
+            Some(AccountFile::load(gshadow, 0o600)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            passwd: AccountFile::load(passwd, 0o644)?,
+            group: AccountFile::load(group, 0o644)?,
+            shadow: AccountFile::load(shadow, 0o600)?,
+            gshadow,
+        })
+    }
+
+    /// Create whatever is missing of the declared groups and users
+    ///
+    /// Groups come first so users can use them as primary or supplementary
+    /// groups; a user without an explicit primary group gets one named
+    /// after it. Names are processed in sorted order so ID allocation does
+    /// not depend on hash order.
+    pub fn ensure(
+        &mut self,
+        users: &HashMap<String, SystemUser>,
+        groups: &HashMap<String, SystemGroup>,
+    ) -> Result<Vec<RequestedAccount>> {
+        let mut requested = Vec::new();
+
+        let mut group_names: Vec<_> = groups.keys().collect();
+        group_names.sort();
+        for name in group_names {
+            let created = self.add_group(name, groups[name].gid)?;
+            requested.push(RequestedAccount { kind: AccountKind::Group, name: name.clone(), created });
+        }
+
+        let mut user_names: Vec<_> = users.keys().collect();
+        user_names.sort();
+        for name in user_names {
+            let user = &users[name];
+            let primary = user.group.as_deref().unwrap_or(name);
+            if user.group.is_none() && self.group.find(primary).is_none() {
+                // Match the group ID to the user ID where possible
+                let gid = user.uid.filter(|uid| !self.group.id_taken(*uid));
+                let created = self.add_group(primary, gid)?;
+                requested.push(RequestedAccount { kind: AccountKind::Group, name: primary.to_string(), created });
+            }
+
+            let created = self.add_user(name, user, primary)?;
+            requested.push(RequestedAccount { kind: AccountKind::User, name: name.clone(), created });
+        }
+
+        Ok(requested)
+    }
+
+    /// Whether `ensure` changed anything that needs saving
+    pub fn is_changed(&self) -> bool {
+        self.files().any(|f| f.changed)
+    }
+
+    /// Paths of the files `save` will write
+    pub fn changed_paths(&self) -> Vec<PathBuf> {
+        self.files().filter(|f| f.changed).map(|f| f.path.clone()).collect()
+    }
+
+    /// Write every changed file
+    pub fn save(&self) -> Result<()> {
+        for file in self.files().filter(|f| f.changed) {
+            file.save()?;
+        }
+        Ok(())
+    }
+
+    fn files(&self) -> impl Iterator<Item = &AccountFile> {
+        [&self.passwd, &self.group, &self.shadow]
+            .into_iter()
+            .chain(self.gshadow.as_ref())
+    }
+
+    fn add_group(&mut self, name: &str, gid: Option<u32>) -> Result<bool> {
+        if self.group.find(name).is_some() {
+            return Ok(false);
+        }
+        let gid = allocate(name, gid, |id| self.group.id_taken(id))?;
+        self.group.push(format!("{}:x:{}:", name, gid));
+        if let Some(gshadow) = &mut self.gshadow {
+            gshadow.push(format!("{}:!*::", name));
+        }
+        Ok(true)
+    }
+
+    fn add_user(&mut self, name: &str, user: &SystemUser, primary: &str) -> Result<bool> {
+        if self.passwd.find(name).is_some() {
+            return Ok(false);
+        }
+
+        let gid = match self.group.find(primary) {
+            Some((_, fields)) => fields.get(2).and_then(|f| f.parse::<u32>().ok()),
+            None => None,
+        }
+        .ok_or_else(|| {
+            RookpkgError::Account(format!("Primary group '{}' of user '{}' does not exist", primary, name))
+        })?;
+        let uid = allocate(name, user.uid, |id| self.passwd.id_taken(id))?;
+
+        self.passwd.push(format!(
+            "{}:x:{}:{}:{}:{}:{}",
+            name, uid, gid, user.comment, user.home, user.shell
+        ));
+        let days = chrono::Utc::now().timestamp() / 86400;
+        self.shadow.push(format!("{}:!*:{}::::::", name, days));
+
+        for group in &user.groups {
+            let (index, fields) = self
+                .group
+                .find(group)
+                .ok_or_else(|| RookpkgError::Account(format!("Group '{}' of user '{}' does not exist", group, name)))?;
+            let members = fields.get(3).copied().unwrap_or("");
+            let line = if members.is_empty() {
+                format!("{}:{}:{}:{}", fields[0], fields[1], fields[2], name)
+            } else {
+                format!("{}:{}:{}:{},{}", fields[0], fields[1], fields[2], members, name)
+            };
+            self.group.lines[index] = line;
+            self.group.changed = true;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Pick an ID: the requested one if it is free, else the highest free
+/// system ID
+fn allocate(name: &str, requested: Option<u32>, taken: impl Fn(u32) -> bool) -> Result<u32> {
+    if let Some(id) = requested {
+        if !taken(id) {
+            return Ok(id);
+        }
+        tracing::warn!("ID {} requested for '{}' is already in use, allocating another", id, name);
+    }
+    match (SYSTEM_ID_MIN..=SYSTEM_ID_MAX).rev().find(|id| !taken(*id)) {
+        Some(id) => Ok(id),
+        None => Err(RookpkgError::Account(format!("No free system ID left for '{}'", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ensure_accounts() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/passwd"), "root:x:0:0:root:/root:/bin/sh\n").unwrap();
+        fs::write(root.join("etc/group"), "root:x:0:\nwww:x:33:\nnet:x:999:\n").unwrap();
+
+        let mut users = HashMap::new();
+        users.insert(
+            "nginx".to_string(),
+            SystemUser {
+                uid: Some(0),
+                groups: vec!["www".to_string()],
+                comment: "nginx web server".to_string(),
+                home: "/var/lib/nginx".to_string(),
+                ..SystemUser::default()
+            },
+        );
+        let mut groups = HashMap::new();
+        groups.insert("www".to_string(), SystemGroup::default());
+
+        let mut accounts = AccountFiles::load(root).unwrap();
+        let requested = accounts.ensure(&users, &groups).unwrap();
+        assert_eq!(
+            requested,
+            vec![
+                RequestedAccount { kind: AccountKind::Group, name: "www".to_string(), created: false },
+                RequestedAccount { kind: AccountKind::Group, name: "nginx".to_string(), created: true },
+                RequestedAccount { kind: AccountKind::User, name: "nginx".to_string(), created: true },
+            ]
+        );
+        accounts.save().unwrap();
+
+        // uid 0 is taken, so the highest free system IDs are used
+        let passwd = fs::read_to_string(root.join("etc/passwd")).unwrap();
+        assert!(passwd.contains("nginx:x:999:998:nginx web server:/var/lib/nginx:/sbin/nologin"));
+        let group = fs::read_to_string(root.join("etc/group")).unwrap();
+        assert!(group.contains("www:x:33:nginx\n"));
+        assert!(group.contains("nginx:x:998:\n"));
+        assert!(fs::read_to_string(root.join("etc/shadow")).unwrap().starts_with("nginx:!*:"));
+
+        // Running again changes nothing
+        let mut accounts = AccountFiles::load(root).unwrap();
+        let requested = accounts.ensure(&users, &groups).unwrap();
+        assert!(requested.iter().all(|r| !r.created));
+        assert!(!accounts.is_changed());
+    }
+}
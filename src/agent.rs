@@ -0,0 +1,239 @@
+//! Signing agent
+//!
+//! Release policy may forbid secret keys on build machines. A signing agent
+//! owns the key instead and signs SHA-256 digests sent to it over a Unix
+//! socket; with `agent_socket` set in `[signing]`, `build`, `repo sign` and
+//! friends sign through it without ever loading secret material. The agent
+//! can keep its key on a hardware token; the reference agent,
+//! `rookpkg-agent`, serves a software key with [`serve`].
+//!
+//! ## Protocol
+//!
+//! One JSON request per line, each answered by one JSON line:
+//!
+//! ```text
+//! {"op":"identity"}
+//! {"type":"identity","fingerprint":"HYBRID:SHA256:...","name":"...","email":"..."}
+//! {"op":"sign","digest":"<hex SHA-256>"}
+//! {"type":"signature","signature":{"ed25519":"...","ml_dsa":"...",...}}
+//! ```
+//!
+//! Failures are answered with `{"type":"error","message":"..."}`. Access is
+//! controlled by the socket's permissions: [`bind`] creates it 0600.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, RookpkgError};
+use crate::signing::{HybridSignature, Signer};
+
+/// A request to the agent
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    /// Which key the agent signs with
+    Identity,
+    /// Sign a hex-encoded SHA-256 digest
+    Sign { digest: String },
+}
+
+/// The agent's answer to a request
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Response {
+    Identity { fingerprint: String, name: String, email: String },
+    Signature { signature: HybridSignature },
+    Error { message: String },
+}
+
+/// A signer that forwards to a signing agent
+#[derive(Debug, Clone)]
+pub struct AgentSigner {
+    socket: PathBuf,
+    fingerprint: String,
+    name: String,
+    email: String,
+}
+
+impl AgentSigner {
+    /// Connect to the agent listening on `socket` and ask for its key
+    pub fn connect(socket: &Path) -> Result<Self> {
+        match request(socket, &Request::Identity)? {
+            Response::Identity { fingerprint, name, email } => Ok(Self {
+                socket: socket.to_path_buf(),
+                fingerprint,
+                name,
+                email,
+            }),
+            Response::Error { message } => Err(RookpkgError::SigningAgent(message)),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Socket the agent listens on
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+impl Signer for AgentSigner {
+    fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+
+    fn sign_digest(&self, message_hash: &[u8]) -> Result<HybridSignature> {
+        let digest = hex::encode(message_hash);
+        match request(&self.socket, &Request::Sign { digest })? {
+            Response::Signature { signature } if signature.fingerprint == self.fingerprint => Ok(signature),
+            Response::Signature { signature } => Err(RookpkgError::SigningAgent(format!(
+                "Agent signed with {} instead of {}",
+                signature.fingerprint, self.fingerprint
+            ))),
+            Response::Error { message } => Err(RookpkgError::SigningAgent(message)),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+fn unexpected(response: &Response) -> RookpkgError {
+    RookpkgError::SigningAgent(format!("Unexpected response: {:?}", response))
+}
+
+/// Send one request over a fresh connection and read the answer
+fn request(socket: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(socket).map_err(|e| {
+        RookpkgError::SigningAgent(format!("Cannot connect to {}: {}", socket.display(), e))
+    })?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut answer = String::new();
+    BufReader::new(&stream).read_line(&mut answer)?;
+    if answer.is_empty() {
+        return Err(RookpkgError::SigningAgent("Agent closed the connection".to_string()));
+    }
+    serde_json::from_str(&answer)
+        .map_err(|e| RookpkgError::SigningAgent(format!("Invalid response: {}", e)))
+}
+
+/// Create the agent socket, accessible only to its owner
+///
+/// A stale socket left by an earlier agent is replaced; any other file at
+/// `socket` is an error.
+pub fn bind(socket: &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if !metadata.file_type().is_socket() {
+            return Err(RookpkgError::SigningAgent(format!(
+                "{} exists and is not a socket",
+                socket.display()
+            )));
+        }
+        fs::remove_file(socket)?;
+    }
+
+    // No window in which the socket is accessible to others
+    let old_umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(old_umask) };
+    Ok(listener?)
+}
+
+/// Answer requests on `listener` with `key` until accepting fails
+///
+/// Connections are served one at a time, and every signature is logged.
+pub fn serve(listener: &UnixListener, key: &(impl Signer + ?Sized)) -> Result<()> {
+    for stream in listener.incoming() {
+        if let Err(e) = handle(stream?, key) {
+            tracing::warn!("Signing agent connection failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(stream: UnixStream, key: &(impl Signer + ?Sized)) -> Result<()> {
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Identity) => Response::Identity {
+                fingerprint: key.fingerprint().to_string(),
+                name: key.name().to_string(),
+                email: key.email().to_string(),
+            },
+            Ok(Request::Sign { digest }) => match hex::decode(&digest) {
+                Ok(hash) if hash.len() == 32 => match key.sign_digest(&hash) {
+                    Ok(signature) => {
+                        tracing::info!("Signed digest {} with {}", digest, key.fingerprint());
+                        Response::Signature { signature }
+                    }
+                    Err(e) => Response::Error { message: e.to_string() },
+                },
+                _ => Response::Error { message: format!("Not a SHA-256 digest: {}", digest) },
+            },
+            Err(e) => Response::Error { message: format!("Invalid request: {}", e) },
+        };
+
+        let mut answer = serde_json::to_vec(&response)?;
+        answer.push(b'\n');
+        (&stream).write_all(&answer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::{self, generate_key, load_public_key};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_agent_signing() {
+        let dir = tempdir().unwrap();
+        let (key, _) = generate_key("Release Key", "release@example.org", dir.path()).unwrap();
+        let public_key = load_public_key(&dir.path().join("signing-key.pub")).unwrap();
+
+        let socket = dir.path().join("agent.sock");
+        let listener = bind(&socket).unwrap();
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+        std::thread::spawn(move || serve(&listener, &key));
+
+        let agent = AgentSigner::connect(&socket).unwrap();
+        assert_eq!(agent.fingerprint(), public_key.fingerprint);
+        assert_eq!(agent.name(), "Release Key");
+
+        let signature = signing::sign_message(&agent, b"package contents").unwrap();
+        signing::verify_signature(&public_key, b"package contents", &signature).unwrap();
+
+        // Malformed requests get an error, not a dropped connection
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        stream.write_all(b"{\"op\":\"sign\",\"digest\":\"abc\"}\n").unwrap();
+        let mut answer = String::new();
+        BufReader::new(&stream).read_line(&mut answer).unwrap();
+        assert!(matches!(serde_json::from_str(&answer).unwrap(), Response::Error { .. }));
+
+        // Nothing listening
+        assert!(matches!(
+            AgentSigner::connect(&dir.path().join("missing.sock")),
+            Err(RookpkgError::SigningAgent(_))
+        ));
+    }
+}
@@ -4,25 +4,43 @@
 //! - .PKGINFO: Package metadata in TOML format
 //! - .FILES: List of installed files with checksums
 //! - .INSTALL: Installation scripts
-//! - .SIGNATURE: Ed25519 signature (required)
+//! - .SIGNATURE: Hybrid signature over the digests of the other members
 //! - data.tar.zst: Compressed file contents
-
-use std::collections::HashMap;
+//!
+//! The signature travels inside the archive, so a package copied anywhere
+//! stays verifiable. It covers the SHA-256 digests of .PKGINFO, .FILES,
+//! .INSTALL and data.tar.zst; opening an archive recomputes them and
+//! refuses a package whose contents no longer match its signature, or that
+//! has members twice or members of any other name. The readers then read
+//! exactly the members that were hashed, and check them again. Checking
+//! the signature itself needs the signer's public key (see
+//! [`PackageArchiveReader::find_signature`]). Packages built before
+//! signatures were embedded carry a detached `<package>.sig` file instead,
+//! which is still accepted.
+
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read};
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 
-use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
-// sha2 imported but compute_sha256 is used from download module
+use sha2::{Digest, Sha256};
 use tar::{Archive, Builder};
 
 use crate::download::compute_sha256;
-use crate::spec::PackageSpec;
+use crate::autodeps::AutoDeps;
+use crate::error::{Context, Result, RookpkgError};
+use crate::fileattrs::{self, Xattrs};
+use crate::package::PackageFile;
+use crate::signing::{self, HybridSignature, LoadedPublicKey, Signer};
+use crate::spec::{FileConfig, PackageSpec, SystemGroup, SystemUser};
 
 /// Package archive file extension
 pub const PKG_EXTENSION: &str = ".rookpkg";
 
+/// Archive member holding the embedded signature
+pub const SIGNATURE_MEMBER: &str = ".SIGNATURE";
+
 /// Package info metadata (stored as .PKGINFO)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageInfo {
@@ -57,16 +75,51 @@ pub struct PackageInfo {
     pub installed_size: u64,
 
     /// Runtime dependencies: name -> version constraint
+    #[serde(serialize_with = "serialize_sorted")]
     pub depends: HashMap<String, String>,
 
     /// Build dependencies (for reference)
+    #[serde(serialize_with = "serialize_sorted")]
     pub build_depends: HashMap<String, String>,
 
     /// Optional dependencies
+    #[serde(serialize_with = "serialize_sorted")]
     pub optional_depends: HashMap<String, Vec<String>>,
 
+    /// Virtual names provided: name -> version ("" = unversioned)
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub provides: HashMap<String, String>,
+
+    /// Conflicting packages: name -> version constraint
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub conflicts: HashMap<String, String>,
+
+    /// Packages replaced by this one: name -> version constraint
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub replaces: HashMap<String, String>,
+
+    /// System users to create on install
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub users: HashMap<String, SystemUser>,
+
+    /// System groups to create on install
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub groups: HashMap<String, SystemGroup>,
+
     /// Package architecture
     pub arch: String,
+
+    /// The spec the package was built from, for `rookpkg rebuild`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub spec: String,
+}
+
+/// Serialize a map in key order, so .PKGINFO is byte-for-byte reproducible
+fn serialize_sorted<V: Serialize, S: serde::Serializer>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 impl PackageInfo {
@@ -81,12 +134,21 @@ impl PackageInfo {
             license: spec.package.license.clone(),
             url: spec.package.url.clone(),
             maintainer: spec.package.maintainer.clone(),
-            build_time: chrono::Utc::now().timestamp(),
+            // SOURCE_DATE_EPOCH, so rebuilds of the same spec stamp the same time
+            build_time: spec
+                .source_date_epoch()
+                .unwrap_or_else(|| chrono::Utc::now().timestamp()),
             installed_size: 0, // Will be calculated during packaging
             depends: spec.depends.clone(),
             build_depends: spec.build_depends.clone(),
             optional_depends: spec.optional_depends.clone(),
+            provides: spec.provides.clone(),
+            conflicts: spec.conflicts.clone(),
+            replaces: spec.replaces.clone(),
+            users: spec.users.clone(),
+            groups: spec.groups.clone(),
             arch: std::env::consts::ARCH.to_string(),
+            spec: spec.text.clone(),
         }
     }
 
@@ -119,6 +181,42 @@ pub struct FileEntry {
 
     /// File type
     pub file_type: FileType,
+
+    /// Owner name (resolved against the target root's /etc/passwd)
+    #[serde(default = "default_owner")]
+    pub owner: String,
+
+    /// Group name (resolved against the target root's /etc/group)
+    #[serde(default = "default_owner")]
+    pub group: String,
+
+    /// Modification time (Unix epoch, 0 = unknown)
+    #[serde(default)]
+    pub mtime: i64,
+
+    /// Extended attributes: name -> hex-encoded value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: Xattrs,
+}
+
+fn default_owner() -> String {
+    "root".to_string()
+}
+
+impl From<&FileEntry> for PackageFile {
+    fn from(entry: &FileEntry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            mode: entry.mode,
+            owner: entry.owner.clone(),
+            group: entry.group.clone(),
+            size_bytes: entry.size,
+            checksum: entry.sha256.clone(),
+            is_config: entry.is_config,
+            mtime: entry.mtime,
+            xattrs: entry.xattrs.clone(),
+        }
+    }
 }
 
 /// Type of file entry
@@ -182,12 +280,88 @@ struct FileList {
     files: Vec<FileEntry>,
 }
 
+/// SHA-256 digests of the archive members a package signature covers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageDigests {
+    /// Digest of .PKGINFO
+    pub pkginfo: String,
+    /// Digest of .FILES
+    pub files: String,
+    /// Digest of .INSTALL (empty if the package has no scripts)
+    #[serde(default)]
+    pub install: String,
+    /// Digest of data.tar.zst
+    pub data: String,
+}
+
+impl PackageDigests {
+    /// The data the embedded signature is made over
+    pub fn signed_data(&self) -> String {
+        format!(
+            "ROOKERY-PACKAGE-V1|{}|{}|{}|{}",
+            self.pkginfo, self.files, self.install, self.data
+        )
+    }
+}
+
+/// Contents of the .SIGNATURE member (JSON)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EmbeddedSignature {
+    digests: PackageDigests,
+    signature: HybridSignature,
+}
+
+/// A package signature and what it was made over
+#[derive(Debug, Clone)]
+pub struct PackageSignature {
+    /// The signature
+    pub signature: HybridSignature,
+    covers: SignedContent,
+}
+
+#[derive(Debug, Clone)]
+enum SignedContent {
+    /// Embedded: the member digests
+    Digests(String),
+    /// Detached: the whole archive file
+    Archive(PathBuf),
+}
+
+impl PackageSignature {
+    /// Whether the signature was embedded in the archive rather than a
+    /// detached file
+    pub fn is_embedded(&self) -> bool {
+        matches!(self.covers, SignedContent::Digests(_))
+    }
+
+    /// Verify the signature against `public_key`
+    pub fn verify(&self, public_key: &LoadedPublicKey) -> Result<()> {
+        match &self.covers {
+            SignedContent::Digests(data) => {
+                signing::verify_signature(public_key, data.as_bytes(), &self.signature)
+            }
+            SignedContent::Archive(path) => signing::verify_file(public_key, path, &self.signature),
+        }
+    }
+}
+
+/// Path of the detached signature file of a package (`<package>.sig`)
+pub fn detached_signature_path(package: &Path) -> PathBuf {
+    let mut path = package.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
 /// Package archive builder
 pub struct PackageArchiveBuilder {
     info: PackageInfo,
     files: Vec<FileEntry>,
     scripts: InstallScripts,
     source_dir: PathBuf,
+    /// Ownership, mode and capability overrides from the spec
+    file_configs: Vec<FileConfig>,
+    /// Detect provides and dependencies from the scanned files
+    auto_depends: bool,
 }
 
 impl PackageArchiveBuilder {
@@ -198,6 +372,8 @@ impl PackageArchiveBuilder {
             files: Vec::new(),
             scripts: InstallScripts::from_spec(spec),
             source_dir: source_dir.to_path_buf(),
+            file_configs: spec.files.config.clone(),
+            auto_depends: spec.package.auto_depends,
         }
     }
 
@@ -212,6 +388,7 @@ impl PackageArchiveBuilder {
             files: &mut Vec<FileEntry>,
             total_size: &mut u64,
             config_patterns: &[String],
+            build_time: i64,
         ) -> Result<()> {
             for entry in fs::read_dir(dir)? {
                 let entry = entry?;
@@ -220,7 +397,9 @@ impl PackageArchiveBuilder {
                 let metadata = fs::symlink_metadata(&path)?;
 
                 // Get path relative to base, then make it absolute for the target system
-                let rel_path = path.strip_prefix(base)?;
+                let rel_path = path.strip_prefix(base).map_err(|_| {
+                    RookpkgError::InvalidPackage(format!("Scanned file outside the destdir: {}", path.display()))
+                })?;
                 let install_path = PathBuf::from("/").join(rel_path);
                 let path_str = install_path.to_string_lossy().to_string();
 
@@ -248,6 +427,8 @@ impl PackageArchiveBuilder {
                 #[cfg(not(unix))]
                 let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
 
+                // Files are built unprivileged; ownership defaults to root
+                // and comes from the spec, never from the build user
                 files.push(FileEntry {
                     path: path_str,
                     size,
@@ -255,11 +436,16 @@ impl PackageArchiveBuilder {
                     mode,
                     is_config,
                     file_type,
+                    owner: default_owner(),
+                    group: default_owner(),
+                    // Nothing is newer than the build, as with tar --clamp-mtime
+                    mtime: fileattrs::mtime(&metadata).min(build_time),
+                    xattrs: fileattrs::read_xattrs(&path)?,
                 });
 
                 // Only recurse into actual directories, not symlinks to directories
                 if metadata.is_dir() {
-                    scan_recursive(&path, base, files, total_size, config_patterns)?;
+                    scan_recursive(&path, base, files, total_size, config_patterns, build_time)?;
                 }
             }
             Ok(())
@@ -277,10 +463,15 @@ impl PackageArchiveBuilder {
             &mut self.files,
             &mut total_size,
             &config_patterns,
+            self.info.build_time,
         )?;
 
         self.info.installed_size = total_size;
         self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        self.apply_file_configs()?;
+        if self.auto_depends {
+            self.add_auto_depends()?;
+        }
 
         tracing::info!(
             "Scanned {} files, total size: {} bytes",
@@ -291,8 +482,64 @@ impl PackageArchiveBuilder {
         Ok(())
     }
 
-    /// Build the package archive
+    /// Merge the automatically detected provides and dependencies into the
+    /// package info; entries written in the spec take precedence
+    fn add_auto_depends(&mut self) -> Result<()> {
+        let detected = AutoDeps::scan(&self.source_dir, &self.files)?;
+        for (name, version) in detected.provides {
+            self.info.provides.entry(name).or_insert(version);
+        }
+        for (name, constraint) in detected.requires {
+            if name != self.info.name && !self.info.provides.contains_key(&name) {
+                self.info.depends.entry(name).or_insert(constraint);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the spec's `[[files.config]]` overrides to the scanned files
+    fn apply_file_configs(&mut self) -> Result<()> {
+        for config in &self.file_configs {
+            let path = format!("/{}", config.path.trim_start_matches('/'));
+            let entry = self
+                .files
+                .iter_mut()
+                .find(|f| f.path == path)
+                .ok_or_else(|| RookpkgError::InvalidSpec(format!("[files] config refers to {}, which was not installed", path)))?;
+
+            if let Some(mode) = &config.mode {
+                let bits = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                    .ok()
+                    .filter(|bits| *bits <= 0o7777)
+                    .ok_or_else(|| RookpkgError::InvalidSpec(format!("Invalid mode '{}' for {}", mode, path)))?;
+                entry.mode = (entry.mode & !0o7777) | bits;
+            }
+            if let Some(owner) = &config.owner {
+                entry.owner = owner.clone();
+            }
+            if let Some(group) = &config.group {
+                entry.group = group.clone();
+            }
+            if let Some(caps) = &config.capabilities {
+                let value = fileattrs::encode_capabilities(caps)
+                    .with_context(|| format!("Invalid capabilities for {}", path))?;
+                entry.xattrs.insert(fileattrs::CAPABILITY_XATTR.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Build an unsigned package archive
     pub fn build(&self, output_dir: &Path) -> Result<PathBuf> {
+        self.build_archive(output_dir, None)
+    }
+
+    /// Build the package archive with a signature embedded as .SIGNATURE
+    pub fn build_signed(&self, output_dir: &Path, key: &dyn Signer) -> Result<PathBuf> {
+        self.build_archive(output_dir, Some(key))
+    }
+
+    fn build_archive(&self, output_dir: &Path, key: Option<&dyn Signer>) -> Result<PathBuf> {
         fs::create_dir_all(output_dir)?;
 
         let output_path = output_dir.join(self.info.filename());
@@ -323,6 +570,26 @@ impl PackageArchiveBuilder {
         let data_zst_path = temp_dir.path().join("data.tar.zst");
         self.compress_zstd(&data_tar_path, &data_zst_path)?;
 
+        // Sign the digests of everything else as .SIGNATURE
+        if let Some(key) = key {
+            let digests = PackageDigests {
+                pkginfo: compute_sha256(&pkginfo_path)?,
+                files: compute_sha256(&files_path)?,
+                install: if install_path.exists() {
+                    compute_sha256(&install_path)?
+                } else {
+                    String::new()
+                },
+                data: compute_sha256(&data_zst_path)?,
+            };
+            let signature = signing::sign_message(key, digests.signed_data().as_bytes())?;
+            let embedded = EmbeddedSignature { digests, signature };
+            fs::write(
+                temp_dir.path().join(SIGNATURE_MEMBER),
+                serde_json::to_string_pretty(&embedded)?,
+            )?;
+        }
+
         // Create the final package archive (tar containing all the above)
         self.create_package_archive(&output_path, temp_dir.path(), &data_zst_path)?;
 
@@ -338,8 +605,12 @@ impl PackageArchiveBuilder {
         // Don't follow symlinks - we want to preserve them in the archive
         builder.follow_symlinks(false);
 
+        // Owners, modes, mtimes and xattrs come from the scanned file list
+        let entries: HashMap<&str, &FileEntry> =
+            self.files.iter().map(|f| (f.path.as_str(), f)).collect();
+
         // Manually walk and add files to properly handle symlinks
-        self.add_dir_recursive(&mut builder, &self.source_dir, Path::new(""))
+        self.add_dir_recursive(&mut builder, &entries, &self.source_dir, Path::new(""))
             .context("Failed to add files to data tar")?;
 
         builder.finish()?;
@@ -348,56 +619,81 @@ impl PackageArchiveBuilder {
 
     /// Recursively add directory contents to tar, properly handling symlinks
     #[allow(clippy::only_used_in_recursion)]
-    fn add_dir_recursive(&self, builder: &mut Builder<File>, dir: &Path, prefix: &Path) -> Result<()> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
+    fn add_dir_recursive(
+        &self,
+        builder: &mut Builder<File>,
+        entries: &HashMap<&str, &FileEntry>,
+        dir: &Path,
+        prefix: &Path,
+    ) -> Result<()> {
+        // Sorted, so the tarball does not depend on filesystem order
+        let mut dir_entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        dir_entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in dir_entries {
             let path = entry.path();
             let metadata = fs::symlink_metadata(&path)
                 .with_context(|| format!("Failed to get metadata for: {}", path.display()))?;
             let name = entry.file_name();
             let archive_path = prefix.join(&name);
+            let install_path = format!("/{}", archive_path.display());
+
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(fileattrs::mtime(&metadata).clamp(0, self.info.build_time.max(0)) as u64);
+            #[cfg(unix)]
+            header.set_mode(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777);
+            if let Some(file_entry) = entries.get(install_path.as_str()) {
+                header.set_mode(file_entry.mode & 0o7777);
+                header.set_mtime(file_entry.mtime.max(0) as u64);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_username(&file_entry.owner)?;
+                header.set_groupname(&file_entry.group)?;
+
+                // xattrs travel as PAX records, the same way GNU tar stores them
+                let xattrs: Vec<(String, Vec<u8>)> = file_entry
+                    .xattrs
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = hex::decode(value).map_err(|_| {
+                            RookpkgError::FileAttributes(format!("Invalid value for xattr {} of {}", name, install_path))
+                        })?;
+                        Ok((format!("SCHILY.xattr.{}", name), value))
+                    })
+                    .collect::<Result<_>>()?;
+                builder.append_pax_extensions(xattrs.iter().map(|(k, v)| (k.as_str(), v.as_slice())))?;
+            }
 
             if metadata.file_type().is_symlink() {
                 // Read the symlink target and add as a symlink entry
                 let target = fs::read_link(&path)
                     .with_context(|| format!("Failed to read symlink: {}", path.display()))?;
-                let mut header = tar::Header::new_gnu();
                 header.set_entry_type(tar::EntryType::Symlink);
                 header.set_size(0);
                 #[cfg(unix)]
                 header.set_mode(0o777);
-                header.set_mtime(
-                    metadata.modified()
-                        .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs())
-                        .unwrap_or(0)
-                );
                 header.set_cksum();
                 builder.append_link(&mut header, &archive_path, &target)
                     .with_context(|| format!("Failed to add symlink {} -> {}", archive_path.display(), target.display()))?;
             } else if metadata.is_dir() {
                 // Add directory entry
-                let mut header = tar::Header::new_gnu();
                 header.set_entry_type(tar::EntryType::Directory);
                 header.set_size(0);
-                #[cfg(unix)]
-                header.set_mode(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()));
-                header.set_mtime(
-                    metadata.modified()
-                        .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs())
-                        .unwrap_or(0)
-                );
                 header.set_cksum();
                 // Directory paths in tar should end with /
                 let dir_path = format!("{}/", archive_path.display());
                 builder.append_data(&mut header, &dir_path, std::io::empty())
                     .with_context(|| format!("Failed to add directory: {}", archive_path.display()))?;
                 // Recurse into the directory
-                self.add_dir_recursive(builder, &path, &archive_path)?;
+                self.add_dir_recursive(builder, entries, &path, &archive_path)?;
             } else {
                 // Regular file
-                let mut file = File::open(&path)
+                let file = File::open(&path)
                     .with_context(|| format!("Failed to open file: {}", path.display()))?;
-                builder.append_file(&archive_path, &mut file)
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(metadata.len());
+                header.set_cksum();
+                builder.append_data(&mut header, &archive_path, file)
                     .with_context(|| format!("Failed to add file: {}", archive_path.display()))?;
             }
         }
@@ -428,21 +724,30 @@ impl PackageArchiveBuilder {
         let file = File::create(output)?;
         let mut builder = Builder::new(file);
 
-        // Add metadata files first
-        let pkginfo = temp_dir.join(".PKGINFO");
-        builder.append_path_with_name(&pkginfo, ".PKGINFO")?;
-
-        let files = temp_dir.join(".FILES");
-        builder.append_path_with_name(&files, ".FILES")?;
-
-        let install = temp_dir.join(".INSTALL");
-        if install.exists() {
-            builder.append_path_with_name(&install, ".INSTALL")?;
+        // Metadata first, then the payload; every member gets the same
+        // owner, mode and mtime so the outer tar is reproducible too
+        let members = [
+            (temp_dir.join(".PKGINFO"), ".PKGINFO"),
+            (temp_dir.join(".FILES"), ".FILES"),
+            (temp_dir.join(".INSTALL"), ".INSTALL"),
+            (temp_dir.join(SIGNATURE_MEMBER), SIGNATURE_MEMBER),
+            (data_zst.to_path_buf(), "data.tar.zst"),
+        ];
+        for (path, name) in &members {
+            if !path.exists() {
+                continue;
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(fs::metadata(path)?.len());
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(self.info.build_time.max(0) as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, File::open(path)?)?;
         }
 
-        // Add compressed data
-        builder.append_path_with_name(data_zst, "data.tar.zst")?;
-
         builder.finish()?;
         Ok(())
     }
@@ -458,122 +763,250 @@ impl PackageArchiveBuilder {
     }
 }
 
+/// Where a member's content lies in the archive file
+#[derive(Debug, Clone, Copy)]
+struct MemberSpan {
+    offset: u64,
+    size: u64,
+}
+
+/// A reader that hashes what passes through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
 /// Package archive reader
 pub struct PackageArchiveReader {
     path: PathBuf,
+    digests: PackageDigests,
+    members: HashMap<String, MemberSpan>,
+    signature: Option<HybridSignature>,
 }
 
 impl PackageArchiveReader {
     /// Open a package archive
+    ///
+    /// Each member may appear once, and only the members of a package are
+    /// accepted. If the package has an embedded signature, the digests it
+    /// covers are checked against the archive's contents.
     pub fn open(path: &Path) -> Result<Self> {
         if !path.exists() {
-            bail!("Package file not found: {}", path.display());
+            return Err(RookpkgError::PackageNotFound(path.display().to_string()));
         }
-        Ok(Self {
-            path: path.to_path_buf(),
-        })
-    }
 
-    /// Read the package info
-    pub fn read_info(&self) -> Result<PackageInfo> {
-        let file = File::open(&self.path)?;
+        let file = File::open(path)?;
         let mut archive = Archive::new(file);
+        let mut digests = PackageDigests::default();
+        let mut members = HashMap::new();
+        let mut embedded: Option<EmbeddedSignature> = None;
 
         for entry in archive.entries()? {
             let mut entry = entry?;
-            let path = entry.path()?;
+            let name = entry.path()?.to_string_lossy().to_string();
+
+            let digest = match name.as_str() {
+                ".PKGINFO" => Some(&mut digests.pkginfo),
+                ".FILES" => Some(&mut digests.files),
+                ".INSTALL" => Some(&mut digests.install),
+                "data.tar.zst" => Some(&mut digests.data),
+                SIGNATURE_MEMBER => None,
+                _ => {
+                    return Err(RookpkgError::InvalidPackage(format!(
+                        "Unexpected member {} in {}",
+                        name,
+                        path.display()
+                    )))
+                }
+            };
+            if !entry.header().entry_type().is_file() {
+                return Err(RookpkgError::InvalidPackage(format!(
+                    "Member {} of {} is not a regular file",
+                    name,
+                    path.display()
+                )));
+            }
+            let span = MemberSpan {
+                offset: entry.raw_file_position(),
+                size: entry.size(),
+            };
+            if members.insert(name.clone(), span).is_some() {
+                return Err(RookpkgError::InvalidPackage(format!(
+                    "{} contains {} more than once",
+                    path.display(),
+                    name
+                )));
+            }
+
+            match digest {
+                Some(digest) => {
+                    let mut hasher = Sha256::new();
+                    std::io::copy(&mut entry, &mut hasher)?;
+                    *digest = hex::encode(hasher.finalize());
+                }
+                None => {
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content)?;
+                    embedded = Some(serde_json::from_str(&content).map_err(|e| {
+                        RookpkgError::InvalidPackage(format!("Failed to parse {}: {}", SIGNATURE_MEMBER, e))
+                    })?);
+                }
+            }
+        }
 
-            if path.to_string_lossy() == ".PKGINFO" {
-                let mut content = String::new();
-                entry.read_to_string(&mut content)?;
-                return toml::from_str(&content).context("Failed to parse .PKGINFO");
+        if let Some(embedded) = &embedded {
+            if embedded.digests != digests {
+                return Err(RookpkgError::SignatureVerificationFailed(format!(
+                    "Contents of {} do not match its embedded signature",
+                    path.display()
+                )));
             }
         }
 
-        bail!("Package does not contain .PKGINFO")
+        Ok(Self {
+            path: path.to_path_buf(),
+            digests,
+            members,
+            signature: embedded.map(|e| e.signature),
+        })
     }
 
-    /// Read the file list
-    pub fn read_files(&self) -> Result<Vec<FileEntry>> {
-        let file = File::open(&self.path)?;
-        let mut archive = Archive::new(file);
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
+    /// The content of a member as hashed by [`open`](Self::open), or None
+    /// if the package does not have it
+    fn member(&self, name: &str) -> Result<Option<HashingReader<std::io::Take<File>>>> {
+        let Some(span) = self.members.get(name) else {
+            return Ok(None);
+        };
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(span.offset))?;
+        Ok(Some(HashingReader {
+            inner: file.take(span.size),
+            hasher: Sha256::new(),
+        }))
+    }
 
-            if path.to_string_lossy() == ".FILES" {
-                let mut content = String::new();
-                entry.read_to_string(&mut content)?;
-                let file_list: FileList = toml::from_str(&content).context("Failed to parse .FILES")?;
-                return Ok(file_list.files);
-            }
+    /// Check that a member read to its end is still what was hashed
+    fn check_member(&self, name: &str, reader: HashingReader<std::io::Take<File>>) -> Result<()> {
+        let expected = match name {
+            ".PKGINFO" => &self.digests.pkginfo,
+            ".FILES" => &self.digests.files,
+            ".INSTALL" => &self.digests.install,
+            _ => &self.digests.data,
+        };
+        if reader.inner.limit() != 0 || hex::encode(reader.hasher.finalize()) != *expected {
+            return Err(RookpkgError::SignatureVerificationFailed(format!(
+                "{} of {} changed after it was opened",
+                name,
+                self.path.display()
+            )));
         }
+        Ok(())
+    }
 
-        bail!("Package does not contain .FILES")
+    /// Read a metadata member as text
+    fn read_member(&self, name: &str) -> Result<Option<String>> {
+        let Some(mut reader) = self.member(name)? else {
+            return Ok(None);
+        };
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        self.check_member(name, reader)?;
+        Ok(Some(content))
     }
 
-    /// Read install scripts
-    pub fn read_scripts(&self) -> Result<Option<InstallScripts>> {
-        let file = File::open(&self.path)?;
-        let mut archive = Archive::new(file);
+    /// SHA-256 digests of the package's members
+    pub fn digests(&self) -> &PackageDigests {
+        &self.digests
+    }
 
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
+    /// The embedded signature, if the package has one
+    pub fn signature(&self) -> Option<&HybridSignature> {
+        self.signature.as_ref()
+    }
 
-            if path.to_string_lossy() == ".INSTALL" {
-                let mut content = String::new();
-                entry.read_to_string(&mut content)?;
-                return Ok(Some(toml::from_str(&content).context("Failed to parse .INSTALL")?));
-            }
+    /// The package's signature: the embedded one, or else a detached
+    /// `<package>.sig` next to the archive
+    pub fn find_signature(&self) -> Result<Option<PackageSignature>> {
+        if let Some(signature) = &self.signature {
+            return Ok(Some(PackageSignature {
+                signature: signature.clone(),
+                covers: SignedContent::Digests(self.digests.signed_data()),
+            }));
         }
 
-        Ok(None)
+        let sig_path = detached_signature_path(&self.path);
+        if !sig_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&sig_path)
+            .with_context(|| format!("Failed to read signature file {}", sig_path.display()))?;
+        let signature = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse signature file {}", sig_path.display()))?;
+        Ok(Some(PackageSignature {
+            signature,
+            covers: SignedContent::Archive(self.path.clone()),
+        }))
     }
 
-    /// Extract the data archive to a directory
-    pub fn extract_data(&self, dest: &Path) -> Result<()> {
-        fs::create_dir_all(dest)?;
-
-        let file = File::open(&self.path)?;
-        let mut archive = Archive::new(file);
-
-        // First, find and extract data.tar.zst
-        let temp_dir = tempfile::tempdir()?;
-        let data_zst = temp_dir.path().join("data.tar.zst");
+    /// Read the package info
+    ///
+    /// Like the other metadata readers, this seeks straight to the member
+    /// hashed on open rather than reading the payload.
+    pub fn read_info(&self) -> Result<PackageInfo> {
+        let content = self
+            .read_member(".PKGINFO")?
+            .ok_or_else(|| RookpkgError::InvalidPackage("Package does not contain .PKGINFO".to_string()))?;
+        toml::from_str(&content)
+            .map_err(|e| RookpkgError::InvalidPackage(format!("Failed to parse .PKGINFO: {}", e)))
+    }
 
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
+    /// Read the file list
+    pub fn read_files(&self) -> Result<Vec<FileEntry>> {
+        let content = self
+            .read_member(".FILES")?
+            .ok_or_else(|| RookpkgError::InvalidPackage("Package does not contain .FILES".to_string()))?;
+        let file_list: FileList = toml::from_str(&content)
+            .map_err(|e| RookpkgError::InvalidPackage(format!("Failed to parse .FILES: {}", e)))?;
+        Ok(file_list.files)
+    }
 
-            if path.to_string_lossy() == "data.tar.zst" {
-                let mut out = File::create(&data_zst)?;
-                std::io::copy(&mut entry, &mut out)?;
-                break;
-            }
-        }
+    /// Read install scripts
+    pub fn read_scripts(&self) -> Result<Option<InstallScripts>> {
+        let Some(content) = self.read_member(".INSTALL")? else {
+            return Ok(None);
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|e| RookpkgError::InvalidPackage(format!("Failed to parse .INSTALL: {}", e)))
+    }
 
-        if !data_zst.exists() {
-            bail!("Package does not contain data.tar.zst");
-        }
+    /// Extract the data archive to a directory
+    ///
+    /// The payload is decompressed straight into tar, without staging the
+    /// compressed or decompressed tarball on disk or in memory. It is hashed
+    /// as it is read; if it no longer matches the digest taken on open, the
+    /// extraction fails and what was written to `dest` must be discarded.
+    pub fn extract_data(&self, dest: &Path) -> Result<()> {
+        fs::create_dir_all(dest)?;
 
-        // Decompress zstd
-        let data_tar = temp_dir.path().join("data.tar");
+        let mut reader = self
+            .member("data.tar.zst")?
+            .ok_or_else(|| RookpkgError::InvalidPackage("Package does not contain data.tar.zst".to_string()))?;
         {
-            let zst_file = File::open(&data_zst)?;
-            let tar_file = File::create(&data_tar)?;
-            let mut decoder = zstd::stream::Decoder::new(zst_file)?;
-            let mut writer = BufWriter::new(tar_file);
-            std::io::copy(&mut decoder, &mut writer)?;
+            let decoder = zstd::stream::Decoder::new(&mut reader)?;
+            Archive::new(decoder).unpack(dest)?;
         }
-
-        // Extract tar
-        let tar_file = File::open(&data_tar)?;
-        let mut data_archive = Archive::new(tar_file);
-        data_archive.unpack(dest)?;
-
-        Ok(())
+        // Whatever tar did not need still counts towards the digest
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        self.check_member("data.tar.zst", reader)
     }
 }
 
@@ -597,7 +1030,13 @@ mod tests {
             depends: HashMap::new(),
             build_depends: HashMap::new(),
             optional_depends: HashMap::new(),
+            provides: HashMap::new(),
+            conflicts: HashMap::new(),
+            replaces: HashMap::new(),
+            users: HashMap::new(),
+            groups: HashMap::new(),
             arch: "x86_64".to_string(),
+            spec: String::new(),
         };
 
         assert_eq!(info.filename(), "hello-2.12-1.x86_64.rookpkg");
@@ -614,4 +1053,264 @@ mod tests {
         };
         assert!(with_post.has_scripts());
     }
+
+    #[test]
+    fn test_file_attributes_in_archive() {
+        let spec = PackageSpec::from_str(
+            r#"
+[package]
+name = "iputils"
+version = "20240117"
+
+[[files.config]]
+path = "/usr/bin/ping"
+mode = "0755"
+capabilities = "cap_net_raw+ep"
+
+[[files.config]]
+path = "usr/bin/newuidmap"
+mode = "4755"
+group = "shadow"
+"#,
+        )
+        .unwrap();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir_all(dest.join("usr/bin")).unwrap();
+        fs::write(dest.join("usr/bin/ping"), "ping").unwrap();
+        fs::write(dest.join("usr/bin/newuidmap"), "newuidmap").unwrap();
+
+        let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+        builder.scan_files().unwrap();
+        let package = builder.build(&temp_dir.path().join("out")).unwrap();
+
+        let files = PackageArchiveReader::open(&package).unwrap().read_files().unwrap();
+        let newuidmap = files.iter().find(|f| f.path == "/usr/bin/newuidmap").unwrap();
+        assert_eq!(newuidmap.mode & 0o7777, 0o4755);
+        assert_eq!((newuidmap.owner.as_str(), newuidmap.group.as_str()), ("root", "shadow"));
+        assert!(newuidmap.mtime > 0);
+        let ping = files.iter().find(|f| f.path == "/usr/bin/ping").unwrap();
+        assert!(ping.xattrs.contains_key(fileattrs::CAPABILITY_XATTR));
+
+        // The data tarball carries the same attributes
+        let data = tempfile::NamedTempFile::new().unwrap();
+        {
+            let mut outer = Archive::new(File::open(&package).unwrap());
+            for entry in outer.entries().unwrap() {
+                let mut entry = entry.unwrap();
+                if entry.path().unwrap().to_string_lossy() == "data.tar.zst" {
+                    zstd::stream::copy_decode(&mut entry, data.reopen().unwrap()).unwrap();
+                }
+            }
+        }
+        let mut inner = Archive::new(data.reopen().unwrap());
+        for entry in inner.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.path().unwrap().ends_with("ping") {
+                assert_eq!(entry.header().username().unwrap(), Some("root"));
+                let pax = entry.pax_extensions().unwrap().unwrap();
+                let keys: Vec<_> = pax.map(|e| e.unwrap().key().unwrap().to_string()).collect();
+                assert_eq!(keys, vec!["SCHILY.xattr.security.capability"]);
+            } else if entry.path().unwrap().ends_with("newuidmap") {
+                assert_eq!(entry.header().mode().unwrap(), 0o4755);
+                assert_eq!(entry.header().groupname().unwrap(), Some("shadow"));
+            }
+        }
+
+        // A config entry for a file that was not installed is an error
+        let bad = PackageSpec::from_str(
+            r#"
+[package]
+name = "x"
+version = "1"
+
+[[files.config]]
+path = "/nope"
+mode = "755"
+"#,
+        )
+        .unwrap();
+        let mut builder = PackageArchiveBuilder::new(&bad, &dest);
+        assert!(builder.scan_files().is_err());
+    }
+
+    #[test]
+    fn test_embedded_signature() {
+        let spec = PackageSpec::from_str("[package]\nname = \"hello\"\nversion = \"1.0\"\n").unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir_all(dest.join("usr/bin")).unwrap();
+        fs::write(dest.join("usr/bin/hello"), "hello").unwrap();
+        let (key, _) = signing::generate_key("Test User", "test@example.org", temp_dir.path()).unwrap();
+        let public_key = signing::load_public_key(&temp_dir.path().join("signing-key.pub")).unwrap();
+
+        let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+        builder.scan_files().unwrap();
+        let signed = builder.build_signed(&temp_dir.path().join("signed"), &key).unwrap();
+        let unsigned = builder.build(&temp_dir.path().join("unsigned")).unwrap();
+
+        // The signature travels with the archive
+        let copy = temp_dir.path().join("copy.rookpkg");
+        fs::copy(&signed, &copy).unwrap();
+        let found = PackageArchiveReader::open(&copy).unwrap().find_signature().unwrap().unwrap();
+        assert!(found.is_embedded());
+        found.verify(&public_key).unwrap();
+        let extracted = temp_dir.path().join("extracted");
+        PackageArchiveReader::open(&copy).unwrap().extract_data(&extracted).unwrap();
+        assert_eq!(fs::read_to_string(extracted.join("usr/bin/hello")).unwrap(), "hello");
+
+        // Unsigned packages fall back to a detached signature
+        assert!(PackageArchiveReader::open(&unsigned).unwrap().find_signature().unwrap().is_none());
+        let detached = signing::sign_file(&key, &unsigned).unwrap();
+        fs::write(detached_signature_path(&unsigned), serde_json::to_string(&detached).unwrap()).unwrap();
+        let found = PackageArchiveReader::open(&unsigned).unwrap().find_signature().unwrap().unwrap();
+        assert!(!found.is_embedded());
+        found.verify(&public_key).unwrap();
+
+        // Changing a member behind the signature's back is refused on open
+        let tampered = temp_dir.path().join("tampered.rookpkg");
+        {
+            let mut original = Archive::new(File::open(&signed).unwrap());
+            let mut builder = Builder::new(File::create(&tampered).unwrap());
+            for entry in original.entries().unwrap() {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().to_string();
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content).unwrap();
+                if name == ".PKGINFO" {
+                    content = String::from_utf8(content).unwrap().replace("hello", "evil").into_bytes();
+                }
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_cksum();
+                builder.append_data(&mut header, &name, content.as_slice()).unwrap();
+            }
+            builder.finish().unwrap();
+        }
+        assert!(matches!(
+            PackageArchiveReader::open(&tampered),
+            Err(RookpkgError::SignatureVerificationFailed(_))
+        ));
+    }
+
+    /// Copy a package's members into a new archive, letting `edit` add
+    /// members before each one
+    fn rewrite_package(package: &Path, out: &Path, edit: impl Fn(&str, &mut Builder<File>)) {
+        let mut original = Archive::new(File::open(package).unwrap());
+        let mut builder = Builder::new(File::create(out).unwrap());
+        for entry in original.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().to_string();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            edit(&name, &mut builder);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, &name, content.as_slice()).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_members_refused() {
+        let spec = PackageSpec::from_str("[package]\nname = \"hello\"\nversion = \"1.0\"\n").unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest = temp_dir.path().join("dest");
+        fs::create_dir_all(dest.join("usr/bin")).unwrap();
+        fs::write(dest.join("usr/bin/hello"), "hello").unwrap();
+        let (key, _) = signing::generate_key("Test User", "test@example.org", temp_dir.path()).unwrap();
+        let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+        builder.scan_files().unwrap();
+        let signed = builder.build_signed(&temp_dir.path().join("signed"), &key).unwrap();
+
+        // A forged member placed before the signed one
+        let add_before = |member: &'static str| {
+            move |name: &str, builder: &mut Builder<File>| {
+                if name == member {
+                    let forged = b"forged";
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(forged.len() as u64);
+                    header.set_cksum();
+                    builder.append_data(&mut header, member, &forged[..]).unwrap();
+                }
+            }
+        };
+        for member in ["data.tar.zst", ".PKGINFO", SIGNATURE_MEMBER] {
+            let forged = temp_dir.path().join("forged.rookpkg");
+            rewrite_package(&signed, &forged, add_before(member));
+            let err = PackageArchiveReader::open(&forged).err().unwrap();
+            assert!(matches!(err, RookpkgError::InvalidPackage(_)), "{}: {}", member, err);
+        }
+
+        // Nor are members of other names accepted
+        let extra = temp_dir.path().join("extra.rookpkg");
+        rewrite_package(&signed, &extra, |name, builder| {
+            if name == "data.tar.zst" {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(0);
+                header.set_cksum();
+                builder.append_data(&mut header, "data.tar.gz", std::io::empty()).unwrap();
+            }
+        });
+        assert!(matches!(PackageArchiveReader::open(&extra), Err(RookpkgError::InvalidPackage(_))));
+
+        // Changing the file after it was opened is noticed by the readers
+        let copy = temp_dir.path().join("copy.rookpkg");
+        fs::copy(&signed, &copy).unwrap();
+        let reader = PackageArchiveReader::open(&copy).unwrap();
+        let mut content = fs::read(&copy).unwrap();
+        let at = content.windows(5).position(|w| w == b"hello").unwrap();
+        content[at..at + 5].copy_from_slice(b"jello");
+        fs::write(&copy, &content).unwrap();
+        assert!(matches!(reader.read_info(), Err(RookpkgError::SignatureVerificationFailed(_))));
+    }
+
+    #[test]
+    fn test_reproducible_archive() {
+        let spec = PackageSpec::from_str(
+            r#"
+[package]
+name = "hello"
+version = "2.12"
+
+[depends]
+glibc = ">= 2.39"
+zlib = ">= 1.3"
+bash = ""
+
+[[changelog]]
+date = "2024-03-01"
+version = "2.12"
+author = "a@example.org"
+changes = ["Update"]
+"#,
+        )
+        .unwrap();
+        let epoch = spec.source_date_epoch().unwrap();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dest = temp_dir.path().join("dest");
+        for name in ["c", "a", "b"] {
+            fs::create_dir_all(dest.join("usr/share").join(name)).unwrap();
+            fs::write(dest.join("usr/share").join(name).join("file"), name).unwrap();
+        }
+
+        let build = |out: &str| {
+            let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+            builder.scan_files().unwrap();
+            assert_eq!(builder.info().build_time, epoch);
+            assert!(builder.files().iter().all(|f| f.mtime <= epoch));
+            builder.build(&temp_dir.path().join(out)).unwrap()
+        };
+        let first = build("one");
+        let second = build("two");
+
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+        let reader = PackageArchiveReader::open(&first).unwrap();
+        let info = reader.read_info().unwrap();
+        assert_eq!(info.spec, spec.text);
+        assert_eq!(info.build_time, epoch);
+    }
 }
@@ -0,0 +1,503 @@
+//! Automatic dependency and provides detection
+//!
+//! After the install phase the files in the destination directory are
+//! scanned for what they offer to and need from other packages:
+//!
+//! - ELF objects provide their `DT_SONAME` as `so:<soname>` and require
+//!   every `DT_NEEDED` library the same way
+//! - executables in `/usr/bin` and `/usr/sbin` provide their own path, and
+//!   scripts require the path of their shebang interpreter (for
+//!   `#!/usr/bin/env <command>`, `/usr/bin/<command>`)
+//! - pkg-config files provide `pc:<name>` at their version and require the
+//!   modules in their `Requires:` field
+//!
+//! The results are ordinary virtual names, so the resolver satisfies them
+//! from whichever package provides them.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::archive::{FileEntry, FileType};
+use crate::error::{Context, Result};
+
+/// Prefix of shared library names
+pub const SONAME_PREFIX: &str = "so:";
+
+/// Prefix of pkg-config module names
+pub const PKGCONFIG_PREFIX: &str = "pc:";
+
+/// Automatically detected relations: name -> version or constraint ("" = any)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutoDeps {
+    /// Names the package provides
+    pub provides: BTreeMap<String, String>,
+    /// Names the package requires
+    pub requires: BTreeMap<String, String>,
+}
+
+impl AutoDeps {
+    /// Scan the files of a package staged under `root`
+    ///
+    /// Requirements the package satisfies itself, including libraries it
+    /// ships without a soname, are left out.
+    pub fn scan(root: &Path, files: &[FileEntry]) -> Result<Self> {
+        let mut deps = Self::default();
+        let mut shipped = HashSet::new();
+
+        for entry in files {
+            let path = root.join(entry.path.trim_start_matches('/'));
+            if let Some(name) = Path::new(&entry.path).file_name() {
+                shipped.insert(name.to_string_lossy().to_string());
+            }
+
+            let command = normalize_path(&entry.path);
+            let in_bin_dir = ["/usr/bin/", "/usr/sbin/"]
+                .iter()
+                .any(|dir| command.strip_prefix(dir).is_some_and(|rest| !rest.contains('/')));
+            if in_bin_dir && entry.file_type != FileType::Directory {
+                deps.provides.insert(command, String::new());
+            }
+
+            if entry.file_type != FileType::Regular {
+                continue;
+            }
+            if entry.path.ends_with(".pc") && entry.path.contains("/pkgconfig/") {
+                deps.scan_pkgconfig(&path)?;
+                continue;
+            }
+
+            let mut magic = [0u8; 4];
+            let read = File::open(&path)
+                .and_then(|mut f| f.read(&mut magic))
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let magic = &magic[..read];
+
+            if magic == b"\x7fELF" {
+                let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                if let Some(dynamic) = ElfDynamic::parse(&data) {
+                    if let Some(soname) = dynamic.soname {
+                        deps.provides.insert(format!("{}{}", SONAME_PREFIX, soname), String::new());
+                    }
+                    for needed in dynamic.needed {
+                        deps.requires.insert(format!("{}{}", SONAME_PREFIX, needed), String::new());
+                    }
+                }
+            } else if magic.starts_with(b"#!") && entry.mode & 0o111 != 0 {
+                if let Some(interpreter) = shebang_interpreter(&path)? {
+                    deps.requires.insert(interpreter, String::new());
+                }
+            }
+        }
+
+        let provides = &deps.provides;
+        deps.requires.retain(|name, _| {
+            let shipped_library = name
+                .strip_prefix(SONAME_PREFIX)
+                .is_some_and(|lib| shipped.contains(lib));
+            !provides.contains_key(name) && !shipped_library
+        });
+        Ok(deps)
+    }
+
+    /// Record what a pkg-config file provides and requires
+    fn scan_pkgconfig(&mut self, path: &Path) -> Result<()> {
+        let Some(module) = path.file_stem() else {
+            return Ok(());
+        };
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let field = |key: &str| {
+            content.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                (name.trim() == key).then(|| value.trim())
+            })
+        };
+
+        // Versions built from variables are not worth expanding
+        let version = field("Version").filter(|v| !v.contains('$')).unwrap_or("");
+        self.provides.insert(
+            format!("{}{}", PKGCONFIG_PREFIX, module.to_string_lossy()),
+            version.to_string(),
+        );
+
+        for (name, constraint) in parse_pkgconfig_requires(field("Requires").unwrap_or("")) {
+            self.requires.insert(format!("{}{}", PKGCONFIG_PREFIX, name), constraint);
+        }
+        Ok(())
+    }
+}
+
+/// Map the merged-`/usr` aliases (`/bin`, `/sbin`) to their real paths
+fn normalize_path(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("/bin/") {
+        format!("/usr/bin/{}", rest)
+    } else if let Some(rest) = path.strip_prefix("/sbin/") {
+        format!("/usr/sbin/{}", rest)
+    } else {
+        path.to_string()
+    }
+}
+
+/// The interpreter named on a script's `#!` line
+///
+/// For `#!/usr/bin/env python3` that is the command env runs, taken to be
+/// in `/usr/bin`.
+fn shebang_interpreter(path: &Path) -> Result<Option<String>> {
+    let mut head = [0u8; 256];
+    let read = File::open(path)
+        .and_then(|mut f| f.read(&mut head))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let line = String::from_utf8_lossy(&head[..read]);
+    let Some(line) = line.strip_prefix("#!").and_then(|rest| rest.lines().next()) else {
+        return Ok(None);
+    };
+    Ok(parse_shebang(line))
+}
+
+/// The interpreter path of a `#!` line (without the `#!`)
+fn parse_shebang(line: &str) -> Option<String> {
+    let mut args = line.split_whitespace();
+    let interpreter = args.next().filter(|interpreter| interpreter.starts_with('/'))?;
+    let interpreter = normalize_path(interpreter);
+    if interpreter != "/usr/bin/env" {
+        return Some(interpreter);
+    }
+
+    // Skip env's options and variable assignments; -u and -C take a value
+    while let Some(arg) = args.next() {
+        if matches!(arg, "-u" | "-C" | "--unset" | "--chdir") {
+            args.next();
+        } else if arg.starts_with('-') || arg.contains('=') {
+            continue;
+        } else if arg.starts_with('/') {
+            return Some(normalize_path(arg));
+        } else if !arg.contains('/') {
+            return Some(format!("/usr/bin/{}", arg));
+        } else {
+            break;
+        }
+    }
+    Some(interpreter)
+}
+
+/// Parse a pkg-config `Requires:` list (`glib-2.0 >= 2.50, zlib`) into
+/// names and constraints
+fn parse_pkgconfig_requires(value: &str) -> Vec<(String, String)> {
+    let tokens: Vec<&str> = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let mut requires = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let name = tokens[i];
+        let mut constraint = String::new();
+        if let (Some(op), Some(version)) = (tokens.get(i + 1), tokens.get(i + 2)) {
+            if ["=", "<", ">", "<=", ">=", "!="].contains(op) {
+                constraint = format!("{} {}", op, version);
+                i += 2;
+            }
+        }
+        requires.push((name.to_string(), constraint));
+        i += 1;
+    }
+    requires
+}
+
+/// The dynamic section entries of an ELF object that matter for linking
+#[derive(Debug, Default)]
+struct ElfDynamic {
+    soname: Option<String>,
+    needed: Vec<String>,
+}
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_SONAME: u64 = 14;
+
+impl ElfDynamic {
+    /// Read the dynamic section of an ELF executable or shared object
+    ///
+    /// Returns None for static binaries, relocatable objects and anything
+    /// malformed.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let elf = ElfReader::new(data)?;
+        let is_64 = elf.is_64;
+
+        let (ph_off, ph_entsize, ph_num) = if is_64 {
+            (elf.u64(32)?, elf.u16(54)? as u64, elf.u16(56)?)
+        } else {
+            (elf.u32(28)? as u64, elf.u16(42)? as u64, elf.u16(44)?)
+        };
+
+        // (type, offset, vaddr, filesz) of each program header
+        let mut segments = Vec::new();
+        for i in 0..ph_num as u64 {
+            let at = usize::try_from(ph_off.checked_add(i.checked_mul(ph_entsize)?)?).ok()?;
+            let field = |offset: usize| at.checked_add(offset);
+            let segment = if is_64 {
+                (elf.u32(at)?, elf.u64(field(8)?)?, elf.u64(field(16)?)?, elf.u64(field(32)?)?)
+            } else {
+                (
+                    elf.u32(at)?,
+                    elf.u32(field(4)?)? as u64,
+                    elf.u32(field(8)?)? as u64,
+                    elf.u32(field(16)?)? as u64,
+                )
+            };
+            segments.push(segment);
+        }
+
+        let &(_, dyn_off, _, dyn_size) = segments.iter().find(|s| s.0 == PT_DYNAMIC)?;
+        let entry_size = if is_64 { 16 } else { 8 };
+        let mut strtab = None;
+        let mut soname = None;
+        let mut needed = Vec::new();
+        for i in 0..dyn_size / entry_size {
+            let at = usize::try_from(dyn_off.checked_add(i * entry_size)?).ok()?;
+            let (tag, value) = if is_64 {
+                (elf.u64(at)?, elf.u64(at.checked_add(8)?)?)
+            } else {
+                (elf.u32(at)? as u64, elf.u32(at.checked_add(4)?)? as u64)
+            };
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => needed.push(value),
+                DT_SONAME => soname = Some(value),
+                DT_STRTAB => strtab = Some(value),
+                _ => {}
+            }
+        }
+
+        // DT_STRTAB is an address; find the file offset through the segment
+        // that loads it. All of these come from the file, so the arithmetic
+        // is checked and a file it does not add up for is skipped.
+        let strtab = strtab?;
+        let &(_, load_off, load_addr, _) = segments
+            .iter()
+            .find(|s| s.0 == PT_LOAD && s.2 <= strtab && strtab < s.2.saturating_add(s.3))?;
+        let strtab_off = strtab.checked_sub(load_addr)?.checked_add(load_off)?;
+
+        let string = |offset: u64| elf.cstr(strtab_off.checked_add(offset)?);
+        Some(Self {
+            soname: soname.and_then(string),
+            needed: needed.into_iter().filter_map(string).collect(),
+        })
+    }
+}
+
+/// Bounds-checked reads from an ELF image in its own byte order
+struct ElfReader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> ElfReader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 64 || &data[..4] != b"\x7fELF" {
+            return None;
+        }
+        let reader = Self {
+            data,
+            is_64: match data[4] {
+                1 => false,
+                2 => true,
+                _ => return None,
+            },
+            big_endian: match data[5] {
+                1 => false,
+                2 => true,
+                _ => return None,
+            },
+        };
+        // Only executables and shared objects are linked at run time
+        matches!(reader.u16(16)?, 2 | 3).then_some(reader)
+    }
+
+    fn bytes<const N: usize>(&self, at: usize) -> Option<[u8; N]> {
+        self.data.get(at..at.checked_add(N)?)?.try_into().ok()
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b = self.bytes(at)?;
+        Some(if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b = self.bytes(at)?;
+        Some(if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }
+
+    fn u64(&self, at: usize) -> Option<u64> {
+        let b = self.bytes(at)?;
+        Some(if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) })
+    }
+
+    fn cstr(&self, at: u64) -> Option<String> {
+        let rest = self.data.get(usize::try_from(at).ok()?..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..end]).ok().map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, file_type: FileType, mode: u32) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            size: 0,
+            sha256: String::new(),
+            mode,
+            is_config: false,
+            file_type,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            mtime: 0,
+            xattrs: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_pkgconfig_requires() {
+        assert_eq!(
+            parse_pkgconfig_requires("glib-2.0 >= 2.50, zlib libffi"),
+            vec![
+                ("glib-2.0".to_string(), ">= 2.50".to_string()),
+                ("zlib".to_string(), String::new()),
+                ("libffi".to_string(), String::new()),
+            ]
+        );
+        assert!(parse_pkgconfig_requires("").is_empty());
+    }
+
+    #[test]
+    fn test_scan_elf() {
+        // The test binary itself is a dynamically linked ELF executable
+        let exe = std::env::current_exe().unwrap();
+        let dynamic = ElfDynamic::parse(&fs::read(&exe).unwrap()).unwrap();
+        assert!(dynamic.needed.iter().any(|lib| lib.starts_with("libc.so")));
+
+        assert!(ElfDynamic::parse(b"#!/bin/sh\n").is_none());
+        let mut truncated = fs::read(&exe).unwrap();
+        truncated.truncate(100);
+        assert!(ElfDynamic::parse(&truncated).is_none());
+    }
+
+    /// A little-endian ELF64 shared object with a PT_LOAD and a PT_DYNAMIC
+    /// segment holding `dynamic` as (tag, value) pairs
+    fn elf64(load: (u64, u64, u64), dynamic: &[(u64, u64)], dyn_size: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        data[16..18].copy_from_slice(&3u16.to_le_bytes());
+        data[32..40].copy_from_slice(&64u64.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&2u16.to_le_bytes());
+
+        let dyn_off = 64 + 2 * 56;
+        let (load_off, load_addr, load_size) = load;
+        let segments = [(PT_LOAD, load_off, load_addr, load_size), (PT_DYNAMIC, dyn_off, 0, dyn_size)];
+        for (kind, offset, addr, size) in segments {
+            let mut header = vec![0u8; 56];
+            header[..4].copy_from_slice(&kind.to_le_bytes());
+            header[8..16].copy_from_slice(&offset.to_le_bytes());
+            header[16..24].copy_from_slice(&addr.to_le_bytes());
+            header[32..40].copy_from_slice(&size.to_le_bytes());
+            data.extend(header);
+        }
+        for (tag, value) in dynamic {
+            data.extend(tag.to_le_bytes());
+            data.extend(value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_scan_hostile_elf() {
+        // A well-formed object: the string table follows the dynamic section
+        let strings_at = 64 + 2 * 56 + 4 * 16;
+        let mut good = elf64(
+            (0, 0x1000, 0x1000),
+            &[(DT_STRTAB, 0x1000 + strings_at), (DT_SONAME, 1), (DT_NEEDED, 11), (DT_NULL, 0)],
+            64,
+        );
+        good.extend(b"\0libfoo.so\0libc.so.6\0");
+        let dynamic = ElfDynamic::parse(&good).unwrap();
+        assert_eq!(dynamic.soname.as_deref(), Some("libfoo.so"));
+        assert_eq!(dynamic.needed, vec!["libc.so.6"]);
+
+        // The string table's file offset overflows
+        let overflow = elf64((u64::MAX, 0x1000, 0x1000), &[(DT_STRTAB, 0x1fff), (DT_NEEDED, 0), (DT_NULL, 0)], 48);
+        assert!(ElfDynamic::parse(&overflow).is_none());
+
+        // The dynamic section claims far more entries than the file holds
+        let truncated = elf64((0, 0, 0x1000), &[(DT_STRTAB, 0)], u64::MAX);
+        assert!(ElfDynamic::parse(&truncated).is_none());
+
+        // Program headers placed at the end of the address space
+        let mut far = good.clone();
+        far[32..40].copy_from_slice(&(u64::MAX - 4).to_le_bytes());
+        assert!(ElfDynamic::parse(&far).is_none());
+
+        // And a scan skips such files instead of failing
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp_dir.path().join("usr/lib")).unwrap();
+        fs::write(temp_dir.path().join("usr/lib/libbad.so"), &overflow).unwrap();
+        let files = vec![entry("/usr/lib/libbad.so", FileType::Regular, 0o755)];
+        let deps = AutoDeps::scan(temp_dir.path(), &files).unwrap();
+        assert!(deps.requires.is_empty() && deps.provides.is_empty());
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang("/bin/sh -e").as_deref(), Some("/usr/bin/sh"));
+        assert_eq!(parse_shebang("/usr/bin/env python3").as_deref(), Some("/usr/bin/python3"));
+        assert_eq!(parse_shebang("/usr/bin/env -S perl -w").as_deref(), Some("/usr/bin/perl"));
+        assert_eq!(parse_shebang("/bin/env -i LANG=C -u HOME bash").as_deref(), Some("/usr/bin/bash"));
+        assert_eq!(parse_shebang("/usr/bin/env /opt/tool").as_deref(), Some("/opt/tool"));
+        assert_eq!(parse_shebang("/usr/bin/env").as_deref(), Some("/usr/bin/env"));
+        assert_eq!(parse_shebang("python3"), None);
+    }
+
+    #[test]
+    fn test_scan_package() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(root.join("usr/lib/pkgconfig")).unwrap();
+        fs::copy(std::env::current_exe().unwrap(), root.join("usr/bin/tool")).unwrap();
+        fs::write(root.join("usr/bin/script"), "#!/bin/sh\nexec tool\n").unwrap();
+        fs::write(root.join("usr/bin/helper"), "#!/usr/bin/tool --flag\n").unwrap();
+        fs::write(
+            root.join("usr/lib/pkgconfig/foo.pc"),
+            "prefix=/usr\nName: foo\nVersion: 1.2.3\nRequires: zlib >= 1.2\n",
+        )
+        .unwrap();
+
+        let files = vec![
+            entry("/usr/bin", FileType::Directory, 0o755),
+            entry("/usr/bin/tool", FileType::Regular, 0o755),
+            entry("/usr/bin/script", FileType::Regular, 0o755),
+            entry("/usr/bin/helper", FileType::Regular, 0o755),
+            entry("/usr/lib/pkgconfig/foo.pc", FileType::Regular, 0o644),
+        ];
+        let deps = AutoDeps::scan(root, &files).unwrap();
+
+        assert_eq!(deps.provides.get("/usr/bin/tool").map(String::as_str), Some(""));
+        assert_eq!(deps.provides.get("pc:foo").map(String::as_str), Some("1.2.3"));
+        assert_eq!(deps.requires.get("pc:zlib").map(String::as_str), Some(">= 1.2"));
+        assert!(deps.requires.contains_key("/usr/bin/sh"));
+        assert!(deps.requires.keys().any(|name| name.starts_with("so:libc.so")));
+        // Satisfied by the package itself
+        assert!(!deps.requires.contains_key("/usr/bin/tool"));
+        assert!(!deps.provides.contains_key("/usr/bin"));
+    }
+}
@@ -0,0 +1,54 @@
+//! rookpkg-agent - reference signing agent
+//!
+//! Holds a signing key and signs digests for rookpkg processes that connect
+//! to its socket, so build machines never load the secret key themselves.
+//! Point `signing.agent_socket` (or `keysign --agent`) at the socket.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+
+use rookpkg::agent;
+use rookpkg::signing::{self, Signer};
+
+/// Rookery OS signing agent
+#[derive(Parser)]
+#[command(name = "rookpkg-agent")]
+#[command(author = "Friendly Society of Corvids")]
+#[command(version)]
+#[command(about = "Signs rookpkg packages and metadata over a Unix socket", long_about = None)]
+struct Cli {
+    /// Path to the signing key (secret key)
+    #[arg(long)]
+    key: std::path::PathBuf,
+
+    /// Path of the socket to listen on
+    #[arg(long)]
+    socket: std::path::PathBuf,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let key = signing::load_signing_key_from_path(&cli.key)
+        .with_context(|| format!("Failed to load signing key: {}", cli.key.display()))?;
+    let listener = agent::bind(&cli.socket)
+        .with_context(|| format!("Failed to create socket: {}", cli.socket.display()))?;
+
+    tracing::info!(
+        "Signing as {} <{}> ({}) on {}",
+        key.name(),
+        key.email(),
+        key.fingerprint(),
+        cli.socket.display()
+    );
+    agent::serve(&listener, &key)?;
+
+    Ok(())
+}
@@ -1,20 +1,31 @@
 //! Build phase executor
 //!
 //! Executes package build phases (prep, configure, build, check, install)
-//! with proper environment setup and sandboxing.
+//! with proper environment setup and sandboxing. Unless sandboxing is
+//! turned off, every phase runs in a [`Sandbox`] with a clean environment,
+//! no network and only `src` and `dest` writable.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::fs::{self, File};
 use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use pubgrub::range::Range;
 
 use crate::config::Config;
-use crate::download::{extract_tarball, Downloader, SourceFile};
-use crate::spec::PackageSpec;
+use crate::database::Database;
+use crate::download::{extract_tarball, verify_checksum, Downloader, SourceFile};
+use crate::error::RookpkgError;
+use crate::repository::{find_candidate, RepoManager, SearchResult};
+use crate::resolver::{
+    format_dep, parse_constraint, parse_version, ResolutionContext, RookeryDependencyProvider,
+};
+use crate::sandbox::Sandbox;
+use crate::spec::{PackageSpec, Patch};
+use crate::version::Version;
 
 /// Build environment for a package
 pub struct BuildEnvironment {
@@ -41,6 +52,15 @@ pub struct BuildEnvironment {
 
     /// Verbose mode - stream output to terminal
     verbose: bool,
+
+    /// Run phases in a sandbox
+    sandbox: bool,
+
+    /// Directory of the spec file, where patch files are looked up
+    spec_dir: Option<PathBuf>,
+
+    /// Enabled build features, for patch conditions
+    features: Vec<String>,
 }
 
 /// Result of a build phase
@@ -126,6 +146,11 @@ impl BuildEnvironment {
         // LFS standard environment
         env.insert("LC_ALL".to_string(), "POSIX".to_string());
 
+        // Reproducible builds: tools stamp this instead of the current time
+        if let Some(epoch) = spec.source_date_epoch() {
+            env.insert("SOURCE_DATE_EPOCH".to_string(), epoch.to_string());
+        }
+
         // Add spec-defined environment variables
         for (key, value) in &spec.environment {
             env.insert(key.clone(), value.clone());
@@ -140,6 +165,9 @@ impl BuildEnvironment {
             jobs,
             downloader,
             verbose: false,
+            sandbox: true,
+            spec_dir: None,
+            features: config.build.features.clone(),
         })
     }
 
@@ -148,6 +176,18 @@ impl BuildEnvironment {
         self.verbose = verbose;
     }
 
+    /// Run phases directly on the host instead of in a sandbox
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    /// Set the directory of the spec file, which patch files are relative to.
+    ///
+    /// Without it, patch files are looked up in the source directory.
+    pub fn set_spec_dir(&mut self, dir: &Path) {
+        self.spec_dir = Some(dir.to_path_buf());
+    }
+
     /// Override the number of parallel jobs
     pub fn set_jobs(&mut self, jobs: u32) {
         self.jobs = jobs;
@@ -203,7 +243,7 @@ impl BuildEnvironment {
         // Sources are now in $ROOKPKG_SOURCES, prep phase will extract them to $ROOKPKG_BUILD
         tracing::info!("Sources downloaded to {:?}", self.downloader.cache_dir());
 
-        Ok(())
+        self.fetch_patches()
     }
 
     /// Download all sources and automatically extract tarballs
@@ -265,9 +305,52 @@ impl BuildEnvironment {
 
         tracing::info!("Sources extracted to {:?}", self.src_dir);
 
+        self.fetch_patches()?;
+
         Ok(extracted_dirs)
     }
 
+    /// Download the patches that come from a URL rather than a file next to the spec
+    fn fetch_patches(&self) -> Result<()> {
+        let patch_files: Vec<SourceFile> = self.applicable_patches().filter_map(patch_source).collect();
+        if !patch_files.is_empty() {
+            tracing::info!("Fetching {} patches", patch_files.len());
+            self.downloader.download_all(&patch_files)?;
+        }
+        Ok(())
+    }
+
+    /// Patches whose condition holds for this build, in order
+    fn applicable_patches(&self) -> impl Iterator<Item = &Patch> {
+        self.spec
+            .patches
+            .iter()
+            .filter(|patch| patch.applies(std::env::consts::ARCH, &self.features))
+    }
+
+    /// Local path of a patch: next to the spec file, or in the download cache
+    fn patch_path(&self, patch: &Patch) -> Result<PathBuf> {
+        if let Some(ref file) = patch.file {
+            let base = self.spec_dir.as_deref().unwrap_or(&self.src_dir);
+            let path = base.join(file);
+            if !path.exists() {
+                bail!("Patch file not found: {}", path.display());
+            }
+            if let Some(ref sha256) = patch.sha256 {
+                if !verify_checksum(&path, sha256)? {
+                    bail!("Checksum mismatch for patch {} (expected: {})", path.display(), sha256);
+                }
+            }
+            return Ok(path);
+        }
+
+        // Already downloaded by fetch_sources, so this is a cache hit
+        match patch_source(patch) {
+            Some(source) => Ok(self.downloader.download(&source)?),
+            None => bail!("Patch {} has neither a file nor a url", patch.name()),
+        }
+    }
+
     /// Get the download cache directory
     pub fn cache_dir(&self) -> &Path {
         self.downloader.cache_dir()
@@ -278,7 +361,7 @@ impl BuildEnvironment {
         self.jobs
     }
 
-    /// Apply patches from the spec
+    /// Apply the spec's patches in order, skipping those whose condition does not hold
     pub fn apply_patches(&self) -> Result<()> {
         if self.spec.patches.is_empty() {
             return Ok(());
@@ -286,16 +369,22 @@ impl BuildEnvironment {
 
         tracing::info!("Applying {} patches", self.spec.patches.len());
 
-        for (name, patch) in &self.spec.patches {
-            tracing::info!("Applying patch: {}", name);
-
-            // Find the patch file relative to sources
-            let patch_path = self.src_dir.join(&patch.file);
-
-            if !patch_path.exists() {
-                bail!("Patch file not found: {}", patch_path.display());
+        for patch in &self.spec.patches {
+            if !patch.applies(std::env::consts::ARCH, &self.features) {
+                tracing::info!(
+                    "Skipping patch {} (condition {} does not hold)",
+                    patch.name(),
+                    patch.condition.as_ref().map(|c| c.to_string()).unwrap_or_default()
+                );
+                continue;
+            }
+            match patch.cve {
+                Some(ref cve) => tracing::info!("Applying patch: {} ({})", patch.name(), cve),
+                None => tracing::info!("Applying patch: {}", patch.name()),
             }
 
+            let patch_path = self.patch_path(patch)?;
+
             let output = Command::new("patch")
                 .arg(format!("-p{}", patch.strip))
                 .arg("-i")
@@ -306,7 +395,7 @@ impl BuildEnvironment {
 
             if !output.status.success() {
                 let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("Patch {} failed: {}", name, stderr);
+                bail!("Patch {} failed: {}", patch.name(), stderr);
             }
         }
 
@@ -379,31 +468,39 @@ impl BuildEnvironment {
         // Try to find the main source directory (usually named pkg-version)
         let work_dir = self.find_source_dir()?;
 
+        let mut command = Command::new("/bin/bash");
+        command.arg(&script_path).current_dir(&work_dir);
+        if self.sandbox {
+            // Nothing from the invoking shell leaks into the build
+            command.env_clear();
+            Sandbox::new(&self.build_dir.join("sandbox"))?
+                .read_only(&self.build_dir)?
+                .read_only(self.cache_dir())?
+                .writable(&self.src_dir)?
+                .writable(&self.dest_dir)?
+                .apply(&mut command, &work_dir)?;
+        }
+        command.envs(&self.env);
+
         // Execute the script
         let start = std::time::Instant::now();
 
         let (exit_code, stdout, stderr) = if self.verbose {
             // Verbose mode: stream output directly to terminal
-            let status = Command::new("/bin/bash")
-                .arg(&script_path)
-                .current_dir(&work_dir)
-                .envs(&self.env)
+            let status = command
                 .stdout(Stdio::inherit())
                 .stderr(Stdio::inherit())
                 .status()
-                .with_context(|| format!("Failed to execute {} phase", phase_name))?;
+                .with_context(|| self.spawn_error(phase_name))?;
 
             (status.code().unwrap_or(-1), String::new(), String::new())
         } else {
             // Quiet mode: capture output
-            let output = Command::new("/bin/bash")
-                .arg(&script_path)
-                .current_dir(&work_dir)
-                .envs(&self.env)
+            let output = command
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
                 .output()
-                .with_context(|| format!("Failed to execute {} phase", phase_name))?;
+                .with_context(|| self.spawn_error(phase_name))?;
 
             let stdout = String::from_utf8_lossy(&output.stdout).to_string();
             let stderr = String::from_utf8_lossy(&output.stderr).to_string();
@@ -456,6 +553,18 @@ impl BuildEnvironment {
         Ok(result)
     }
 
+    fn spawn_error(&self, phase_name: &str) -> String {
+        if self.sandbox {
+            format!(
+                "Failed to execute {} phase in the build sandbox \
+                (does the kernel allow unprivileged user namespaces? --no-sandbox skips it)",
+                phase_name
+            )
+        } else {
+            format!("Failed to execute {} phase", phase_name)
+        }
+    }
+
     /// Find the main source directory after extraction
     fn find_source_dir(&self) -> Result<PathBuf> {
         // Look for a single directory in src_dir (common pattern after tar extraction)
@@ -545,11 +654,209 @@ impl BuildEnvironment {
     }
 }
 
+/// Download description of a patch that has no local file
+fn patch_source(patch: &Patch) -> Option<SourceFile> {
+    if patch.file.is_some() {
+        return None;
+    }
+    let url = patch.url.as_ref()?;
+    Some(SourceFile::new(url, patch.sha256.as_deref().unwrap_or_default()))
+}
+
+/// Build order of a tree of specs
+///
+/// A spec depends on the specs in the tree that supply one of its `depends`
+/// or `build-depends`, either by package name or through `provides`.
+/// Dependencies on anything outside the tree (or on the package itself) are
+/// left to the repositories.
+#[derive(Debug, Clone, Default)]
+pub struct BuildGraph {
+    /// Package name -> packages in the tree that must be built first
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl BuildGraph {
+    /// Build the graph for a set of specs with distinct package names
+    pub fn new<'a>(specs: impl IntoIterator<Item = &'a PackageSpec>) -> Self {
+        let specs: Vec<&PackageSpec> = specs.into_iter().collect();
+
+        // Which package in the tree supplies a name; real names win over provides
+        let mut suppliers: HashMap<&str, &str> = HashMap::new();
+        for spec in &specs {
+            for provided in spec.provides.keys() {
+                suppliers.entry(provided.as_str()).or_insert(&spec.package.name);
+            }
+        }
+        for spec in &specs {
+            suppliers.insert(&spec.package.name, &spec.package.name);
+        }
+
+        let mut edges = BTreeMap::new();
+        for spec in &specs {
+            let deps: BTreeSet<String> = spec
+                .runtime_deps()
+                .chain(spec.build_deps())
+                .filter_map(|(name, _)| suppliers.get(name).copied())
+                .filter(|supplier| *supplier != spec.package.name)
+                .map(str::to_string)
+                .collect();
+            edges.insert(spec.package.name.clone(), deps);
+        }
+
+        Self { edges }
+    }
+
+    /// All packages in the graph, in name order
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.edges.keys().map(String::as_str)
+    }
+
+    /// Packages in the tree that must be built before `name`
+    pub fn dependencies(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.edges.get(name).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Dependency cycles, each as the packages along it with the first repeated at the end
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = Vec::new();
+        for component in self.strongly_connected() {
+            if component.len() < 2 {
+                continue;
+            }
+            // Shortest way from the first member back to itself within the component
+            let start = component.iter().next().expect("component is not empty");
+            let mut came_from: HashMap<&str, &str> = HashMap::new();
+            let mut queue = VecDeque::from([start.as_str()]);
+            'search: while let Some(current) = queue.pop_front() {
+                for next in self.dependencies(current) {
+                    if !component.contains(next) {
+                        continue;
+                    }
+                    if next == start {
+                        let mut cycle = vec![start.clone()];
+                        let mut node = current;
+                        while node != start {
+                            cycle.push(node.to_string());
+                            node = came_from[node];
+                        }
+                        cycle.push(start.clone());
+                        cycle.reverse();
+                        cycles.push(cycle);
+                        break 'search;
+                    }
+                    if !came_from.contains_key(next) {
+                        came_from.insert(next, current);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        cycles
+    }
+
+    /// A serial build order: every package after its dependencies, ties broken by name
+    pub fn order(&self) -> Result<Vec<String>> {
+        let cycles = self.cycles();
+        if !cycles.is_empty() {
+            let described: Vec<String> = cycles.iter().map(|c| c.join(" -> ")).collect();
+            return Err(anyhow!(RookpkgError::DependencyResolution(format!(
+                "dependency cycle: {}",
+                described.join("; ")
+            ))));
+        }
+
+        let mut order = Vec::new();
+        let mut built: BTreeSet<&str> = BTreeSet::new();
+        while built.len() < self.edges.len() {
+            let next = self
+                .packages()
+                .find(|name| !built.contains(name) && self.dependencies(name).all(|d| built.contains(d)))
+                .expect("an acyclic graph always has a buildable package");
+            built.insert(next);
+            order.push(next.to_string());
+        }
+        Ok(order)
+    }
+
+    /// Strongly connected components (Tarjan), each as a sorted set
+    fn strongly_connected(&self) -> Vec<BTreeSet<String>> {
+        struct State<'a> {
+            index: HashMap<&'a str, usize>,
+            lowlink: HashMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            on_stack: BTreeSet<&'a str>,
+            components: Vec<BTreeSet<String>>,
+        }
+
+        fn visit<'a>(graph: &'a BuildGraph, node: &'a str, state: &mut State<'a>) {
+            let index = state.index.len();
+            state.index.insert(node, index);
+            state.lowlink.insert(node, index);
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for next in graph.dependencies(node) {
+                if !state.index.contains_key(next) {
+                    visit(graph, next, state);
+                    let low = state.lowlink[node].min(state.lowlink[next]);
+                    state.lowlink.insert(node, low);
+                } else if state.on_stack.contains(next) {
+                    let low = state.lowlink[node].min(state.index[next]);
+                    state.lowlink.insert(node, low);
+                }
+            }
+
+            if state.lowlink[node] == state.index[node] {
+                let mut component = BTreeSet::new();
+                while let Some(member) = state.stack.pop() {
+                    state.on_stack.remove(member);
+                    component.insert(member.to_string());
+                    if member == node {
+                        break;
+                    }
+                }
+                state.components.push(component);
+            }
+        }
+
+        let mut state = State {
+            index: HashMap::new(),
+            lowlink: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            components: Vec::new(),
+        };
+        for node in self.packages() {
+            if !state.index.contains_key(node) {
+                visit(self, node, &mut state);
+            }
+        }
+        state.components.sort();
+        state.components
+    }
+}
+
 /// Builder for constructing packages
 pub struct PackageBuilder {
     config: Config,
 }
 
+/// How a spec's build dependencies are met on this system
+#[derive(Debug, Clone, Default)]
+pub struct BuildDepsPlan {
+    /// Build dependencies already met: (dependency as written, installed package-version)
+    pub satisfied: Vec<(String, String)>,
+    /// Packages to install from the repositories, including their own dependencies
+    pub missing: Vec<SearchResult>,
+}
+
+impl BuildDepsPlan {
+    /// Check if everything needed for the build is installed
+    pub fn is_satisfied(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
 impl PackageBuilder {
     /// Create a new package builder
     pub fn new(config: Config) -> Self {
@@ -559,7 +866,10 @@ impl PackageBuilder {
     /// Build a package from a spec file
     pub fn build_from_spec(&self, spec_path: &Path) -> Result<BuildEnvironment> {
         let spec = PackageSpec::from_file(spec_path)?;
-        let env = BuildEnvironment::new(spec, &self.config)?;
+        let mut env = BuildEnvironment::new(spec, &self.config)?;
+        if let Some(dir) = spec_path.parent() {
+            env.set_spec_dir(dir);
+        }
         Ok(env)
     }
 
@@ -567,6 +877,100 @@ impl PackageBuilder {
     pub fn build(&self, spec: PackageSpec) -> Result<BuildEnvironment> {
         BuildEnvironment::new(spec, &self.config)
     }
+
+    /// Resolve the spec's build dependencies against the installed packages
+    /// and the cached repository indexes.
+    ///
+    /// Installed packages are kept at their current version, so the plan only
+    /// ever adds packages. Fails with [`RookpkgError::DependencyResolution`]
+    /// when no such set exists.
+    pub fn resolve_build_deps(&self, spec: &PackageSpec) -> Result<BuildDepsPlan> {
+        if spec.build_depends.is_empty() {
+            return Ok(BuildDepsPlan::default());
+        }
+
+        let db_path = self.config.database_path();
+        let db = if db_path.exists() {
+            Some(Database::open(&db_path)?)
+        } else {
+            None
+        };
+        let installed = match db {
+            Some(ref db) => db.list_packages()?,
+            None => Vec::new(),
+        };
+
+        let mut manager = RepoManager::new(&self.config)?;
+        manager.load_caches()?;
+        let candidates = manager.package_candidates();
+
+        let mut provider = RookeryDependencyProvider::new();
+        for results in candidates.values() {
+            for result in results {
+                provider.add_entry(&result.package);
+            }
+        }
+
+        let mut context = ResolutionContext::default();
+        let mut root_deps: HashMap<String, Range<Version>> = HashMap::new();
+        if let Some(ref db) = db {
+            for pkg in &installed {
+                provider.add_installed(pkg, &db.get_dependencies(&pkg.name)?, &db.get_provides(&pkg.name)?);
+                provider.prefer(&pkg.name);
+                context.installed.insert(pkg.name.clone(), pkg.version.clone());
+                root_deps.insert(pkg.name.clone(), Range::exact(parse_version(&pkg.version)));
+            }
+        }
+
+        let mut build_deps: Vec<(&str, &str)> = spec.build_deps().collect();
+        build_deps.sort();
+        for (name, constraint) in &build_deps {
+            let range = parse_constraint(constraint).map_err(|e| {
+                anyhow!(RookpkgError::InvalidSpec(format!(
+                    "build dependency {}: {}",
+                    format_dep(name, constraint),
+                    e
+                )))
+            })?;
+            context.requested.insert(name.to_string());
+            let merged = match root_deps.get(*name) {
+                Some(existing) => existing.intersection(&range),
+                None => range,
+            };
+            root_deps.insert(name.to_string(), merged);
+        }
+
+        let root_version = provider.set_root(root_deps);
+        let solution = provider
+            .solve(root_version, &context)
+            .map_err(|report| anyhow!(RookpkgError::DependencyResolution(report.to_string())))?;
+
+        let mut plan = BuildDepsPlan::default();
+        for (name, constraint) in &build_deps {
+            // A virtual name is met by whichever installed package provides it
+            let met_by = parse_constraint(constraint)
+                .ok()
+                .and_then(|range| provider.satisfied_by(name, &range, &solution));
+            if let Some(pkg) = met_by.and_then(|p| installed.iter().find(|i| i.name == p)) {
+                plan.satisfied.push((format_dep(name, constraint), pkg.full_version()));
+            }
+        }
+
+        let mut missing: Vec<_> = solution
+            .iter()
+            .filter(|(pkg, _)| !context.installed.contains_key(*pkg))
+            .collect();
+        missing.sort_by(|a, b| a.0.cmp(b.0));
+        for (pkg, version) in missing {
+            let result = candidates
+                .get(pkg)
+                .and_then(|c| find_candidate(c, version, None))
+                .ok_or_else(|| anyhow!(RookpkgError::PackageNotFound(format!("{}-{}", pkg, version))))?;
+            plan.missing.push(result.clone());
+        }
+
+        Ok(plan)
+    }
 }
 
 #[cfg(test)]
@@ -689,6 +1093,129 @@ mod tests {
         }
     }
 
+    fn build_deps_config(root: &Path) -> Config {
+        let mut config = Config::default().with_sysroot(root.to_path_buf());
+        config.paths.cache_dir = root.join("cache");
+        config
+    }
+
+    fn spec_with_build_deps(build_depends: &str) -> PackageSpec {
+        PackageSpec::from_str(&format!(
+            r#"
+            [package]
+            name = "test-pkg"
+            version = "1.0.0"
+            summary = "Test package"
+
+            [build-depends]
+            {}
+        "#,
+            build_depends
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_build_deps_installed() {
+        use crate::package::{InstallReason, InstalledPackage};
+
+        let temp = tempfile::tempdir().unwrap();
+        let config = build_deps_config(temp.path());
+        let db_path = config.database_path();
+        fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+        let db = Database::open(&db_path).unwrap();
+        db.add_package(&InstalledPackage {
+            name: "meson".to_string(),
+            version: "1.4.0".to_string(),
+            release: 1,
+            install_date: 0,
+            size_bytes: 0,
+            checksum: String::new(),
+            spec: String::new(),
+            install_reason: InstallReason::Explicit,
+        })
+        .unwrap();
+
+        let builder = PackageBuilder::new(config);
+        let plan = builder
+            .resolve_build_deps(&spec_with_build_deps(r#"meson = ">= 1.2""#))
+            .unwrap();
+        assert!(plan.is_satisfied());
+        assert_eq!(plan.satisfied, vec![("meson >= 1.2".to_string(), "1.4.0-1".to_string())]);
+
+        // An installed version below the constraint cannot be replaced by a build
+        let err = builder
+            .resolve_build_deps(&spec_with_build_deps(r#"meson = ">= 2.0""#))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RookpkgError>(),
+            Some(RookpkgError::DependencyResolution(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_build_deps_unavailable() {
+        let temp = tempfile::tempdir().unwrap();
+        let builder = PackageBuilder::new(build_deps_config(temp.path()));
+
+        // Nothing to resolve without build dependencies
+        let plan = builder.resolve_build_deps(&spec_with_build_deps("")).unwrap();
+        assert!(plan.is_satisfied());
+        assert!(plan.satisfied.is_empty());
+
+        let err = builder
+            .resolve_build_deps(&spec_with_build_deps(r#"meson = ">= 1.2""#))
+            .unwrap_err();
+        assert!(err.to_string().contains("meson"));
+    }
+
+    fn graph_spec(name: &str, depends: &[&str], build_depends: &[&str], provides: &[&str]) -> PackageSpec {
+        let table = |deps: &[&str]| {
+            deps.iter().map(|d| format!("{} = \"\"\n", d)).collect::<String>()
+        };
+        PackageSpec::from_str(&format!(
+            "[package]\nname = \"{}\"\nversion = \"1.0\"\n\n[depends]\n{}\n[build-depends]\n{}\n[provides]\n{}",
+            name,
+            table(depends),
+            table(build_depends),
+            table(provides)
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_graph_order() {
+        let specs = vec![
+            graph_spec("app", &["libfoo", "glibc"], &["meson"], &[]),
+            graph_spec("libfoo", &["glibc"], &["cc"], &[]),
+            graph_spec("gcc", &["glibc"], &["gcc"], &["cc"]),
+            graph_spec("glibc", &[], &["python"], &[]),
+        ];
+        let graph = BuildGraph::new(&specs);
+
+        // meson and python are not in the tree; gcc needing itself is left to the repositories
+        assert_eq!(graph.dependencies("app").collect::<Vec<_>>(), vec!["glibc", "libfoo"]);
+        assert_eq!(graph.dependencies("libfoo").collect::<Vec<_>>(), vec!["gcc", "glibc"]);
+        assert_eq!(graph.dependencies("gcc").collect::<Vec<_>>(), vec!["glibc"]);
+        assert!(graph.cycles().is_empty());
+        assert_eq!(graph.order().unwrap(), vec!["glibc", "gcc", "libfoo", "app"]);
+    }
+
+    #[test]
+    fn test_build_graph_cycles() {
+        let specs = vec![
+            graph_spec("a", &["b"], &[], &[]),
+            graph_spec("b", &[], &["c"], &[]),
+            graph_spec("c", &["a"], &[], &[]),
+            graph_spec("d", &["a"], &[], &[]),
+        ];
+        let graph = BuildGraph::new(&specs);
+
+        assert_eq!(graph.cycles(), vec![vec!["a", "b", "c", "a"]]);
+        let err = graph.order().unwrap_err();
+        assert!(err.to_string().contains("a -> b -> c -> a"));
+    }
+
     #[test]
     fn test_extract_tarball_exists() {
         // Verify extract_tarball function exists and is callable
@@ -697,6 +1224,6 @@ mod tests {
 
         // The function signature is (archive: &Path, dest_dir: &Path) -> Result<()>
         // We don't actually call it since we'd need a real tarball
-        let _f: fn(&Path, &Path) -> Result<()> = extract_tarball;
+        let _f: fn(&Path, &Path) -> crate::error::Result<()> = extract_tarball;
     }
 }
@@ -6,9 +6,9 @@
 use anyhow::{Context, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::cve::{CveAuditor, ReferenceType, Severity, SpecUpdater, VulnerablePackage};
-use crate::database::Database;
+use rookpkg::config::Config;
+use rookpkg::cve::{CveAuditor, ReferenceType, Severity, SpecUpdater, VulnerablePackage};
+use rookpkg::database::Database;
 
 /// Run the audit command
 pub fn run(
@@ -25,9 +25,9 @@ pub fn run(
 
     // Clear cache if requested
     if clear_cache {
-        println!("{} Clearing CVE database cache...", "🗑".cyan());
+        status!("{} Clearing CVE database cache...", "🗑".cyan());
         auditor.clear_cache()?;
-        println!("{} Cache cleared.\n", "✓".green());
+        status!("{} Cache cleared.\n", "✓".green());
     }
 
     // If looking up a specific CVE, just show that and return
@@ -62,7 +62,7 @@ pub fn run(
         return Ok(());
     }
 
-    println!(
+    status!(
         "{} Auditing {} installed package(s) for vulnerabilities...\n",
         "🔍".cyan(),
         installed.len()
@@ -139,7 +139,7 @@ pub fn run(
 
 /// Look up a specific CVE by ID
 fn lookup_cve(auditor: &CveAuditor, cve_id: &str, json_output: bool) -> Result<()> {
-    println!("{} Looking up {}...\n", "🔍".cyan(), cve_id.cyan());
+    status!("{} Looking up {}...\n", "🔍".cyan(), cve_id.cyan());
 
     match auditor.get_cve(cve_id)? {
         Some(cve) => {
@@ -618,7 +618,7 @@ fn try_version_upgrade(
     new_version: &str,
     _config: &Config,
 ) -> Result<bool> {
-    use crate::spec::PackageSpec;
+    use rookpkg::spec::PackageSpec;
 
     // Read the spec to get the current source URL
     let spec = PackageSpec::from_file(spec_path)?;
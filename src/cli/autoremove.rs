@@ -5,10 +5,10 @@
 use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::hooks::HookResult;
-use crate::transaction::Transaction;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::hooks::HookResult;
+use rookpkg::transaction::Transaction;
 
 /// Run the autoremove command
 pub fn run(dry_run: bool, config: &Config) -> Result<()> {
@@ -86,7 +86,7 @@ pub fn run(dry_run: bool, config: &Config) -> Result<()> {
         tx.remove(&pkg.name);
     }
 
-    match tx.execute_with_hooks(&config.hooks) {
+    match tx.execute_with_hooks(&config.hooks, &config.snapshot) {
         Ok((pre_results, post_results)) => {
             print_hook_results("pre-transaction", &pre_results);
 
@@ -164,7 +164,7 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
 
 /// Mark a package as explicitly installed (won't be autoremoved)
 pub fn mark_explicit(packages: &[String], config: &Config) -> Result<()> {
-    use crate::package::InstallReason;
+    use rookpkg::package::InstallReason;
 
     let db_path = config.database_path();
     let db = Database::open(&db_path)?;
@@ -229,7 +229,7 @@ pub fn mark_explicit(packages: &[String], config: &Config) -> Result<()> {
 
 /// Mark a package as a dependency (can be autoremoved)
 pub fn mark_dependency(packages: &[String], config: &Config) -> Result<()> {
-    use crate::package::InstallReason;
+    use rookpkg::package::InstallReason;
 
     let db_path = config.database_path();
     let db = Database::open(&db_path)?;
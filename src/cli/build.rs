@@ -3,21 +3,19 @@
 use std::path::Path;
 
 use anyhow::{bail, Result};
-use chrono::Utc;
 use colored::Colorize;
 
-use crate::archive::PackageArchiveBuilder;
-use crate::build::{PackageBuilder, PhaseResult};
+use rookpkg::archive::PackageArchiveBuilder;
+use rookpkg::build::{PackageBuilder, PhaseResult};
 use crate::cli::inspect::validate_built_archive;
-use crate::config::Config;
-use crate::database::Database;
-use crate::delta::DeltaBuilder;
-use crate::download::compute_sha256;
-use crate::package::InstallReason;
-use crate::repository::{PackageEntry, PackageIndex};
-use crate::signing::{self, sign_file};
-use crate::spec::PackageSpec;
-use crate::transaction::Transaction;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::delta::DeltaBuilder;
+use rookpkg::package::InstallReason;
+use rookpkg::repository::{PackageEntry, PackageIndex, RepoManager};
+use rookpkg::signing::{self, sign_file};
+use rookpkg::spec::PackageSpec;
+use rookpkg::transaction::Transaction;
 
 #[allow(clippy::too_many_arguments)]
 pub fn run(
@@ -29,19 +27,22 @@ pub fn run(
     delta_from: Option<&Path>,
     jobs: Option<usize>,
     auto_extract: bool,
+    sandbox: bool,
+    install_build_deps: bool,
+    remove_build_deps: bool,
     config: &Config,
 ) -> Result<()> {
     // CRITICAL: Check for signing key FIRST
     println!("{}", "Checking signing key...".cyan());
 
-    let signing_key = match signing::load_signing_key(config) {
+    let signing_key = match signing::load_signer(config) {
         Ok(key) => {
             // Show key owner info
             println!(
                 "  {} Signing key: {} <{}>",
                 "✓".green(),
-                key.name.cyan(),
-                key.email.dimmed()
+                key.name().cyan(),
+                key.email().dimmed()
             );
             println!(
                 "    Fingerprint: {}",
@@ -49,6 +50,15 @@ pub fn run(
             );
             key
         }
+        Err(e) if config.signing.agent_socket.is_some() => {
+            eprintln!();
+            eprintln!("{}", "FATAL: Signing agent unavailable!".red().bold());
+            eprintln!();
+            eprintln!("Start the agent that holds the signing key, e.g.:");
+            eprintln!("  {} --key <signing-key.secret> --socket <socket>", "rookpkg-agent".cyan());
+            eprintln!();
+            bail!("Signing key required: {}", e);
+        }
         Err(e) => {
             eprintln!();
             eprintln!("{}", "FATAL: No signing key found!".red().bold());
@@ -79,6 +89,48 @@ pub fn run(
     // Read spec first
     let spec = PackageSpec::from_file(spec_path)?;
 
+    // Build dependencies must be present before any phase runs
+    let installed_deps = ensure_build_deps(&spec, install_build_deps, config)?;
+
+    let result = build_spec(
+        spec_path,
+        &spec,
+        install,
+        output,
+        batch,
+        update_index,
+        delta_from,
+        jobs,
+        auto_extract,
+        sandbox,
+        signing_key.as_ref(),
+        config,
+    );
+
+    // Like makepkg -r, take the build dependencies out again even if the build failed
+    if remove_build_deps && !installed_deps.is_empty() {
+        remove_installed_build_deps(&installed_deps, config)?;
+    }
+
+    result
+}
+
+/// Build, sign and optionally install and index a package from a parsed spec
+#[allow(clippy::too_many_arguments)]
+fn build_spec(
+    spec_path: &Path,
+    spec: &PackageSpec,
+    install: bool,
+    output: Option<&Path>,
+    batch: bool,
+    update_index: bool,
+    delta_from: Option<&Path>,
+    jobs: Option<usize>,
+    auto_extract: bool,
+    sandbox: bool,
+    signing_key: &dyn signing::Signer,
+    config: &Config,
+) -> Result<()> {
     // Create build environment using PackageBuilder::build() with the parsed spec
     // (build_from_spec would re-parse, so using build() is more efficient)
     let builder = PackageBuilder::new(config.clone());
@@ -88,6 +140,10 @@ pub fn run(
     if let Some(j) = jobs {
         build_env.set_jobs(j as u32);
     }
+    build_env.set_sandbox(sandbox);
+    if let Some(dir) = spec_path.parent() {
+        build_env.set_spec_dir(dir);
+    }
 
     println!(
         "  {} {}-{}-{}",
@@ -124,6 +180,11 @@ pub fn run(
         "✓".green(),
         build_env.jobs()
     );
+    if sandbox {
+        println!("  {} Sandbox: no network, read-only root", "✓".green());
+    } else {
+        println!("  {} Sandbox: disabled (phases run directly on the host)", "!".yellow());
+    }
 
     // Build using either batch mode (build_all) or individual phases
     if batch {
@@ -211,7 +272,7 @@ pub fn run(
     println!("{}", "Creating package archive...".cyan());
 
     let output_dir = output.unwrap_or(Path::new("."));
-    let mut archive_builder = PackageArchiveBuilder::new(&spec, build_env.dest_dir());
+    let mut archive_builder = PackageArchiveBuilder::new(spec, build_env.dest_dir());
     archive_builder.scan_files()?;
 
     // Use info() and files() to show what will be packaged
@@ -234,32 +295,16 @@ pub fn run(
     // Validate archive before building
     validate_built_archive(&archive_builder)?;
 
-    let package_path = archive_builder.build(output_dir)?;
+    let package_path = archive_builder.build_signed(output_dir, signing_key)?;
     println!(
         "  {} Package created: {}",
         "✓".green(),
         package_path.display()
     );
-
-    // Sign package
-    println!("{}", "Signing package...".cyan());
-
-    let signature = sign_file(&signing_key, &package_path)?;
-
-    // Write signature file
-    let sig_path = package_path.with_extension("rookpkg.sig");
-    let sig_json = serde_json::to_string_pretty(&signature)?;
-    std::fs::write(&sig_path, &sig_json)?;
-
     println!(
         "  {} Signed with key: {}",
         "✓".green(),
-        signing::get_fingerprint(&signing_key).dimmed()
-    );
-    println!(
-        "  {} Signature: {}",
-        "✓".green(),
-        sig_path.display()
+        signing::get_fingerprint(signing_key).dimmed()
     );
 
     // Generate delta package if requested
@@ -304,7 +349,7 @@ pub fn run(
                             );
 
                             // Sign the delta file
-                            let delta_signature = sign_file(&signing_key, &delta_file)?;
+                            let delta_signature = sign_file(signing_key, &delta_file)?;
                             let delta_sig_path = delta_file.with_extension("rookdelta.sig");
                             let delta_sig_json = serde_json::to_string_pretty(&delta_signature)?;
                             std::fs::write(&delta_sig_path, &delta_sig_json)?;
@@ -341,7 +386,7 @@ pub fn run(
     println!("{}", "Build complete!".green().bold());
     println!();
     println!("  {}: {}", "Package".bold(), package_path.display());
-    println!("  {}: {}", "Signature".bold(), sig_path.display());
+    println!("  {}: {}", "Signed by".bold(), signing::get_fingerprint(signing_key));
     if let Some(ref delta) = delta_path {
         println!("  {}: {}", "Delta".bold(), delta.display());
     }
@@ -354,131 +399,248 @@ pub fn run(
     if install {
         println!();
         println!("{}", "Installing built package...".cyan());
-
-        // Open database (use sysroot-relative path)
-        let db_path = config.database_path();
-        let db = Database::open(&db_path)?;
-
-        // Check if already installed
-        if let Some(existing) = db.get_package(&spec.package.name)? {
-            println!(
-                "  {} Package {} already installed ({})",
-                "!".yellow(),
-                spec.package.name.bold(),
-                existing.full_version()
-            );
+        if !install_built_package(spec, &package_path, config)? {
             println!("  Use {} to upgrade.", "rookpkg upgrade".bold());
             return Ok(());
         }
-
-        // Install using transaction
-        let db = Database::open(&db_path)?;
-        let root = config.sysroot();
-        let mut tx = Transaction::new(root, db)?;
-
-        let version = format!("{}-{}", spec.package.version, spec.package.release);
-        // Build and install is always explicit
-        tx.install(&spec.package.name, &version, &package_path, InstallReason::Explicit);
-
-        match tx.execute() {
-            Ok(()) => {
-                println!(
-                    "  {} Package installed successfully",
-                    "✓".green().bold()
-                );
-            }
-            Err(e) => {
-                println!(
-                    "  {} Installation failed: {}",
-                    "✗".red().bold(),
-                    e
-                );
-                bail!("Installation failed: {}", e);
-            }
-        }
     }
 
     // Update local package index if requested
     if update_index {
         println!();
         println!("{}", "Updating local package index...".cyan());
+        update_local_index(&package_path, output_dir, signing_key)?;
+    }
 
-        let index_path = output_dir.join("packages.json");
+    Ok(())
+}
 
-        // Load existing index or create new one
-        let mut pkg_index = if index_path.exists() {
-            let content = std::fs::read_to_string(&index_path)?;
-            serde_json::from_str::<PackageIndex>(&content)
-                .unwrap_or_else(|_| PackageIndex::new("local"))
-        } else {
-            PackageIndex::new("local")
-        };
-
-        // Create package entry from spec and built package info
-        let pkg_size = std::fs::metadata(&package_path)?.len();
-        let entry = PackageEntry {
-            name: spec.package.name.clone(),
-            version: spec.package.version.clone(),
-            release: spec.package.release,
-            description: spec.package.description.clone(),
-            arch: "x86_64".to_string(),
-            size: pkg_size,
-            sha256: compute_sha256(&package_path)?,
-            filename: package_path
-                .file_name()
-                .map(|n| n.to_string_lossy().to_string())
-                .unwrap_or_default(),
-            // Convert HashMap<String, String> keys to Vec<String> for dependencies
-            depends: spec.depends.keys().cloned().collect(),
-            build_depends: spec.build_depends.keys().cloned().collect(),
-            provides: Vec::new(),
-            conflicts: Vec::new(),
-            replaces: Vec::new(),
-            license: if spec.package.license.is_empty() {
-                None
-            } else {
-                Some(spec.package.license.clone())
-            },
-            homepage: if spec.package.url.is_empty() {
-                None
-            } else {
-                Some(spec.package.url.clone())
-            },
-            maintainer: if spec.package.maintainer.is_empty() {
-                None
-            } else {
-                Some(spec.package.maintainer.clone())
-            },
-            build_date: Some(Utc::now()),
-        };
+/// Install a freshly built package as explicitly installed.
+///
+/// Returns false, leaving the system alone, when some version of the
+/// package is already installed.
+pub(crate) fn install_built_package(spec: &PackageSpec, package_path: &Path, config: &Config) -> Result<bool> {
+    // Open database (use sysroot-relative path)
+    let db_path = config.database_path();
+    let db = Database::open(&db_path)?;
+
+    // Check if already installed
+    if let Some(existing) = db.get_package(&spec.package.name)? {
+        println!(
+            "  {} Package {} already installed ({})",
+            "!".yellow(),
+            spec.package.name.bold(),
+            existing.full_version()
+        );
+        return Ok(false);
+    }
 
-        // Add package to index (this uses PackageIndex::add_package)
-        pkg_index.add_package(entry);
+    // Install using transaction
+    let db = Database::open(&db_path)?;
+    let root = config.sysroot();
+    let mut tx = Transaction::new(root, db)?;
 
-        // Write updated index
-        let index_content = serde_json::to_string_pretty(&pkg_index)?;
-        std::fs::write(&index_path, &index_content)?;
+    let version = format!("{}-{}", spec.package.version, spec.package.release);
+    // Build and install is always explicit
+    tx.install(&spec.package.name, &version, package_path, InstallReason::Explicit);
 
-        println!(
-            "  {} Updated {} ({} packages)",
-            "✓".green(),
-            index_path.display(),
-            pkg_index.count
-        );
+    match tx.execute() {
+        Ok(()) => {
+            println!(
+                "  {} Package installed successfully",
+                "✓".green().bold()
+            );
+        }
+        Err(e) => {
+            println!(
+                "  {} Installation failed: {}",
+                "✗".red().bold(),
+                e
+            );
+            bail!("Installation failed: {}", e);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Add a built package to the signed `packages.json` index in its output directory
+pub(crate) fn update_local_index(
+    package_path: &Path,
+    output_dir: &Path,
+    signing_key: &dyn signing::Signer,
+) -> Result<()> {
+    let index_path = output_dir.join("packages.json");
+
+    // Load existing index or create new one
+    let mut pkg_index = if index_path.exists() {
+        let content = std::fs::read_to_string(&index_path)?;
+        serde_json::from_str::<PackageIndex>(&content)
+            .unwrap_or_else(|_| PackageIndex::new("local"))
+    } else {
+        PackageIndex::new("local")
+    };
+
+    // The entry comes from the archive, so it carries the automatically
+    // detected provides and dependencies as well as the spec's
+    let filename = package_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let entry = PackageEntry::from_archive(package_path, filename)?;
+
+    // Add package to index (this uses PackageIndex::add_package)
+    pkg_index.add_package(entry);
+
+    // Write updated index
+    let index_content = serde_json::to_string_pretty(&pkg_index)?;
+    std::fs::write(&index_path, &index_content)?;
+
+    println!(
+        "  {} Updated {} ({} packages)",
+        "✓".green(),
+        index_path.display(),
+        pkg_index.count
+    );
+
+    // Sign the index file
+    let sig_path = index_path.with_extension("json.sig");
+    let index_sig = signing::sign_file(signing_key, &index_path)?;
+    let sig_json = serde_json::to_string_pretty(&index_sig)?;
+    std::fs::write(&sig_path, &sig_json)?;
+
+    println!(
+        "  {} Signed index: {}",
+        "✓".green(),
+        sig_path.display()
+    );
+
+    Ok(())
+}
+
+/// Check the spec's build dependencies, installing missing ones if asked.
+///
+/// Returns the names of the packages that were installed for the build.
+pub(crate) fn ensure_build_deps(
+    spec: &PackageSpec,
+    install_missing: bool,
+    config: &Config,
+) -> Result<Vec<String>> {
+    if spec.build_depends.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    println!("{}", "Checking build dependencies...".cyan());
+    let builder = PackageBuilder::new(config.clone());
+    let plan = builder.resolve_build_deps(spec)?;
+
+    for (dep, installed) in &plan.satisfied {
+        println!("  {} {} ({})", "✓".green(), dep, installed.dimmed());
+    }
 
-        // Sign the index file
-        let sig_path = index_path.with_extension("json.sig");
-        let index_sig = signing::sign_file(&signing_key, &index_path)?;
-        let sig_json = serde_json::to_string_pretty(&index_sig)?;
-        std::fs::write(&sig_path, &sig_json)?;
+    if plan.is_satisfied() {
+        return Ok(Vec::new());
+    }
 
+    for result in &plan.missing {
         println!(
-            "  {} Signed index: {}",
-            "✓".green(),
-            sig_path.display()
+            "  {} {}-{} {} {}",
+            "✗".red(),
+            result.package.name.bold(),
+            result.package.version,
+            "from".dimmed(),
+            result.repository.cyan()
+        );
+    }
+
+    if !install_missing {
+        eprintln!();
+        eprintln!(
+            "Use {} to install them before building.",
+            "--install-build-deps".bold()
+        );
+        bail!(
+            "{} missing build dependenc{}",
+            plan.missing.len(),
+            if plan.missing.len() == 1 { "y" } else { "ies" }
         );
     }
 
+    println!("{}", "Installing build dependencies...".cyan());
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    let mut verified_packages = Vec::new();
+    for result in &plan.missing {
+        let verified = manager
+            .download_and_verify_package(&result.package, &result.repository, config)
+            .map_err(|e| anyhow::anyhow!("Failed to download/verify {}: {}", result.package.name, e))?;
+        verified_packages.push(verified);
+    }
+
+    let db = Database::open(&config.database_path())?;
+    let mut tx = Transaction::with_options(config.sysroot(), db, config.options.clone())?;
+    for verified in &verified_packages {
+        let version = format!("{}-{}", verified.package.version, verified.package.release);
+        tx.install(&verified.package.name, &version, &verified.path, InstallReason::Dependency);
+    }
+
+    tx.execute_with_hooks(&config.hooks, &config.snapshot)
+        .map_err(|e| anyhow::anyhow!("Installing build dependencies failed: {}", e))?;
+    println!(
+        "  {} {} build dependenc{} installed",
+        "✓".green(),
+        verified_packages.len(),
+        if verified_packages.len() == 1 { "y" } else { "ies" }
+    );
+
+    Ok(plan.missing.iter().map(|r| r.package.name.clone()).collect())
+}
+
+/// Remove packages installed by [`ensure_build_deps`] once the build is over.
+///
+/// Packages that something outside the set has come to depend on in the
+/// meantime (such as the freshly built package) are left installed.
+pub(crate) fn remove_installed_build_deps(names: &[String], config: &Config) -> Result<()> {
+    println!("{}", "Removing build dependencies...".cyan());
+
+    let db = Database::open(&config.database_path())?;
+    let mut to_remove = Vec::new();
+    for name in names {
+        let needed_by: Vec<String> = db
+            .get_reverse_dependencies(name)?
+            .into_iter()
+            .filter(|rdep| !names.contains(rdep))
+            .collect();
+        if needed_by.is_empty() {
+            to_remove.push(name.clone());
+        } else {
+            println!(
+                "  {} Keeping {} (required by {})",
+                "!".yellow(),
+                name.bold(),
+                needed_by.join(", ")
+            );
+        }
+    }
+
+    if to_remove.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = Transaction::with_options(config.sysroot(), db, config.options.clone())?;
+    for name in &to_remove {
+        tx.remove(name);
+    }
+    tx.execute_with_hooks(&config.hooks, &config.snapshot)
+        .map_err(|e| anyhow::anyhow!("Removing build dependencies failed: {}", e))?;
+    println!(
+        "  {} {} build dependenc{} removed",
+        "✓".green(),
+        to_remove.len(),
+        if to_remove.len() == 1 { "y" } else { "ies" }
+    );
+
     Ok(())
 }
 
@@ -1,13 +1,20 @@
 //! Buildall command implementation - build all .rook specs in a directory
+//!
+//! Specs are built in dependency order, independent ones concurrently.
 
-use std::path::Path;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::time::Instant;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::signing;
+use rookpkg::archive::PackageArchiveBuilder;
+use rookpkg::build::{BuildEnvironment, BuildGraph, PackageBuilder};
+use rookpkg::config::Config;
+use rookpkg::signing;
+use rookpkg::spec::PackageSpec;
 
 /// Result of building a single package
 struct BuildResult {
@@ -16,18 +23,25 @@ struct BuildResult {
     success: bool,
     duration_secs: f64,
     error: Option<String>,
-    package_path: Option<std::path::PathBuf>,
+    package_path: Option<PathBuf>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     spec_dir: &Path,
     output: Option<&Path>,
     continue_on_error: bool,
     jobs: Option<usize>,
+    workers: usize,
     skip_built: bool,
     verbose: bool,
+    sandbox: bool,
+    install_build_deps: bool,
+    install: bool,
+    update_index: bool,
     config: &Config,
 ) -> Result<()> {
+    let workers = workers.max(1);
     let start_time = Instant::now();
 
     // Validate spec directory
@@ -41,13 +55,13 @@ pub fn run(
     // CRITICAL: Check for signing key FIRST
     println!("{}", "Checking signing key...".cyan());
 
-    let signing_key = match signing::load_signing_key(config) {
+    let signing_key = match signing::load_signer(config) {
         Ok(key) => {
             println!(
                 "  {} Signing key: {} <{}>",
                 "✓".green(),
-                key.name.cyan(),
-                key.email.dimmed()
+                key.name().cyan(),
+                key.email().dimmed()
             );
             println!(
                 "    Fingerprint: {}",
@@ -55,6 +69,15 @@ pub fn run(
             );
             key
         }
+        Err(e) if config.signing.agent_socket.is_some() => {
+            eprintln!();
+            eprintln!("{}", "FATAL: Signing agent unavailable!".red().bold());
+            eprintln!();
+            eprintln!("Start the agent that holds the signing key, e.g.:");
+            eprintln!("  {} --key <signing-key.secret> --socket <socket>", "rookpkg-agent".cyan());
+            eprintln!();
+            bail!("Signing key required: {}", e);
+        }
         Err(e) => {
             eprintln!();
             eprintln!("{}", "FATAL: No signing key found!".red().bold());
@@ -112,43 +135,74 @@ pub fn run(
         output_dir.display()
     );
 
-    // Show parallel jobs if specified
+    // Show parallelism
+    println!("  {} Concurrent builds: {}", "→".cyan(), workers);
     if let Some(j) = jobs {
-        println!("  {} Parallel builds: {}", "→".cyan(), j);
+        println!("  {} Parallel jobs per build: {}", "→".cyan(), j);
+    }
+
+    // Parse every spec up front: the build order depends on all of them
+    let mut specs: BTreeMap<String, (PathBuf, PackageSpec)> = BTreeMap::new();
+    for spec_path in &spec_files {
+        let spec = PackageSpec::from_file(spec_path)
+            .with_context(|| format!("Failed to parse {}", spec_path.display()))?;
+        if let Some((other, _)) = specs.get(&spec.package.name) {
+            bail!(
+                "{} and {} both define package {}",
+                other.display(),
+                spec_path.display(),
+                spec.package.name
+            );
+        }
+        specs.insert(spec.package.name.clone(), (spec_path.clone(), spec));
     }
 
-    // Filter out already-built packages if --skip-built is set
-    let mut skipped_count = 0;
+    let graph = BuildGraph::new(specs.values().map(|(_, spec)| spec));
+    let cycles = graph.cycles();
+    if !cycles.is_empty() {
+        eprintln!();
+        eprintln!("{}", "Dependency cycles between spec files:".red().bold());
+        for cycle in &cycles {
+            eprintln!("  {} {}", "✗".red(), cycle.join(" → "));
+        }
+        eprintln!();
+        eprintln!("Break each cycle by building one of its packages on its own first.");
+        bail!("Cannot order spec files: {} dependency cycle(s)", cycles.len());
+    }
+
+    // Already-built packages count as done for the packages that depend on them
+    let mut done: HashSet<String> = HashSet::new();
     if skip_built {
-        let original_count = spec_files.len();
-        spec_files.retain(|spec_path| {
-            // Parse spec to get name/version
-            if let Ok(spec) = crate::spec::PackageSpec::from_file(spec_path) {
-                let pkg_filename = format!(
-                    "{}-{}-{}.{}.rookpkg",
-                    spec.package.name,
-                    spec.package.version,
-                    spec.package.release,
-                    std::env::consts::ARCH
-                );
-                let pkg_path = output_dir.join(&pkg_filename);
-                if pkg_path.exists() {
-                    return false; // Skip this one
-                }
+        for (name, (_, spec)) in &specs {
+            let pkg_filename = format!(
+                "{}-{}-{}.{}.rookpkg",
+                spec.package.name,
+                spec.package.version,
+                spec.package.release,
+                std::env::consts::ARCH
+            );
+            if output_dir.join(&pkg_filename).exists() {
+                done.insert(name.clone());
             }
-            true // Keep this one
-        });
-        skipped_count = original_count - spec_files.len();
-        if skipped_count > 0 {
+        }
+        if !done.is_empty() {
             println!(
                 "  {} Skipping {} already-built packages",
                 "→".cyan(),
-                skipped_count
+                done.len()
             );
         }
     }
+    let skipped_count = done.len();
 
-    if spec_files.is_empty() {
+    // Dependencies first; among packages that are ready, this order decides
+    let mut waiting: Vec<String> = graph
+        .order()?
+        .into_iter()
+        .filter(|name| !done.contains(name))
+        .collect();
+
+    if waiting.is_empty() {
         println!();
         println!(
             "{} All packages already built!",
@@ -157,97 +211,194 @@ pub fn run(
         return Ok(());
     }
 
+    // Without installing or indexing, a dependent would be built against
+    // whatever the host has instead of the package built before it
+    if !install && !update_index {
+        let building: HashSet<&str> = waiting.iter().map(String::as_str).collect();
+        let chained: Vec<(&str, &str)> = waiting
+            .iter()
+            .flat_map(|name| {
+                graph
+                    .dependencies(name)
+                    .filter(|dep| building.contains(dep))
+                    .map(move |dep| (name.as_str(), dep))
+            })
+            .collect();
+        if !chained.is_empty() {
+            eprintln!();
+            eprintln!("{}", "Packages depend on others built in this run:".red().bold());
+            for (name, dep) in &chained {
+                eprintln!("  {} {} needs {}", "✗".red(), name, dep);
+            }
+            eprintln!();
+            eprintln!(
+                "Use {} or {} so each package is available to its dependents,",
+                "--install".bold(),
+                "--index".bold()
+            );
+            eprintln!("or build the packages they need first.");
+            bail!(
+                "{} package(s) would build against the host instead of packages from this run",
+                chained.iter().map(|(name, _)| *name).collect::<HashSet<_>>().len()
+            );
+        }
+    }
+
+    let total = waiting.len();
     println!();
     println!(
         "{}",
-        format!("Building {} packages...", spec_files.len()).cyan().bold()
+        format!("Building {} packages...", total).cyan().bold()
     );
     println!();
 
     // Track results
     let mut results: Vec<BuildResult> = Vec::new();
-    let mut success_count = 0;
-    let mut fail_count = 0;
-
-    // Build each package
-    for (index, spec_path) in spec_files.iter().enumerate() {
-        let pkg_start = Instant::now();
-
-        // Parse spec to get name/version for display
-        let spec_name = spec_path
-            .file_stem()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_else(|| "unknown".to_string());
-
-        println!(
-            "[{}/{}] {} {}",
-            index + 1,
-            spec_files.len(),
-            "Building".cyan(),
-            spec_name.bold()
-        );
-
-        // Build the package
-        let result = build_single_package(
-            spec_path,
-            output_dir,
-            &signing_key,
-            verbose,
-            jobs,
-            config,
-        );
-
-        let duration = pkg_start.elapsed().as_secs_f64();
-
-        match result {
-            Ok((name, version, package_path)) => {
-                success_count += 1;
+    let mut failed: HashSet<String> = HashSet::new();
+    let mut first_failure: Option<String> = None;
+    let mut started = 0;
+
+    // Workers only run the build phases. Packaging, signing, installing and
+    // indexing happen here, one package at a time, and a package's
+    // dependents are only started once that is done.
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel::<(String, Instant, Result<BuildEnvironment>)>();
+        let mut running = 0;
+
+        loop {
+            // Packages whose dependencies failed cannot be built
+            while let Some(pos) = waiting
+                .iter()
+                .position(|name| graph.dependencies(name).any(|dep| failed.contains(dep)))
+            {
+                let name = waiting.remove(pos);
+                let dep = graph
+                    .dependencies(&name)
+                    .find(|dep| failed.contains(*dep))
+                    .unwrap_or_default()
+                    .to_string();
                 println!(
-                    "  {} {}-{} built in {:.1}s",
-                    "✓".green(),
+                    "  {} {} skipped: {} failed to build",
+                    "✗".red(),
                     name,
-                    version,
-                    duration
+                    dep
                 );
                 results.push(BuildResult {
-                    name,
-                    version,
-                    success: true,
-                    duration_secs: duration,
-                    error: None,
-                    package_path: Some(package_path),
+                    name: name.clone(),
+                    version: specs[&name].1.full_version(),
+                    success: false,
+                    duration_secs: 0.0,
+                    error: Some(format!("dependency {} failed to build", dep)),
+                    package_path: None,
                 });
+                failed.insert(name);
             }
-            Err(e) => {
-                fail_count += 1;
+
+            // Start every package whose dependencies are all done
+            while first_failure.is_none() && running < workers {
+                let Some(pos) = waiting
+                    .iter()
+                    .position(|name| graph.dependencies(name).all(|dep| done.contains(dep)))
+                else {
+                    break;
+                };
+                let name = waiting.remove(pos);
+                let (spec_path, spec) = specs[&name].clone();
+                started += 1;
                 println!(
-                    "  {} {} failed: {}",
-                    "✗".red(),
-                    spec_name,
-                    e
+                    "[{}/{}] {} {}",
+                    started,
+                    total,
+                    "Building".cyan(),
+                    name.bold()
                 );
-                results.push(BuildResult {
-                    name: spec_name.clone(),
-                    version: "?".to_string(),
-                    success: false,
-                    duration_secs: duration,
-                    error: Some(e.to_string()),
-                    package_path: None,
+
+                running += 1;
+                let sender = sender.clone();
+                let pkg_start = Instant::now();
+
+                // Build dependencies may need a transaction, so they are
+                // checked here rather than in the worker
+                if let Err(e) = super::build::ensure_build_deps(&spec, install_build_deps, config) {
+                    let _ = sender.send((name, pkg_start, Err(e)));
+                    continue;
+                }
+                scope.spawn(move || {
+                    let result = run_build_phases(spec_path.parent(), spec, verbose, sandbox, jobs, config);
+                    let _ = sender.send((name, pkg_start, result));
                 });
+            }
 
-                if !continue_on_error {
-                    eprintln!();
-                    eprintln!(
-                        "{} Use {} to continue building remaining packages on failure.",
-                        "Tip:".yellow().bold(),
-                        "--continue".cyan()
+            if running == 0 {
+                break;
+            }
+            let Ok((name, pkg_start, result)) = receiver.recv() else {
+                break;
+            };
+            running -= 1;
+
+            let spec = &specs[&name].1;
+            let result = result.and_then(|build_env| {
+                finish_package(spec, &build_env, output_dir, signing_key.as_ref(), install, update_index, config)
+            });
+            let duration = pkg_start.elapsed().as_secs_f64();
+
+            match result {
+                Ok(package_path) => {
+                    println!(
+                        "  {} {}-{} built in {:.1}s",
+                        "✓".green(),
+                        name,
+                        spec.full_version(),
+                        duration
                     );
-                    bail!("Build failed for {}", spec_name);
+                    results.push(BuildResult {
+                        name: name.clone(),
+                        version: spec.full_version(),
+                        success: true,
+                        duration_secs: duration,
+                        error: None,
+                        package_path: Some(package_path),
+                    });
+                    done.insert(name);
+                }
+                Err(e) => {
+                    println!(
+                        "  {} {} failed: {}",
+                        "✗".red(),
+                        name,
+                        e
+                    );
+                    results.push(BuildResult {
+                        name: name.clone(),
+                        version: spec.full_version(),
+                        success: false,
+                        duration_secs: duration,
+                        error: Some(e.to_string()),
+                        package_path: None,
+                    });
+                    if !continue_on_error && first_failure.is_none() {
+                        first_failure = Some(name.clone());
+                    }
+                    failed.insert(name);
                 }
             }
         }
+    });
+
+    if let Some(name) = first_failure {
+        eprintln!();
+        eprintln!(
+            "{} Use {} to continue building remaining packages on failure.",
+            "Tip:".yellow().bold(),
+            "--continue".cyan()
+        );
+        bail!("Build failed for {}", name);
     }
 
+    let success_count = results.iter().filter(|r| r.success).count();
+    let fail_count = results.len() - success_count;
+
     // Print summary
     let total_duration = start_time.elapsed().as_secs_f64();
 
@@ -343,31 +494,25 @@ pub fn run(
     Ok(())
 }
 
-/// Build a single package from a spec file
-fn build_single_package(
-    spec_path: &Path,
-    output_dir: &Path,
-    signing_key: &signing::LoadedSigningKey,
+/// Run the build phases for one package, leaving the result in its dest directory
+pub(crate) fn run_build_phases(
+    spec_dir: Option<&Path>,
+    spec: PackageSpec,
     verbose: bool,
+    sandbox: bool,
     jobs: Option<usize>,
     config: &Config,
-) -> Result<(String, String, std::path::PathBuf)> {
-    use crate::archive::PackageArchiveBuilder;
-    use crate::build::PackageBuilder;
-    use crate::signing::sign_file;
-    use crate::spec::PackageSpec;
-
-    // Parse spec file
-    let spec = PackageSpec::from_file(spec_path)?;
-    let name = spec.package.name.clone();
-    let version = format!("{}-{}", spec.package.version, spec.package.release);
-
+) -> Result<BuildEnvironment> {
     // Create build environment
     let builder = PackageBuilder::new(config.clone());
-    let mut build_env = builder.build(spec.clone())?;
+    let mut build_env = builder.build(spec)?;
 
     // Enable verbose mode if requested
     build_env.set_verbose(verbose);
+    build_env.set_sandbox(sandbox);
+    if let Some(dir) = spec_dir {
+        build_env.set_spec_dir(dir);
+    }
 
     // Override jobs if specified
     if let Some(j) = jobs {
@@ -391,23 +536,39 @@ fn build_single_package(
         }
     }
 
-    // Collect installed files
-    let _installed_files = build_env.collect_installed_files()?;
+    Ok(build_env)
+}
 
+/// Package, sign and clean up a built package, then install or index it so
+/// the packages that depend on it can use it
+fn finish_package(
+    spec: &PackageSpec,
+    build_env: &BuildEnvironment,
+    output_dir: &Path,
+    signing_key: &dyn signing::Signer,
+    install: bool,
+    update_index: bool,
+    config: &Config,
+) -> Result<PathBuf> {
     // Create package archive
-    let mut archive_builder = PackageArchiveBuilder::new(&spec, build_env.dest_dir());
+    let mut archive_builder = PackageArchiveBuilder::new(spec, build_env.dest_dir());
     archive_builder.scan_files()?;
 
-    let package_path = archive_builder.build(output_dir)?;
-
-    // Sign package
-    let signature = sign_file(signing_key, &package_path)?;
-    let sig_path = package_path.with_extension("rookpkg.sig");
-    let sig_json = serde_json::to_string_pretty(&signature)?;
-    std::fs::write(&sig_path, &sig_json)?;
+    let package_path = archive_builder.build_signed(output_dir, signing_key)?;
 
     // Clean up build directory
     build_env.clean()?;
 
-    Ok((name, version, package_path))
+    if install && !super::build::install_built_package(spec, &package_path, config)? {
+        println!(
+            "  {} Packages depending on {} will build against the installed version",
+            "!".yellow(),
+            spec.package.name
+        );
+    }
+    if update_index {
+        super::build::update_local_index(&package_path, output_dir, signing_key)?;
+    }
+
+    Ok(package_path)
 }
@@ -4,27 +4,152 @@ use std::path::Path;
 
 use anyhow::Result;
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::download::compute_sha256;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::download::compute_sha256;
+use rookpkg::fileattrs::{self, IdMap};
+use rookpkg::package::PackageFile;
+use rookpkg::RookpkgError;
 
-pub fn run(package: Option<&str>, config: &Config) -> Result<()> {
+use super::{print_json, OutputFormat};
+
+/// `check --output json`
+#[derive(Serialize)]
+struct CheckJson {
+    packages: Vec<PackageCheckJson>,
+}
+
+#[derive(Serialize)]
+struct PackageCheckJson {
+    name: String,
+    version: String,
+    /// Number of files recorded for the package
+    files: usize,
+    /// Whether no file is missing, modified or has wrong attributes
+    intact: bool,
+    issues: Vec<FileIssueJson>,
+}
+
+#[derive(Serialize)]
+struct FileIssueJson {
+    path: String,
+    /// "missing", "modified", "attributes" or "error"
+    problem: &'static str,
+    /// The differing attributes, or the error message
+    details: Vec<String>,
+}
+
+/// Result of checking one installed file
+enum FileStatus {
+    Ok,
+    Missing,
+    Modified,
+    /// Contents intact, but ownership, mode or xattrs differ
+    Attributes(Vec<String>),
+    Error(String),
+}
+
+/// Compare an installed file with what the database recorded for it
+fn verify_file(root: &Path, file: &PackageFile, ids: &IdMap) -> FileStatus {
+    let path = root.join(file.path.trim_start_matches('/'));
+
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return FileStatus::Missing,
+    };
+
+    // Directories are shared and not verified
+    if metadata.is_dir() {
+        return FileStatus::Ok;
+    }
+
+    if !file.checksum.is_empty() && metadata.is_file() {
+        match compute_sha256(&path) {
+            Ok(actual) if actual != file.checksum => return FileStatus::Modified,
+            Ok(_) => {}
+            Err(e) => return FileStatus::Error(e.to_string()),
+        }
+    }
+
+    match fileattrs::differences(&path, file.mode, &file.owner, &file.group, &file.xattrs, ids) {
+        Ok(diffs) if diffs.is_empty() => FileStatus::Ok,
+        Ok(diffs) => FileStatus::Attributes(diffs),
+        Err(e) => FileStatus::Error(e.to_string()),
+    }
+}
+
+pub fn run(package: Option<&str>, output: OutputFormat, config: &Config) -> Result<()> {
     let db_path = config.database_path();
+    if output == OutputFormat::Json {
+        return check_json(package, config);
+    }
     if !db_path.exists() {
         println!("{}", "No packages installed.".yellow());
         return Ok(());
     }
 
     let db = Database::open(&db_path)?;
+    let root = config.sysroot();
+    let ids = IdMap::load(root)?;
 
     match package {
-        Some(name) => check_package(&db, name),
-        None => check_all_packages(&db),
+        Some(name) => check_package(&db, root, &ids, name),
+        None => check_all_packages(&db, root, &ids),
+    }
+}
+
+fn check_json(package: Option<&str>, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    if !db_path.exists() {
+        if let Some(name) = package {
+            return Err(RookpkgError::NotInstalled(name.to_string()).into());
+        }
+        return print_json(&CheckJson { packages: Vec::new() });
     }
+
+    let db = Database::open(&db_path)?;
+    let root = config.sysroot();
+    let ids = IdMap::load(root)?;
+
+    let installed = match package {
+        Some(name) => vec![db
+            .get_package(name)?
+            .ok_or_else(|| RookpkgError::NotInstalled(name.to_string()))?],
+        None => db.list_packages()?,
+    };
+
+    let mut packages = Vec::new();
+    for pkg in installed {
+        let files = db.get_files(&pkg.name)?;
+        let issues: Vec<_> = files
+            .iter()
+            .filter_map(|file| {
+                let (problem, details) = match verify_file(root, file, &ids) {
+                    FileStatus::Ok => return None,
+                    FileStatus::Missing => ("missing", Vec::new()),
+                    FileStatus::Modified => ("modified", Vec::new()),
+                    FileStatus::Attributes(diffs) => ("attributes", diffs),
+                    FileStatus::Error(e) => ("error", vec![e]),
+                };
+                Some(FileIssueJson { path: file.path.clone(), problem, details })
+            })
+            .collect();
+
+        packages.push(PackageCheckJson {
+            version: pkg.full_version(),
+            name: pkg.name,
+            files: files.len(),
+            intact: issues.iter().all(|i| i.problem == "error"),
+            issues,
+        });
+    }
+
+    print_json(&CheckJson { packages })
 }
 
-fn check_package(db: &Database, name: &str) -> Result<()> {
+fn check_package(db: &Database, root: &Path, ids: &IdMap, name: &str) -> Result<()> {
     println!("{} {}...", "Checking package".cyan(), name.bold());
     println!();
 
@@ -46,49 +171,26 @@ fn check_package(db: &Database, name: &str) -> Result<()> {
     let mut ok_count = 0;
     let mut missing_count = 0;
     let mut modified_count = 0;
+    let mut attribute_count = 0;
 
     for file in &files {
-        let path = Path::new(&file.path);
-
-        if !path.exists() {
-            println!("  {} {} (missing)", "✗".red(), file.path);
-            missing_count += 1;
-            continue;
-        }
-
-        // Skip directories
-        if path.is_dir() {
-            ok_count += 1;
-            continue;
-        }
-
-        // Verify checksum if available
-        if !file.checksum.is_empty() {
-            match compute_sha256(path) {
-                Ok(actual) => {
-                    if actual != file.checksum {
-                        println!(
-                            "  {} {} (modified)",
-                            "!".yellow(),
-                            file.path
-                        );
-                        modified_count += 1;
-                    } else {
-                        ok_count += 1;
-                    }
-                }
-                Err(e) => {
-                    println!(
-                        "  {} {} (error: {})",
-                        "?".yellow(),
-                        file.path,
-                        e
-                    );
-                }
+        match verify_file(root, file, ids) {
+            FileStatus::Ok => ok_count += 1,
+            FileStatus::Missing => {
+                println!("  {} {} (missing)", "✗".red(), file.path);
+                missing_count += 1;
+            }
+            FileStatus::Modified => {
+                println!("  {} {} (modified)", "!".yellow(), file.path);
+                modified_count += 1;
+            }
+            FileStatus::Attributes(diffs) => {
+                println!("  {} {} ({})", "!".yellow(), file.path, diffs.join(", "));
+                attribute_count += 1;
+            }
+            FileStatus::Error(e) => {
+                println!("  {} {} (error: {})", "?".yellow(), file.path, e);
             }
-        } else {
-            // No checksum, just verify existence
-            ok_count += 1;
         }
     }
 
@@ -110,7 +212,11 @@ fn check_package(db: &Database, name: &str) -> Result<()> {
         println!("  {} {} files modified", "!".yellow(), modified_count);
     }
 
-    if missing_count == 0 && modified_count == 0 {
+    if attribute_count > 0 {
+        println!("  {} {} files with wrong ownership, mode or xattrs", "!".yellow(), attribute_count);
+    }
+
+    if missing_count == 0 && modified_count == 0 && attribute_count == 0 {
         println!();
         println!(
             "  {} Package {} is intact",
@@ -134,7 +240,7 @@ fn check_package(db: &Database, name: &str) -> Result<()> {
     Ok(())
 }
 
-fn check_all_packages(db: &Database) -> Result<()> {
+fn check_all_packages(db: &Database, root: &Path, ids: &IdMap) -> Result<()> {
     println!("{}", "Checking all installed packages...".cyan());
     println!();
 
@@ -154,28 +260,16 @@ fn check_all_packages(db: &Database) -> Result<()> {
         let mut pkg_ok = true;
         let mut missing = 0;
         let mut modified = 0;
+        let mut attributes = 0;
 
         for file in &files {
-            let path = Path::new(&file.path);
-
-            if !path.exists() {
-                missing += 1;
-                pkg_ok = false;
-                continue;
-            }
-
-            if path.is_dir() {
-                continue;
-            }
-
-            if !file.checksum.is_empty() {
-                if let Ok(actual) = compute_sha256(path) {
-                    if actual != file.checksum {
-                        modified += 1;
-                        pkg_ok = false;
-                    }
-                }
+            match verify_file(root, file, ids) {
+                FileStatus::Ok | FileStatus::Error(_) => continue,
+                FileStatus::Missing => missing += 1,
+                FileStatus::Modified => modified += 1,
+                FileStatus::Attributes(_) => attributes += 1,
             }
+            pkg_ok = false;
         }
 
         let status = if pkg_ok {
@@ -186,10 +280,10 @@ fn check_all_packages(db: &Database) -> Result<()> {
             "!".yellow()
         };
 
-        let issues = if missing > 0 || modified > 0 {
+        let issues = if missing > 0 || modified > 0 || attributes > 0 {
             format!(
-                " ({} missing, {} modified)",
-                missing, modified
+                " ({} missing, {} modified, {} wrong attributes)",
+                missing, modified, attributes
             )
             .dimmed()
             .to_string()
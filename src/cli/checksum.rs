@@ -6,9 +6,9 @@ use std::path::Path;
 use anyhow::{bail, Context, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::download::{compute_sha256, Downloader, SourceFile};
-use crate::spec::PackageSpec;
+use rookpkg::config::Config;
+use rookpkg::download::{compute_sha256, Downloader, SourceFile};
+use rookpkg::spec::PackageSpec;
 
 /// Run the checksum command
 pub fn run(spec_path: &Path, update: bool, config: &Config) -> Result<()> {
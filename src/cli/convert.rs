@@ -5,8 +5,8 @@ use std::path::Path;
 use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::convert::ArchConverter;
+use rookpkg::config::Config;
+use rookpkg::convert::ArchConverter;
 
 /// Run Arch Linux conversion for a single package
 pub fn run_arch_single(pkg_name: &str, output: Option<&Path>, _config: &Config) -> Result<()> {
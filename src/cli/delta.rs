@@ -10,10 +10,25 @@ use std::path::Path;
 
 use anyhow::{bail, Context, Result};
 use colored::Colorize;
-
-use crate::config::Config;
-use crate::delta::{DeltaApplier, DeltaBuilder, DeltaInfo, RepoDeltaIndex};
-use crate::signing::{self, HybridSignature, LoadedPublicKey};
+use serde::Serialize;
+
+use rookpkg::config::Config;
+use rookpkg::delta::{DeltaApplier, DeltaBuilder, DeltaInfo, RepoDeltaIndex};
+use rookpkg::signing::{self, HybridSignature, LoadedPublicKey};
+
+use super::{print_json, OutputFormat};
+
+/// `delta info --output json`: the delta's metadata plus what it saves
+#[derive(Serialize)]
+struct DeltaInfoJson {
+    #[serde(flatten)]
+    info: DeltaInfo,
+    /// Size of the delta file on disk
+    file_size: u64,
+    savings_percent: f64,
+    /// Whether the delta is worth using over the full package
+    worthwhile: bool,
+}
 
 /// Build a delta package between two versions of a package
 pub fn build(old_package: &Path, new_package: &Path, output_dir: &Path, _config: &Config) -> Result<()> {
@@ -128,12 +143,8 @@ pub fn apply(
     // Find the signing key
     let public_key = find_signing_key(&signature.fingerprint, config)?;
 
-    // Read delta content for verification
-    let delta_content = std::fs::read(delta_file)
-        .with_context(|| "Failed to read delta file for verification")?;
-
     // Verify the signature
-    signing::verify_signature(&public_key, &delta_content, &signature)
+    signing::verify_file(&public_key, delta_file, &signature)
         .with_context(|| "Delta signature verification failed - file may be tampered!")?;
 
     println!(
@@ -184,7 +195,7 @@ pub fn apply(
 }
 
 /// Show information about a delta package
-pub fn info(delta_file: &Path, _config: &Config) -> Result<()> {
+pub fn info(delta_file: &Path, output: OutputFormat, _config: &Config) -> Result<()> {
     if !delta_file.exists() {
         bail!("Delta file not found: {}", delta_file.display());
     }
@@ -196,6 +207,15 @@ pub fn info(delta_file: &Path, _config: &Config) -> Result<()> {
         .map(|m| m.len())
         .unwrap_or(0);
 
+    if output == OutputFormat::Json {
+        return print_json(&DeltaInfoJson {
+            file_size: delta_size,
+            savings_percent: info.savings_percent(),
+            worthwhile: info.is_worthwhile(),
+            info,
+        });
+    }
+
     println!("{}", "Delta Package Information".cyan().bold());
     println!();
     println!("  {}: {}", "Package".white().bold(), info.name);
@@ -284,7 +304,7 @@ pub fn index(repo_dir: &Path, _config: &Config) -> Result<()> {
         if path.extension().and_then(|s| s.to_str()) == Some("rookdelta") {
             match read_delta_info(&path) {
                 Ok(info) => {
-                    let delta_entry = crate::delta::DeltaEntry {
+                    let delta_entry = rookpkg::delta::DeltaEntry {
                         from_version: info.old_version.clone(),
                         from_release: info.old_release,
                         to_version: info.new_version.clone(),
@@ -295,7 +315,7 @@ pub fn index(repo_dir: &Path, _config: &Config) -> Result<()> {
                             .to_string_lossy()
                             .to_string(),
                         size: std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
-                        sha256: crate::download::compute_sha256(&path).unwrap_or_default(),
+                        sha256: rookpkg::download::compute_sha256(&path).unwrap_or_default(),
                     };
                     index.add_delta(&info.name, delta_entry);
                     count += 1;
@@ -2,20 +2,137 @@
 
 use anyhow::Result;
 use colored::Colorize;
+use serde::Serialize;
 
 use std::collections::HashMap;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::repository::RepoManager;
-use crate::resolver::RookeryDependencyProvider;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::repository::RepoManager;
+use rookpkg::resolver::{parse_version, RookeryDependencyProvider};
+use rookpkg::RookpkgError;
 
-pub fn run(package: &str, reverse: bool, config: &Config) -> Result<()> {
-    if reverse {
-        show_reverse_dependencies(package, config)
-    } else {
-        show_dependencies(package, config)
+use super::{print_json, OutputFormat};
+
+/// `depends --output json`
+#[derive(Serialize)]
+struct DependsJson {
+    package: String,
+    /// "installed" or "available"
+    source: &'static str,
+    /// Repository the package was found in, if it is not installed
+    repository: Option<String>,
+    dependencies: Vec<DependencyJson>,
+}
+
+#[derive(Serialize)]
+struct DependencyJson {
+    name: String,
+    /// Empty if any version will do
+    constraint: String,
+    /// "runtime", "build" or "optional"; None for repository packages
+    #[serde(rename = "type")]
+    dep_type: Option<String>,
+    /// Installed (for installed packages) or available in a repository
+    satisfied: bool,
+}
+
+/// `depends --reverse --output json`
+#[derive(Serialize)]
+struct ReverseJson {
+    package: String,
+    required_by: Vec<RequiredByJson>,
+}
+
+#[derive(Serialize)]
+struct RequiredByJson {
+    name: String,
+    /// Full version, if the dependent is still installed
+    version: Option<String>,
+}
+
+pub fn run(package: &str, reverse: bool, output: OutputFormat, config: &Config) -> Result<()> {
+    match (reverse, output) {
+        (false, OutputFormat::Json) => dependencies_json(package, config),
+        (false, OutputFormat::Text) => show_dependencies(package, config),
+        (true, OutputFormat::Json) => reverse_dependencies_json(package, config),
+        (true, OutputFormat::Text) => show_reverse_dependencies(package, config),
+    }
+}
+
+fn dependencies_json(package: &str, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    if db_path.exists() {
+        let db = Database::open(&db_path)?;
+        if db.get_package(package)?.is_some() {
+            let mut dependencies = Vec::new();
+            for dep in db.get_dependencies(package)? {
+                dependencies.push(DependencyJson {
+                    satisfied: db.get_package(&dep.depends_on)?.is_some()
+                        || !db.get_providers(&dep.depends_on)?.is_empty(),
+                    dep_type: Some(dep.dep_type.to_string()),
+                    name: dep.depends_on,
+                    constraint: dep.constraint,
+                });
+            }
+            return print_json(&DependsJson {
+                package: package.to_string(),
+                source: "installed",
+                repository: None,
+                dependencies,
+            });
+        }
     }
+
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    let result = manager
+        .find_package(package)
+        .ok_or_else(|| RookpkgError::PackageNotFound(package.to_string()))?;
+    let dependencies = result
+        .package
+        .depends
+        .iter()
+        .map(|dep| {
+            let (name, constraint) = match dep.find(['>', '<', '=']) {
+                Some(pos) => (dep[..pos].trim(), dep[pos..].trim()),
+                None => (dep.trim(), ""),
+            };
+            DependencyJson {
+                name: name.to_string(),
+                constraint: constraint.to_string(),
+                dep_type: None,
+                satisfied: manager.find_package(name).is_some(),
+            }
+        })
+        .collect();
+
+    print_json(&DependsJson {
+        package: package.to_string(),
+        source: "available",
+        repository: Some(result.repository),
+        dependencies,
+    })
+}
+
+fn reverse_dependencies_json(package: &str, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    if !db_path.exists() {
+        return Err(RookpkgError::NotInstalled(package.to_string()).into());
+    }
+    let db = Database::open(&db_path)?;
+    if db.get_package(package)?.is_none() {
+        return Err(RookpkgError::NotInstalled(package.to_string()).into());
+    }
+
+    let mut required_by = Vec::new();
+    for name in db.get_reverse_dependencies(package)? {
+        let version = db.get_package(&name)?.map(|p| p.full_version());
+        required_by.push(RequiredByJson { name, version });
+    }
+
+    print_json(&ReverseJson { package: package.to_string(), required_by })
 }
 
 fn show_dependencies(package: &str, config: &Config) -> Result<()> {
@@ -34,7 +151,9 @@ fn show_dependencies(package: &str, config: &Config) -> Result<()> {
             } else {
                 println!("  {}", "[installed]".green());
                 for dep in &deps {
-                    let installed = db.get_package(&dep.depends_on)?.is_some();
+                    // Virtual names (such as sonames) are met by their providers
+                    let installed = db.get_package(&dep.depends_on)?.is_some()
+                        || !db.get_providers(&dep.depends_on)?.is_empty();
                     let status = if installed {
                         "✓".green()
                     } else {
@@ -70,14 +189,7 @@ fn show_dependencies(package: &str, config: &Config) -> Result<()> {
     for repo in manager.enabled_repos() {
         if let Some(ref index) = repo.index {
             for pkg in &index.packages {
-                // Parse version for semver
-                let parts: Vec<u32> = pkg.version.split('.').filter_map(|s| s.parse().ok()).collect();
-                let version = pubgrub::version::SemanticVersion::new(
-                    parts.first().copied().unwrap_or(0),
-                    parts.get(1).copied().unwrap_or(0),
-                    parts.get(2).copied().unwrap_or(0),
-                );
-                provider.add_package(&pkg.name, version, HashMap::new());
+                provider.add_package(&pkg.name, parse_version(&pkg.version), HashMap::new());
             }
         }
     }
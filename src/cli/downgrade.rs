@@ -0,0 +1,379 @@
+//! Downgrade command implementation
+//!
+//! Moves installed packages back to an older build, taken from the
+//! repositories or from archives left in the package cache. The rest of the
+//! system stays pinned, so the resolver refuses a downgrade that would break
+//! a package depending on the downgraded one.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use colored::Colorize;
+use pubgrub::range::Range;
+
+use crate::cli::install::print_resolution_failure;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::hooks::HookResult;
+use rookpkg::package::{InstallReason, InstalledPackage};
+use rookpkg::repository::{find_candidate, BuildSource, PackageEntry, RepoManager, VerifiedPackage};
+use rookpkg::resolver::{
+    needs_upgrade, parse_version, PackageRequest, ResolutionContext, RookeryDependencyProvider,
+};
+use rookpkg::transaction::TransactionBuilder;
+use rookpkg::version::Version;
+
+/// A package being moved to an older build
+struct Downgrade<'a> {
+    installed: &'a InstalledPackage,
+    package: PackageEntry,
+    source: BuildSource,
+}
+
+impl Downgrade<'_> {
+    fn target_full(&self) -> String {
+        format!("{}-{}", self.package.version, self.package.release)
+    }
+}
+
+pub fn run(packages: &[String], dry_run: bool, json: bool, config: &Config) -> Result<()> {
+    if dry_run {
+        status!("{}", "Dry run mode - no changes will be made".yellow());
+        status!();
+    }
+
+    let db_path = config.database_path();
+    if !db_path.exists() {
+        bail!("No packages installed yet.");
+    }
+    let db = Database::open(&db_path)?;
+    let installed = db.list_packages()?;
+
+    let mut requests: Vec<(PackageRequest, &InstalledPackage)> = Vec::new();
+    for arg in packages {
+        let request = PackageRequest::parse(arg).map_err(|e| anyhow!(e))?;
+        let Some(pkg) = installed.iter().find(|p| p.name == request.name) else {
+            bail!("Package '{}' is not installed", request.name);
+        };
+        if db.is_package_held(&pkg.name)? {
+            bail!(
+                "Package '{}' is held. Use {} first.",
+                pkg.name,
+                format!("rookpkg unhold {}", pkg.name).bold()
+            );
+        }
+        requests.push((request, pkg));
+    }
+
+    status!("{}", "Loading repository data...".cyan());
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    // Repository entries for everything, plus cached archives of the
+    // packages being downgraded
+    let candidates = manager.package_candidates();
+    let mut provider = RookeryDependencyProvider::new();
+    for results in candidates.values() {
+        for result in results {
+            provider.add_entry(&result.package);
+        }
+    }
+
+    let mut builds: HashMap<String, Vec<(PackageEntry, BuildSource)>> = HashMap::new();
+    for (request, _) in &requests {
+        let list = builds.entry(request.name.clone()).or_default();
+        for result in candidates.get(&request.name).into_iter().flatten() {
+            list.push((result.package.clone(), BuildSource::Repository(result.repository.clone())));
+        }
+        for cached in manager.cached_versions(&request.name) {
+            let known = list.iter().any(|(p, _)| {
+                p.version == cached.version && p.release == cached.release
+            });
+            if !known {
+                provider.add_entry(&cached);
+                list.push((cached, BuildSource::Cache));
+            }
+        }
+    }
+
+    // Everything else stays exactly where it is, so packages depending on
+    // a downgraded one must accept its older version
+    status!("{}", "Resolving dependencies...".cyan());
+    status!();
+
+    let mut context = ResolutionContext::default();
+    let mut root_deps: HashMap<String, Range<Version>> = HashMap::new();
+    for pkg in &installed {
+        provider.add_installed(pkg, &db.get_dependencies(&pkg.name)?, &db.get_provides(&pkg.name)?);
+        provider.prefer(&pkg.name);
+        context.installed.insert(pkg.name.clone(), pkg.version.clone());
+        root_deps.insert(pkg.name.clone(), Range::exact(parse_version(&pkg.version)));
+    }
+    for hold in db.list_held_packages()? {
+        context.held.insert(hold.name);
+    }
+
+    for (request, pkg) in &requests {
+        let installed_version = parse_version(&pkg.version);
+        // Only an explicit release may stay on the installed version
+        let range = if request.release.is_some() && request.range.contains(&installed_version) {
+            request.range.clone()
+        } else {
+            request
+                .range
+                .intersection(&Range::strictly_lower_than(installed_version))
+        };
+        if range == Range::none() {
+            bail!(
+                "{} {} is installed; {} does not name an older version",
+                pkg.name,
+                pkg.full_version(),
+                request
+            );
+        }
+        context.requested.insert(pkg.name.clone());
+        root_deps.insert(pkg.name.clone(), range);
+    }
+
+    let root_version = provider.set_root(root_deps);
+    let solution = match provider.solve(root_version, &context) {
+        Ok(solution) => solution,
+        Err(report) => {
+            print_resolution_failure(&report, json)?;
+            bail!("Could not resolve dependencies for the downgrade");
+        }
+    };
+
+    // Pick the build for each downgraded package: the requested release, or
+    // the newest one that is still older than what is installed
+    let mut downgrades: Vec<Downgrade> = Vec::new();
+    for (request, pkg) in &requests {
+        let version = &solution[&pkg.name];
+        let build = builds[&pkg.name]
+            .iter()
+            .filter(|(p, _)| &parse_version(&p.version) == version)
+            .filter(|(p, _)| request.release.is_none_or(|r| p.release == r))
+            .filter(|(p, _)| needs_upgrade(&p.version, p.release, &pkg.version, pkg.release))
+            .max_by_key(|(p, _)| p.release);
+        let Some((package, source)) = build else {
+            bail!(
+                "No older build of {} matching {} is available in the repositories or the package cache",
+                pkg.name,
+                request
+            );
+        };
+        downgrades.push(Downgrade {
+            installed: pkg,
+            package: package.clone(),
+            source: source.clone(),
+        });
+    }
+
+    // Older builds may need packages that are not installed yet
+    let mut new_packages: Vec<(PackageEntry, String)> = Vec::new();
+    let mut names: Vec<&String> = solution.keys().collect();
+    names.sort();
+    for pkg in names {
+        if context.installed.contains_key(pkg) {
+            continue;
+        }
+        let Some(result) = candidates
+            .get(pkg)
+            .and_then(|c| find_candidate(c, &solution[pkg], None))
+        else {
+            bail!("{} {} was selected but is not in any repository", pkg, solution[pkg]);
+        };
+        new_packages.push((result.package.clone(), result.repository.clone()));
+    }
+
+    status!(
+        "{} {} package(s) will be downgraded:",
+        "→".cyan(),
+        downgrades.len()
+    );
+    status!();
+    for downgrade in &downgrades {
+        status!(
+            "  {} {} {} → {} (from {})",
+            "↓".yellow(),
+            downgrade.installed.name.bold(),
+            downgrade.installed.full_version().dimmed(),
+            downgrade.target_full().yellow(),
+            downgrade.source.to_string().cyan()
+        );
+    }
+    status!();
+
+    if !new_packages.is_empty() {
+        status!(
+            "{} {} new package(s) will be installed:",
+            "→".cyan(),
+            new_packages.len()
+        );
+        status!();
+        for (package, repo) in &new_packages {
+            status!(
+                "  {} {}-{} {} {} {}",
+                "+".green(),
+                package.name.bold(),
+                package.version,
+                "from".dimmed(),
+                repo.cyan(),
+                "(dependency)".dimmed()
+            );
+        }
+        status!();
+    }
+
+    if dry_run {
+        status!("{}", "Dry run complete - no packages downloaded.".yellow());
+        return Ok(());
+    }
+
+    // Fetch and verify every archive before touching the system
+    status!("{}", "Downloading and verifying packages...".cyan());
+    status!();
+
+    let mut verified_downgrades: Vec<(&Downgrade, VerifiedPackage)> = Vec::new();
+    for downgrade in &downgrades {
+        status_part!(
+            "  {} {}-{}... ",
+            "↓".cyan(),
+            downgrade.package.name,
+            downgrade.target_full()
+        );
+        match manager.fetch_build(&downgrade.package, &downgrade.source, config) {
+            Ok(verified) => {
+                status!("{} [{}]", "✓".green(), verified.signature_status.description());
+                verified_downgrades.push((downgrade, verified));
+            }
+            Err(e) => {
+                status!("{}", "✗".red());
+                bail!("Failed to download/verify {}: {}", downgrade.package.name, e);
+            }
+        }
+    }
+
+    let mut verified_new: Vec<VerifiedPackage> = Vec::new();
+    for (package, repo) in &new_packages {
+        status_part!("  {} {}-{}... ", "↓".cyan(), package.name, package.version);
+        match manager.download_and_verify_package(package, repo, config) {
+            Ok(verified) => {
+                status!("{} [{}]", "✓".green(), verified.signature_status.description());
+                verified_new.push(verified);
+            }
+            Err(e) => {
+                status!("{}", "✗".red());
+                bail!("Failed to download/verify {}: {}", package.name, e);
+            }
+        }
+    }
+
+    status!();
+    status!("{}", "Installing older builds...".cyan());
+    status!();
+
+    // A downgrade is an upgrade operation whose target is older
+    let mut builder = TransactionBuilder::new(config.sysroot());
+    for (downgrade, verified) in &verified_downgrades {
+        builder = builder.upgrade(
+            &downgrade.installed.name,
+            &downgrade.installed.full_version(),
+            &downgrade.target_full(),
+            &verified.path,
+        );
+    }
+    for verified in &verified_new {
+        let version = format!("{}-{}", verified.package.version, verified.package.release);
+        builder = builder.install(&verified.package.name, &version, &verified.path, InstallReason::Dependency);
+    }
+
+    // Re-open database for transaction execution
+    let db = Database::open(&db_path)?;
+
+    match builder.execute_with_hooks(db, &config.hooks, &config.snapshot) {
+        Ok((pre_results, post_results)) => {
+            print_hook_results("pre-transaction", &pre_results);
+
+            status!(
+                "{} {} package(s) downgraded successfully",
+                "✓".green().bold(),
+                verified_downgrades.len()
+            );
+            if !verified_new.is_empty() {
+                status!(
+                    "{} {} dependency package(s) installed",
+                    "✓".green().bold(),
+                    verified_new.len()
+                );
+            }
+
+            print_hook_results("post-transaction", &post_results);
+        }
+        Err(e) => {
+            status!(
+                "{} Downgrade failed: {}",
+                "✗".red().bold(),
+                e
+            );
+            bail!("Downgrade transaction failed: {}", e);
+        }
+    }
+
+    status!();
+    status!(
+        "Use {} to keep them from being upgraded again.",
+        "rookpkg hold <package>".bold()
+    );
+
+    Ok(())
+}
+
+/// Print hook execution results
+fn print_hook_results(phase: &str, results: &[HookResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let success_count = results.iter().filter(|r| r.success).count();
+    let fail_count = results.len() - success_count;
+
+    if fail_count == 0 {
+        status!(
+            "  {} {} hook(s) ran successfully",
+            "→".cyan(),
+            results.len()
+        );
+    } else {
+        status!(
+            "  {} {} {} hook(s): {} succeeded, {} failed",
+            "!".yellow(),
+            results.len(),
+            phase,
+            success_count,
+            fail_count
+        );
+        for result in results {
+            if !result.success {
+                status!(
+                    "    {} {} (exit code: {:?})",
+                    "✗".red(),
+                    result.name,
+                    result.exit_code
+                );
+                // Show stderr if available
+                if !result.stderr.is_empty() {
+                    for line in result.stderr.lines().take(3) {
+                        status!("      {}", line.dimmed());
+                    }
+                }
+                // Show stdout if stderr is empty but stdout has content
+                else if !result.stdout.is_empty() {
+                    for line in result.stdout.lines().take(3) {
+                        status!("      {}", line.dimmed());
+                    }
+                }
+            }
+        }
+    }
+}
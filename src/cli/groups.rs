@@ -4,18 +4,68 @@
 
 use anyhow::{bail, Result};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::repository::RepoManager;
+use rookpkg::config::Config;
+use rookpkg::repository::{GroupSearchResult, RepoManager};
+
+use super::{print_json, OutputFormat};
+
+/// `groups --output json`
+#[derive(Serialize)]
+struct GroupList {
+    groups: Vec<GroupJson>,
+}
+
+/// One group; also the whole output of `groups <name> --output json`
+#[derive(Serialize)]
+struct GroupJson {
+    name: String,
+    repository: String,
+    description: String,
+    /// Required for a base system
+    essential: bool,
+    packages: Vec<String>,
+    optional: Vec<String>,
+}
+
+impl From<GroupSearchResult> for GroupJson {
+    fn from(result: GroupSearchResult) -> Self {
+        Self {
+            name: result.group.name,
+            repository: result.repository,
+            description: result.group.description,
+            essential: result.group.essential,
+            packages: result.group.packages,
+            optional: result.group.optional,
+        }
+    }
+}
 
 /// Run the groups command
 ///
 /// If `group_name` is provided, show details about that group.
 /// Otherwise, list all available groups.
-pub fn run(group_name: Option<&str>, config: &Config) -> Result<()> {
+pub fn run(group_name: Option<&str>, output: OutputFormat, config: &Config) -> Result<()> {
     // Initialize repository manager and load cached metadata
     let mut manager = RepoManager::new(config)?;
 
+    if output == OutputFormat::Json {
+        if !config.repositories.is_empty() {
+            manager.load_caches()?;
+        }
+        return match group_name {
+            Some(name) => match manager.find_group(name) {
+                Some(result) => print_json(&GroupJson::from(result)),
+                None => bail!("Package group '{}' not found", name),
+            },
+            None => {
+                let groups = manager.list_groups().into_iter().map(GroupJson::from).collect();
+                print_json(&GroupList { groups })
+            }
+        };
+    }
+
     // Check if we have any repos
     if config.repositories.is_empty() {
         println!("{}", "No repositories configured.".yellow());
@@ -0,0 +1,474 @@
+//! History command implementation
+//!
+//! Lists recorded transactions and replays them backwards (undo) or
+//! forwards again (redo).
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{TimeZone, Utc};
+use colored::Colorize;
+
+use rookpkg::config::Config;
+use rookpkg::database::{Database, HistoryAction, HistoryEntry};
+use rookpkg::hooks::HookResult;
+use rookpkg::package::InstallReason;
+use rookpkg::repository::{BuildSource, PackageEntry, RepoManager};
+use rookpkg::resolver::needs_upgrade;
+use rookpkg::transaction::TransactionBuilder;
+
+/// Which way a recorded transaction is replayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Undo,
+    Redo,
+}
+
+/// One package change needed to replay a transaction
+enum Step {
+    Install { package: String, version: String, reason: InstallReason },
+    Remove { package: String, version: String },
+    Change { package: String, from: String, to: String },
+}
+
+/// List recorded transactions, newest first
+pub fn list(limit: usize, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    if !db_path.exists() {
+        println!("{}", "No transactions recorded yet.".dimmed());
+        return Ok(());
+    }
+    let db = Database::open(&db_path)?;
+    let entries = db.list_history(Some(limit))?;
+
+    if entries.is_empty() {
+        println!("{}", "No transactions recorded yet.".dimmed());
+        return Ok(());
+    }
+
+    println!(
+        "{:>5}  {:<16}  {:<10}  {:<22}  {}",
+        "ID".bold(),
+        "Date".bold(),
+        "User".bold(),
+        "Changes".bold(),
+        "Command".bold()
+    );
+    for entry in &entries {
+        let result = if entry.result == "completed" {
+            String::new()
+        } else {
+            format!(" ({})", entry.result.replace('_', " "))
+        };
+        println!(
+            "{:>5}  {:<16}  {:<10}  {:<22}  {}{}",
+            entry.id,
+            format_date(entry.timestamp, "%Y-%m-%d %H:%M"),
+            entry.user,
+            summarize(entry),
+            entry.command_line.dimmed(),
+            result.red()
+        );
+    }
+
+    println!();
+    println!("Use {} for details.", "rookpkg history info <id>".bold());
+
+    Ok(())
+}
+
+/// Show the operations of a recorded transaction
+pub fn info(id: i64, config: &Config) -> Result<()> {
+    let db = Database::open(&config.database_path())?;
+    let entry = db
+        .get_history(id)?
+        .ok_or_else(|| anyhow!("Transaction #{} not found in history", id))?;
+
+    println!("{} {}", "Transaction:".bold(), format!("#{}", entry.id).cyan());
+    println!("{} {}", "Date:".bold(), format_date(entry.timestamp, "%Y-%m-%d %H:%M:%S"));
+    println!("{} {}", "User:".bold(), entry.user);
+    println!("{} {}", "Command:".bold(), entry.command_line);
+    let result = match entry.result.as_str() {
+        "completed" => entry.result.green(),
+        other => other.replace('_', " ").red(),
+    };
+    println!("{} {}", "Result:".bold(), result);
+    println!("{} {}", "Journal ID:".bold(), entry.transaction_id.dimmed());
+    let snapshots: Vec<String> = db
+        .list_snapshots()?
+        .into_iter()
+        .filter(|s| s.transaction_id == entry.transaction_id)
+        .map(|s| s.snapshot_id)
+        .rev()
+        .collect();
+    if !snapshots.is_empty() {
+        println!("{} {}", "Snapshots:".bold(), snapshots.join(", "));
+    }
+    println!();
+
+    for op in &entry.operations {
+        let old = op.old_version.as_deref().unwrap_or("");
+        let new = op.new_version.as_deref().unwrap_or("");
+        match op.action {
+            HistoryAction::Install => println!(
+                "  {} {} {} {}",
+                "+".green(),
+                op.package.bold(),
+                new.green(),
+                format!("({})", op.install_reason).dimmed()
+            ),
+            HistoryAction::Remove => println!(
+                "  {} {} {}",
+                "-".red(),
+                op.package.bold(),
+                old.red()
+            ),
+            HistoryAction::Upgrade => {
+                let arrow = if is_newer(old, new) { "↑".cyan() } else { "↓".yellow() };
+                println!(
+                    "  {} {} {} → {}",
+                    arrow,
+                    op.package.bold(),
+                    old.dimmed(),
+                    new.green()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Revert a recorded transaction
+pub fn undo(id: i64, dry_run: bool, config: &Config) -> Result<()> {
+    replay(id, Direction::Undo, dry_run, config)
+}
+
+/// Apply a recorded transaction again (typically after undoing it)
+pub fn redo(id: i64, dry_run: bool, config: &Config) -> Result<()> {
+    replay(id, Direction::Redo, dry_run, config)
+}
+
+fn replay(id: i64, direction: Direction, dry_run: bool, config: &Config) -> Result<()> {
+    let verb = match direction {
+        Direction::Undo => "undo",
+        Direction::Redo => "redo",
+    };
+
+    if dry_run {
+        println!("{}", "Dry run mode - no changes will be made".yellow());
+        println!();
+    }
+
+    let db_path = config.database_path();
+    let db = Database::open(&db_path)?;
+    let entry = db
+        .get_history(id)?
+        .ok_or_else(|| anyhow!("Transaction #{} not found in history", id))?;
+    if entry.result != "completed" {
+        bail!(
+            "Transaction #{} did not complete ({}); there is nothing to {}",
+            id,
+            entry.result.replace('_', " "),
+            verb
+        );
+    }
+
+    println!(
+        "{} transaction #{} ({})",
+        match direction {
+            Direction::Undo => "Undoing",
+            Direction::Redo => "Redoing",
+        }
+        .cyan(),
+        id,
+        entry.command_line.dimmed()
+    );
+    println!();
+
+    // Undo walks from each operation's "after" state back to its "before"
+    // state in reverse order; redo walks forwards again
+    let mut ops: Vec<_> = entry.operations.iter().collect();
+    if direction == Direction::Undo {
+        ops.reverse();
+    }
+
+    let mut steps = Vec::new();
+    let mut mismatches = Vec::new();
+    for op in ops {
+        let (from, to) = match direction {
+            Direction::Undo => (&op.new_version, &op.old_version),
+            Direction::Redo => (&op.old_version, &op.new_version),
+        };
+
+        let current = db.get_package(&op.package)?.map(|p| p.full_version());
+        if &current != from {
+            mismatches.push(format!(
+                "{} is {} (expected {})",
+                op.package,
+                current.as_deref().unwrap_or("not installed"),
+                from.as_deref().unwrap_or("not installed")
+            ));
+            continue;
+        }
+
+        let step = match (from, to) {
+            (Some(from), None) => Step::Remove {
+                package: op.package.clone(),
+                version: from.clone(),
+            },
+            (None, Some(to)) => Step::Install {
+                package: op.package.clone(),
+                version: to.clone(),
+                reason: op.install_reason,
+            },
+            (Some(from), Some(to)) if from != to => Step::Change {
+                package: op.package.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            },
+            _ => continue,
+        };
+        steps.push(step);
+    }
+
+    if !mismatches.is_empty() {
+        println!("{}", "The system has changed since this transaction:".red().bold());
+        for mismatch in &mismatches {
+            println!("  {} {}", "✗".red(), mismatch);
+        }
+        println!();
+        bail!("Cannot {} transaction #{}", verb, id);
+    }
+
+    if steps.is_empty() {
+        println!("{}", "Nothing to do.".yellow());
+        return Ok(());
+    }
+
+    // Removing a package must not strand anything that still needs it
+    let removed: Vec<&str> = steps
+        .iter()
+        .filter_map(|s| match s {
+            Step::Remove { package, .. } => Some(package.as_str()),
+            _ => None,
+        })
+        .collect();
+    for package in &removed {
+        let dependents: Vec<String> = db
+            .get_reverse_dependencies(package)?
+            .into_iter()
+            .filter(|d| !removed.contains(&d.as_str()))
+            .collect();
+        if !dependents.is_empty() {
+            bail!(
+                "Cannot {} transaction #{}: {} is required by {}",
+                verb,
+                id,
+                package,
+                dependents.join(", ")
+            );
+        }
+    }
+
+    // Locate an archive for every build that has to be installed
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    let mut builds: Vec<Option<(PackageEntry, BuildSource)>> = Vec::new();
+    for step in &steps {
+        let target = match step {
+            Step::Install { package, version, .. } => Some((package, version)),
+            Step::Change { package, to, .. } => Some((package, to)),
+            Step::Remove { .. } => None,
+        };
+        let build = match target {
+            Some((package, full_version)) => {
+                let (version, release) = split_full_version(full_version)?;
+                let found = manager.find_build(package, &version, release).ok_or_else(|| {
+                    anyhow!(
+                        "{}-{} is no longer available in any repository or the package cache",
+                        package,
+                        full_version
+                    )
+                })?;
+                Some(found)
+            }
+            None => None,
+        };
+        builds.push(build);
+    }
+
+    for (step, build) in steps.iter().zip(&builds) {
+        let source = build
+            .as_ref()
+            .map(|(_, source)| format!(" (from {})", source))
+            .unwrap_or_default();
+        match step {
+            Step::Install { package, version, .. } => {
+                println!("  {} {} {}{}", "+".green(), package.bold(), version.green(), source.dimmed())
+            }
+            Step::Remove { package, version } => {
+                println!("  {} {} {}", "-".red(), package.bold(), version.red())
+            }
+            Step::Change { package, from, to } => {
+                let arrow = if is_newer(from, to) { "↑".cyan() } else { "↓".yellow() };
+                println!(
+                    "  {} {} {} → {}{}",
+                    arrow,
+                    package.bold(),
+                    from.dimmed(),
+                    to.green(),
+                    source.dimmed()
+                )
+            }
+        }
+    }
+    println!();
+
+    if dry_run {
+        println!("{}", "Dry run complete - no changes made.".yellow());
+        return Ok(());
+    }
+
+    status!("{}", "Downloading and verifying packages...".cyan());
+    status!();
+
+    let mut builder = TransactionBuilder::new(config.sysroot());
+    for (step, build) in steps.iter().zip(&builds) {
+        let verified = match build {
+            Some((package, source)) => {
+                status_part!("  {} {}-{}... ", "↓".cyan(), package.name, package.version);
+                match manager.fetch_build(package, source, config) {
+                    Ok(verified) => {
+                        status!("{} [{}]", "✓".green(), verified.signature_status.description());
+                        Some(verified)
+                    }
+                    Err(e) => {
+                        status!("{}", "✗".red());
+                        bail!("Failed to download/verify {}: {}", package.name, e);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        builder = match (step, verified) {
+            (Step::Remove { package, .. }, _) => builder.remove(package),
+            (Step::Install { package, version, reason }, Some(verified)) => {
+                builder.install(package, version, &verified.path, *reason)
+            }
+            (Step::Change { package, from, to }, Some(verified)) => {
+                builder.upgrade(package, from, to, &verified.path)
+            }
+            _ => unreachable!("every install and change has a build"),
+        };
+    }
+    println!();
+
+    // Re-open database for transaction execution
+    let db = Database::open(&db_path)?;
+
+    match builder.execute_with_hooks(db, &config.hooks, &config.snapshot) {
+        Ok((pre_results, post_results)) => {
+            print_hook_results("pre-transaction", &pre_results);
+            println!(
+                "{} Transaction #{} {}",
+                "✓".green().bold(),
+                id,
+                match direction {
+                    Direction::Undo => "undone",
+                    Direction::Redo => "redone",
+                }
+            );
+            print_hook_results("post-transaction", &post_results);
+        }
+        Err(e) => {
+            println!("{} {} failed: {}", "✗".red().bold(), verb, e);
+            bail!("History {} failed: {}", verb, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Count a transaction's operations by kind, e.g. "install 2, upgrade 1"
+fn summarize(entry: &HistoryEntry) -> String {
+    let mut parts = Vec::new();
+    for action in [HistoryAction::Install, HistoryAction::Upgrade, HistoryAction::Remove] {
+        let count = entry.operations.iter().filter(|op| op.action == action).count();
+        if count > 0 {
+            parts.push(format!("{} {}", action, count));
+        }
+    }
+    parts.join(", ")
+}
+
+/// Split "1.2.3-4" into its version and release
+fn split_full_version(full: &str) -> Result<(String, u32)> {
+    full.rsplit_once('-')
+        .and_then(|(version, release)| Some((version.to_string(), release.parse().ok()?)))
+        .ok_or_else(|| anyhow!("Malformed version in history: {}", full))
+}
+
+/// Whether `to` is a newer build than `from` (both "version-release")
+fn is_newer(from: &str, to: &str) -> bool {
+    match (split_full_version(from), split_full_version(to)) {
+        (Ok((fv, fr)), Ok((tv, tr))) => needs_upgrade(&fv, fr, &tv, tr),
+        _ => true,
+    }
+}
+
+fn format_date(timestamp: i64, format: &str) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|d| d.format(format).to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Print hook execution results
+fn print_hook_results(phase: &str, results: &[HookResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let success_count = results.iter().filter(|r| r.success).count();
+    let fail_count = results.len() - success_count;
+
+    if fail_count == 0 {
+        println!(
+            "  {} {} hook(s) ran successfully",
+            "→".cyan(),
+            results.len()
+        );
+    } else {
+        println!(
+            "  {} {} {} hook(s): {} succeeded, {} failed",
+            "!".yellow(),
+            results.len(),
+            phase,
+            success_count,
+            fail_count
+        );
+        for result in results {
+            if !result.success {
+                println!(
+                    "    {} {} (exit code: {:?})",
+                    "✗".red(),
+                    result.name,
+                    result.exit_code
+                );
+                // Show stderr if available
+                if !result.stderr.is_empty() {
+                    for line in result.stderr.lines().take(3) {
+                        println!("      {}", line.dimmed());
+                    }
+                }
+                // Show stdout if stderr is empty but stdout has content
+                else if !result.stdout.is_empty() {
+                    for line in result.stdout.lines().take(3) {
+                        println!("      {}", line.dimmed());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -5,9 +5,18 @@
 use anyhow::Result;
 use chrono::{TimeZone, Utc};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::database::Database;
+use rookpkg::config::Config;
+use rookpkg::database::{Database, HoldInfo};
+
+use super::{print_json, OutputFormat};
+
+/// `holds --output json`
+#[derive(Serialize)]
+struct HoldList {
+    holds: Vec<HoldInfo>,
+}
 
 /// Hold a package (prevent automatic upgrades)
 pub fn hold(packages: &[String], reason: Option<&str>, config: &Config) -> Result<()> {
@@ -136,11 +145,17 @@ pub fn unhold(packages: &[String], config: &Config) -> Result<()> {
 }
 
 /// Show detailed info about a specific held package
-pub fn show_hold(name: &str, config: &Config) -> Result<()> {
+pub fn show_hold(name: &str, output: OutputFormat, config: &Config) -> Result<()> {
     let db_path = config.database_path();
     let db = Database::open(&db_path)?;
 
-    match db.get_hold_info(name)? {
+    let hold = db.get_hold_info(name)?;
+    if output == OutputFormat::Json {
+        // The hold, or null if the package is not held
+        return print_json(&hold);
+    }
+
+    match hold {
         Some(hold) => {
             let version_str = hold.version.as_deref().unwrap_or("(any version)");
             let date = Utc.timestamp_opt(hold.held_date, 0)
@@ -164,11 +179,14 @@ pub fn show_hold(name: &str, config: &Config) -> Result<()> {
 }
 
 /// List all held packages
-pub fn list_holds(config: &Config) -> Result<()> {
+pub fn list_holds(output: OutputFormat, config: &Config) -> Result<()> {
     let db_path = config.database_path();
     let db = Database::open(&db_path)?;
 
     let holds = db.list_held_packages()?;
+    if output == OutputFormat::Json {
+        return print_json(&HoldList { holds });
+    }
 
     if holds.is_empty() {
         println!("{}", "No packages are held.".dimmed());
@@ -7,8 +7,8 @@ use std::path::Path;
 use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::hooks::{HookEvent, HookManager};
+use rookpkg::config::Config;
+use rookpkg::hooks::{HookEvent, HookManager};
 
 /// List all installed hooks
 pub fn list(config: &Config) -> Result<()> {
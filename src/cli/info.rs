@@ -3,12 +3,71 @@
 use anyhow::Result;
 use chrono::{TimeZone, Utc};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::repository::RepoManager;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::package::InstallReason;
+use rookpkg::repository::RepoManager;
+use rookpkg::RookpkgError;
+
+use super::{print_json, OutputFormat};
+
+/// `info --output json`, tagged by `status`
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum InfoJson {
+    Installed {
+        name: String,
+        version: String,
+        release: u32,
+        size_bytes: u64,
+        /// Unix timestamp
+        install_date: i64,
+        install_reason: InstallReason,
+        checksum: String,
+        dependencies: Vec<DependencyJson>,
+        required_by: Vec<String>,
+        accounts: Vec<AccountJson>,
+        /// Number of files the package owns
+        files: usize,
+    },
+    Available {
+        name: String,
+        version: String,
+        release: u32,
+        repository: String,
+        /// Size of the package file in bytes
+        size: u64,
+        description: String,
+        license: Option<String>,
+        homepage: Option<String>,
+        /// Dependency strings as listed in the repository index
+        dependencies: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct DependencyJson {
+    name: String,
+    /// Empty if any version will do
+    constraint: String,
+}
+
+#[derive(Serialize)]
+struct AccountJson {
+    /// "user" or "group"
+    kind: String,
+    name: String,
+    /// Whether installing the package created it
+    created: bool,
+}
+
+pub fn run(package: &str, deps: bool, output: OutputFormat, config: &Config) -> Result<()> {
+    if output == OutputFormat::Json {
+        return info_json(package, config);
+    }
 
-pub fn run(package: &str, deps: bool, config: &Config) -> Result<()> {
     // First try to find installed package
     let db_path = config.database_path();
     if db_path.exists() {
@@ -33,6 +92,18 @@ pub fn run(package: &str, deps: bool, config: &Config) -> Result<()> {
                 println!("  {:<14} {}", "Checksum:".bold(), pkg.checksum.dimmed());
             }
 
+            let accounts = db.get_accounts(package)?;
+            if !accounts.is_empty() {
+                let names: Vec<String> = accounts
+                    .iter()
+                    .map(|a| {
+                        let origin = if a.created { "created" } else { "existing" };
+                        format!("{} {} ({})", a.kind, a.name, origin)
+                    })
+                    .collect();
+                println!("  {:<14} {}", "Accounts:".bold(), names.join(", "));
+            }
+
             if deps {
                 println!();
                 println!("{}", "Dependencies:".bold());
@@ -140,6 +211,59 @@ pub fn run(package: &str, deps: bool, config: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Print the package as JSON; dependencies are always included
+fn info_json(package: &str, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    if db_path.exists() {
+        let db = Database::open(&db_path)?;
+        if let Some(pkg) = db.get_package(package)? {
+            let dependencies = db
+                .get_dependencies(package)?
+                .into_iter()
+                .map(|dep| DependencyJson { name: dep.depends_on, constraint: dep.constraint })
+                .collect();
+            let accounts = db
+                .get_accounts(package)?
+                .into_iter()
+                .map(|a| AccountJson { kind: a.kind.to_string(), name: a.name, created: a.created })
+                .collect();
+
+            return print_json(&InfoJson::Installed {
+                dependencies,
+                required_by: db.get_reverse_dependencies(package)?,
+                accounts,
+                files: db.get_files(package)?.len(),
+                name: pkg.name,
+                version: pkg.version,
+                release: pkg.release,
+                size_bytes: pkg.size_bytes,
+                install_date: pkg.install_date,
+                install_reason: pkg.install_reason,
+                checksum: pkg.checksum,
+            });
+        }
+    }
+
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    let result = manager
+        .find_package(package)
+        .ok_or_else(|| RookpkgError::PackageNotFound(package.to_string()))?;
+    let pkg = result.package;
+    print_json(&InfoJson::Available {
+        name: pkg.name,
+        version: pkg.version,
+        release: pkg.release,
+        repository: result.repository,
+        size: pkg.size,
+        description: pkg.description,
+        license: pkg.license.filter(|l| !l.is_empty()),
+        homepage: pkg.homepage.filter(|h| !h.is_empty()),
+        dependencies: pkg.depends,
+    })
+}
+
 fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -6,23 +6,71 @@ use std::path::Path;
 
 use anyhow::Result;
 use colored::Colorize;
+use serde::Serialize;
+
+use rookpkg::archive::{FileEntry, InstallScripts, PackageArchiveBuilder, PackageArchiveReader, PackageInfo};
+use rookpkg::build::PackageBuilder;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::spec::PackageSpec;
+
+use super::{print_json, OutputFormat};
+
+/// `inspect --output json`, tagged by `kind`
+///
+/// The payloads are the package's own metadata formats: `.PKGINFO`,
+/// `.FILES` and `.INSTALL` of an archive, or the parsed `.rook` spec.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum InspectJson {
+    Archive {
+        info: Box<PackageInfo>,
+        files: Vec<FileEntry>,
+        scripts: Option<InstallScripts>,
+    },
+    Spec {
+        spec: Box<PackageSpec>,
+    },
+}
 
-use crate::archive::{PackageArchiveBuilder, PackageArchiveReader};
-use crate::build::PackageBuilder;
-use crate::config::Config;
-use crate::database::Database;
-use crate::spec::PackageSpec;
+/// `inspect --validate --output json`, printed once the spec passed
+#[derive(Serialize)]
+struct ValidJson {
+    name: String,
+    version: String,
+    release: u32,
+    valid: bool,
+}
 
 /// Inspect a package archive or spec file
-pub fn run(path: &Path, show_files: bool, show_scripts: bool, validate: bool, config: &Config) -> Result<()> {
+pub fn run(
+    path: &Path,
+    show_files: bool,
+    show_scripts: bool,
+    validate: bool,
+    output: OutputFormat,
+    config: &Config,
+) -> Result<()> {
     // If validate flag is set, perform validation using build_from_spec and in-memory database
     if validate {
-        return validate_spec(path, config);
+        return validate_spec(path, output, config);
     }
 
     // Determine if this is a .rookpkg archive or a .rook spec file
     let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
 
+    if output == OutputFormat::Json {
+        let inspected = match extension {
+            "rookpkg" => archive_json(&PackageArchiveReader::open(path)?)?,
+            "rook" => InspectJson::Spec { spec: Box::new(PackageSpec::from_file(path)?) },
+            _ => match PackageArchiveReader::open(path) {
+                Ok(reader) => archive_json(&reader)?,
+                Err(_) => InspectJson::Spec { spec: Box::new(PackageSpec::from_file(path)?) },
+            },
+        };
+        return print_json(&inspected);
+    }
+
     match extension {
         "rookpkg" => inspect_archive(path, show_files, show_scripts),
         "rook" => inspect_spec(path),
@@ -37,6 +85,14 @@ pub fn run(path: &Path, show_files: bool, show_scripts: bool, validate: bool, co
     }
 }
 
+fn archive_json(reader: &PackageArchiveReader) -> Result<InspectJson> {
+    Ok(InspectJson::Archive {
+        info: Box::new(reader.read_info()?),
+        files: reader.read_files()?,
+        scripts: reader.read_scripts()?,
+    })
+}
+
 /// Inspect a package archive file
 fn inspect_archive(path: &Path, show_files: bool, show_scripts: bool) -> Result<()> {
     println!("{} {}", "Inspecting archive:".bold(), path.display());
@@ -83,12 +139,19 @@ fn inspect_archive_reader(reader: &PackageArchiveReader, show_files: bool, show_
     if show_files {
         for file in &files {
             let config_marker = if file.is_config { " [config]" } else { "" };
+            let xattr_marker = if file.xattrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", file.xattrs.keys().cloned().collect::<Vec<_>>().join(", "))
+            };
             println!(
-                "  {:>8} {:04o} {}{}",
+                "  {:>8} {:04o} {:<17} {}{}{}",
                 format_size(file.size),
                 file.mode & 0o7777,
+                format!("{}:{}", file.owner, file.group),
                 file.path,
-                config_marker.yellow()
+                config_marker.yellow(),
+                xattr_marker.cyan()
             );
         }
     } else {
@@ -321,7 +384,7 @@ pub fn validate_built_archive(builder: &PackageArchiveBuilder) -> Result<()> {
     println!("  {} No duplicate paths", "✓".green());
 
     // Validate all regular files have checksums (directories and symlinks don't need them)
-    use crate::archive::FileType;
+    use rookpkg::archive::FileType;
     for file in files {
         if file.file_type == FileType::Regular && file.sha256.is_empty() {
             anyhow::bail!("Regular file missing checksum: {}", file.path);
@@ -334,14 +397,14 @@ pub fn validate_built_archive(builder: &PackageArchiveBuilder) -> Result<()> {
 }
 
 /// Validate a spec file by creating a build environment and testing with in-memory database
-fn validate_spec(path: &Path, config: &Config) -> Result<()> {
-    println!("{} {}", "Validating spec file:".bold(), path.display());
-    println!();
+fn validate_spec(path: &Path, output: OutputFormat, config: &Config) -> Result<()> {
+    status!("{} {}", "Validating spec file:".bold(), path.display());
+    status!();
 
     // Step 1: Validate spec file can be parsed
-    println!("{}", "Parsing spec file...".cyan());
+    status!("{}", "Parsing spec file...".cyan());
     let spec = PackageSpec::from_file(path)?;
-    println!(
+    status!(
         "  {} {}-{}-{}",
         "✓".green(),
         spec.package.name,
@@ -350,32 +413,41 @@ fn validate_spec(path: &Path, config: &Config) -> Result<()> {
     );
 
     // Step 2: Validate build environment can be created using build_from_spec
-    println!("{}", "Creating build environment (using build_from_spec)...".cyan());
+    status!("{}", "Creating build environment (using build_from_spec)...".cyan());
     let builder = PackageBuilder::new(config.clone());
     let build_env = builder.build_from_spec(path)?;
-    println!(
+    status!(
         "  {} Build directory: {}",
         "✓".green(),
         build_env.build_dir().display()
     );
 
     // Step 3: Validate database operations using in-memory database
-    println!("{}", "Testing database operations (in-memory)...".cyan());
+    status!("{}", "Testing database operations (in-memory)...".cyan());
     let _db = Database::open_in_memory()?;
-    println!("  {} In-memory database created", "✓".green());
+    status!("  {} In-memory database created", "✓".green());
 
     // Clean up the build environment
-    println!("{}", "Cleaning up...".cyan());
+    status!("{}", "Cleaning up...".cyan());
     build_env.clean()?;
-    println!("  {} Build directory cleaned", "✓".green());
+    status!("  {} Build directory cleaned", "✓".green());
 
-    println!();
-    println!(
+    status!();
+    status!(
         "{} Spec file {} is valid and ready to build",
         "✓".green().bold(),
         path.display()
     );
 
+    if output == OutputFormat::Json {
+        print_json(&ValidJson {
+            name: spec.package.name,
+            version: spec.package.version,
+            release: spec.package.release,
+            valid: true,
+        })?;
+    }
+
     Ok(())
 }
 
@@ -3,57 +3,69 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use colored::Colorize;
 use pubgrub::range::Range;
-use pubgrub::solver::resolve;
-use pubgrub::version::SemanticVersion;
-
-use crate::archive::PackageArchiveReader;
-use crate::config::Config;
-use crate::database::Database;
-use crate::hooks::HookResult;
-use crate::package::InstallReason;
-use crate::repository::{PackageEntry, RepoManager, SignatureStatus, VerifiedPackage};
-use crate::resolver::{needs_upgrade, parse_constraint, Package, RookeryDependencyProvider};
-use crate::signing::TrustLevel;
-use crate::transaction::{ConflictType, Transaction, TransactionBuilder};
-
-pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool, force: bool, config: &Config) -> Result<()> {
+
+use rookpkg::archive::PackageArchiveReader;
+use rookpkg::config::Config;
+use rookpkg::database::{Database, HistoryAction, HistoryOperation};
+use rookpkg::hooks::HookResult;
+use rookpkg::package::InstallReason;
+use rookpkg::repository::{find_candidate, PackageEntry, RepoManager, SignatureStatus, VerifiedPackage};
+use rookpkg::resolver::{
+    needs_upgrade, parse_version, PackageRequest, ResolutionContext, ResolutionReport,
+    RookeryDependencyProvider,
+};
+use rookpkg::signing::TrustLevel;
+use rookpkg::version::Version;
+use rookpkg::transaction::{ConflictType, Transaction, TransactionBuilder};
+
+use super::{DryRunPlan, PlannedOperation};
+
+pub fn run(
+    packages: &[String],
+    local: bool,
+    dry_run: bool,
+    download_only: bool,
+    force: bool,
+    json: bool,
+    config: &Config,
+) -> Result<()> {
     if dry_run {
-        println!("{}", "Dry run mode - no changes will be made".yellow());
-        println!();
+        status!("{}", "Dry run mode - no changes will be made".yellow());
+        status!();
     }
 
     if download_only {
-        println!("{}", "Download-only mode - packages will be cached but not installed".yellow());
-        println!();
+        status!("{}", "Download-only mode - packages will be cached but not installed".yellow());
+        status!();
     }
 
     if force {
-        println!("{}", "Force mode - file conflicts will be overwritten".yellow());
-        println!();
+        status!("{}", "Force mode - file conflicts will be overwritten".yellow());
+        status!();
     }
 
     // Handle local package installation
     if local {
         if download_only {
-            println!("{}", "Note: --download-only has no effect with --local (files are already local)".yellow());
+            status!("{}", "Note: --download-only has no effect with --local (files are already local)".yellow());
         }
         return run_local(packages, dry_run, force, config);
     }
 
-    println!("{}", "Loading repository data...".cyan());
+    status!("{}", "Loading repository data...".cyan());
 
     // Initialize repository manager and load cached metadata
     let mut manager = RepoManager::new(config)?;
 
     // Check if we have any repos
     if config.repositories.is_empty() {
-        println!();
-        println!("{}", "No repositories configured.".yellow());
-        println!("Run {} to add repositories.", "rookpkg update".bold());
-        return Ok(());
+        status!();
+        status!("{}", "No repositories configured.".yellow());
+        status!("Run {} to add repositories.", "rookpkg update".bold());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
     // Load caches and show repo status
@@ -75,105 +87,189 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
     // Expand @group references to individual packages
     let expanded_packages = expand_groups(packages, &manager)?;
 
-    println!("{}", "Resolving dependencies...".cyan());
-    println!();
+    status!("{}", "Resolving dependencies...".cyan());
+    status!();
 
-    // Build dependency provider from repository data
+    // Installed packages take part in resolution, provider selection and conflict checks
+    let db_path = config.database_path();
+    let existing_db = if db_path.exists() {
+        Some(Database::open(&db_path)?)
+    } else {
+        None
+    };
+    let installed = match existing_db {
+        Some(ref db) => db.list_packages()?,
+        None => Vec::new(),
+    };
+
+    // Build dependency provider from every version the repositories offer;
+    // each package comes from the highest-priority repository carrying it
+    let candidates = manager.package_candidates();
     let mut provider = RookeryDependencyProvider::new();
-    let mut package_map: HashMap<String, (PackageEntry, String)> = HashMap::new();
-
-    // Add all available packages to the provider
-    for repo in manager.enabled_repos() {
-        if let Some(ref index) = repo.index {
-            for pkg in &index.packages {
-                // Parse version
-                let version = parse_version(&pkg.version);
-
-                // Parse dependencies
-                let mut deps = HashMap::new();
-                for dep_str in &pkg.depends {
-                    // Format: "name" or "name >= 1.0"
-                    let (dep_name, constraint) = parse_dep_string(dep_str);
-                    if let Ok(range) = parse_constraint(&constraint) {
-                        deps.insert(dep_name.to_string(), range);
-                    }
-                }
+    for results in candidates.values() {
+        for result in results {
+            provider.add_entry(&result.package);
+        }
+    }
 
-                provider.add_package(&pkg.name, version, deps);
-                package_map.insert(pkg.name.clone(), (pkg.clone(), repo.name.clone()));
-            }
+    let mut context = ResolutionContext::default();
+    if let Some(ref db) = existing_db {
+        for pkg in &installed {
+            provider.add_installed(pkg, &db.get_dependencies(&pkg.name)?, &db.get_provides(&pkg.name)?);
+            provider.prefer(&pkg.name);
+            context.installed.insert(pkg.name.clone(), pkg.version.clone());
+        }
+        for hold in db.list_held_packages()? {
+            context.held.insert(hold.name);
         }
     }
 
-    // Find each requested package first
+    // Find each requested package first (virtual names count if something provides them)
     let mut not_found = Vec::new();
-    let mut root_packages = Vec::new();
-
-    for package_name in &expanded_packages {
-        if package_map.contains_key(package_name) {
-            root_packages.push(package_name.clone());
+    let mut root_packages: Vec<PackageRequest> = Vec::new();
+
+    for package_arg in &expanded_packages {
+        let request = PackageRequest::parse(package_arg).map_err(|e| anyhow!(e))?;
+        let release_available = match request.release {
+            Some(release) => candidates.get(&request.name).is_some_and(|c| {
+                c.iter().any(|r| {
+                    r.package.release == release
+                        && request.range.contains(&parse_version(&r.package.version))
+                })
+            }),
+            None => true,
+        };
+        if provider.is_known(&request.name) && release_available {
+            provider.prefer(&request.name);
+            root_packages.push(request);
         } else {
-            println!("  {} {} {}", "✗".red(), package_name.bold(), "(not found)".red());
-            not_found.push(package_name.clone());
+            status!("  {} {} {}", "✗".red(), request.to_string().bold(), "(not found)".red());
+            not_found.push(request.to_string());
+        }
+    }
+
+    // A version request for an installed package is a change of version,
+    // which install leaves to upgrade and downgrade
+    for request in &root_packages {
+        let Some(pkg) = installed.iter().find(|p| p.name == request.name) else {
+            continue;
+        };
+        let matches = request.range.contains(&parse_version(&pkg.version))
+            && request.release.is_none_or(|r| r == pkg.release);
+        if !matches {
+            bail!(
+                "{} {} is installed, which does not match {}.\n\
+                Use {} or {} to change its version.",
+                pkg.name,
+                pkg.full_version(),
+                request,
+                "rookpkg upgrade".bold(),
+                format!("rookpkg downgrade '{}'", request).bold()
+            );
         }
     }
 
     if !not_found.is_empty() {
-        println!();
-        println!(
+        status!();
+        status!(
             "{} {} package(s) not found:",
             "Error:".red().bold(),
             not_found.len()
         );
         for name in &not_found {
-            println!("  - {}", name);
+            status!("  - {}", name);
         }
-        println!();
-        println!("Try {} to refresh package lists.", "rookpkg update".bold());
+        status!();
+        status!("Try {} to refresh package lists.", "rookpkg update".bold());
         bail!("Some packages not found");
     }
 
     if root_packages.is_empty() {
-        println!("{}", "Nothing to install.".yellow());
-        return Ok(());
+        status!("{}", "Nothing to install.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
     // Resolve dependencies using PubGrub
-    println!("  Resolving dependency tree...");
-
-    // Create a virtual root package that depends on all requested packages
-    let mut root_deps: HashMap<String, Range<SemanticVersion>> = HashMap::new();
-    for pkg_name in &root_packages {
-        root_deps.insert(pkg_name.clone(), Range::any());
+    status!("  Resolving dependency tree...");
+
+    // Create a virtual root package that depends on all requested packages.
+    // Installed packages stay so their conflicts are checked too: held ones
+    // at their installed version, others are upgraded only when a requested
+    // package needs it.
+    let mut root_deps: HashMap<String, Range<Version>> = HashMap::new();
+    for pkg in installed.iter().filter(|p| context.held.contains(&p.name)) {
+        root_deps.insert(pkg.name.clone(), Range::exact(parse_version(&pkg.version)));
+    }
+    for request in &root_packages {
+        context.requested.insert(request.name.clone());
+        root_deps
+            .entry(request.name.clone())
+            .or_insert_with(|| request.range.clone());
+    }
+    for pkg in &installed {
+        let version = parse_version(&pkg.version);
+        if !context.requested.contains(&pkg.name) {
+            provider.favour(&pkg.name, version.clone());
+        }
+        root_deps
+            .entry(pkg.name.clone())
+            .or_insert_with(|| Range::higher_than(version));
     }
-    provider.add_package("__root__", SemanticVersion::new(1, 0, 0), root_deps);
+    let root_version = provider.set_root(root_deps);
 
-    let solution = match resolve(&provider, Package("__root__".to_string()), SemanticVersion::new(1, 0, 0)) {
+    let solution = match provider.solve(root_version, &context) {
         Ok(sol) => sol,
-        Err(e) => {
-            println!();
-            println!("{}", "Dependency resolution failed:".red().bold());
-            println!("  {}", e);
+        Err(report) => {
+            print_resolution_failure(&report, json)?;
             bail!("Could not resolve dependencies");
         }
     };
 
-    // Build install list from solution (excluding virtual root)
+    // Build install list from solution (excluding installed packages the
+    // solution keeps at their version)
     let mut to_install: Vec<(PackageEntry, String)> = Vec::new();
-    for pkg in solution.keys() {
-        if pkg.0 != "__root__" {
-            if let Some((entry, repo)) = package_map.get(&pkg.0) {
-                to_install.push((entry.clone(), repo.clone()));
+    for (pkg, version) in &solution {
+        if let Some(installed_version) = context.installed.get(pkg) {
+            let installed_version = parse_version(installed_version);
+            if *version == installed_version {
+                continue;
+            }
+            if *version < installed_version {
+                bail!(
+                    "Installing would need {} {} (installed: {}).\n\
+                    Use {} to change its version.",
+                    pkg,
+                    version,
+                    installed_version,
+                    "rookpkg downgrade".bold()
+                );
             }
         }
+        let release = root_packages
+            .iter()
+            .find(|r| &r.name == pkg)
+            .and_then(|r| r.release);
+        if let Some(result) = candidates
+            .get(pkg)
+            .and_then(|c| find_candidate(c, version, release))
+        {
+            to_install.push((result.package.clone(), result.repository.clone()));
+        }
     }
 
-    // Show resolved packages
-    let requested_set: std::collections::HashSet<_> = root_packages.iter().collect();
+    // Show resolved packages; a requested virtual name makes its provider explicit
+    let requested_set: std::collections::HashSet<String> = root_packages
+        .iter()
+        .map(|request| {
+            provider
+                .satisfied_by(&request.name, &request.range, &solution)
+                .unwrap_or_else(|| request.name.clone())
+        })
+        .collect();
     for (pkg, repo) in &to_install {
         let is_dep = !requested_set.contains(&pkg.name);
         if is_dep {
-            println!(
+            status!(
                 "  {} {}-{} {} {} {}",
                 "✓".green(),
                 pkg.name.bold(),
@@ -183,7 +279,7 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
                 "(dependency)".dimmed()
             );
         } else {
-            println!(
+            status!(
                 "  {} {}-{} {} {}",
                 "✓".green(),
                 pkg.name.bold(),
@@ -194,24 +290,45 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
         }
     }
 
-    println!();
+    status!();
 
     if to_install.is_empty() {
-        println!("{}", "Nothing to install.".yellow());
-        return Ok(());
+        status!("{}", "Nothing to install.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
     // Calculate total download size
     let total_size: u64 = to_install.iter().map(|(p, _)| p.size).sum();
-    println!(
+    status!(
         "Total download size: {}",
         format_size(total_size).cyan()
     );
-    println!();
+    status!();
 
     if dry_run {
-        println!("{}", "Dry run complete - no packages downloaded.".yellow());
-        return Ok(());
+        status!("{}", "Dry run complete - no packages downloaded.".yellow());
+        let operations = to_install
+            .iter()
+            .map(|(pkg, repo)| {
+                let existing = installed.iter().find(|p| p.name == pkg.name);
+                PlannedOperation {
+                    operation: HistoryOperation {
+                        action: if existing.is_some() { HistoryAction::Upgrade } else { HistoryAction::Install },
+                        package: pkg.name.clone(),
+                        old_version: existing.map(|p| p.full_version()),
+                        new_version: Some(format!("{}-{}", pkg.version, pkg.release)),
+                        install_reason: match existing {
+                            Some(p) => p.install_reason,
+                            None if requested_set.contains(&pkg.name) => InstallReason::Explicit,
+                            None => InstallReason::Dependency,
+                        },
+                    },
+                    repository: Some(repo.clone()),
+                }
+            })
+            .collect();
+        let plan = DryRunPlan { operations, download_size: total_size, ..Default::default() };
+        return plan.print_if_requested(dry_run);
     }
 
     // Pre-download all packages to cache (batch download)
@@ -221,8 +338,8 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
     let _downloaded_paths = manager.download_packages(&download_list)?;
 
     // Download and verify packages
-    println!("{}", "Downloading and verifying packages...".cyan());
-    println!();
+    status!("{}", "Downloading and verifying packages...".cyan());
+    status!();
 
     let mut verified_packages: Vec<VerifiedPackage> = Vec::new();
 
@@ -239,7 +356,7 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
             ""
         };
 
-        print!(
+        status_part!(
             "  {} {}-{} {}... ",
             "↓".cyan(),
             package.name,
@@ -258,36 +375,36 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
                         TrustLevel::Marginal => "marginal".yellow(),
                         TrustLevel::Unknown => "unknown".red(),
                     };
-                    println!("{} [signed by {} ({})]", "✓".green(), signer.cyan(), trust_color);
+                    status!("{} [signed by {} ({})]", "✓".green(), signer.cyan(), trust_color);
                 }
 
                 verified_packages.push(verified);
             }
             Err(e) => {
-                println!("{}", "✗".red());
+                status!("{}", "✗".red());
                 bail!("Failed to download/verify {}: {}", package.name, e);
             }
         }
     }
 
-    println!();
+    status!();
 
     // Summary
     let verified_count = verified_packages.iter().filter(|p| p.is_verified()).count();
     let trusted_count = verified_packages.iter().filter(|p| p.is_trusted()).count();
 
     if verified_count == verified_packages.len() {
-        println!(
+        status!(
             "{} All {} package(s) have valid signatures",
             "✓".green().bold(),
             verified_count
         );
         if trusted_count == verified_packages.len() {
-            println!("  All signatures from trusted keys");
+            status!("  All signatures from trusted keys");
         }
     } else {
         let unsigned_count = verified_packages.len() - verified_count;
-        println!(
+        status!(
             "{} {} of {} package(s) verified, {} unsigned/unknown",
             "!".yellow().bold(),
             verified_count,
@@ -296,43 +413,48 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
         );
     }
 
-    println!();
+    status!();
 
     // Download-only mode: exit after downloading and verifying
     if download_only {
-        println!(
+        status!(
             "{} {} package(s) downloaded to cache",
             "✓".green().bold(),
             verified_packages.len()
         );
-        println!();
-        println!("Cached packages:");
+        status!();
+        status!("Cached packages:");
         for verified in &verified_packages {
-            println!(
+            status!(
                 "  {} {}",
                 "→".cyan(),
                 verified.path.display()
             );
         }
-        println!();
-        println!("To install these packages later, run:");
-        println!("  {} {}", "rookpkg install".bold(), packages.join(" "));
+        status!();
+        status!("To install these packages later, run:");
+        status!("  {} {}", "rookpkg install".bold(), packages.join(" "));
         return Ok(());
     }
 
     // Install packages using transaction
-    println!("{}", "Installing packages...".cyan());
-    println!();
+    status!("{}", "Installing packages...".cyan());
+    status!();
 
     // Open or create database (use sysroot-relative path)
-    let db_path = config.database_path();
     let db = Database::open(&db_path)?;
 
-    // Check for already installed packages
+    // Installed dependencies the solution needs newer are upgraded; other
+    // already installed packages are left alone
     let mut already_installed = Vec::new();
+    let mut upgrades = Vec::new();
     for verified in &verified_packages {
         if let Ok(Some(existing)) = db.get_package(&verified.package.name) {
-            already_installed.push((verified.package.name.clone(), existing.version.clone()));
+            if needs_upgrade(&existing.version, existing.release, &verified.package.version, verified.package.release) {
+                upgrades.push((verified.package.name.clone(), existing.full_version()));
+            } else {
+                already_installed.push((verified.package.name.clone(), existing.version.clone()));
+            }
         }
     }
 
@@ -343,17 +465,17 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
         .collect();
 
     if !already_installed.is_empty() {
-        println!("{}", "Some packages are already installed:".yellow());
+        status!("{}", "Some packages are already installed:".yellow());
         for (name, version) in &already_installed {
-            println!("  {} {} ({})", "!".yellow(), name.bold(), version);
+            status!("  {} {} ({})", "!".yellow(), name.bold(), version);
         }
-        println!();
-        println!("Use {} to update existing packages.", "rookpkg upgrade".bold());
-        println!();
+        status!();
+        status!("Use {} to update existing packages.", "rookpkg upgrade".bold());
+        status!();
     }
 
     if packages_to_install.is_empty() {
-        println!("{}", "Nothing new to install.".yellow());
+        status!("{}", "Nothing new to install.".yellow());
         return Ok(());
     }
 
@@ -366,6 +488,17 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
 
     for verified in &packages_to_install {
         let version = format!("{}-{}", verified.package.version, verified.package.release);
+        if let Some((_, old_version)) = upgrades.iter().find(|(n, _)| n == &verified.package.name) {
+            status!(
+                "  {} {} {} -> {} (needed by the requested packages)",
+                "↑".cyan(),
+                verified.package.name.bold(),
+                old_version,
+                version
+            );
+            tx.upgrade(&verified.package.name, old_version, &version, &verified.path);
+            continue;
+        }
         // Determine if this package was explicitly requested or is a dependency
         let reason = if requested_set.contains(&verified.package.name) {
             InstallReason::Explicit
@@ -376,13 +509,13 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
     }
 
     // Check for file conflicts before executing
-    println!("{}", "Checking for file conflicts...".cyan());
+    status!("{}", "Checking for file conflicts...".cyan());
     let conflicts = tx.check_conflicts(false)?;
 
     if !conflicts.is_empty() {
-        println!();
-        println!("{}", "File conflicts detected:".red().bold());
-        println!();
+        status!();
+        status!("{}", "File conflicts detected:".red().bold());
+        status!();
 
         for conflict in &conflicts {
             let conflict_desc = match &conflict.conflict_with {
@@ -393,7 +526,7 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
                     "unowned file on filesystem".to_string()
                 }
             };
-            println!(
+            status!(
                 "  {} {} ({})",
                 "✗".red(),
                 conflict.path.bold(),
@@ -401,7 +534,7 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
             );
         }
 
-        println!();
+        status!();
         bail!(
             "Cannot install: {} file conflict(s) detected. \
             Remove conflicting package(s) first.",
@@ -409,16 +542,16 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
         );
     }
 
-    println!("  {} No conflicts found", "✓".green());
-    println!();
+    status!("  {} No conflicts found", "✓".green());
+    status!();
 
     // Execute transaction with hooks
-    match tx.execute_with_hooks(&config.hooks) {
+    match tx.execute_with_hooks(&config.hooks, &config.snapshot) {
         Ok((pre_results, post_results)) => {
             // Show hook execution summary
             print_hook_results("pre-transaction", &pre_results);
 
-            println!(
+            status!(
                 "{} {} package(s) installed successfully",
                 "✓".green().bold(),
                 packages_to_install.len()
@@ -427,7 +560,7 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
             print_hook_results("post-transaction", &post_results);
         }
         Err(e) => {
-            println!(
+            status!(
                 "{} Installation failed: {}",
                 "✗".red().bold(),
                 e
@@ -436,12 +569,72 @@ pub fn run(packages: &[String], local: bool, dry_run: bool, download_only: bool,
         }
     }
 
-    println!();
-    println!("{}", "Installation complete!".green());
+    status!();
+    status!("{}", "Installation complete!".green());
 
     Ok(())
 }
 
+/// What installing local archives would do: fresh installs, or upgrades of
+/// installed packages (reinstalls and downgrades are skipped, as in the
+/// real run)
+fn local_plan(to_process: &[(PathBuf, rookpkg::archive::PackageInfo)], config: &Config) -> Result<DryRunPlan> {
+    let db_path = config.database_path();
+    let db = if db_path.exists() {
+        Some(Database::open(&db_path)?)
+    } else {
+        None
+    };
+
+    let mut plan = DryRunPlan::default();
+    for (_, info) in to_process {
+        let existing = match db {
+            Some(ref db) => db.get_package(&info.name)?,
+            None => None,
+        };
+        let action = match existing {
+            None => HistoryAction::Install,
+            Some(ref pkg) if needs_upgrade(&pkg.version, pkg.release, &info.version, info.release) => {
+                HistoryAction::Upgrade
+            }
+            Some(_) => continue,
+        };
+        plan.operations.push(PlannedOperation {
+            operation: HistoryOperation {
+                action,
+                package: info.name.clone(),
+                old_version: existing.as_ref().map(|p| p.full_version()),
+                new_version: Some(format!("{}-{}", info.version, info.release)),
+                install_reason: existing.map(|p| p.install_reason).unwrap_or(InstallReason::Explicit),
+            },
+            repository: None,
+        });
+    }
+    Ok(plan)
+}
+
+/// Print a dependency resolution failure as text or JSON
+pub(crate) fn print_resolution_failure(report: &ResolutionReport, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+        return Ok(());
+    }
+
+    status!();
+    status!("{}", "Dependency resolution failed:".red().bold());
+    for line in &report.explanation {
+        for (i, part) in line.lines().enumerate() {
+            if i == 0 {
+                status!("  {} {}", "✗".red(), part);
+            } else {
+                status!("    {}", part);
+            }
+        }
+    }
+    status!();
+    Ok(())
+}
+
 /// Print hook execution results
 fn print_hook_results(phase: &str, results: &[HookResult]) {
     if results.is_empty() {
@@ -452,13 +645,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
     let fail_count = results.len() - success_count;
 
     if fail_count == 0 {
-        println!(
+        status!(
             "  {} {} hook(s) ran successfully",
             "→".cyan(),
             results.len()
         );
     } else {
-        println!(
+        status!(
             "  {} {} {} hook(s): {} succeeded, {} failed",
             "!".yellow(),
             results.len(),
@@ -468,7 +661,7 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
         );
         for result in results {
             if !result.success {
-                println!(
+                status!(
                     "    {} {} (exit code: {:?})",
                     "✗".red(),
                     result.name,
@@ -477,13 +670,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
                 // Show stderr if available
                 if !result.stderr.is_empty() {
                     for line in result.stderr.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
                 // Show stdout if stderr is empty but stdout has content
                 else if !result.stdout.is_empty() {
                     for line in result.stdout.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
             }
@@ -508,36 +701,6 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
-/// Parse a version string to SemanticVersion
-fn parse_version(s: &str) -> SemanticVersion {
-    let parts: Vec<&str> = s.split('.').collect();
-
-    let major: u32 = parts
-        .first()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-
-    let minor: u32 = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
-
-    let patch: u32 = parts.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
-
-    SemanticVersion::new(major, minor, patch)
-}
-
-/// Parse a dependency string like "name" or "name >= 1.0"
-fn parse_dep_string(dep: &str) -> (&str, String) {
-    // Try to find an operator
-    for op in &[">=", "<=", "==", "!=", ">", "<", "="] {
-        if let Some(pos) = dep.find(op) {
-            let name = dep[..pos].trim();
-            let constraint = dep[pos..].trim();
-            return (name, constraint.to_string());
-        }
-    }
-    // No operator found - name only, any version
-    (dep.trim(), "*".to_string())
-}
-
 /// Expand @group references to individual packages
 ///
 /// Package names starting with '@' are treated as group references.
@@ -577,17 +740,17 @@ fn expand_groups(packages: &[String], manager: &RepoManager) -> Result<Vec<Strin
 
     // Print group expansion summary
     if !groups_found.is_empty() {
-        println!("{}", "Expanding package groups...".cyan());
-        println!();
+        status!("{}", "Expanding package groups...".cyan());
+        status!();
         for (name, count) in &groups_found {
-            println!(
+            status!(
                 "  {} @{} → {} package(s)",
                 "→".cyan(),
                 name.bold(),
                 count
             );
         }
-        println!();
+        status!();
     }
 
     Ok(expanded)
@@ -599,11 +762,12 @@ fn expand_groups(packages: &[String], manager: &RepoManager) -> Result<Vec<Strin
 /// If a package is already installed, it will be upgraded if the
 /// local package is newer.
 fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -> Result<()> {
-    println!("{}", "Installing local package(s)...".cyan());
-    println!();
+    status!("{}", "Installing local package(s)...".cyan());
+    status!();
 
-    // Verify all files exist and are valid packages
-    let mut to_process: Vec<(PathBuf, crate::archive::PackageInfo)> = Vec::new();
+    // Verify all files exist, are valid packages and are signed by a known key
+    let manager = RepoManager::new(config)?;
+    let mut to_process: Vec<(PathBuf, rookpkg::archive::PackageInfo)> = Vec::new();
 
     for pkg_path in packages {
         let path = PathBuf::from(pkg_path);
@@ -614,29 +778,35 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
         // Open and read package info
         let reader = PackageArchiveReader::open(&path)?;
         let info = reader.read_info()?;
+        // Unsigned, unknown-key and invalid packages are refused like repository ones
+        let signed_by = match manager.verify_local_package(&path, config)? {
+            SignatureStatus::Verified { signer, .. } => format!(", signed by {}", signer),
+            _ => String::new(),
+        };
 
-        println!(
-            "  {} {}-{}-{} ({})",
+        status!(
+            "  {} {}-{}-{} ({}{})",
             "→".cyan(),
             info.name.bold(),
             info.version,
             info.release,
-            format_size(std::fs::metadata(&path)?.len())
+            format_size(std::fs::metadata(&path)?.len()),
+            signed_by.dimmed()
         );
 
         to_process.push((path, info));
     }
 
-    println!();
+    status!();
 
     if to_process.is_empty() {
-        println!("{}", "Nothing to install.".yellow());
-        return Ok(());
+        status!("{}", "Nothing to install.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
     if dry_run {
-        println!("{}", "Dry run complete - no packages installed.".yellow());
-        return Ok(());
+        status!("{}", "Dry run complete - no packages installed.".yellow());
+        return local_plan(&to_process, config)?.print_if_requested(dry_run);
     }
 
     // Open database (use sysroot-relative path)
@@ -644,8 +814,8 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
     let db = Database::open(&db_path)?;
 
     // Categorize packages: fresh installs vs upgrades vs same version vs downgrades
-    let mut fresh_installs: Vec<(PathBuf, crate::archive::PackageInfo)> = Vec::new();
-    let mut upgrades: Vec<(PathBuf, crate::archive::PackageInfo, String, u32)> = Vec::new();
+    let mut fresh_installs: Vec<(PathBuf, rookpkg::archive::PackageInfo)> = Vec::new();
+    let mut upgrades: Vec<(PathBuf, rookpkg::archive::PackageInfo, String, u32)> = Vec::new();
     let mut same_version: Vec<(String, String)> = Vec::new();
     let mut downgrades: Vec<(String, String, String)> = Vec::new();
 
@@ -674,18 +844,18 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
 
     // Report same version packages
     if !same_version.is_empty() {
-        println!("{}", "Already at requested version:".dimmed());
+        status!("{}", "Already at requested version:".dimmed());
         for (name, version) in &same_version {
-            println!("  {} {} ({})", "=".dimmed(), name, version.dimmed());
+            status!("  {} {} ({})", "=".dimmed(), name, version.dimmed());
         }
-        println!();
+        status!();
     }
 
     // Report downgrades (but don't process them)
     if !downgrades.is_empty() {
-        println!("{}", "Skipping downgrades (use --force to override):".yellow());
+        status!("{}", "Skipping downgrades (use --force to override):".yellow());
         for (name, installed, requested) in &downgrades {
-            println!(
+            status!(
                 "  {} {} ({} → {})",
                 "↓".yellow(),
                 name.bold(),
@@ -693,14 +863,14 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
                 requested.dimmed()
             );
         }
-        println!();
+        status!();
     }
 
     // Report what will be installed/upgraded
     if !fresh_installs.is_empty() {
-        println!("{}", "New installations:".green());
+        status!("{}", "New installations:".green());
         for (_, info) in &fresh_installs {
-            println!(
+            status!(
                 "  {} {}-{}-{}",
                 "+".green(),
                 info.name.bold(),
@@ -708,13 +878,13 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
                 info.release
             );
         }
-        println!();
+        status!();
     }
 
     if !upgrades.is_empty() {
-        println!("{}", "Upgrades:".cyan());
+        status!("{}", "Upgrades:".cyan());
         for (_, info, old_version, old_release) in &upgrades {
-            println!(
+            status!(
                 "  {} {} {}-{} → {}-{}",
                 "↑".cyan(),
                 info.name.bold(),
@@ -724,11 +894,11 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
                 info.release.to_string().green()
             );
         }
-        println!();
+        status!();
     }
 
     if fresh_installs.is_empty() && upgrades.is_empty() {
-        println!("{}", "Nothing to do.".yellow());
+        status!("{}", "Nothing to do.".yellow());
         return Ok(());
     }
 
@@ -757,31 +927,31 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
     }
 
     // Execute the transaction
-    println!("{}", "Processing packages...".cyan());
-    println!();
+    status!("{}", "Processing packages...".cyan());
+    status!();
 
     // Re-open database for transaction (use sysroot-relative path)
     let db = Database::open(&config.database_path())?;
 
-    match builder.execute_with_hooks(db, &config.hooks) {
+    match builder.execute_with_hooks(db, &config.hooks, &config.snapshot) {
         Ok((pre_results, post_results)) => {
             print_hook_results("pre-transaction", &pre_results);
 
             if !fresh_installs.is_empty() && !upgrades.is_empty() {
-                println!(
+                status!(
                     "{} {} package(s) installed, {} upgraded",
                     "✓".green().bold(),
                     fresh_installs.len(),
                     upgrades.len()
                 );
             } else if !fresh_installs.is_empty() {
-                println!(
+                status!(
                     "{} {} package(s) installed successfully",
                     "✓".green().bold(),
                     fresh_installs.len()
                 );
             } else {
-                println!(
+                status!(
                     "{} {} package(s) upgraded successfully",
                     "✓".green().bold(),
                     upgrades.len()
@@ -791,7 +961,7 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
             print_hook_results("post-transaction", &post_results);
         }
         Err(e) => {
-            println!(
+            status!(
                 "{} Operation failed: {}",
                 "✗".red().bold(),
                 e
@@ -800,8 +970,8 @@ fn run_local(packages: &[String], dry_run: bool, force: bool, config: &Config) -
         }
     }
 
-    println!();
-    println!("{}", "Done!".green());
+    status!();
+    status!("{}", "Done!".green());
 
     Ok(())
 }
@@ -5,8 +5,8 @@ use std::path::Path;
 use anyhow::Result;
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::signing;
+use rookpkg::config::Config;
+use rookpkg::signing;
 
 pub fn run(name: &str, email: &str, output: Option<&Path>, config: &Config) -> Result<()> {
     println!("{}", "Generating hybrid Ed25519 + ML-DSA-65 signing key...".cyan());
@@ -1,66 +1,78 @@
 //! Key management CLI commands
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::signing::{self, KeyCertification, TrustLevel};
+use rookpkg::agent::AgentSigner;
+use rookpkg::config::Config;
+use rookpkg::database::RevokedKey;
+use rookpkg::repository;
+use rookpkg::signing::{self, KeyCertification, TrustLevel};
 
-/// List all trusted signing keys
-pub fn list_keys(config: &Config) -> Result<()> {
-    println!("{}", "Trusted signing keys:".bold());
-    println!();
+use super::{print_json, OutputFormat};
 
-    let mut found_keys = false;
+/// `keylist --output json`
+#[derive(Serialize)]
+struct KeyList {
+    keys: Vec<KeyJson>,
+    revoked: Vec<RevokedKey>,
+}
+
+#[derive(Serialize)]
+struct KeyJson {
+    fingerprint: String,
+    name: String,
+    email: String,
+    algorithm: String,
+    /// "unknown", "marginal", "full" or "ultimate"
+    trust: &'static str,
+    /// "master" or "packager"
+    role: &'static str,
+    path: PathBuf,
+}
 
-    // List master keys
+/// List all trusted signing keys
+pub fn list_keys(output: OutputFormat, config: &Config) -> Result<()> {
     let master_dir = &config.signing.master_keys_dir;
-    if master_dir.exists() {
-        for entry in fs::read_dir(master_dir)
-            .with_context(|| format!("Failed to read master keys dir: {}", master_dir.display()))?
-        {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map(|e| e == "pub").unwrap_or(false) {
-                match signing::load_public_key(&path) {
-                    Ok(key) => {
-                        found_keys = true;
-                        print_key_info(&key, "master");
-                    }
-                    Err(e) => {
-                        eprintln!("  {} {} - {}", "!".yellow(), path.display(), e);
-                    }
-                }
-            }
-        }
+    let packager_dir = &config.signing.packager_keys_dir;
+    let mut keys = load_keys_in(master_dir, "master")?;
+    keys.extend(load_keys_in(packager_dir, "packager")?);
+    let revoked = repository::load_revoked_keys(config)?;
+
+    if output == OutputFormat::Json {
+        let keys = keys
+            .into_iter()
+            .map(|(path, key, role)| KeyJson {
+                trust: match key.trust_level {
+                    TrustLevel::Ultimate => "ultimate",
+                    TrustLevel::Full => "full",
+                    TrustLevel::Marginal => "marginal",
+                    TrustLevel::Unknown => "unknown",
+                },
+                algorithm: key.algorithm.to_string(),
+                fingerprint: key.fingerprint,
+                name: key.name,
+                email: key.email,
+                role,
+                path,
+            })
+            .collect();
+        return print_json(&KeyList { keys, revoked });
     }
 
-    // List packager keys
-    let packager_dir = &config.signing.packager_keys_dir;
-    if packager_dir.exists() {
-        for entry in fs::read_dir(packager_dir)
-            .with_context(|| format!("Failed to read packager keys dir: {}", packager_dir.display()))?
-        {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().map(|e| e == "pub").unwrap_or(false) {
-                match signing::load_public_key(&path) {
-                    Ok(key) => {
-                        found_keys = true;
-                        print_key_info(&key, "packager");
-                    }
-                    Err(e) => {
-                        eprintln!("  {} {} - {}", "!".yellow(), path.display(), e);
-                    }
-                }
-            }
-        }
+    println!("{}", "Trusted signing keys:".bold());
+    println!();
+
+    for (_, key, role) in &keys {
+        print_key_info(key, role);
     }
 
-    if !found_keys {
+    if keys.is_empty() {
         println!("  {}", "(no trusted keys found)".dimmed());
         println!();
         println!("{}", "Hint:".cyan().bold());
@@ -69,9 +81,48 @@ pub fn list_keys(config: &Config) -> Result<()> {
         println!("  Packager keys are stored in: {}", packager_dir.display());
     }
 
+    if !revoked.is_empty() {
+        println!("{}", "Revoked keys:".bold());
+        println!();
+        for key in &revoked {
+            let since = DateTime::from_timestamp(key.revoked_date, 0)
+                .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            println!("  {} [{}]", key.fingerprint.red(), since);
+            println!("    {}", key.reason);
+            println!("    Revoked by: {}", key.revoked_by.dimmed());
+            println!();
+        }
+    }
+
     Ok(())
 }
 
+/// Load the public keys in a key directory, warning about unreadable ones
+fn load_keys_in(dir: &Path, role: &'static str) -> Result<Vec<(PathBuf, signing::LoadedPublicKey, &'static str)>> {
+    let mut keys = Vec::new();
+    if !dir.exists() {
+        return Ok(keys);
+    }
+
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {} keys dir: {}", role, dir.display()))?
+    {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().map(|e| e == "pub").unwrap_or(false) {
+            match signing::load_public_key(&path) {
+                Ok(key) => keys.push((path, key, role)),
+                Err(e) => {
+                    eprintln!("  {} {} - {}", "!".yellow(), path.display(), e);
+                }
+            }
+        }
+    }
+
+    Ok(keys)
+}
+
 /// Print information about a key
 fn print_key_info(key: &signing::LoadedPublicKey, key_type: &str) {
     let trust_color = match key.trust_level {
@@ -239,13 +290,14 @@ fn remove_key_by_fingerprint(dir: &Path, fingerprint: &str) -> Result<bool> {
 /// by the project master key.
 pub fn sign_key(
     key_to_sign: &str,
-    master_key_path: &str,
+    master_key_path: Option<&Path>,
+    agent_socket: Option<&Path>,
     purpose: Option<&str>,
+    expires_days: u32,
     output: Option<&Path>,
     config: &Config,
 ) -> Result<()> {
     let key_path = Path::new(key_to_sign);
-    let master_path = Path::new(master_key_path);
 
     // Load the key to be signed
     if !key_path.exists() {
@@ -261,22 +313,37 @@ pub fn sign_key(
     println!("  Algorithm: {}", public_key.algorithm);
     println!();
 
-    // Load the master signing key
-    if !master_path.exists() {
-        bail!("Master key not found: {}", master_key_path);
-    }
-
-    let master_key = signing::load_signing_key_from_path(master_path)
-        .with_context(|| format!("Failed to load master key: {}", master_path.display()))?;
+    // Load the master signing key, or let the agent holding it sign
+    let master_key: Box<dyn signing::Signer> = match (master_key_path, agent_socket) {
+        (_, Some(socket)) => Box::new(
+            AgentSigner::connect(socket)
+                .with_context(|| format!("Failed to reach signing agent: {}", socket.display()))?,
+        ),
+        (Some(master_path), None) => {
+            if !master_path.exists() {
+                bail!("Master key not found: {}", master_path.display());
+            }
+            Box::new(
+                signing::load_signing_key_from_path(master_path)
+                    .with_context(|| format!("Failed to load master key: {}", master_path.display()))?,
+            )
+        }
+        (None, None) => bail!("Either --master or --agent is required"),
+    };
 
     println!("{}", "Certifying with:".bold());
-    println!("  Fingerprint: {}", master_key.fingerprint.cyan());
-    println!("  Name: {} <{}>", master_key.name, master_key.email);
+    println!("  Fingerprint: {}", master_key.fingerprint().cyan());
+    println!("  Name: {} <{}>", master_key.name(), master_key.email());
+    if let Some(socket) = agent_socket {
+        println!("  Agent: {}", socket.display());
+    }
     println!();
 
     // Create the certification
     let purpose = purpose.unwrap_or("packager");
-    let certification = signing::certify_key(&master_key, &public_key, purpose, None)?;
+    let expires = (expires_days > 0)
+        .then(|| (Utc::now() + Duration::days(i64::from(expires_days))).to_rfc3339());
+    let certification = signing::certify_key(&master_key, &public_key, purpose, expires.as_deref())?;
 
     // Determine output path
     let cert_path = if let Some(out) = output {
@@ -304,6 +371,9 @@ pub fn sign_key(
     println!("  Certifier: {}", certification.certifier_name);
     println!("  Purpose: {}", certification.purpose);
     println!("  Timestamp: {}", certification.signature.timestamp);
+    if !certification.expires.is_empty() {
+        println!("  Expires: {}", certification.expires);
+    }
 
     Ok(())
 }
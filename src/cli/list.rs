@@ -2,18 +2,108 @@
 
 use anyhow::Result;
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::package::AvailablePackage;
-use crate::repository::RepoManager;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::package::{AvailablePackage, InstallReason};
+use rookpkg::repository::RepoManager;
 
-pub fn run(available: bool, filter: Option<&str>, all_versions: bool, config: &Config) -> Result<()> {
-    if available {
-        list_available(filter, all_versions, config)
+use super::{print_json, OutputFormat};
+
+/// `list --output json`
+#[derive(Serialize)]
+struct InstalledList {
+    packages: Vec<InstalledJson>,
+}
+
+#[derive(Serialize)]
+struct InstalledJson {
+    name: String,
+    version: String,
+    release: u32,
+    size_bytes: u64,
+    /// Unix timestamp
+    install_date: i64,
+    install_reason: InstallReason,
+}
+
+/// `list --available --output json`
+#[derive(Serialize)]
+struct AvailableList {
+    packages: Vec<AvailableJson>,
+}
+
+#[derive(Serialize)]
+struct AvailableJson {
+    name: String,
+    version: String,
+    release: u32,
+    repository: String,
+    arch: String,
+    /// Size of the package file in bytes
+    size: u64,
+    description: String,
+}
+
+pub fn run(available: bool, filter: Option<&str>, all_versions: bool, output: OutputFormat, config: &Config) -> Result<()> {
+    match (available, output) {
+        (false, OutputFormat::Json) => list_installed_json(filter, config),
+        (false, OutputFormat::Text) => list_installed(filter, config),
+        (true, OutputFormat::Json) => list_available_json(filter, config),
+        (true, OutputFormat::Text) => list_available(filter, all_versions, config),
+    }
+}
+
+fn list_installed_json(filter: Option<&str>, config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    let packages = if db_path.exists() {
+        Database::open(&db_path)?.list_packages()?
     } else {
-        list_installed(filter, config)
+        Vec::new()
+    };
+
+    let packages = packages
+        .into_iter()
+        .filter(|p| filter.is_none_or(|pattern| p.name.contains(pattern)))
+        .map(|p| InstalledJson {
+            name: p.name,
+            version: p.version,
+            release: p.release,
+            size_bytes: p.size_bytes,
+            install_date: p.install_date,
+            install_reason: p.install_reason,
+        })
+        .collect();
+
+    print_json(&InstalledList { packages })
+}
+
+fn list_available_json(filter: Option<&str>, config: &Config) -> Result<()> {
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+
+    let mut packages = Vec::new();
+    for repo in manager.enabled_repos() {
+        let Some(ref index) = repo.index else { continue };
+        for pkg in &index.packages {
+            if filter.is_some_and(|pattern| !pkg.name.contains(pattern) && !pkg.description.contains(pattern)) {
+                continue;
+            }
+            packages.push(AvailableJson {
+                name: pkg.name.clone(),
+                version: pkg.version.clone(),
+                release: pkg.release,
+                repository: repo.name.clone(),
+                arch: pkg.arch.clone(),
+                size: pkg.size,
+                description: pkg.description.clone(),
+            });
+        }
     }
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    print_json(&AvailableList { packages })
 }
 
 fn list_installed(filter: Option<&str>, config: &Config) -> Result<()> {
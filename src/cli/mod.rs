@@ -1,10 +1,14 @@
 //! Command-line interface for rookpkg
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use anyhow::{bail, Result};
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
+use rookpkg::config::Config;
+use rookpkg::database::HistoryOperation;
 
 /// Check if the current user is root (UID 0)
 fn is_root() -> bool {
@@ -39,6 +43,100 @@ fn require_root(operation: &str, dry_run: bool) -> Result<()> {
     );
 }
 
+/// Output format of query commands and dry runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable, coloured text
+    #[default]
+    Text,
+    /// One JSON document on stdout (schemas are listed in the README)
+    Json,
+}
+
+/// Whether a JSON document is being printed, see `status!`
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Print progress and human-readable results
+///
+/// Goes to stdout normally, but to stderr while `--output json` (or a
+/// command's `--json`) is in effect, so stdout carries nothing but the JSON
+/// document. Used by the commands whose dry runs print a planned
+/// transaction or that report resolution failures as JSON, and by
+/// `inspect --validate`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::cli::json_output() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Like `status!`, but without ending the line, for progress that a later
+/// `status!` completes
+macro_rules! status_part {
+    ($($arg:tt)*) => {
+        if $crate::cli::json_output() {
+            eprint!($($arg)*);
+        } else {
+            print!($($arg)*);
+        }
+    };
+}
+
+fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Print a JSON document to stdout
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Transaction a dry run of install, remove or upgrade would carry out,
+/// printed as the JSON document of `--dry-run --output json`
+#[derive(Default, Serialize)]
+struct DryRunPlan {
+    /// Package operations, in the order they would be applied
+    operations: Vec<PlannedOperation>,
+    /// Bytes to download (0 for removals and local packages)
+    download_size: u64,
+    /// Packages the upgrade leaves below their newest version
+    held_back: Vec<PlannedHold>,
+}
+
+impl DryRunPlan {
+    /// Print the plan if this is a dry run with `--output json`
+    fn print_if_requested(&self, dry_run: bool) -> Result<()> {
+        if dry_run && json_output() {
+            print_json(self)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct PlannedOperation {
+    /// action, package, old_version, new_version and install_reason, as
+    /// the transaction history would record them
+    #[serde(flatten)]
+    operation: HistoryOperation,
+    /// Repository the new version comes from, if any
+    repository: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlannedHold {
+    name: String,
+    /// Version that stays installed
+    selected: String,
+    /// Newest version a repository offers
+    newest: String,
+    reason: String,
+}
+
 mod audit;
 mod autoremove;
 mod build;
@@ -48,7 +146,9 @@ mod checksum;
 mod convert;
 mod delta;
 mod depends;
+mod downgrade;
 mod groups;
+mod history;
 mod hold;
 mod hooks;
 mod info;
@@ -57,10 +157,12 @@ mod install;
 mod keygen;
 mod keys;
 mod list;
+mod rebuild;
 mod recover;
 mod remove;
 mod repo;
 mod search;
+mod snapshot;
 mod update;
 mod upgrade;
 mod verify;
@@ -69,7 +171,8 @@ mod verify;
 pub enum Commands {
     /// Install a package
     Install {
-        /// Package name(s) to install, or path(s) to local .rookpkg files with --local
+        /// Package name(s) to install, optionally with a version (foo=1.2.3-4, 'foo<2'),
+        /// or path(s) to local .rookpkg files with --local
         #[arg(required = true)]
         packages: Vec<String>,
 
@@ -89,6 +192,10 @@ pub enum Commands {
         /// Use with caution - primarily for bulk installations like ISO building.
         #[arg(long)]
         force: bool,
+
+        /// Same as --output json
+        #[arg(long)]
+        json: bool,
     },
 
     /// Remove a package
@@ -147,7 +254,7 @@ pub enum Commands {
         install: bool,
 
         /// Output directory for built package
-        #[arg(short, long)]
+        #[arg(short, long = "out")]
         output: Option<std::path::PathBuf>,
 
         /// Run all phases in sequence without detailed progress
@@ -169,16 +276,28 @@ pub enum Commands {
         /// Automatically extract source archives (skip manual prep phase extraction)
         #[arg(long)]
         auto_extract: bool,
+
+        /// Run build phases directly on the host instead of in a sandbox
+        #[arg(long)]
+        no_sandbox: bool,
+
+        /// Install missing build dependencies from the repositories
+        #[arg(short = 's', long)]
+        install_build_deps: bool,
+
+        /// Remove the build dependencies installed by --install-build-deps afterwards
+        #[arg(short = 'r', long, requires = "install_build_deps")]
+        remove_build_deps: bool,
     },
 
-    /// Build all .rook spec files in a directory
+    /// Build all .rook spec files in a directory, dependencies first
     Buildall {
         /// Directory containing .rook spec files
         #[arg(default_value = "specs")]
         spec_dir: std::path::PathBuf,
 
         /// Output directory for built packages
-        #[arg(short, long)]
+        #[arg(short, long = "out")]
         output: Option<std::path::PathBuf>,
 
         /// Continue building remaining packages on failure
@@ -189,6 +308,18 @@ pub enum Commands {
         #[arg(short, long)]
         jobs: Option<usize>,
 
+        /// Number of independent packages to build at the same time
+        #[arg(short, long, default_value_t = 1)]
+        workers: usize,
+
+        /// Install each package after building it, before its dependents are built
+        #[arg(long)]
+        install: bool,
+
+        /// Add each package to the packages.json index in the output directory
+        #[arg(long)]
+        index: bool,
+
         /// Skip packages that already have a .rookpkg file in the output directory
         #[arg(long)]
         skip_built: bool,
@@ -196,6 +327,36 @@ pub enum Commands {
         /// Show real-time build output (stream to terminal)
         #[arg(long)]
         stream: bool,
+
+        /// Run build phases directly on the host instead of in a sandbox
+        #[arg(long)]
+        no_sandbox: bool,
+
+        /// Install missing build dependencies from the repositories
+        #[arg(short = 's', long)]
+        install_build_deps: bool,
+    },
+
+    /// Rebuild a package from the spec recorded in it
+    Rebuild {
+        /// Package file (.rookpkg) or name of a repository package
+        package: String,
+
+        /// Compare the rebuild with the published package instead of keeping it
+        #[arg(long)]
+        verify: bool,
+
+        /// Output directory for the rebuilt package
+        #[arg(short, long = "out")]
+        output: Option<std::path::PathBuf>,
+
+        /// Number of parallel jobs for compilation
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Run build phases directly on the host instead of in a sandbox
+        #[arg(long)]
+        no_sandbox: bool,
     },
 
     /// Generate a new signing key
@@ -209,7 +370,7 @@ pub enum Commands {
         email: String,
 
         /// Output directory for keys
-        #[arg(long)]
+        #[arg(long = "out")]
         output: Option<std::path::PathBuf>,
     },
 
@@ -237,15 +398,23 @@ pub enum Commands {
         key: std::path::PathBuf,
 
         /// Path to master signing key (secret key)
-        #[arg(long)]
-        master: std::path::PathBuf,
+        #[arg(long, required_unless_present = "agent")]
+        master: Option<std::path::PathBuf>,
+
+        /// Certify through the signing agent holding the master key
+        #[arg(long, conflicts_with = "master", value_name = "SOCKET")]
+        agent: Option<std::path::PathBuf>,
 
         /// Certification purpose (default: "packager")
         #[arg(long)]
         purpose: Option<String>,
 
+        /// Days until the certification expires (0 for never)
+        #[arg(long, default_value_t = 365)]
+        expires_days: u32,
+
         /// Output path for certification file
-        #[arg(short, long)]
+        #[arg(short, long = "out")]
         output: Option<std::path::PathBuf>,
     },
 
@@ -270,6 +439,26 @@ pub enum Commands {
         /// Don't actually upgrade, just show what would happen
         #[arg(long)]
         dry_run: bool,
+
+        /// Same as --output json
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Downgrade installed packages to an older build
+    Downgrade {
+        /// Package(s) to downgrade, optionally with a version
+        /// (e.g. foo, foo=1.2.3-4, 'foo<2'); without one, the previous build is used
+        #[arg(required = true)]
+        packages: Vec<String>,
+
+        /// Don't actually downgrade, just show what would happen
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Same as --output json
+        #[arg(long)]
+        json: bool,
     },
 
     /// Audit installed packages for security vulnerabilities (CVE)
@@ -426,6 +615,14 @@ pub enum Commands {
     #[command(subcommand)]
     Hook(HookCommands),
 
+    /// Show, undo and redo past transactions
+    #[command(subcommand)]
+    History(HistoryCommands),
+
+    /// List filesystem snapshots and roll back to them
+    #[command(subcommand)]
+    Snapshot(SnapshotCommands),
+
     /// Convert packages from other distributions
     #[command(subcommand)]
     Convert(ConvertCommands),
@@ -445,7 +642,7 @@ pub enum ConvertCommands {
         all: bool,
 
         /// Output directory for converted .rook files
-        #[arg(short, long)]
+        #[arg(short, long = "out")]
         output: Option<std::path::PathBuf>,
     },
 }
@@ -480,6 +677,36 @@ pub enum RepoCommands {
         #[arg(default_value = ".")]
         path: std::path::PathBuf,
     },
+
+    /// Add a key to the repository's signed revocation list
+    Revoke {
+        /// Fingerprint of the key to revoke
+        fingerprint: String,
+
+        /// Path to repository directory (default: current directory)
+        #[arg(default_value = ".")]
+        path: std::path::PathBuf,
+
+        /// Why the key is revoked
+        #[arg(long)]
+        reason: String,
+
+        /// Refuse signatures made since this time (RFC 3339, default: now);
+        /// for a stolen key, use a time before it was stolen
+        #[arg(long)]
+        since: Option<String>,
+    },
+
+    /// Hand the repository over to a new signing key
+    Rotate {
+        /// Path to the new secret key (its .pub file must be next to it)
+        #[arg(long)]
+        new_key: std::path::PathBuf,
+
+        /// Path to repository directory (default: current directory)
+        #[arg(default_value = ".")]
+        path: std::path::PathBuf,
+    },
 }
 
 /// Delta package subcommands
@@ -496,7 +723,7 @@ pub enum DeltaCommands {
         new: std::path::PathBuf,
 
         /// Output directory for delta file
-        #[arg(short, long, default_value = ".")]
+        #[arg(short, long = "out", default_value = ".")]
         output: std::path::PathBuf,
     },
 
@@ -511,7 +738,7 @@ pub enum DeltaCommands {
         delta: std::path::PathBuf,
 
         /// Output directory for new package
-        #[arg(short, long, default_value = ".")]
+        #[arg(short, long = "out", default_value = ".")]
         output: std::path::PathBuf,
     },
 
@@ -552,38 +779,117 @@ pub enum HookCommands {
     },
 }
 
+/// Transaction history subcommands
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// List recorded transactions, newest first
+    List {
+        /// Maximum number of transactions to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Show the package changes of a transaction
+    Info {
+        /// Transaction ID from `history list`
+        id: i64,
+    },
+
+    /// Revert a transaction
+    Undo {
+        /// Transaction ID from `history list`
+        id: i64,
+
+        /// Don't actually change anything, just show what would happen
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Apply an undone transaction again
+    Redo {
+        /// Transaction ID from `history list`
+        id: i64,
+
+        /// Don't actually change anything, just show what would happen
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Snapshot subcommands
+#[derive(Subcommand)]
+pub enum SnapshotCommands {
+    /// List snapshots taken around transactions, newest first
+    List,
+
+    /// Restore the system to a snapshot
+    Rollback {
+        /// Snapshot ID from `snapshot list`
+        id: String,
+
+        /// Don't actually change anything, just show what would happen
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 /// Execute a CLI command
-pub fn execute(command: Commands, config: &Config) -> Result<()> {
+pub fn execute(command: Commands, output: OutputFormat, config: &Config) -> Result<()> {
+    // A command's --json is the same as --output json
+    let output = match &command {
+        Commands::Install { json: true, .. }
+        | Commands::Upgrade { json: true, .. }
+        | Commands::Downgrade { json: true, .. }
+        | Commands::Audit { json: true, .. } => OutputFormat::Json,
+        _ => output,
+    };
+    JSON_OUTPUT.store(output == OutputFormat::Json, Ordering::Relaxed);
+
     match command {
-        Commands::Install { packages, local, dry_run, download_only, force } => {
+        Commands::Install { packages, local, dry_run, download_only, force, json: _ } => {
             // download_only doesn't need root since it only caches packages
             require_root("install", dry_run || download_only)?;
-            install::run(&packages, local, dry_run, download_only, force, config)
+            install::run(&packages, local, dry_run, download_only, force, output == OutputFormat::Json, config)
         }
         Commands::Remove { packages, cascade, dry_run } => {
             require_root("remove", dry_run)?;
             remove::run(&packages, cascade, dry_run, config)
         }
         Commands::List { available, filter, all_versions } => {
-            list::run(available, filter.as_deref(), all_versions, config)
+            list::run(available, filter.as_deref(), all_versions, output, config)
         }
         Commands::Info { package, deps } => {
-            info::run(&package, deps, config)
+            info::run(&package, deps, output, config)
         }
         Commands::Search { query } => {
-            search::run(&query, config)
+            search::run(&query, output, config)
         }
-        Commands::Build { spec, install, output, batch, index, delta_from, jobs, auto_extract } => {
-            build::run(&spec, install, output.as_deref(), batch, index, delta_from.as_deref(), jobs, auto_extract, config)
+        Commands::Build {
+            spec, install, output, batch, index, delta_from, jobs, auto_extract, no_sandbox,
+            install_build_deps, remove_build_deps,
+        } => {
+            build::run(
+                &spec, install, output.as_deref(), batch, index, delta_from.as_deref(), jobs,
+                auto_extract, !no_sandbox, install_build_deps, remove_build_deps, config,
+            )
+        }
+        Commands::Buildall {
+            spec_dir, output, continue_on_error, jobs, workers, install, index, skip_built, stream,
+            no_sandbox, install_build_deps,
+        } => {
+            buildall::run(
+                &spec_dir, output.as_deref(), continue_on_error, jobs, workers, skip_built, stream,
+                !no_sandbox, install_build_deps, install, index, config,
+            )
         }
-        Commands::Buildall { spec_dir, output, continue_on_error, jobs, skip_built, stream } => {
-            buildall::run(&spec_dir, output.as_deref(), continue_on_error, jobs, skip_built, stream, config)
+        Commands::Rebuild { package, verify, output, jobs, no_sandbox } => {
+            rebuild::run(&package, verify, output.as_deref(), jobs, !no_sandbox, config)
         }
         Commands::Keygen { name, email, output } => {
             keygen::run(&name, &email, output.as_deref(), config)
         }
         Commands::Keylist => {
-            keys::list_keys(config)
+            keys::list_keys(output, config)
         }
         Commands::KeyTrust { key } => {
             require_root("keytrust", false)?;  // modifies system keyring
@@ -593,11 +899,13 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
             require_root("keyuntrust", false)?;  // modifies system keyring
             keys::untrust_key(&fingerprint, config)
         }
-        Commands::KeySign { key, master, purpose, output } => {
+        Commands::KeySign { key, master, agent, purpose, expires_days, output } => {
             keys::sign_key(
                 key.to_str().unwrap_or(""),
-                master.to_str().unwrap_or(""),
+                master.as_deref(),
+                agent.as_deref(),
                 purpose.as_deref(),
+                expires_days,
                 output.as_deref(),
                 config,
             )
@@ -611,22 +919,26 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
         Commands::Update => {
             update::run(config)
         }
-        Commands::Upgrade { dry_run } => {
+        Commands::Upgrade { dry_run, json: _ } => {
             require_root("upgrade", dry_run)?;
-            upgrade::run(dry_run, config)
+            upgrade::run(dry_run, output == OutputFormat::Json, config)
         }
-        Commands::Audit { fix, json, package, cve, clear_cache } => {
+        Commands::Downgrade { packages, dry_run, json: _ } => {
+            require_root("downgrade", dry_run)?;
+            downgrade::run(&packages, dry_run, output == OutputFormat::Json, config)
+        }
+        Commands::Audit { fix, json: _, package, cve, clear_cache } => {
             // fix mode needs root to modify spec files and rebuild
             if fix {
                 require_root("audit --fix", false)?;
             }
-            audit::run(fix, json, package.as_deref(), cve.as_deref(), clear_cache, config)
+            audit::run(fix, output == OutputFormat::Json, package.as_deref(), cve.as_deref(), clear_cache, config)
         }
         Commands::Depends { package, reverse } => {
-            depends::run(&package, reverse, config)
+            depends::run(&package, reverse, output, config)
         }
         Commands::Check { package } => {
-            check::run(package.as_deref(), config)
+            check::run(package.as_deref(), output, config)
         }
         Commands::Checksum { path, update, all, continue_on_error } => {
             if all {
@@ -637,8 +949,8 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
         }
         Commands::Clean { all } => {
             require_root("clean", false)?;  // clean always modifies system cache
-            use crate::download::Downloader;
-            use crate::repository::RepoManager;
+            use rookpkg::download::Downloader;
+            use rookpkg::repository::RepoManager;
 
             let manager = RepoManager::new(config)?;
 
@@ -709,7 +1021,7 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
             Ok(())
         }
         Commands::Groups { group } => {
-            groups::run(group.as_deref(), config)
+            groups::run(group.as_deref(), output, config)
         }
         Commands::Recover { transaction_id } => {
             require_root("recover", false)?;  // recover modifies system state
@@ -725,9 +1037,9 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
         }
         Commands::Holds { package } => {
             if let Some(name) = package {
-                hold::show_hold(&name, config)
+                hold::show_hold(&name, output, config)
             } else {
-                hold::list_holds(config)
+                hold::list_holds(output, config)
             }
         }
         Commands::Autoremove { dry_run } => {
@@ -743,7 +1055,7 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
             autoremove::mark_dependency(&packages, config)
         }
         Commands::Inspect { path, files, scripts, validate } => {
-            inspect::run(&path, files, scripts, validate, config)
+            inspect::run(&path, files, scripts, validate, output, config)
         }
         Commands::Repo(subcmd) => {
             match subcmd {
@@ -756,6 +1068,12 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
                 RepoCommands::Sign { path } => {
                     repo::sign(&path, config)
                 }
+                RepoCommands::Revoke { fingerprint, path, reason, since } => {
+                    repo::revoke(&path, &fingerprint, &reason, since.as_deref(), config)
+                }
+                RepoCommands::Rotate { new_key, path } => {
+                    repo::rotate(&path, &new_key, config)
+                }
             }
         }
         Commands::Delta(subcmd) => {
@@ -767,7 +1085,7 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
                     delta::apply(&old, &delta_file, &output, config)
                 }
                 DeltaCommands::Info { delta: delta_file } => {
-                    delta::info(&delta_file, config)
+                    delta::info(&delta_file, output, config)
                 }
                 DeltaCommands::Index { path } => {
                     delta::index(&path, config)
@@ -789,6 +1107,33 @@ pub fn execute(command: Commands, config: &Config) -> Result<()> {
                 }
             }
         }
+        Commands::History(subcmd) => {
+            match subcmd {
+                HistoryCommands::List { limit } => {
+                    history::list(limit, config)
+                }
+                HistoryCommands::Info { id } => {
+                    history::info(id, config)
+                }
+                HistoryCommands::Undo { id, dry_run } => {
+                    require_root("history undo", dry_run)?;
+                    history::undo(id, dry_run, config)
+                }
+                HistoryCommands::Redo { id, dry_run } => {
+                    require_root("history redo", dry_run)?;
+                    history::redo(id, dry_run, config)
+                }
+            }
+        }
+        Commands::Snapshot(subcmd) => {
+            match subcmd {
+                SnapshotCommands::List => snapshot::list(config),
+                SnapshotCommands::Rollback { id, dry_run } => {
+                    require_root("snapshot rollback", dry_run)?;
+                    snapshot::rollback(&id, dry_run, config)
+                }
+            }
+        }
         Commands::Convert(subcmd) => {
             match subcmd {
                 ConvertCommands::Arch { package, all, output } => {
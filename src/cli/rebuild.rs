@@ -0,0 +1,175 @@
+//! Rebuild command implementation - rebuild a package from its recorded spec
+//!
+//! Every package records the spec it was built from in .PKGINFO. With
+//! `--verify` the rebuild is compared member by member against the
+//! published archive, which only matches if the build is reproducible.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use rookpkg::archive::{PackageArchiveBuilder, PackageArchiveReader, PackageDigests, PKG_EXTENSION};
+use rookpkg::config::Config;
+use rookpkg::repository::RepoManager;
+use rookpkg::signing;
+use rookpkg::spec::PackageSpec;
+
+/// Rebuild a package archive or repository package from its recorded spec
+pub fn run(
+    package: &str,
+    verify: bool,
+    output: Option<&Path>,
+    jobs: Option<usize>,
+    sandbox: bool,
+    config: &Config,
+) -> Result<()> {
+    let published = locate_package(package, config)?;
+    let reader = PackageArchiveReader::open(&published)?;
+    let info = reader.read_info()?;
+
+    if info.spec.is_empty() {
+        bail!(
+            "{} does not record the spec it was built from; rebuild it with a newer rookpkg first",
+            published.display()
+        );
+    }
+    if info.arch != std::env::consts::ARCH {
+        bail!(
+            "{} was built for {}, this host is {}",
+            info.filename(),
+            info.arch,
+            std::env::consts::ARCH
+        );
+    }
+    let spec = PackageSpec::from_str(&info.spec)
+        .with_context(|| format!("Failed to parse the spec recorded in {}", published.display()))?;
+
+    println!(
+        "{} {}-{}",
+        "Rebuilding".cyan(),
+        spec.package.name.bold(),
+        spec.full_version()
+    );
+    match spec.source_date_epoch() {
+        Some(epoch) => println!("  {} SOURCE_DATE_EPOCH={}", "→".cyan(), epoch),
+        None => println!(
+            "  {} The spec has no changelog date; timestamps will not be reproducible",
+            "!".yellow()
+        ),
+    }
+
+    // Only the spec is recorded, so local patch files cannot be found
+    if spec.patches.iter().any(|p| p.url.is_none()) {
+        println!(
+            "  {} The spec applies local patch files, which are not recorded in the package",
+            "!".yellow()
+        );
+    }
+
+    super::build::ensure_build_deps(&spec, false, config)?;
+    let build_env = super::buildall::run_build_phases(None, spec.clone(), false, sandbox, jobs, config)?;
+
+    let mut archive_builder = PackageArchiveBuilder::new(&spec, build_env.dest_dir());
+    archive_builder.scan_files()?;
+
+    if !verify {
+        let signing_key = signing::load_signer(config).context("Signing key required")?;
+        let output_dir = output.unwrap_or(Path::new("."));
+        let package_path = archive_builder.build_signed(output_dir, signing_key.as_ref())?;
+        build_env.clean()?;
+        println!("  {} Package: {}", "✓".green(), package_path.display());
+        return Ok(());
+    }
+
+    // The signature is made with a different key (or randomized), so only
+    // the signed-over members are compared
+    let temp_dir = tempfile::tempdir()?;
+    let rebuilt = archive_builder.build(temp_dir.path())?;
+    build_env.clean()?;
+    let rebuilt_reader = PackageArchiveReader::open(&rebuilt)?;
+
+    let differing = differing_members(reader.digests(), rebuilt_reader.digests());
+    println!();
+    for (member, published_digest, rebuilt_digest) in member_digests(reader.digests(), rebuilt_reader.digests()) {
+        if published_digest == rebuilt_digest {
+            println!("  {} {:<14} {}", "✓".green(), member, published_digest.dimmed());
+        } else {
+            println!("  {} {:<14} differs", "✗".red(), member);
+            println!("      published: {}", published_digest);
+            println!("      rebuilt:   {}", rebuilt_digest);
+        }
+    }
+    println!();
+
+    if !differing.is_empty() {
+        if let Some(output_dir) = output {
+            std::fs::create_dir_all(output_dir)?;
+            let kept = output_dir.join(info.filename());
+            std::fs::copy(&rebuilt, &kept)?;
+            println!("  {} Rebuilt package kept at {}", "→".cyan(), kept.display());
+        }
+        bail!(
+            "{} is not reproducible: {} differ",
+            info.filename(),
+            differing.join(", ")
+        );
+    }
+
+    println!(
+        "{} {} is reproducible",
+        "✓".green().bold(),
+        info.filename()
+    );
+    Ok(())
+}
+
+/// A local .rookpkg file, or the newest repository build of a package name
+fn locate_package(package: &str, config: &Config) -> Result<PathBuf> {
+    let path = Path::new(package);
+    if package.ends_with(PKG_EXTENSION) || path.exists() {
+        if !path.exists() {
+            bail!("Package file not found: {}", path.display());
+        }
+        return Ok(path.to_path_buf());
+    }
+
+    let mut manager = RepoManager::new(config)?;
+    manager.load_caches()?;
+    let found = manager
+        .find_package(package)
+        .with_context(|| format!("Package '{}' not found in any repository", package))?;
+    println!(
+        "  {} {}-{}-{} from {}",
+        "↓".cyan(),
+        found.package.name,
+        found.package.version,
+        found.package.release,
+        found.repository
+    );
+    let verified = manager.download_and_verify_package(&found.package, &found.repository, config)?;
+    Ok(verified.path)
+}
+
+/// The signed-over members with their published and rebuilt digests
+fn member_digests<'a>(
+    published: &'a PackageDigests,
+    rebuilt: &'a PackageDigests,
+) -> [(&'static str, &'a str, &'a str); 4] {
+    [
+        (".PKGINFO", &published.pkginfo, &rebuilt.pkginfo),
+        (".FILES", &published.files, &rebuilt.files),
+        (".INSTALL", &published.install, &rebuilt.install),
+        ("data.tar.zst", &published.data, &rebuilt.data),
+    ]
+}
+
+/// Names of the members whose digests differ
+fn differing_members(published: &PackageDigests, rebuilt: &PackageDigests) -> Vec<&'static str> {
+    member_digests(published, rebuilt)
+        .into_iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(member, _, _)| member)
+        .collect()
+}
+
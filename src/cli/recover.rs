@@ -7,9 +7,9 @@ use std::path::Path;
 use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::transaction::{Transaction, TransactionState};
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::transaction::{Transaction, TransactionState};
 
 /// List or resume incomplete transactions
 pub fn run(resume_id: Option<&str>, config: &Config) -> Result<()> {
@@ -3,15 +3,17 @@
 use anyhow::{bail, Result};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::hooks::HookResult;
-use crate::transaction::{Operation, TransactionBuilder};
+use rookpkg::config::Config;
+use rookpkg::database::{Database, HistoryAction, HistoryOperation};
+use rookpkg::hooks::HookResult;
+use rookpkg::transaction::{Operation, TransactionBuilder};
+
+use super::{DryRunPlan, PlannedOperation};
 
 pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -> Result<()> {
     if dry_run {
-        println!("{}", "Dry run mode - no changes will be made".yellow());
-        println!();
+        status!("{}", "Dry run mode - no changes will be made".yellow());
+        status!();
     }
 
     // Open database (use sysroot-relative path)
@@ -19,8 +21,8 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
     let db = if db_path.exists() {
         Database::open(&db_path)?
     } else {
-        println!("{}", "No packages installed yet.".yellow());
-        return Ok(());
+        status!("{}", "No packages installed yet.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     };
 
     // Check which packages are installed
@@ -47,46 +49,46 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
 
     // Report not installed packages
     if !not_installed.is_empty() {
-        println!("{}", "Some packages are not installed:".yellow());
+        status!("{}", "Some packages are not installed:".yellow());
         for name in &not_installed {
-            println!("  {} {}", "!".yellow(), name);
+            status!("  {} {}", "!".yellow(), name);
         }
-        println!();
+        status!();
     }
 
     // Report blocked packages
     if !blocked.is_empty() {
-        println!("{}", "Some packages cannot be removed due to dependencies:".red());
+        status!("{}", "Some packages cannot be removed due to dependencies:".red());
         for (name, rdeps) in &blocked {
-            println!(
+            status!(
                 "  {} {} is required by: {}",
                 "✗".red(),
                 name.bold(),
                 rdeps.join(", ")
             );
         }
-        println!();
-        println!(
+        status!();
+        status!(
             "Use {} to remove dependent packages too.",
             "--cascade".bold()
         );
-        println!();
+        status!();
     }
 
     if to_remove.is_empty() {
         if blocked.is_empty() && not_installed.is_empty() {
-            println!("{}", "Nothing to remove.".yellow());
+            status!("{}", "Nothing to remove.".yellow());
         }
-        return Ok(());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
     // Show what will be removed
-    println!("{}", "The following packages will be removed:".cyan());
-    println!();
+    status!("{}", "The following packages will be removed:".cyan());
+    status!();
 
     let mut total_size: u64 = 0;
     for pkg in &to_remove {
-        println!(
+        status!(
             "  {} {}-{}",
             "✗".red(),
             pkg.name.bold(),
@@ -110,7 +112,7 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
                             && !to_remove.iter().any(|p| p.name == rdep)
                         {
                             if let Some(rdep_pkg) = db.get_package(&rdep)? {
-                                println!(
+                                status!(
                                     "  {} {}-{} {}",
                                     "✗".red(),
                                     rdep_pkg.name.bold(),
@@ -129,21 +131,34 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
         to_remove.extend(additional);
     }
 
-    println!();
-    println!(
+    status!();
+    status!(
         "Space to be freed: {}",
         format_size(total_size).green()
     );
-    println!();
+    status!();
 
     if dry_run {
-        println!("{}", "Dry run complete - no packages removed.".yellow());
-        return Ok(());
+        status!("{}", "Dry run complete - no packages removed.".yellow());
+        let operations = to_remove
+            .iter()
+            .map(|pkg| PlannedOperation {
+                operation: HistoryOperation {
+                    action: HistoryAction::Remove,
+                    package: pkg.name.clone(),
+                    old_version: Some(pkg.full_version()),
+                    new_version: None,
+                    install_reason: pkg.install_reason,
+                },
+                repository: None,
+            })
+            .collect();
+        return DryRunPlan { operations, ..Default::default() }.print_if_requested(dry_run);
     }
 
     // Perform removal using TransactionBuilder for cleaner API
-    println!("{}", "Removing packages...".cyan());
-    println!();
+    status!("{}", "Removing packages...".cyan());
+    status!();
 
     // Build remove operations
     let root = config.sysroot();
@@ -166,11 +181,11 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
     // Re-open database for transaction
     let db = Database::open(&db_path)?;
 
-    match builder.execute_with_hooks(db, &config.hooks) {
+    match builder.execute_with_hooks(db, &config.hooks, &config.snapshot) {
         Ok((pre_results, post_results)) => {
             print_hook_results("pre-transaction", &pre_results);
 
-            println!(
+            status!(
                 "{} {} package(s) removed successfully",
                 "✓".green().bold(),
                 to_remove.len()
@@ -179,7 +194,7 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
             print_hook_results("post-transaction", &post_results);
         }
         Err(e) => {
-            println!(
+            status!(
                 "{} Removal failed: {}",
                 "✗".red().bold(),
                 e
@@ -188,8 +203,8 @@ pub fn run(packages: &[String], cascade: bool, dry_run: bool, config: &Config) -
         }
     }
 
-    println!();
-    println!("{}", "Removal complete!".green());
+    status!();
+    status!("{}", "Removal complete!".green());
 
     Ok(())
 }
@@ -204,13 +219,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
     let fail_count = results.len() - success_count;
 
     if fail_count == 0 {
-        println!(
+        status!(
             "  {} {} hook(s) ran successfully",
             "→".cyan(),
             results.len()
         );
     } else {
-        println!(
+        status!(
             "  {} {} {} hook(s): {} succeeded, {} failed",
             "!".yellow(),
             results.len(),
@@ -220,7 +235,7 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
         );
         for result in results {
             if !result.success {
-                println!(
+                status!(
                     "    {} {} (exit code: {:?})",
                     "✗".red(),
                     result.name,
@@ -229,13 +244,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
                 // Show stderr if available
                 if !result.stderr.is_empty() {
                     for line in result.stderr.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
                 // Show stdout if stderr is empty but stdout has content
                 else if !result.stdout.is_empty() {
                     for line in result.stdout.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
             }
@@ -1,17 +1,20 @@
 //! Repository management CLI commands
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
 use chrono::Utc;
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::delta::RepoDeltaIndex;
-use crate::download::compute_sha256;
-use crate::repository::{PackageEntry, PackageGroup, PackageIndex, RepoMetadata, RepoSigningInfo, RepositoryInfo};
-use crate::signing;
+use rookpkg::archive::PackageArchiveReader;
+use rookpkg::config::Config;
+use rookpkg::database::RevokedKey;
+use rookpkg::delta::RepoDeltaIndex;
+use rookpkg::repository::{
+    self, PackageEntry, PackageGroup, PackageIndex, RepoMetadata, RepoSigningInfo, RepositoryInfo,
+};
+use rookpkg::signing::{self, KeyRotation, RevocationEntry, RevocationList, SignatureEnvelope};
 
 /// Initialize a new repository
 pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Result<()> {
@@ -21,7 +24,7 @@ pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Resu
     println!();
 
     // Check for signing key
-    let signing_key = signing::load_signing_key(config)
+    let signing_key = signing::load_signer(config)
         .context("A signing key is required to create a repository")?;
 
     // Create directory structure
@@ -41,10 +44,13 @@ pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Resu
             description: description.to_string(),
             version: 1,
             updated: Some(Utc::now()),
+            index_valid_days: None,
         },
         signing: RepoSigningInfo {
-            fingerprint: signing_key.fingerprint.clone(),
+            fingerprint: signing_key.fingerprint().to_string(),
             public_key: None, // Will be set when public key is added
+            fingerprints: Vec::new(),
+            threshold: None,
         },
         mirrors: Vec::new(),
     };
@@ -54,6 +60,9 @@ pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Resu
 
     println!("  {} Created {}", "✓".green(), repo_toml_path.display());
 
+    let repo_sig_path = write_signature(&signing_key, &repo_toml_path)?;
+    println!("  {} Created {}", "✓".green(), repo_sig_path.display());
+
     // Create empty packages.json
     let index = PackageIndex::new(name);
     let index_path = path.join("packages.json");
@@ -63,10 +72,7 @@ pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Resu
     println!("  {} Created {}", "✓".green(), index_path.display());
 
     // Sign the index
-    let sig_path = path.join("packages.json.sig");
-    let signature = signing::sign_file(&signing_key, &index_path)?;
-    let sig_json = serde_json::to_string_pretty(&signature)?;
-    fs::write(&sig_path, &sig_json)?;
+    let sig_path = write_signature(&signing_key, &index_path)?;
 
     println!("  {} Created {}", "✓".green(), sig_path.display());
 
@@ -76,12 +82,13 @@ pub fn init(path: &Path, name: &str, description: &str, config: &Config) -> Resu
     println!("Structure:");
     println!("  {}/", path.display());
     println!("  ├── repo.toml           # Repository metadata");
+    println!("  ├── repo.toml.sig       # Metadata signature");
     println!("  ├── packages.json       # Package index");
     println!("  ├── packages.json.sig   # Index signature");
     println!("  └── packages/           # Package files");
     println!();
     println!("To add packages:");
-    println!("  rookpkg build <spec.rook> --output {} --index", packages_dir.display());
+    println!("  rookpkg build <spec.rook> --out {} --index", packages_dir.display());
     println!();
     println!("To host the repository:");
     println!("  Serve this directory with any static file server (nginx, Apache, S3, etc.)");
@@ -96,7 +103,7 @@ pub fn refresh(path: &Path, config: &Config) -> Result<()> {
     println!();
 
     // Check for signing key
-    let signing_key = signing::load_signing_key(config)
+    let signing_key = signing::load_signer(config)
         .context("A signing key is required to sign the repository index")?;
 
     // Load repo.toml
@@ -111,6 +118,10 @@ pub fn refresh(path: &Path, config: &Config) -> Result<()> {
 
     let repo_content = fs::read_to_string(&repo_toml_path)?;
     let metadata: RepoMetadata = toml::from_str(&repo_content)?;
+    require_repo_key(&metadata, &signing_key)?;
+
+    // Keys revoked here or by the repository itself sign nothing new
+    let revoked = repo_revocations(path, config)?;
 
     // Scan packages directory
     let packages_dir = path.join("packages");
@@ -134,23 +145,20 @@ pub fn refresh(path: &Path, config: &Config) -> Result<()> {
                 Some(pkg_entry) => {
                     scanned += 1;
 
-                    // Check for signature file
-                    let sig_path = file_path.with_extension("rookpkg.sig");
-                    let sig_status = if sig_path.exists() {
-                        // Verify the signature
-                        match verify_package_signature(&file_path, &sig_path, config) {
-                            Ok(signer) => {
-                                signed += 1;
-                                format!("{} ({})", "✓".green(), signer.dimmed())
-                            }
-                            Err(e) => {
-                                invalid_sig += 1;
-                                format!("{} {}", "✗".red(), e.to_string().dimmed())
-                            }
+                    // Verify the embedded (or detached) signature
+                    let sig_status = match verify_package_signature(&file_path, &revoked, config) {
+                        Ok(Some(signer)) => {
+                            signed += 1;
+                            format!("{} ({})", "✓".green(), signer.dimmed())
+                        }
+                        Ok(None) => {
+                            unsigned += 1;
+                            format!("{}", "unsigned".yellow())
+                        }
+                        Err(e) => {
+                            invalid_sig += 1;
+                            format!("{} {}", "✗".red(), e.to_string().dimmed())
                         }
-                    } else {
-                        unsigned += 1;
-                        format!("{}", "unsigned".yellow())
                     };
 
                     println!("  {} {} {}", "→".cyan(), pkg_entry.name, sig_status);
@@ -286,6 +294,7 @@ pub fn refresh(path: &Path, config: &Config) -> Result<()> {
     }
 
     // Write updated index
+    index.stamp(metadata.repository.index_valid_days);
     let index_path = path.join("packages.json");
     let index_content = serde_json::to_string_pretty(&index)?;
     fs::write(&index_path, &index_content)?;
@@ -298,17 +307,22 @@ pub fn refresh(path: &Path, config: &Config) -> Result<()> {
         index.count
     );
 
-    // Sign the index
-    let sig_path = path.join("packages.json.sig");
-    let signature = signing::sign_file(&signing_key, &index_path)?;
-    let sig_json = serde_json::to_string_pretty(&signature)?;
-    fs::write(&sig_path, &sig_json)?;
-
+    // Sign the index, and repo.toml in case it was edited
+    let sig_path = write_signature(&signing_key, &index_path)?;
     println!(
         "  {} Signed index: {}",
         "✓".green(),
         sig_path.display()
     );
+    let repo_sig_path = write_signature(&signing_key, &repo_toml_path)?;
+    println!(
+        "  {} Signed metadata: {}",
+        "✓".green(),
+        repo_sig_path.display()
+    );
+    if let Some(until) = index.valid_until {
+        println!("  {} Valid until: {}", "→".cyan(), until);
+    }
 
     println!();
     println!(
@@ -325,7 +339,7 @@ pub fn sign(path: &Path, config: &Config) -> Result<()> {
     println!("{}", "Signing repository index...".cyan());
 
     // Check for signing key
-    let signing_key = signing::load_signing_key(config)
+    let signing_key = signing::load_signer(config)
         .context("A signing key is required to sign the repository index")?;
 
     let index_path = path.join("packages.json");
@@ -333,11 +347,30 @@ pub fn sign(path: &Path, config: &Config) -> Result<()> {
         bail!("Package index not found: {}", index_path.display());
     }
 
+    // An index that expires gets a new validity period with every round of
+    // signatures; further keys of a threshold repository join the round
+    let repo_toml_path = path.join("repo.toml");
+    let mut valid_until = None;
+    let mut required = 1;
+    if repo_toml_path.exists() {
+        let metadata: RepoMetadata = toml::from_str(&fs::read_to_string(&repo_toml_path)?)?;
+        require_repo_key(&metadata, &signing_key)?;
+        required = metadata.signing.required_signatures();
+        let envelope = read_envelope(&index_path)?;
+        let new_round = !envelope.covers(&fs::read(&index_path)?)
+            || envelope.is_signed_by(signing_key.fingerprint());
+        if let Some(days) = metadata.repository.index_valid_days.filter(|_| new_round) {
+            let mut index: PackageIndex = serde_json::from_str(&fs::read_to_string(&index_path)?)
+                .context("Failed to parse packages.json")?;
+            index.stamp(Some(days));
+            fs::write(&index_path, serde_json::to_string_pretty(&index)?)?;
+            valid_until = index.valid_until;
+        }
+    }
+
     // Sign the index
-    let sig_path = path.join("packages.json.sig");
-    let signature = signing::sign_file(&signing_key, &index_path)?;
-    let sig_json = serde_json::to_string_pretty(&signature)?;
-    fs::write(&sig_path, &sig_json)?;
+    let sig_path = write_signature(&signing_key, &index_path)?;
+    let signatures = read_envelope(&index_path)?.signatures.len();
 
     println!(
         "{} Signed: {} -> {}",
@@ -345,33 +378,235 @@ pub fn sign(path: &Path, config: &Config) -> Result<()> {
         index_path.display(),
         sig_path.display()
     );
-    println!("  Signed by: {} <{}>", signing_key.name, signing_key.email);
-    println!("  Fingerprint: {}", signing_key.fingerprint.dimmed());
+
+    // repo.toml carries the mirror list and key fingerprint clients act on
+    if repo_toml_path.exists() {
+        let repo_sig_path = write_signature(&signing_key, &repo_toml_path)?;
+        println!(
+            "{} Signed: {} -> {}",
+            "✓".green().bold(),
+            repo_toml_path.display(),
+            repo_sig_path.display()
+        );
+    }
+    println!("  Signed by: {} <{}>", signing_key.name(), signing_key.email());
+    println!("  Fingerprint: {}", signing_key.fingerprint().dimmed());
+    if let Some(until) = valid_until {
+        println!("  Valid until: {}", until);
+    }
+    if required > 1 {
+        let status = format!("{} of {} required", signatures, required);
+        if signatures >= required {
+            println!("  Signatures: {}", status.green());
+        } else {
+            println!("  Signatures: {} - more release keys need to run {}", status.yellow(), "rookpkg repo sign".bold());
+        }
+    }
+
+    Ok(())
+}
+
+/// Revoke a key in the repository's signed revocation list
+pub fn revoke(path: &Path, fingerprint: &str, reason: &str, since: Option<&str>, config: &Config) -> Result<()> {
+    let signing_key = signing::load_signer(config)
+        .context("A signing key is required to sign the revocation list")?;
+
+    let revocations_path = path.join("revocations.json");
+    let mut entries = if revocations_path.exists() {
+        load_revocation_list(&revocations_path)?.entries
+    } else {
+        Vec::new()
+    };
+
+    let revoked_at = since.map(str::to_string).unwrap_or_else(|| Utc::now().to_rfc3339());
+    entries.retain(|e| e.fingerprint != fingerprint);
+    entries.push(RevocationEntry {
+        fingerprint: fingerprint.to_string(),
+        revoked_at: revoked_at.clone(),
+        reason: reason.to_string(),
+    });
+
+    let list = signing::sign_revocation_list(&signing_key, entries)?;
+    fs::write(&revocations_path, serde_json::to_string_pretty(&list)?)?;
+
+    println!("{} Revoked {}", "✓".green().bold(), fingerprint.cyan());
+    println!("  Reason: {}", reason);
+    println!("  Refusing signatures made since: {}", revoked_at);
+    println!("  Written to: {}", revocations_path.display());
+    println!();
+    println!("Clients pick the revocation up with {}.", "rookpkg update".bold());
+
+    Ok(())
+}
+
+/// Hand the repository over to a new signing key
+///
+/// The current key signs a rotation statement for the new one, and
+/// everything the repository signs is re-signed with the new key.
+pub fn rotate(path: &Path, new_key_path: &Path, config: &Config) -> Result<()> {
+    let old_key = signing::load_signer(config)
+        .context("The current signing key is required to sign the rotation")?;
+    let new_key = signing::load_signing_key_from_path(new_key_path)
+        .with_context(|| format!("Failed to load new key: {}", new_key_path.display()))?;
+    let new_pub_path = new_key_path.with_extension("pub");
+    let new_public_key = fs::read_to_string(&new_pub_path)
+        .with_context(|| format!("Failed to read new public key: {}", new_pub_path.display()))?;
+
+    let repo_toml_path = path.join("repo.toml");
+    let index_path = path.join("packages.json");
+    if !repo_toml_path.exists() || !index_path.exists() {
+        bail!("Not a repository: {} (missing repo.toml or packages.json)", path.display());
+    }
+    let mut metadata: RepoMetadata = toml::from_str(&fs::read_to_string(&repo_toml_path)?)?;
+    require_repo_key(&metadata, &old_key)?;
+
+    println!("{}", "Rotating repository key...".cyan());
+    println!("  From: {}", old_key.fingerprint().dimmed());
+    println!("  To:   {}", new_key.fingerprint.cyan());
+    println!();
+
+    let rotation = signing::rotate_key(&old_key, &new_key, &new_public_key)?;
+    let rotations_path = path.join("rotations.json");
+    let mut rotations: Vec<KeyRotation> = if rotations_path.exists() {
+        serde_json::from_str(&fs::read_to_string(&rotations_path)?)
+            .context("Failed to parse rotations.json")?
+    } else {
+        Vec::new()
+    };
+    rotations.push(rotation);
+    fs::write(&rotations_path, serde_json::to_string_pretty(&rotations)?)?;
+    println!("  {} Updated {}", "✓".green(), rotations_path.display());
+
+    metadata.signing.rotate(old_key.fingerprint(), &new_key.fingerprint);
+    fs::write(&repo_toml_path, toml::to_string_pretty(&metadata)?)?;
+    for file in [&repo_toml_path, &index_path] {
+        let sig_path = write_signature(&new_key, file)?;
+        println!("  {} Signed: {}", "✓".green(), sig_path.display());
+    }
+
+    // Clients only take revocations from the current repository key
+    let revocations_path = path.join("revocations.json");
+    if revocations_path.exists() {
+        let list = load_revocation_list(&revocations_path)?;
+        if list.issuer == old_key.fingerprint() {
+            let list = signing::sign_revocation_list(&new_key, list.entries)?;
+            fs::write(&revocations_path, serde_json::to_string_pretty(&list)?)?;
+            println!("  {} Signed: {}", "✓".green(), revocations_path.display());
+        }
+    }
+
+    println!();
+    println!("{} Repository key rotated", "✓".green().bold());
+    println!();
+    println!(
+        "Set {} to {} before the next refresh.",
+        "signing.user_signing_key".bold(),
+        new_key_path.display()
+    );
 
     Ok(())
 }
 
-/// Verify a package signature and return the signer name
-fn verify_package_signature(pkg_path: &Path, sig_path: &Path, config: &Config) -> Result<String> {
-    use crate::signing::HybridSignature;
+/// Refuse to sign a repository with a key other than the ones it names
+fn require_repo_key(metadata: &RepoMetadata, key: &dyn signing::Signer) -> Result<()> {
+    if !metadata.signing.allows(key.fingerprint()) {
+        bail!(
+            "repo.toml names signing key(s) {}, but the configured key is {}",
+            metadata.signing.keys().join(", "),
+            key.fingerprint()
+        );
+    }
+    Ok(())
+}
 
-    // Read the signature file
-    let sig_content = fs::read_to_string(sig_path)
-        .context("Failed to read signature file")?;
-    let signature: HybridSignature = serde_json::from_str(&sig_content)
-        .context("Failed to parse signature file")?;
+fn load_revocation_list(path: &Path) -> Result<RevocationList> {
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Keys revoked locally, by the repository's revocation list, or rotated out
+fn repo_revocations(path: &Path, config: &Config) -> Result<Vec<RevokedKey>> {
+    let mut revoked = repository::load_revoked_keys(config)?;
+
+    let revocations_path = path.join("revocations.json");
+    if revocations_path.exists() {
+        let list = load_revocation_list(&revocations_path)?;
+        for entry in &list.entries {
+            revoked.push(RevokedKey {
+                fingerprint: entry.fingerprint.clone(),
+                revoked_date: entry.revocation_time()?.timestamp(),
+                reason: entry.reason.clone(),
+                revoked_by: list.issuer.clone(),
+            });
+        }
+    }
+
+    let rotations_path = path.join("rotations.json");
+    if rotations_path.exists() {
+        let rotations: Vec<KeyRotation> = serde_json::from_str(&fs::read_to_string(&rotations_path)?)
+            .context("Failed to parse rotations.json")?;
+        for rotation in rotations {
+            revoked.push(RevokedKey {
+                revoked_date: rotation.effective_time()?.timestamp(),
+                reason: format!("Rotated to {}", rotation.new_key),
+                revoked_by: rotation.old_key.clone(),
+                fingerprint: rotation.old_key,
+            });
+        }
+    }
+
+    // The earliest revocation of a key is the one that counts
+    revoked.sort_by_key(|r| r.revoked_date);
+    Ok(revoked)
+}
+
+/// Sign a file into the envelope in `<file>.sig` next to it
+///
+/// The signature joins those of other keys if they signed the same content.
+fn write_signature(key: &dyn signing::Signer, path: &Path) -> Result<PathBuf> {
+    let mut envelope = read_envelope(path)?;
+    envelope.sign(key, &fs::read(path)?)?;
+    let sig_path = signature_path(path);
+    fs::write(&sig_path, serde_json::to_string_pretty(&envelope)?)?;
+    Ok(sig_path)
+}
+
+/// The signature envelope of a file (empty if it has none)
+fn read_envelope(path: &Path) -> Result<SignatureEnvelope> {
+    let sig_path = signature_path(path);
+    if !sig_path.exists() {
+        return Ok(SignatureEnvelope::default());
+    }
+    SignatureEnvelope::parse(&fs::read_to_string(&sig_path)?)
+        .with_context(|| format!("Failed to parse {}", sig_path.display()))
+}
+
+fn signature_path(path: &Path) -> PathBuf {
+    let mut sig_path = path.as_os_str().to_owned();
+    sig_path.push(".sig");
+    PathBuf::from(sig_path)
+}
+
+/// Verify a package signature and return the signer name (None if unsigned)
+fn verify_package_signature(
+    pkg_path: &Path,
+    revoked: &[RevokedKey],
+    config: &Config,
+) -> Result<Option<String>> {
+    let Some(package_signature) = PackageArchiveReader::open(pkg_path)?.find_signature()? else {
+        return Ok(None);
+    };
+    let signature = &package_signature.signature;
 
     // Find the public key
     let public_key = find_signing_key(&signature.fingerprint, config)?;
 
-    // Read the package file and verify
-    let pkg_content = fs::read(pkg_path)
-        .context("Failed to read package file")?;
-
-    signing::verify_signature(&public_key, &pkg_content, &signature)
+    package_signature
+        .verify(&public_key)
         .context("Signature verification failed")?;
+    repository::check_key_validity(signature, revoked, config)?;
 
-    Ok(format!("{} <{}>", public_key.name, public_key.email))
+    Ok(Some(format!("{} <{}>", public_key.name, public_key.email)))
 }
 
 /// Find a signing key by fingerprint in the configured key directories
@@ -443,12 +678,6 @@ fn search_key_in_dir(dir: &Path, fingerprint: &str) -> Result<Option<signing::Lo
 
 /// Scan a package file and extract metadata for the index
 fn scan_package(path: &Path) -> Result<Option<PackageEntry>> {
-    use crate::archive::PackageArchiveReader;
-    use chrono::{TimeZone, Utc};
-
-    let reader = PackageArchiveReader::open(path)?;
-    let info = reader.read_info()?;
-
     // Get filename relative to packages dir
     let filename = format!(
         "packages/{}",
@@ -457,30 +686,5 @@ fn scan_package(path: &Path) -> Result<Option<PackageEntry>> {
             .unwrap_or_default()
     );
 
-    // Convert dependencies from HashMap to Vec<String> (just names, constraints optional)
-    let depends: Vec<String> = info.depends.keys().cloned().collect();
-    let build_depends: Vec<String> = info.build_depends.keys().cloned().collect();
-
-    // Convert build_time (unix timestamp) to DateTime
-    let build_date = Utc.timestamp_opt(info.build_time, 0).single();
-
-    Ok(Some(PackageEntry {
-        name: info.name.clone(),
-        version: info.version.clone(),
-        release: info.release,
-        description: info.description.clone(),
-        arch: info.arch.clone(),
-        size: fs::metadata(path)?.len(),
-        sha256: compute_sha256(path)?,
-        filename,
-        depends,
-        build_depends,
-        provides: Vec::new(),     // Not stored in PackageInfo
-        conflicts: Vec::new(),    // Not stored in PackageInfo
-        replaces: Vec::new(),     // Not stored in PackageInfo
-        license: if info.license.is_empty() { None } else { Some(info.license.clone()) },
-        homepage: if info.url.is_empty() { None } else { Some(info.url.clone()) },
-        maintainer: if info.maintainer.is_empty() { None } else { Some(info.maintainer.clone()) },
-        build_date,
-    }))
+    Ok(Some(PackageEntry::from_archive(path, filename)?))
 }
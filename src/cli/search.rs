@@ -2,12 +2,49 @@
 
 use anyhow::Result;
 use colored::Colorize;
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::database::Database;
-use crate::repository::RepoManager;
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::repository::RepoManager;
+
+use super::{print_json, OutputFormat};
+
+/// `search --output json`
+#[derive(Serialize)]
+struct SearchJson {
+    /// Installed packages whose name matches
+    installed: Vec<PackageJson>,
+    /// Package groups whose name or description matches
+    groups: Vec<GroupJson>,
+    /// Matching repository packages that are not installed
+    available: Vec<PackageJson>,
+}
+
+#[derive(Serialize)]
+struct PackageJson {
+    name: String,
+    version: String,
+    release: u32,
+    /// None for installed packages
+    repository: Option<String>,
+    description: String,
+}
+
+#[derive(Serialize)]
+struct GroupJson {
+    name: String,
+    repository: String,
+    description: String,
+    packages: Vec<String>,
+    optional: Vec<String>,
+}
+
+pub fn run(query: &str, output: OutputFormat, config: &Config) -> Result<()> {
+    if output == OutputFormat::Json {
+        return search_json(query, config);
+    }
 
-pub fn run(query: &str, config: &Config) -> Result<()> {
     println!("{} '{}'", "Searching for:".cyan(), query.bold());
     println!();
 
@@ -160,6 +197,71 @@ pub fn run(query: &str, config: &Config) -> Result<()> {
     Ok(())
 }
 
+fn search_json(query: &str, config: &Config) -> Result<()> {
+    let query_lower = query.to_lowercase();
+
+    let db_path = config.database_path();
+    let installed = if db_path.exists() {
+        Database::open(&db_path)?.list_packages()?
+    } else {
+        Vec::new()
+    };
+    let installed_names: Vec<String> = installed.iter().map(|p| p.name.clone()).collect();
+
+    let mut result = SearchJson {
+        installed: installed
+            .into_iter()
+            .filter(|p| p.name.to_lowercase().contains(&query_lower))
+            .map(|p| PackageJson {
+                name: p.name,
+                version: p.version,
+                release: p.release,
+                repository: None,
+                description: String::new(),
+            })
+            .collect(),
+        groups: Vec::new(),
+        available: Vec::new(),
+    };
+
+    if !config.repositories.is_empty() {
+        let mut manager = RepoManager::new(config)?;
+        manager.load_caches()?;
+
+        result.groups = manager
+            .list_groups()
+            .into_iter()
+            .filter(|g| {
+                g.group.name.to_lowercase().contains(&query_lower)
+                    || g.group.description.to_lowercase().contains(&query_lower)
+            })
+            .map(|g| GroupJson {
+                name: g.group.name,
+                repository: g.repository,
+                description: g.group.description,
+                packages: g.group.packages,
+                optional: g.group.optional,
+            })
+            .collect();
+
+        result.available = manager
+            .search(query)
+            .into_iter()
+            .filter(|r| !installed_names.contains(&r.package.name))
+            .map(|r| PackageJson {
+                name: r.package.name,
+                version: r.package.version,
+                release: r.package.release,
+                repository: Some(r.repository),
+                description: r.package.description,
+            })
+            .collect();
+        result.available.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    print_json(&result)
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
@@ -0,0 +1,126 @@
+//! Snapshot command implementation
+//!
+//! Lists the filesystem snapshots taken around transactions and rolls the
+//! system back to one of them.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{TimeZone, Utc};
+use colored::Colorize;
+
+use rookpkg::config::Config;
+use rookpkg::database::Database;
+use rookpkg::snapshot::{self, RollbackOutcome};
+use rookpkg::transaction::Transaction;
+
+/// List recorded snapshots, newest first
+pub fn list(config: &Config) -> Result<()> {
+    let db_path = config.database_path();
+    let snapshots = if db_path.exists() {
+        Database::open(&db_path)?.list_snapshots()?
+    } else {
+        Vec::new()
+    };
+
+    if snapshots.is_empty() {
+        println!("{}", "No snapshots recorded.".dimmed());
+        if !config.snapshot.enabled {
+            println!("Enable them with {} in the configuration.", "[snapshot] enabled = true".bold());
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{:<28}  {:<4}  {:<7}  {:<16}  {:>7}  {}",
+        "Snapshot".bold(),
+        "When".bold(),
+        "Backend".bold(),
+        "Date".bold(),
+        "History".bold(),
+        "Command".bold()
+    );
+    for record in &snapshots {
+        let history = record
+            .history_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<28}  {:<4}  {:<7}  {:<16}  {:>7}  {}",
+            record.snapshot_id,
+            record.kind,
+            record.backend,
+            format_date(record.created),
+            history,
+            record.command_line.as_deref().unwrap_or("").dimmed()
+        );
+    }
+
+    println!();
+    println!("Use {} to restore one.", "rookpkg snapshot rollback <snapshot>".bold());
+
+    Ok(())
+}
+
+/// Roll the system back to a snapshot
+pub fn rollback(snapshot_id: &str, dry_run: bool, config: &Config) -> Result<()> {
+    let root = config.sysroot();
+    let db_path = config.database_path();
+    let db = Database::open(&db_path)?;
+    let record = db
+        .get_snapshot(snapshot_id)?
+        .ok_or_else(|| anyhow!("Snapshot {} not found (see `rookpkg snapshot list`)", snapshot_id))?;
+
+    println!(
+        "{} Rolling back to {} ({} snapshot of transaction {}, {})",
+        "→".cyan(),
+        record.snapshot_id.bold(),
+        record.kind,
+        record.transaction_id,
+        format_date(record.created)
+    );
+    if let Some(command_line) = &record.command_line {
+        println!("  {}", command_line.dimmed());
+    }
+
+    if dry_run {
+        println!();
+        println!("{}", "Dry run - no changes made.".yellow());
+        return Ok(());
+    }
+
+    let pending = Transaction::list_pending(root)?;
+    if !pending.is_empty() {
+        bail!(
+            "Transaction {} is still in progress; run `rookpkg recover` first",
+            pending.join(", ")
+        );
+    }
+
+    drop(db);
+
+    let provider = snapshot::provider(record.backend, &config.snapshot, root);
+    let outcome = provider.rollback(&record.snapshot_id, &db_path)?;
+
+    println!();
+    match outcome {
+        RollbackOutcome::Applied => {
+            println!("{} Rolled back to snapshot {}", "✓".green().bold(), record.snapshot_id);
+        }
+        RollbackOutcome::RebootRequired => {
+            println!(
+                "{} Snapshot {} is now the default subvolume; reboot to complete the rollback",
+                "!".yellow().bold(),
+                record.snapshot_id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a Unix timestamp in UTC
+fn format_date(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -3,8 +3,8 @@
 use anyhow::Result;
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::repository::RepoManager;
+use rookpkg::config::Config;
+use rookpkg::repository::RepoManager;
 
 /// Run the update command
 pub fn run(config: &Config) -> Result<()> {
@@ -1,62 +1,80 @@
 //! Upgrade command implementation
 
+use std::collections::{HashMap, HashSet};
+
 use anyhow::{bail, Result};
 use colored::Colorize;
-
-use crate::config::Config;
-use crate::database::Database;
-use crate::hooks::HookResult;
-use crate::repository::{RepoManager, SignatureStatus};
-use crate::resolver::needs_upgrade;
-use crate::signing::TrustLevel;
-use crate::transaction::TransactionBuilder;
+use pubgrub::range::Range;
+
+use rookpkg::config::Config;
+use rookpkg::database::{Database, HistoryAction, HistoryOperation};
+use rookpkg::hooks::HookResult;
+use rookpkg::package::{InstallReason, InstalledPackage};
+use rookpkg::repository::{find_candidate, PackageEntry, RepoManager, SearchResult, SignatureStatus};
+use crate::cli::install::print_resolution_failure;
+use rookpkg::resolver::{
+    needs_upgrade, parse_constraint, parse_dep_string, parse_version, resolve_upgrade, HeldBack,
+    ResolutionContext, RookeryDependencyProvider,
+};
+use rookpkg::signing::TrustLevel;
+use rookpkg::transaction::TransactionBuilder;
+use rookpkg::version::Version;
+
+use super::{DryRunPlan, PlannedHold, PlannedOperation};
 
 /// Upgradeable package info
 struct UpgradeCandidate {
     name: String,
     installed_version: String,
     installed_release: u32,
-    available_version: String,
-    available_release: u32,
+    package: PackageEntry,
     repo_name: String,
 }
 
+/// An installed package superseded by a repository package that replaces it
+struct Replacement {
+    old_name: String,
+    old_full: String,
+    new_name: String,
+    install_reason: InstallReason,
+}
+
 impl UpgradeCandidate {
     fn installed_full(&self) -> String {
         format!("{}-{}", self.installed_version, self.installed_release)
     }
 
     fn available_full(&self) -> String {
-        format!("{}-{}", self.available_version, self.available_release)
+        format!("{}-{}", self.package.version, self.package.release)
     }
 }
 
-pub fn run(dry_run: bool, config: &Config) -> Result<()> {
+pub fn run(dry_run: bool, json: bool, config: &Config) -> Result<()> {
     if dry_run {
-        println!("{}", "Dry run mode - no changes will be made".yellow());
-        println!();
+        status!("{}", "Dry run mode - no changes will be made".yellow());
+        status!();
     }
 
-    println!("{}", "Checking for upgrades...".cyan());
-    println!();
+    status!("{}", "Checking for upgrades...".cyan());
+    status!();
 
     // Open database (use sysroot-relative path)
     let db_path = config.database_path();
     let db = if db_path.exists() {
         Database::open(&db_path)?
     } else {
-        println!("{}", "No packages installed yet.".yellow());
-        return Ok(());
+        status!("{}", "No packages installed yet.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     };
 
     // Get installed packages
     let installed = db.list_packages()?;
     if installed.is_empty() {
-        println!("{}", "No packages installed.".yellow());
-        return Ok(());
+        status!("{}", "No packages installed.".yellow());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
-    println!(
+    status!(
         "  {} {} package(s) installed",
         "✓".green(),
         installed.len()
@@ -67,129 +85,281 @@ pub fn run(dry_run: bool, config: &Config) -> Result<()> {
     manager.load_caches()?;
 
     if config.repositories.is_empty() {
-        println!();
-        println!("{}", "No repositories configured.".yellow());
-        println!("Run {} to add repositories.", "rookpkg update".bold());
-        return Ok(());
+        status!();
+        status!("{}", "No repositories configured.".yellow());
+        status!("Run {} to add repositories.", "rookpkg update".bold());
+        return DryRunPlan::default().print_if_requested(dry_run);
     }
 
-    // Find upgradeable packages
-    let mut upgrades: Vec<UpgradeCandidate> = Vec::new();
-    let mut held_packages: Vec<(String, String)> = Vec::new();
+    // Every version the solver may pick; each package comes from the
+    // highest-priority repository that carries it
+    let candidates = manager.package_candidates();
+    let mut provider = RookeryDependencyProvider::new();
+    for results in candidates.values() {
+        for result in results {
+            provider.add_entry(&result.package);
+        }
+    }
 
-    for pkg in &installed {
-        // Check if package is held
-        if db.is_package_held(&pkg.name)? {
-            if let Some(result) = manager.find_package(&pkg.name) {
-                let available = &result.package;
-                // Only report if there's actually an upgrade available
-                if needs_upgrade(&pkg.version, pkg.release, &available.version, available.release) {
-                    let available_full = format!("{}-{}", available.version, available.release);
-                    held_packages.push((pkg.name.clone(), available_full));
+    let held: HashSet<String> = db
+        .list_held_packages()?
+        .into_iter()
+        .map(|h| h.name)
+        .collect();
+
+    // Find installed packages replaced by another repository package
+    let mut replacements: Vec<Replacement> = Vec::new();
+    let mut names: Vec<&String> = candidates.keys().collect();
+    names.sort();
+    for name in names {
+        if installed.iter().any(|p| &p.name == name) {
+            continue;
+        }
+        for result in &candidates[name] {
+            for replaces in &result.package.replaces {
+                let (old_name, constraint) = parse_dep_string(replaces);
+                let Some(old) = installed.iter().find(|p| p.name == old_name) else {
+                    continue;
+                };
+                if replacements.iter().any(|r| r.old_name == old.name) || held.contains(&old.name) {
+                    continue;
+                }
+                let old_version = parse_version(&old.version);
+                if !parse_constraint(&constraint).map(|r| r.contains(&old_version)).unwrap_or(false) {
+                    continue;
                 }
+                replacements.push(Replacement {
+                    old_name: old.name.clone(),
+                    old_full: old.full_version(),
+                    new_name: name.clone(),
+                    install_reason: old.install_reason,
+                });
             }
-            continue;  // Skip held packages
         }
+    }
 
-        if let Some(result) = manager.find_package(&pkg.name) {
-            let available = &result.package;
-
-            // Compare versions using proper semantic versioning
-            if needs_upgrade(&pkg.version, pkg.release, &available.version, available.release) {
-                upgrades.push(UpgradeCandidate {
-                    name: pkg.name.clone(),
-                    installed_version: pkg.version.clone(),
-                    installed_release: pkg.release,
-                    available_version: available.version.clone(),
-                    available_release: available.release,
-                    repo_name: result.repository.clone(),
-                });
-            }
+    // One problem for the whole system: held packages stay where they are,
+    // everything else may only move forward, replacements come in fresh
+    status!("  Resolving upgrade...");
+    let mut context = ResolutionContext::default();
+    let mut root_deps: HashMap<String, Range<Version>> = HashMap::new();
+    for pkg in &installed {
+        context.installed.insert(pkg.name.clone(), pkg.version.clone());
+        // A replaced package is left out so dependencies on its name can
+        // fall through to the replacement's provides
+        if replacements.iter().any(|r| r.old_name == pkg.name) {
+            continue;
+        }
+        provider.add_installed(pkg, &db.get_dependencies(&pkg.name)?, &db.get_provides(&pkg.name)?);
+        provider.prefer(&pkg.name);
+
+        let version = parse_version(&pkg.version);
+        if held.contains(&pkg.name) {
+            context.held.insert(pkg.name.clone());
+            root_deps.insert(pkg.name.clone(), Range::exact(version));
+        } else {
+            context.upgrading.insert(pkg.name.clone());
+            root_deps.insert(pkg.name.clone(), Range::higher_than(version));
+        }
+    }
+    for replacement in &replacements {
+        provider.prefer(&replacement.new_name);
+        context.requested.insert(replacement.new_name.clone());
+        root_deps.insert(replacement.new_name.clone(), Range::any());
+    }
+
+    let resolution = match resolve_upgrade(&mut provider, root_deps, &context) {
+        Ok(resolution) => resolution,
+        Err(report) => {
+            print_resolution_failure(&report, json)?;
+            bail!("Could not resolve dependencies for the upgrade");
+        }
+    };
+
+    // Turn the solution into upgrades, new dependencies and replacements
+    let mut upgrades: Vec<UpgradeCandidate> = Vec::new();
+    for pkg in &installed {
+        if held.contains(&pkg.name) || replacements.iter().any(|r| r.old_name == pkg.name) {
+            continue;
+        }
+        let Some(version) = resolution.selected.get(&pkg.name) else {
+            continue;
+        };
+        let Some(result) = candidates.get(&pkg.name).and_then(|c| find_candidate(c, version, None)) else {
+            continue;
+        };
+        if needs_upgrade(&pkg.version, pkg.release, &result.package.version, result.package.release) {
+            upgrades.push(UpgradeCandidate {
+                name: pkg.name.clone(),
+                installed_version: pkg.version.clone(),
+                installed_release: pkg.release,
+                package: result.package.clone(),
+                repo_name: result.repository.clone(),
+            });
         }
     }
 
-    println!();
+    let mut new_packages: Vec<(&SearchResult, InstallReason)> = Vec::new();
+    let mut solution: Vec<(&String, &Version)> = resolution.selected.iter().collect();
+    solution.sort();
+    for (name, version) in solution {
+        if context.installed.contains_key(name) {
+            continue;
+        }
+        let Some(result) = candidates.get(name).and_then(|c| find_candidate(c, version, None)) else {
+            bail!("{} {} was selected but is not in any repository", name, version);
+        };
+        let reason = replacements
+            .iter()
+            .find(|r| &r.new_name == name)
+            .map(|r| r.install_reason)
+            .unwrap_or(InstallReason::Dependency);
+        new_packages.push((result, reason));
+    }
+
+    status!();
 
-    // Show held packages that have updates available
-    if !held_packages.is_empty() {
-        println!("{}", "Held packages (skipped):".yellow());
-        for (name, available) in &held_packages {
-            println!(
-                "  {} {} ({} available)",
+    // Show packages that stay behind and why
+    if !resolution.held_back.is_empty() {
+        status!("{}", "Held back:".yellow());
+        for held_back in &resolution.held_back {
+            status!(
+                "  {} {} {} ({} available)",
                 "⏸".yellow(),
-                name.bold(),
-                available.dimmed()
+                held_back.name.bold(),
+                held_back.selected.to_string().dimmed(),
+                held_back.newest.to_string().dimmed()
             );
+            status!("      {}", held_back.reason);
+        }
+        status!();
+        if resolution.held_back.iter().any(|h| held.contains(&h.name)) {
+            status!(
+                "Use {} to release holds.",
+                "rookpkg unhold <package>".bold()
+            );
+            status!();
         }
-        println!();
-        println!(
-            "Use {} to release holds.",
-            "rookpkg unhold <package>".bold()
-        );
-        println!();
     }
 
-    if upgrades.is_empty() {
-        println!("{}", "All packages are up to date.".green());
-        return Ok(());
+    if upgrades.is_empty() && new_packages.is_empty() {
+        status!("{}", "All packages are up to date.".green());
+        return plan(&installed, &upgrades, &replacements, &new_packages, &resolution.held_back, 0)
+            .print_if_requested(dry_run);
     }
 
     // Show upgrades
-    println!(
-        "{} {} package(s) can be upgraded:",
-        "→".cyan(),
-        upgrades.len()
-    );
-    println!();
+    if !upgrades.is_empty() {
+        status!(
+            "{} {} package(s) can be upgraded:",
+            "→".cyan(),
+            upgrades.len()
+        );
+        status!();
+
+        for upgrade in &upgrades {
+            status!(
+                "  {} {} {} → {} (from {})",
+                "↑".cyan(),
+                upgrade.name.bold(),
+                upgrade.installed_full().dimmed(),
+                upgrade.available_full().green(),
+                upgrade.repo_name.cyan()
+            );
+        }
 
-    for upgrade in &upgrades {
-        println!(
-            "  {} {} {} → {} (from {})",
-            "↑".cyan(),
-            upgrade.name.bold(),
-            upgrade.installed_full().dimmed(),
-            upgrade.available_full().green(),
-            upgrade.repo_name.cyan()
+        status!();
+    }
+
+    let dependencies: Vec<&SearchResult> = new_packages
+        .iter()
+        .filter(|(r, _)| !replacements.iter().any(|rep| rep.new_name == r.package.name))
+        .map(|(r, _)| *r)
+        .collect();
+    if !dependencies.is_empty() {
+        status!(
+            "{} {} new package(s) will be installed:",
+            "→".cyan(),
+            dependencies.len()
         );
+        status!();
+
+        for result in &dependencies {
+            status!(
+                "  {} {}-{} {} {} {}",
+                "+".green(),
+                result.package.name.bold(),
+                result.package.version,
+                "from".dimmed(),
+                result.repository.cyan(),
+                "(dependency)".dimmed()
+            );
+        }
+
+        status!();
     }
 
-    println!();
+    if !replacements.is_empty() {
+        status!(
+            "{} {} package(s) will be replaced:",
+            "→".cyan(),
+            replacements.len()
+        );
+        status!();
+
+        for replacement in &replacements {
+            let Some((result, _)) = new_packages
+                .iter()
+                .find(|(r, _)| r.package.name == replacement.new_name)
+            else {
+                continue;
+            };
+            status!(
+                "  {} {} {} → {}-{}-{} (from {})",
+                "⇄".cyan(),
+                replacement.old_name.bold(),
+                replacement.old_full.dimmed(),
+                result.package.name.bold(),
+                result.package.version.green(),
+                result.package.release.to_string().green(),
+                result.repository.cyan()
+            );
+        }
+
+        status!();
+    }
 
     // Calculate download size
-    let total_size: u64 = upgrades
-        .iter()
-        .filter_map(|u| manager.find_package(&u.name))
-        .map(|r| r.package.size)
-        .sum();
+    let total_size: u64 = upgrades.iter().map(|u| u.package.size).sum::<u64>()
+        + new_packages.iter().map(|(r, _)| r.package.size).sum::<u64>();
 
-    println!(
+    status!(
         "Total download size: {}",
         format_size(total_size).cyan()
     );
-    println!();
+    status!();
 
     if dry_run {
-        println!("{}", "Dry run complete - no packages downloaded.".yellow());
-        return Ok(());
+        status!("{}", "Dry run complete - no packages downloaded.".yellow());
+        return plan(&installed, &upgrades, &replacements, &new_packages, &resolution.held_back, total_size)
+            .print_if_requested(dry_run);
     }
 
     // Download and verify packages
-    println!("{}", "Downloading and verifying packages...".cyan());
-    println!();
+    status!("{}", "Downloading and verifying packages...".cyan());
+    status!();
 
     let mut verified_packages = Vec::new();
 
     for upgrade in &upgrades {
-        let result = manager.find_package(&upgrade.name).unwrap();
-
-        print!(
+        status_part!(
             "  {} {}-{}... ",
             "↓".cyan(),
-            result.package.name,
-            result.package.version
+            upgrade.package.name,
+            upgrade.package.version
         );
 
-        match manager.download_and_verify_package(&result.package, &result.repository, config) {
+        match manager.download_and_verify_package(&upgrade.package, &upgrade.repo_name, config) {
             Ok(verified) => {
                 // Use SignatureStatus methods for consistent display
                 let sig_desc = verified.signature_status.description();
@@ -200,30 +370,51 @@ pub fn run(dry_run: bool, config: &Config) -> Result<()> {
                         TrustLevel::Marginal => "marginal".yellow(),
                         TrustLevel::Unknown => "unknown".red(),
                     };
-                    println!("{} [signed by {} ({}) - {}]", "✓".green(), signer.cyan(), trust_color, fingerprint.dimmed());
+                    status!("{} [signed by {} ({}) - {}]", "✓".green(), signer.cyan(), trust_color, fingerprint.dimmed());
                     tracing::debug!("Signature status: {}", sig_desc);
                 }
 
-                verified_packages.push((upgrade, verified, result.repository.clone()));
+                verified_packages.push((upgrade, verified));
             }
             Err(e) => {
-                println!("{}", "✗".red());
+                status!("{}", "✗".red());
                 bail!("Failed to download/verify {}: {}", upgrade.name, e);
             }
         }
     }
 
-    println!();
+    let mut verified_new = Vec::new();
 
-    // Perform upgrades using TransactionBuilder for cleaner API
-    println!("{}", "Installing upgrades...".cyan());
-    println!();
+    for (result, reason) in &new_packages {
+        status_part!(
+            "  {} {}-{}... ",
+            "↓".cyan(),
+            result.package.name,
+            result.package.version
+        );
+
+        match manager.download_and_verify_package(&result.package, &result.repository, config) {
+            Ok(verified) => {
+                status!("{} [{}]", "✓".green(), verified.signature_status.description());
+                verified_new.push((&result.package, *reason, verified));
+            }
+            Err(e) => {
+                status!("{}", "✗".red());
+                bail!("Failed to download/verify {}: {}", result.package.name, e);
+            }
+        }
+    }
+
+    status!();
+
+    // Upgrades, new dependencies and replacements all go into one transaction
+    status!("{}", "Installing upgrades...".cyan());
+    status!();
 
-    // Build upgrade operations
     let root = config.sysroot();
     let mut builder = TransactionBuilder::new(root);
 
-    for (upgrade, verified, _repo) in &verified_packages {
+    for (upgrade, verified) in &verified_packages {
         builder = builder.upgrade(
             &upgrade.name,
             &upgrade.installed_full(),
@@ -232,23 +423,46 @@ pub fn run(dry_run: bool, config: &Config) -> Result<()> {
         );
     }
 
+    for replacement in &replacements {
+        builder = builder.remove(&replacement.old_name);
+    }
+
+    for (package, reason, verified) in &verified_new {
+        let version = format!("{}-{}", package.version, package.release);
+        builder = builder.install(&package.name, &version, &verified.path, *reason);
+    }
+
     // Re-open database for transaction execution
     let db = Database::open(&db_path)?;
 
-    match builder.execute_with_hooks(db, &config.hooks) {
+    match builder.execute_with_hooks(db, &config.hooks, &config.snapshot) {
         Ok((pre_results, post_results)) => {
             print_hook_results("pre-transaction", &pre_results);
 
-            println!(
+            status!(
                 "{} {} package(s) upgraded successfully",
                 "✓".green().bold(),
                 verified_packages.len()
             );
+            if !dependencies.is_empty() {
+                status!(
+                    "{} {} dependency package(s) installed",
+                    "✓".green().bold(),
+                    dependencies.len()
+                );
+            }
+            if !replacements.is_empty() {
+                status!(
+                    "{} {} package(s) replaced",
+                    "✓".green().bold(),
+                    replacements.len()
+                );
+            }
 
             print_hook_results("post-transaction", &post_results);
         }
         Err(e) => {
-            println!(
+            status!(
                 "{} Upgrade failed: {}",
                 "✗".red().bold(),
                 e
@@ -257,12 +471,80 @@ pub fn run(dry_run: bool, config: &Config) -> Result<()> {
         }
     }
 
-    println!();
-    println!("{}", "Upgrade complete!".green());
+    status!();
+    status!("{}", "Upgrade complete!".green());
 
     Ok(())
 }
 
+/// The upgrade transaction in the order `run` builds it: upgrades,
+/// removals of replaced packages, then new packages
+fn plan(
+    installed: &[InstalledPackage],
+    upgrades: &[UpgradeCandidate],
+    replacements: &[Replacement],
+    new_packages: &[(&SearchResult, InstallReason)],
+    held_back: &[HeldBack],
+    download_size: u64,
+) -> DryRunPlan {
+    let mut operations = Vec::new();
+    for upgrade in upgrades {
+        let install_reason = installed
+            .iter()
+            .find(|p| p.name == upgrade.name)
+            .map(|p| p.install_reason)
+            .unwrap_or_default();
+        operations.push(PlannedOperation {
+            operation: HistoryOperation {
+                action: HistoryAction::Upgrade,
+                package: upgrade.name.clone(),
+                old_version: Some(upgrade.installed_full()),
+                new_version: Some(upgrade.available_full()),
+                install_reason,
+            },
+            repository: Some(upgrade.repo_name.clone()),
+        });
+    }
+    for replacement in replacements {
+        operations.push(PlannedOperation {
+            operation: HistoryOperation {
+                action: HistoryAction::Remove,
+                package: replacement.old_name.clone(),
+                old_version: Some(replacement.old_full.clone()),
+                new_version: None,
+                install_reason: replacement.install_reason,
+            },
+            repository: None,
+        });
+    }
+    for (result, reason) in new_packages {
+        operations.push(PlannedOperation {
+            operation: HistoryOperation {
+                action: HistoryAction::Install,
+                package: result.package.name.clone(),
+                old_version: None,
+                new_version: Some(format!("{}-{}", result.package.version, result.package.release)),
+                install_reason: *reason,
+            },
+            repository: Some(result.repository.clone()),
+        });
+    }
+
+    DryRunPlan {
+        operations,
+        download_size,
+        held_back: held_back
+            .iter()
+            .map(|h| PlannedHold {
+                name: h.name.clone(),
+                selected: h.selected.to_string(),
+                newest: h.newest.to_string(),
+                reason: h.reason.clone(),
+            })
+            .collect(),
+    }
+}
+
 /// Print hook execution results
 fn print_hook_results(phase: &str, results: &[HookResult]) {
     if results.is_empty() {
@@ -273,13 +555,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
     let fail_count = results.len() - success_count;
 
     if fail_count == 0 {
-        println!(
+        status!(
             "  {} {} hook(s) ran successfully",
             "→".cyan(),
             results.len()
         );
     } else {
-        println!(
+        status!(
             "  {} {} {} hook(s): {} succeeded, {} failed",
             "!".yellow(),
             results.len(),
@@ -289,7 +571,7 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
         );
         for result in results {
             if !result.success {
-                println!(
+                status!(
                     "    {} {} (exit code: {:?})",
                     "✗".red(),
                     result.name,
@@ -298,13 +580,13 @@ fn print_hook_results(phase: &str, results: &[HookResult]) {
                 // Show stderr if available
                 if !result.stderr.is_empty() {
                     for line in result.stderr.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
                 // Show stdout if stderr is empty but stdout has content
                 else if !result.stdout.is_empty() {
                     for line in result.stdout.lines().take(3) {
-                        println!("      {}", line.dimmed());
+                        status!("      {}", line.dimmed());
                     }
                 }
             }
@@ -3,38 +3,41 @@
 use std::fs;
 use std::path::Path;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use colored::Colorize;
 
-use crate::config::Config;
-use crate::signing::{self, verify_file, HybridSignature, KeyAlgorithm, TrustLevel};
+use rookpkg::archive::{detached_signature_path, PackageArchiveReader};
+use rookpkg::config::Config;
+use rookpkg::repository::{check_key_validity, load_revoked_keys};
+use rookpkg::signing::{self, KeyAlgorithm, TrustLevel};
 
 /// Verify a package's signature
 pub fn run(package_path: &Path, config: &Config) -> Result<()> {
     println!("{} {}", "Verifying:".bold(), package_path.display());
     println!();
 
-    // Read the package signature file (package.rookpkg -> package.rookpkg.sig)
-    let sig_path = package_path.with_extension("rookpkg.sig");
-    if !sig_path.exists() {
+    // The signature is embedded in the archive; older packages have a .sig beside it
+    let reader = PackageArchiveReader::open(package_path)?;
+    let Some(package_signature) = reader.find_signature()? else {
         bail!(
-            "Signature file not found: {}\n\
-            Packages must have a .rookpkg.sig file with the signature.",
-            sig_path.display()
+            "Package is not signed: it has no embedded signature and no {} file",
+            detached_signature_path(package_path).display()
         );
-    }
-
-    let sig_content = fs::read_to_string(&sig_path)
-        .with_context(|| format!("Failed to read signature file: {}", sig_path.display()))?;
-
-    let signature: HybridSignature = serde_json::from_str(&sig_content)
-        .with_context(|| "Failed to parse signature file")?;
+    };
+    let signature = &package_signature.signature;
 
+    if package_signature.is_embedded() {
+        println!("  Signature: embedded");
+    } else {
+        println!("  Signature: {}", detached_signature_path(package_path).display());
+    }
     println!("  Signature fingerprint: {}", signature.fingerprint.cyan());
     println!("  Signed at: {}", signature.timestamp);
 
     // Find the public key
-    let public_key = find_public_key(&signature.fingerprint, config)?;
+    let signed_at = signing::signature_time(signature)?;
+    let public_key = find_public_key(&signature.fingerprint, signed_at, config)?;
 
     println!("  Signer: {} <{}>", public_key.name, public_key.email);
     println!("  Algorithm: {}", public_key.algorithm);
@@ -49,10 +52,18 @@ pub fn run(package_path: &Path, config: &Config) -> Result<()> {
     println!("  Trust level: {}", trust_status);
     println!();
 
-    // Verify the signature using verify_file helper
+    // A key only vouches for what it signed while it was valid
+    if let Err(e) = check_key_validity(signature, &load_revoked_keys(config)?, config) {
+        println!("{}", "  ✗ SIGNING KEY WAS NOT VALID AT SIGNING TIME".red().bold());
+        println!();
+        println!("{}", "⚠️  DO NOT INSTALL THIS PACKAGE!".red().bold());
+        println!();
+        return Err(e.into());
+    }
+
     println!("{}", "Verifying signatures...".dimmed());
 
-    match verify_file(&public_key, package_path, &signature) {
+    match package_signature.verify(&public_key) {
         Ok(()) => {
             println!();
             println!("{}", "  Ed25519 signature:   ✓ VALID".green());
@@ -99,8 +110,8 @@ pub fn run(package_path: &Path, config: &Config) -> Result<()> {
     }
 }
 
-/// Find a public key by fingerprint
-fn find_public_key(fingerprint: &str, config: &Config) -> Result<signing::LoadedPublicKey> {
+/// Find a public key by fingerprint, judging certifications as of `signed_at`
+fn find_public_key(fingerprint: &str, signed_at: DateTime<Utc>, config: &Config) -> Result<signing::LoadedPublicKey> {
     // Search master keys
     if let Some(key) = search_keys_in_dir(&config.signing.master_keys_dir, fingerprint)? {
         let mut key = key;
@@ -118,7 +129,7 @@ fn find_public_key(fingerprint: &str, config: &Config) -> Result<signing::Loaded
             // Try to find the certifying master key
             if let Some(master_key) = search_keys_in_dir(&config.signing.master_keys_dir, &cert.certifier_key)? {
                 // Verify the certification
-                if signing::verify_certification(&cert, &key, &master_key).is_ok() {
+                if signing::verify_certification_at(&cert, &key, &master_key, signed_at).is_ok() {
                     tracing::debug!(
                         "Key {} certified by master key {} for purpose '{}'",
                         key.fingerprint,
@@ -2,9 +2,11 @@
 
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Result, RookpkgError};
+use crate::snapshot::SnapshotBackend;
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -40,6 +42,10 @@ pub struct Config {
     #[serde(default)]
     pub options: OptionsConfig,
 
+    /// Filesystem snapshot configuration
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+
     /// System root directory (for installing to alternate roots like /mnt/rootfs)
     /// This is NOT serialized - it's set via --root CLI flag only
     #[serde(skip)]
@@ -57,6 +63,7 @@ impl Default for Config {
             hooks: HooksConfig::default(),
             download: DownloadConfig::default(),
             options: OptionsConfig::default(),
+            snapshot: SnapshotConfig::default(),
             sysroot: PathBuf::from("/"),
         }
     }
@@ -95,6 +102,10 @@ pub struct SigningConfig {
     /// Path to user's signing key
     pub user_signing_key: PathBuf,
 
+    /// Socket of a signing agent to sign with instead of `user_signing_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_socket: Option<PathBuf>,
+
     /// Allowed signature algorithms
     #[serde(default = "default_algorithms")]
     pub allowed_algorithms: Vec<String>,
@@ -123,6 +134,7 @@ impl Default for SigningConfig {
             master_keys_dir: PathBuf::from("/etc/rookpkg/keys/master"),
             packager_keys_dir: PathBuf::from("/etc/rookpkg/keys/packagers"),
             user_signing_key: config_dir.join("signing-key.secret"),
+            agent_socket: None,
             allowed_algorithms: default_algorithms(),
         }
     }
@@ -143,6 +155,17 @@ pub struct RepositoryConfig {
     /// Priority (lower = higher priority)
     #[serde(default = "default_priority")]
     pub priority: u32,
+
+    /// Full fingerprint of the key that must sign repo.toml and the package index
+    ///
+    /// Without one, the key named by the first repo.toml fetched is kept
+    /// for later updates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+
+    /// Refuse package indexes generated longer ago than this many days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_metadata_age_days: Option<u32>,
 }
 
 fn default_priority() -> u32 {
@@ -160,6 +183,10 @@ pub struct BuildConfig {
     /// Number of parallel jobs for make
     #[serde(default = "default_jobs")]
     pub jobs: u32,
+
+    /// Build features enabled for `feature:` patch conditions
+    #[serde(default)]
+    pub features: Vec<String>,
 }
 
 fn default_jobs() -> u32 {
@@ -178,6 +205,7 @@ impl Default for BuildConfig {
             build_dir: PathBuf::from("/var/lib/rookpkg/build"),
             cache_dir: PathBuf::from("/var/lib/rookpkg/cache"),
             jobs: default_jobs(),
+            features: Vec::new(),
         }
     }
 }
@@ -252,6 +280,45 @@ impl Default for HooksConfig {
     }
 }
 
+/// Filesystem snapshots taken around transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    /// Take a snapshot before and after every transaction
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Snapshot backend ("btrfs" or "archive")
+    #[serde(default)]
+    pub backend: SnapshotBackend,
+
+    /// Directory snapshots are stored in
+    #[serde(default = "default_snapshot_dir")]
+    pub dir: PathBuf,
+
+    /// Subvolume to snapshot (btrfs backend only)
+    #[serde(default = "default_snapshot_subvolume")]
+    pub subvolume: PathBuf,
+}
+
+fn default_snapshot_dir() -> PathBuf {
+    PathBuf::from("/var/lib/rookpkg/snapshots")
+}
+
+fn default_snapshot_subvolume() -> PathBuf {
+    PathBuf::from("/")
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: SnapshotBackend::default(),
+            dir: default_snapshot_dir(),
+            subvolume: default_snapshot_subvolume(),
+        }
+    }
+}
+
 /// Download configuration for parallel package downloads
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadConfig {
@@ -469,10 +536,12 @@ impl Config {
 
         match config_path {
             Some(path) => {
-                let content = std::fs::read_to_string(&path)
-                    .with_context(|| format!("Failed to read config: {}", path.display()))?;
-                toml::from_str(&content)
-                    .with_context(|| format!("Failed to parse config: {}", path.display()))
+                let content = std::fs::read_to_string(&path).map_err(|e| {
+                    RookpkgError::Config(format!("Failed to read {}: {}", path.display(), e))
+                })?;
+                toml::from_str(&content).map_err(|e| {
+                    RookpkgError::Config(format!("Failed to parse {}: {}", path.display(), e))
+                })
             }
             None => Ok(Config::default()),
         }
@@ -292,6 +292,12 @@ impl ArchConverter {
     pub fn pkgbuild_to_rook(&self, pkg: &Pkgbuild) -> Result<String> {
         let mut rook = String::new();
 
+        rook.push_str(&format!(
+            "# Converted from PKGBUILD {} {}\n\n",
+            pkg.pkgname,
+            pkg.full_version()
+        ));
+
         // [package] section
         rook.push_str("[package]\n");
         rook.push_str(&format!(
@@ -351,9 +357,6 @@ impl ArchConverter {
             rook.push('\n');
         }
 
-        // [patches] section (empty for now, would need to handle patch sources)
-        rook.push_str("[patches]\n\n");
-
         // [build_depends] section
         if !pkg.makedepends.is_empty() || !pkg.checkdepends.is_empty() {
             rook.push_str("[build_depends]\n");
@@ -421,6 +424,38 @@ impl ArchConverter {
             rook.push('\n');
         }
 
+        // [provides] section: virtual names, value is the provided version
+        if !pkg.provides.is_empty() {
+            rook.push_str("[provides]\n");
+            for prov in &pkg.provides {
+                let (name, version) = parse_dependency(prov);
+                let version = version
+                    .map(|v| v.trim_start_matches('=').trim().to_string())
+                    .unwrap_or_default();
+                rook.push_str(&format!("\"{}\" = \"{}\"\n", name, version));
+            }
+            rook.push('\n');
+        }
+
+        // [conflicts] and [replaces] sections: value is a version constraint
+        for (section, entries) in [("conflicts", &pkg.conflicts), ("replaces", &pkg.replaces)] {
+            if entries.is_empty() {
+                continue;
+            }
+            rook.push_str(&format!("[{}]\n", section));
+            for entry in entries {
+                if let Some(mapped) = self.map_dependency(entry) {
+                    let (name, version) = parse_dependency(&mapped);
+                    rook.push_str(&format!(
+                        "{} = \"{}\"\n",
+                        name,
+                        version.unwrap_or_else(|| "*".to_string())
+                    ));
+                }
+            }
+            rook.push('\n');
+        }
+
         // [environment] section
         rook.push_str("[environment]\n\n");
 
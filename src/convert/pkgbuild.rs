@@ -103,12 +103,12 @@ impl Pkgbuild {
         // Array start: varname=(
         let array_start_re = Regex::new(r#"^([a-zA-Z_][a-zA-Z0-9_]*)=\((.*)$"#)?;
 
-        let mut lines = content.lines().peekable();
+        let lines = content.lines();
         let mut current_var: Option<String> = None;
         let mut current_array: Vec<String> = Vec::new();
         let mut in_array = false;
 
-        while let Some(line) = lines.next() {
+        for line in lines {
             let trimmed = line.trim();
 
             // Skip comments and empty lines (unless in array)
@@ -183,7 +183,7 @@ impl Pkgbuild {
         let mut quote_char = ' ';
         let mut chars = line.chars().peekable();
 
-        while let Some(c) = chars.next() {
+        for c in chars.by_ref() {
             match c {
                 '"' | '\'' if !in_quotes => {
                     in_quotes = true;
@@ -489,10 +489,12 @@ package() {
 
     #[test]
     fn test_expand_variables() {
-        let mut pkg = Pkgbuild::default();
-        pkg.pkgname = "mypackage".to_string();
-        pkg.pkgver = "2.0.0".to_string();
-        pkg.pkgrel = "1".to_string();
+        let pkg = Pkgbuild {
+            pkgname: "mypackage".to_string(),
+            pkgver: "2.0.0".to_string(),
+            pkgrel: "1".to_string(),
+            ..Default::default()
+        };
 
         let input = "cd $srcdir/${pkgname}-${pkgver}";
         let expanded = pkg.expand_variables(input);
@@ -36,7 +36,7 @@ impl Severity {
     }
 
     /// Parse severity from string (OSV format)
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_uppercase().as_str() {
             "CRITICAL" => Severity::Critical,
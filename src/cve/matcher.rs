@@ -4,7 +4,7 @@
 //! fixed versions, and other criteria.
 
 use super::database::{CveRecord, Severity, VersionRange};
-use semver::Version;
+use crate::version::Version;
 
 /// A package with known vulnerabilities
 #[derive(Debug, Clone)]
@@ -173,7 +173,7 @@ impl CveMatcher {
         after_start && before_end
     }
 
-    /// Compare versions (semver-aware with fallback)
+    /// Compare versions (distribution ordering with fallback)
     fn version_greater(&self, a: &str, b: &str) -> bool {
         match (Version::parse(a), Version::parse(b)) {
             (Ok(va), Ok(vb)) => va > vb,
@@ -217,7 +217,6 @@ impl Default for CveMatcher {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cve::database::Reference;
 
     fn make_cve(id: &str, fixed: Option<&str>) -> CveRecord {
         CveRecord {
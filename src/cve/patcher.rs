@@ -5,6 +5,7 @@
 
 use super::database::{CveRecord, ReferenceType};
 use super::matcher::VulnerablePackage;
+use crate::spec::legacy_patch_order;
 use anyhow::{Context, Result};
 use reqwest::blocking::Client;
 use std::collections::HashMap;
@@ -289,14 +290,20 @@ impl SpecUpdater {
             }
         }
 
-        // Add patches to the [patches] section
-        let patches_table = spec
-            .get_mut("patches")
-            .and_then(|p| p.as_table_mut());
+        // Append the patches to the ordered [[patches]] list, converting an
+        // older [patches] table (applied in key order) first
+        if let Some(table) = spec.as_table_mut() {
+            let mut list = match table.remove("patches") {
+                Some(toml::Value::Array(list)) => list,
+                Some(toml::Value::Table(named)) => {
+                    let mut named: Vec<_> = named.into_iter().collect();
+                    named.sort_by(|a, b| legacy_patch_order(&a.0, &b.0));
+                    named.into_iter().map(|(_, patch)| patch).collect()
+                }
+                _ => Vec::new(),
+            };
 
-        if let Some(patches_table) = patches_table {
-            for (i, patch) in patches.iter().enumerate() {
-                let key = format!("patch{}", patches_table.len() + i);
+            for patch in patches {
                 let mut patch_entry = toml::map::Map::new();
                 patch_entry.insert(
                     "url".to_string(),
@@ -308,12 +315,18 @@ impl SpecUpdater {
                         toml::Value::String(sha256.clone()),
                     );
                 }
+                patch_entry.insert(
+                    "cve".to_string(),
+                    toml::Value::String(patch.cve_id.clone()),
+                );
                 patch_entry.insert(
                     "description".to_string(),
                     toml::Value::String(patch.description.clone()),
                 );
-                patches_table.insert(key, toml::Value::Table(patch_entry));
+                list.push(toml::Value::Table(patch_entry));
             }
+
+            table.insert("patches".to_string(), toml::Value::Array(list));
         }
 
         // Add changelog entry
@@ -522,4 +535,40 @@ changes = ["Initial release"]
         // Check patch was added
         assert!(updated.contains("CVE-2024-0001"));
     }
+
+    #[test]
+    fn test_spec_updater_appends_patches() {
+        let spec_content = r#"
+[package]
+name = "test"
+version = "1.0.0"
+release = 3
+
+[patches]
+patch10 = { file = "third.patch" }
+patch2 = { file = "second.patch" }
+patch0 = { file = "first.patch" }
+"#;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let spec_path = temp_dir.path().join("test.rook");
+        fs::write(&spec_path, spec_content).unwrap();
+
+        let patches = vec![PatchInfo {
+            cve_id: "CVE-2024-0002".to_string(),
+            url: "http://example.com/cve-fix.patch".to_string(),
+            filename: "cve-fix.patch".to_string(),
+            sha256: Some("abc789".to_string()),
+            description: "Security fix".to_string(),
+        }];
+
+        let updated = SpecUpdater::update_spec(&spec_path, &patches, false).unwrap();
+        let spec = crate::spec::PackageSpec::from_str(&updated).unwrap();
+
+        let names: Vec<_> = spec.patches.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["first.patch", "second.patch", "third.patch", "cve-fix.patch"]);
+        assert_eq!(spec.patches[3].cve.as_deref(), Some("CVE-2024-0002"));
+        assert_eq!(spec.patches[3].sha256.as_deref(), Some("abc789"));
+        assert_eq!(spec.package.release, 3);
+    }
 }
@@ -1,11 +1,17 @@
 //! SQLite database for package tracking
 
+use std::collections::HashMap;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
-use anyhow::{Context, Result};
 use rusqlite::{Connection, params};
+use serde::Serialize;
 
+use crate::accounts::{AccountKind, RequestedAccount};
+use crate::error::{Result, RookpkgError};
 use crate::package::{InstalledPackage, InstallReason, PackageFile, Dependency, DependencyType};
+use crate::snapshot::{SnapshotBackend, SnapshotKind};
 
 /// Package database
 pub struct Database {
@@ -21,7 +27,7 @@ impl Database {
         }
 
         let conn = Connection::open(path)
-            .with_context(|| format!("Failed to open database: {}", path.display()))?;
+            .map_err(|e| RookpkgError::Database(format!("Failed to open {}: {}", path.display(), e)))?;
 
         let db = Self { conn };
         db.initialize()?;
@@ -92,6 +98,28 @@ impl Database {
             tracing::info!("Migration complete - files table now supports shared ownership");
         }
 
+        // File mtimes and xattrs were added after the files table
+        let files_exists: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'files'",
+            [],
+            |row| row.get(0),
+        )?;
+        if files_exists {
+            let has_mtime: bool = self.conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('files') WHERE name = 'mtime'",
+                [],
+                |row| row.get(0),
+            )?;
+            if !has_mtime {
+                self.conn.execute_batch(
+                    r#"
+                    ALTER TABLE files ADD COLUMN mtime INTEGER NOT NULL DEFAULT 0;
+                    ALTER TABLE files ADD COLUMN xattrs TEXT NOT NULL DEFAULT '{}';
+                    "#,
+                )?;
+            }
+        }
+
         Ok(())
     }
 
@@ -128,6 +156,8 @@ impl Database {
                 size_bytes INTEGER NOT NULL,
                 checksum TEXT NOT NULL,
                 is_config INTEGER NOT NULL DEFAULT 0,
+                mtime INTEGER NOT NULL DEFAULT 0,
+                xattrs TEXT NOT NULL DEFAULT '{}',  -- JSON: name -> hex value
                 FOREIGN KEY (package_id) REFERENCES packages(id) ON DELETE CASCADE,
                 UNIQUE(package_id, path)
             );
@@ -142,6 +172,15 @@ impl Database {
                 FOREIGN KEY (package_id) REFERENCES packages(id) ON DELETE CASCADE
             );
 
+            -- Names installed packages provide, including detected sonames
+            CREATE TABLE IF NOT EXISTS provides (
+                id INTEGER PRIMARY KEY,
+                package_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,  -- empty when unversioned
+                FOREIGN KEY (package_id) REFERENCES packages(id) ON DELETE CASCADE
+            );
+
             -- Available packages (repository metadata)
             CREATE TABLE IF NOT EXISTS available_packages (
                 id INTEGER PRIMARY KEY,
@@ -186,11 +225,55 @@ impl Database {
                 reason TEXT NOT NULL DEFAULT ''
             );
 
+            -- Transaction history (one row per executed transaction)
+            CREATE TABLE IF NOT EXISTS transactions (
+                id INTEGER PRIMARY KEY,
+                transaction_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                user TEXT NOT NULL,
+                command_line TEXT NOT NULL,
+                result TEXT NOT NULL
+            );
+
+            -- Operations performed by each recorded transaction
+            CREATE TABLE IF NOT EXISTS transaction_operations (
+                id INTEGER PRIMARY KEY,
+                history_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                package TEXT NOT NULL,
+                old_version TEXT,  -- NULL for installs
+                new_version TEXT,  -- NULL for removals
+                install_reason TEXT NOT NULL DEFAULT 'explicit',
+                FOREIGN KEY (history_id) REFERENCES transactions(id) ON DELETE CASCADE
+            );
+
+            -- Filesystem snapshots, linked to the transaction they were taken around
+            CREATE TABLE IF NOT EXISTS snapshots (
+                id INTEGER PRIMARY KEY,
+                snapshot_id TEXT NOT NULL UNIQUE,
+                transaction_id TEXT NOT NULL,
+                kind TEXT NOT NULL,  -- pre or post
+                backend TEXT NOT NULL,
+                created INTEGER NOT NULL
+            );
+
+            -- System users and groups requested by packages (kept after removal,
+            -- since the accounts themselves are never deleted)
+            CREATE TABLE IF NOT EXISTS package_accounts (
+                package TEXT NOT NULL,
+                kind TEXT NOT NULL,  -- user or group
+                name TEXT NOT NULL,
+                created INTEGER NOT NULL,  -- whether installing the package created it
+                PRIMARY KEY (package, kind, name)
+            );
+
             -- Create indices
+            CREATE INDEX IF NOT EXISTS idx_history_ops ON transaction_operations(history_id);
             CREATE INDEX IF NOT EXISTS idx_files_path ON files(path);
             CREATE INDEX IF NOT EXISTS idx_files_package ON files(package_id);
             CREATE INDEX IF NOT EXISTS idx_deps_package ON dependencies(package_id);
             CREATE INDEX IF NOT EXISTS idx_deps_name ON dependencies(depends_on);
+            CREATE INDEX IF NOT EXISTS idx_provides_name ON provides(name);
             CREATE INDEX IF NOT EXISTS idx_available_name ON available_packages(name);
             "#,
         )?;
@@ -277,16 +360,16 @@ impl Database {
             })
         })?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to list packages")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list packages"))
     }
 
     /// Add a file to a package
     pub fn add_file(&self, package_id: i64, file: &PackageFile) -> Result<()> {
         self.conn.execute(
             r#"
-            INSERT INTO files (package_id, path, mode, owner, "group", size_bytes, checksum, is_config)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            INSERT INTO files (package_id, path, mode, owner, "group", size_bytes, checksum, is_config, mtime, xattrs)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
             "#,
             params![
                 package_id,
@@ -297,6 +380,8 @@ impl Database {
                 file.size_bytes,
                 file.checksum,
                 file.is_config,
+                file.mtime,
+                serde_json::to_string(&file.xattrs)?,
             ],
         )?;
 
@@ -307,7 +392,8 @@ impl Database {
     pub fn get_files(&self, package_name: &str) -> Result<Vec<PackageFile>> {
         let mut stmt = self.conn.prepare(
             r#"
-            SELECT f.path, f.mode, f.owner, f."group", f.size_bytes, f.checksum, f.is_config
+            SELECT f.path, f.mode, f.owner, f."group", f.size_bytes, f.checksum, f.is_config,
+                   f.mtime, f.xattrs
             FROM files f
             JOIN packages p ON f.package_id = p.id
             WHERE p.name = ?1
@@ -324,11 +410,13 @@ impl Database {
                 size_bytes: row.get(4)?,
                 checksum: row.get(5)?,
                 is_config: row.get(6)?,
+                mtime: row.get(7)?,
+                xattrs: serde_json::from_str(&row.get::<_, String>(8)?).unwrap_or_default(),
             })
         })?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to get files")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to get files"))
     }
 
     /// Check if a file path is owned by any package (returns first owner for backwards compat)
@@ -351,8 +439,8 @@ impl Database {
 
         let rows = stmt.query_map(params![path], |row| row.get(0))?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to get file owners")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to get file owners"))
     }
 
     /// Add a dependency
@@ -394,25 +482,71 @@ impl Database {
             })
         })?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to get dependencies")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to get dependencies"))
     }
 
-    /// Get reverse dependencies (packages that depend on this one)
+    /// Get reverse dependencies (packages that depend on this one, by name
+    /// or through something it provides)
     pub fn get_reverse_dependencies(&self, package_name: &str) -> Result<Vec<String>> {
         let mut stmt = self.conn.prepare(
             r#"
             SELECT DISTINCT p.name
             FROM dependencies d
             JOIN packages p ON d.package_id = p.id
-            WHERE d.depends_on = ?1
+            WHERE p.name != ?1
+              AND (d.depends_on = ?1 OR d.depends_on IN (
+                  SELECT pr.name FROM provides pr
+                  JOIN packages q ON pr.package_id = q.id
+                  WHERE q.name = ?1
+              ))
             "#
         )?;
 
         let rows = stmt.query_map(params![package_name], |row| row.get(0))?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to get reverse dependencies")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to get reverse dependencies"))
+    }
+
+    /// Record a name an installed package provides
+    pub fn add_provide(&self, package_id: i64, name: &str, version: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO provides (package_id, name, version) VALUES (?1, ?2, ?3)",
+            params![package_id, name, version],
+        )?;
+        Ok(())
+    }
+
+    /// Get the names a package provides: name -> version ("" = unversioned)
+    pub fn get_provides(&self, package_name: &str) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT pr.name, pr.version
+            FROM provides pr
+            JOIN packages p ON pr.package_id = p.id
+            WHERE p.name = ?1
+            "#
+        )?;
+        let rows = stmt.query_map(params![package_name], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<HashMap<_, _>>>()
+            .map_err(db_error("Failed to get provides"))
+    }
+
+    /// Get the installed packages that provide a name
+    pub fn get_providers(&self, name: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT DISTINCT p.name
+            FROM provides pr
+            JOIN packages p ON pr.package_id = p.id
+            WHERE pr.name = ?1
+            ORDER BY p.name
+            "#
+        )?;
+        let rows = stmt.query_map(params![name], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to get providers"))
     }
 
     /// Hold a package (prevent automatic upgrades)
@@ -492,8 +626,48 @@ impl Database {
             })
         })?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to list held packages")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list held packages"))
+    }
+
+    /// Record a revoked key
+    ///
+    /// A key revoked again keeps the earlier of the two revocation dates, so
+    /// an older revocation list can never shorten a revocation.
+    pub fn revoke_key(&self, revoked: &RevokedKey) -> Result<()> {
+        self.conn.execute(
+            r#"
+            INSERT INTO revoked_keys (fingerprint, revoked_date, reason, revoked_by)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(fingerprint) DO UPDATE SET
+                revoked_date = excluded.revoked_date,
+                reason = excluded.reason,
+                revoked_by = excluded.revoked_by
+            WHERE excluded.revoked_date < revoked_keys.revoked_date
+            "#,
+            params![revoked.fingerprint, revoked.revoked_date, revoked.reason, revoked.revoked_by],
+        )?;
+
+        Ok(())
+    }
+
+    /// List all revoked keys
+    pub fn list_revoked_keys(&self) -> Result<Vec<RevokedKey>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT fingerprint, revoked_date, reason, revoked_by FROM revoked_keys ORDER BY revoked_date"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(RevokedKey {
+                fingerprint: row.get(0)?,
+                revoked_date: row.get(1)?,
+                reason: row.get(2)?,
+                revoked_by: row.get(3)?,
+            })
+        })?;
+
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list revoked keys"))
     }
 
     /// Set the install reason for a package
@@ -543,8 +717,8 @@ impl Database {
             })
         })?;
 
-        rows.collect::<Result<Vec<_>, _>>()
-            .context("Failed to list dependency packages")
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list dependency packages"))
     }
 
     /// Find orphan packages (dependencies no longer needed by any explicit package)
@@ -595,11 +769,15 @@ impl Database {
         while let Some(pkg_name) = to_process.pop() {
             let deps = self.get_dependencies(&pkg_name)?;
             for dep in deps {
-                if !needed.contains(&dep.depends_on) {
-                    // Check if this dependency is actually installed
-                    if self.get_package(&dep.depends_on)?.is_some() {
-                        needed.insert(dep.depends_on.clone());
-                        to_process.push(dep.depends_on);
+                // A virtual name (such as a soname) keeps its providers
+                let providers = if self.get_package(&dep.depends_on)?.is_some() {
+                    vec![dep.depends_on]
+                } else {
+                    self.get_providers(&dep.depends_on)?
+                };
+                for provider in providers {
+                    if needed.insert(provider.clone()) {
+                        to_process.push(provider);
                     }
                 }
             }
@@ -607,10 +785,280 @@ impl Database {
 
         Ok(needed)
     }
+
+    /// Record an executed transaction in the history
+    ///
+    /// Returns the history ID of the new record.
+    pub fn record_transaction(
+        &self,
+        transaction_id: &str,
+        user: &str,
+        command_line: &str,
+        result: &str,
+        operations: &[HistoryOperation],
+    ) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO transactions (transaction_id, timestamp, user, command_line, result)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                transaction_id,
+                chrono::Utc::now().timestamp(),
+                user,
+                command_line,
+                result
+            ],
+        )?;
+        let history_id = tx.last_insert_rowid();
+
+        for op in operations {
+            tx.execute(
+                "INSERT INTO transaction_operations
+                 (history_id, action, package, old_version, new_version, install_reason)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    history_id,
+                    op.action.to_string(),
+                    op.package,
+                    op.old_version,
+                    op.new_version,
+                    op.install_reason.to_string()
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(history_id)
+    }
+
+    /// List recorded transactions, newest first
+    pub fn list_history(&self, limit: Option<usize>) -> Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, transaction_id, timestamp, user, command_line, result
+             FROM transactions ORDER BY id DESC LIMIT ?1"
+        )?;
+
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        let rows = stmt.query_map(params![limit], Self::history_from_row)?;
+        let mut entries = rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list transaction history"))?;
+
+        for entry in &mut entries {
+            entry.operations = self.get_history_operations(entry.id)?;
+        }
+
+        Ok(entries)
+    }
+
+    /// Get a recorded transaction by history ID
+    pub fn get_history(&self, id: i64) -> Result<Option<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, transaction_id, timestamp, user, command_line, result
+             FROM transactions WHERE id = ?1"
+        )?;
+
+        let mut rows = stmt.query_map(params![id], Self::history_from_row)?;
+        match rows.next() {
+            Some(entry) => {
+                let mut entry = entry?;
+                entry.operations = self.get_history_operations(id)?;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn history_from_row(row: &rusqlite::Row) -> rusqlite::Result<HistoryEntry> {
+        Ok(HistoryEntry {
+            id: row.get(0)?,
+            transaction_id: row.get(1)?,
+            timestamp: row.get(2)?,
+            user: row.get(3)?,
+            command_line: row.get(4)?,
+            result: row.get(5)?,
+            operations: Vec::new(),
+        })
+    }
+
+    fn get_history_operations(&self, history_id: i64) -> Result<Vec<HistoryOperation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT action, package, old_version, new_version, install_reason
+             FROM transaction_operations WHERE history_id = ?1 ORDER BY id"
+        )?;
+
+        let rows = stmt.query_map(params![history_id], |row| {
+            let action: String = row.get(0)?;
+            let reason: String = row.get(4)?;
+            Ok(HistoryOperation {
+                action: action.parse().unwrap_or(HistoryAction::Install),
+                package: row.get(1)?,
+                old_version: row.get(2)?,
+                new_version: row.get(3)?,
+                install_reason: reason.parse().unwrap_or_default(),
+            })
+        })?;
+
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to read transaction operations"))
+    }
+
+    /// Record that `package` requested a system account
+    ///
+    /// An account stays marked as created by the package when a later
+    /// upgrade finds it already present.
+    pub fn add_account(&self, package: &str, account: &RequestedAccount) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO package_accounts (package, kind, name, created) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (package, kind, name) DO UPDATE SET created = created OR excluded.created",
+            params![package, account.kind.to_string(), account.name, account.created],
+        )?;
+        Ok(())
+    }
+
+    /// Get the system accounts a package requested
+    pub fn get_accounts(&self, package: &str) -> Result<Vec<RequestedAccount>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT kind, name, created FROM package_accounts WHERE package = ?1 ORDER BY kind, name",
+        )?;
+        let rows = stmt.query_map(params![package], |row| {
+            let kind: String = row.get(0)?;
+            Ok(RequestedAccount {
+                kind: kind.parse().unwrap_or(AccountKind::User),
+                name: row.get(1)?,
+                created: row.get(2)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to read package accounts"))
+    }
+
+    /// Write a consistent copy of the database to `path`
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        // VACUUM INTO refuses to overwrite an existing file
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        self.conn
+            .execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map_err(|e| RookpkgError::Database(format!("Failed to copy database to {}: {}", path.display(), e)))?;
+        Ok(())
+    }
+
+    /// Replace the installed packages, their files, dependencies, provides
+    /// and account records with those of a database copy made by
+    /// [`Self::backup_to`]
+    ///
+    /// History, snapshots, holds and keys are left as they are, so a
+    /// rollback does not forget what happened after the copy was taken.
+    pub fn restore_packages_from(&self, path: &Path) -> Result<()> {
+        // Bring an older copy up to the current schema first
+        drop(Self::open(path)?);
+
+        self.conn
+            .execute("ATTACH DATABASE ?1 AS snapshot", params![path.to_string_lossy()])
+            .map_err(|e| RookpkgError::Database(format!("Failed to open {}: {}", path.display(), e)))?;
+        let restored = self.conn.execute_batch(
+            r#"
+            BEGIN;
+            DELETE FROM main.packages;
+            DELETE FROM main.files;
+            DELETE FROM main.dependencies;
+            DELETE FROM main.provides;
+            DELETE FROM main.package_accounts;
+            INSERT INTO main.packages SELECT * FROM snapshot.packages;
+            INSERT INTO main.files SELECT * FROM snapshot.files;
+            INSERT INTO main.dependencies SELECT * FROM snapshot.dependencies;
+            INSERT INTO main.provides SELECT * FROM snapshot.provides;
+            INSERT INTO main.package_accounts SELECT * FROM snapshot.package_accounts;
+            COMMIT;
+            "#,
+        );
+        if restored.is_err() {
+            self.conn.execute_batch("ROLLBACK").ok();
+        }
+        self.conn.execute("DETACH DATABASE snapshot", []).ok();
+        restored.map_err(|e| {
+            RookpkgError::Database(format!("Failed to restore packages from {}: {}", path.display(), e))
+        })
+    }
+
+    /// Record a snapshot (ignored if it is already recorded)
+    pub fn add_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO snapshots (snapshot_id, transaction_id, kind, backend, created)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                snapshot.snapshot_id,
+                snapshot.transaction_id,
+                snapshot.kind.to_string(),
+                snapshot.backend.to_string(),
+                snapshot.created
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// List recorded snapshots, newest first
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotRecord>> {
+        let mut stmt = self.conn.prepare(&format!("{} ORDER BY s.id DESC", SNAPSHOT_QUERY))?;
+        let rows = stmt.query_map([], Self::snapshot_from_row)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error("Failed to list snapshots"))
+    }
+
+    /// Get a recorded snapshot by its snapshot ID
+    pub fn get_snapshot(&self, snapshot_id: &str) -> Result<Option<SnapshotRecord>> {
+        let mut stmt = self.conn.prepare(&format!("{} WHERE s.snapshot_id = ?1", SNAPSHOT_QUERY))?;
+        let mut rows = stmt.query_map(params![snapshot_id], Self::snapshot_from_row)?;
+        rows.next().transpose().map_err(db_error("Failed to read snapshot"))
+    }
+
+    fn snapshot_from_row(row: &rusqlite::Row) -> rusqlite::Result<SnapshotRecord> {
+        let kind: String = row.get(2)?;
+        let backend: String = row.get(3)?;
+        Ok(SnapshotRecord {
+            snapshot_id: row.get(0)?,
+            transaction_id: row.get(1)?,
+            kind: kind.parse().unwrap_or(SnapshotKind::Pre),
+            backend: backend.parse().unwrap_or_default(),
+            created: row.get(4)?,
+            history_id: row.get(5)?,
+            command_line: row.get(6)?,
+        })
+    }
 }
 
-/// Information about a held package
+/// Turn a failed query into a database error saying what was being done
+fn db_error(what: &'static str) -> impl FnOnce(rusqlite::Error) -> RookpkgError {
+    move |e| RookpkgError::Database(format!("{}: {}", what, e))
+}
+
+/// Snapshot columns plus the history record of the linked transaction
+const SNAPSHOT_QUERY: &str = "
+    SELECT s.snapshot_id, s.transaction_id, s.kind, s.backend, s.created, t.id, t.command_line
+    FROM snapshots s
+    LEFT JOIN transactions t ON t.id = (
+        SELECT MAX(id) FROM transactions WHERE transaction_id = s.transaction_id
+    )";
+
+/// A filesystem snapshot taken around a transaction
 #[derive(Debug, Clone)]
+pub struct SnapshotRecord {
+    /// Backend-specific snapshot name
+    pub snapshot_id: String,
+    /// ID of the on-disk transaction the snapshot belongs to
+    pub transaction_id: String,
+    pub kind: SnapshotKind,
+    pub backend: SnapshotBackend,
+    /// When the snapshot was taken (Unix timestamp)
+    pub created: i64,
+    /// History ID of the transaction, once it has been recorded
+    pub history_id: Option<i64>,
+    pub command_line: Option<String>,
+}
+
+/// Information about a held package
+#[derive(Debug, Clone, Serialize)]
 pub struct HoldInfo {
     /// Package name
     pub name: String,
@@ -622,6 +1070,81 @@ pub struct HoldInfo {
     pub reason: String,
 }
 
+/// A signing key that may no longer be used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RevokedKey {
+    /// Fingerprint of the revoked key
+    pub fingerprint: String,
+    /// Signatures made from this time on are refused (Unix timestamp)
+    pub revoked_date: i64,
+    /// Why the key was revoked
+    pub reason: String,
+    /// Fingerprint of the key that issued the revocation
+    pub revoked_by: String,
+}
+
+/// Kind of change a recorded operation made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Install,
+    Remove,
+    Upgrade,
+}
+
+impl fmt::Display for HistoryAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryAction::Install => write!(f, "install"),
+            HistoryAction::Remove => write!(f, "remove"),
+            HistoryAction::Upgrade => write!(f, "upgrade"),
+        }
+    }
+}
+
+impl FromStr for HistoryAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "install" => Ok(HistoryAction::Install),
+            "remove" => Ok(HistoryAction::Remove),
+            "upgrade" => Ok(HistoryAction::Upgrade),
+            _ => Err(format!("Unknown history action: {}", s)),
+        }
+    }
+}
+
+/// One package operation of a recorded transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryOperation {
+    pub action: HistoryAction,
+    pub package: String,
+    /// Full version (version-release) before the transaction, if installed
+    pub old_version: Option<String>,
+    /// Full version after the transaction, if installed
+    pub new_version: Option<String>,
+    /// Install reason of the package (before removal, for removals)
+    pub install_reason: InstallReason,
+}
+
+/// A transaction recorded in the history
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    /// History ID (what `rookpkg history` commands take)
+    pub id: i64,
+    /// ID of the on-disk transaction that ran
+    pub transaction_id: String,
+    /// When the transaction finished (Unix timestamp)
+    pub timestamp: i64,
+    /// User who ran the command (the invoking user under sudo)
+    pub user: String,
+    pub command_line: String,
+    /// Final transaction state: completed, rolled_back or failed
+    pub result: String,
+    pub operations: Vec<HistoryOperation>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -715,4 +1238,96 @@ mod tests {
         let orphan_names: Vec<&str> = orphans.iter().map(|p| p.name.as_str()).collect();
         assert!(!orphan_names.contains(&"lib-needed"));
     }
+
+    #[test]
+    fn test_provides() {
+        let db = Database::open_in_memory().unwrap();
+        let package = |name: &str, install_reason| InstalledPackage {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            release: 1,
+            install_date: 1234567890,
+            size_bytes: 0,
+            checksum: String::new(),
+            spec: String::new(),
+            install_reason,
+        };
+        let curl = db.add_package(&package("curl", InstallReason::Explicit)).unwrap();
+        let openssl = db.add_package(&package("openssl", InstallReason::Dependency)).unwrap();
+
+        db.add_provide(openssl, "so:libssl.so.3", "").unwrap();
+        db.add_dependency(&Dependency {
+            package_id: curl,
+            depends_on: "so:libssl.so.3".to_string(),
+            constraint: String::new(),
+            dep_type: DependencyType::Runtime,
+        }).unwrap();
+
+        assert_eq!(db.get_provides("openssl").unwrap().get("so:libssl.so.3"), Some(&String::new()));
+        assert_eq!(db.get_providers("so:libssl.so.3").unwrap(), vec!["openssl"]);
+        assert_eq!(db.get_reverse_dependencies("openssl").unwrap(), vec!["curl"]);
+        assert!(db.find_orphans().unwrap().is_empty());
+
+        // Provides go with the package
+        db.remove_package("openssl").unwrap();
+        assert!(db.get_providers("so:libssl.so.3").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transaction_history() {
+        let db = Database::open_in_memory().unwrap();
+
+        let upgrade = HistoryOperation {
+            action: HistoryAction::Upgrade,
+            package: "openssl".to_string(),
+            old_version: Some("3.0.0-1".to_string()),
+            new_version: Some("3.1.0-1".to_string()),
+            install_reason: InstallReason::Dependency,
+        };
+        let first = db
+            .record_transaction("tx1", "alice", "rookpkg upgrade", "completed", &[upgrade])
+            .unwrap();
+        let second = db
+            .record_transaction("tx2", "alice", "rookpkg remove curl", "failed", &[])
+            .unwrap();
+        assert!(second > first);
+
+        let entry = db.get_history(first).unwrap().unwrap();
+        assert_eq!(entry.transaction_id, "tx1");
+        assert_eq!(entry.command_line, "rookpkg upgrade");
+        assert_eq!(entry.operations.len(), 1);
+        assert_eq!(entry.operations[0].action, HistoryAction::Upgrade);
+        assert_eq!(entry.operations[0].install_reason, InstallReason::Dependency);
+        assert!(db.get_history(second + 1).unwrap().is_none());
+
+        let latest = db.list_history(Some(1)).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].id, second);
+        assert_eq!(db.list_history(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_revoked_keys() {
+        let db = Database::open_in_memory().unwrap();
+        let revoked = RevokedKey {
+            fingerprint: "HYBRID:SHA256:0123".to_string(),
+            revoked_date: 2000,
+            reason: "Rotated".to_string(),
+            revoked_by: "HYBRID:SHA256:0123".to_string(),
+        };
+        db.revoke_key(&revoked).unwrap();
+
+        // A later date does not replace the recorded one, an earlier does
+        db.revoke_key(&RevokedKey { revoked_date: 3000, ..revoked.clone() }).unwrap();
+        assert_eq!(db.list_revoked_keys().unwrap(), vec![revoked.clone()]);
+
+        let compromised = RevokedKey {
+            revoked_date: 1000,
+            reason: "Compromised".to_string(),
+            revoked_by: "HYBRID:SHA256:4567".to_string(),
+            ..revoked
+        };
+        db.revoke_key(&compromised).unwrap();
+        assert_eq!(db.list_revoked_keys().unwrap(), vec![compromised]);
+    }
 }
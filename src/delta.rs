@@ -8,7 +8,10 @@
 //!
 //! A delta file is a tar archive containing:
 //! - `.DELTAINFO` - Delta metadata (TOML format)
-//! - `data.delta.zst` - Compressed binary diff of data.tar.zst
+//! - `data.delta.zst` - Compressed binary diff of the uncompressed data tarballs
+//!
+//! Building and applying a delta streams both tarballs through small
+//! buffers, so memory use does not grow with the package size.
 //!
 //! ## Usage
 //!
@@ -26,7 +29,7 @@
 
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
@@ -35,6 +38,7 @@ use sha2::{Digest, Sha256};
 
 use crate::archive::{PackageArchiveReader, PackageInfo};
 use crate::download::compute_sha256;
+use crate::resolver::compare_versions;
 
 /// Delta file extension
 pub const DELTA_EXTENSION: &str = ".rookdelta";
@@ -45,6 +49,15 @@ const MIN_SAVINGS_PERCENT: u64 = 10;
 /// Block size for delta computation (4KB)
 const BLOCK_SIZE: usize = 4096;
 
+/// Longest insert operation; longer runs of new bytes are split
+const MAX_INSERT_SIZE: usize = 1024 * 1024;
+
+/// How much of the new data is read at a time while diffing
+const WINDOW_READ_SIZE: usize = 64 * 1024;
+
+/// Magic and format version at the start of the delta stream
+const DELTA_MAGIC: &[u8; 10] = b"ROOKDELTA\x01";
+
 /// Delta package metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeltaInfo {
@@ -117,7 +130,7 @@ pub enum DeltaAlgorithm {
 }
 
 /// A delta operation in the binary diff
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum DeltaOp {
     /// Copy bytes from the old file at given offset and length
     Copy { offset: u64, length: u64 },
@@ -125,15 +138,406 @@ enum DeltaOp {
     Insert { data: Vec<u8> },
 }
 
-/// Delta data structure (internal format)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct DeltaData {
-    /// Operations to transform old -> new
-    ops: Vec<DeltaOp>,
+impl DeltaOp {
+    /// Serialize the operation
+    fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        match self {
+            DeltaOp::Copy { offset, length } => {
+                out.write_all(&[0x01])?; // Copy marker
+                out.write_all(&offset.to_le_bytes())?;
+                out.write_all(&length.to_le_bytes())?;
+            }
+            DeltaOp::Insert { data } => {
+                out.write_all(&[0x02])?; // Insert marker
+                out.write_all(&(data.len() as u64).to_le_bytes())?;
+                out.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Header of the delta stream (internal format); `op_count` operations
+/// follow it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeltaHeader {
     /// Expected size of the output
     output_size: u64,
     /// Checksum of the output
     output_sha256: String,
+    /// Number of operations
+    op_count: u32,
+}
+
+impl DeltaHeader {
+    /// Serialize the header
+    fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        out.write_all(DELTA_MAGIC)?;
+        out.write_all(&self.output_size.to_le_bytes())?;
+        out.write_all(&hex::decode(&self.output_sha256)?)?;
+        out.write_all(&self.op_count.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Parse the header at the start of a delta stream
+    fn read_from(input: &mut impl Read) -> Result<Self> {
+        let mut magic = [0u8; 10];
+        input.read_exact(&mut magic).context("Invalid delta file format")?;
+        if &magic != DELTA_MAGIC {
+            bail!("Invalid delta file format");
+        }
+
+        let output_size = read_u64(input)?;
+        let mut sha = [0u8; 32];
+        input.read_exact(&mut sha).context("Delta file truncated")?;
+        let mut count = [0u8; 4];
+        input.read_exact(&mut count).context("Delta file truncated")?;
+
+        Ok(Self {
+            output_size,
+            output_sha256: hex::encode(sha),
+            op_count: u32::from_le_bytes(count),
+        })
+    }
+}
+
+/// Read a little-endian u64 from a delta stream
+fn read_u64(input: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes).context("Delta file truncated")?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Decompress the data.tar.zst payload of a package into `output`
+fn extract_data_tar(package: &Path, output: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(File::open(package)?);
+
+    for entry in archive.entries_with_seek()? {
+        let entry = entry?;
+        if entry.path()?.to_string_lossy() == "data.tar.zst" {
+            zstd::stream::copy_decode(entry, File::create(output)?)?;
+            return Ok(());
+        }
+    }
+
+    bail!("Package does not contain data.tar.zst")
+}
+
+/// Simple hash function for blocks
+fn hash_block(data: &[u8]) -> u64 {
+    // FNV-1a hash
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Read into `buf` until it is full or the input ends
+fn read_up_to(input: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Sequential view of the new data, keeping at least a block ahead
+struct Window<R> {
+    reader: R,
+    buf: Vec<u8>,
+    start: usize,
+    eof: bool,
+}
+
+impl<R: Read> Window<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, buf: Vec::new(), start: 0, eof: false }
+    }
+
+    /// Make at least `n` bytes visible, unless the input ends first
+    fn fill(&mut self, n: usize) -> Result<()> {
+        while self.buf.len() - self.start < n && !self.eof {
+            self.buf.drain(..self.start);
+            self.start = 0;
+            let len = self.buf.len();
+            self.buf.resize(len + WINDOW_READ_SIZE, 0);
+            let read = self.reader.read(&mut self.buf[len..])?;
+            self.buf.truncate(len + read);
+            self.eof = read == 0;
+        }
+        Ok(())
+    }
+
+    /// The visible bytes
+    fn data(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+    }
+}
+
+/// Delta operations spooled to a file until their count is known
+struct OpSpool {
+    file: BufWriter<File>,
+    count: u32,
+    /// Copy still being extended by contiguous copies
+    pending_copy: Option<(u64, u64)>,
+}
+
+impl OpSpool {
+    fn new() -> Result<Self> {
+        Ok(Self { file: BufWriter::new(tempfile::tempfile()?), count: 0, pending_copy: None })
+    }
+
+    fn copy(&mut self, offset: u64, length: u64) -> Result<()> {
+        match &mut self.pending_copy {
+            Some((start, len)) if *start + *len == offset => *len += length,
+            _ => {
+                self.flush_copy()?;
+                self.pending_copy = Some((offset, length));
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, data: Vec<u8>) -> Result<()> {
+        self.flush_copy()?;
+        self.push(DeltaOp::Insert { data })
+    }
+
+    fn flush_copy(&mut self) -> Result<()> {
+        match self.pending_copy.take() {
+            Some((offset, length)) => self.push(DeltaOp::Copy { offset, length }),
+            None => Ok(()),
+        }
+    }
+
+    fn push(&mut self, op: DeltaOp) -> Result<()> {
+        op.write_to(&mut self.file)?;
+        self.count = self
+            .count
+            .checked_add(1)
+            .ok_or_else(|| anyhow::anyhow!("Delta has too many operations"))?;
+        Ok(())
+    }
+
+    /// The spooled operations, rewound, and their count
+    fn finish(mut self) -> Result<(File, u32)> {
+        self.flush_copy()?;
+        let mut file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok((file, self.count))
+    }
+}
+
+/// Write the delta turning `old` into `new` (both uncompressed data
+/// tarballs) to `output`, zstd-compressed
+///
+/// Block-based diff (simplified bsdiff-style algorithm). Only the block
+/// hashes of `old` are kept in memory; both files are read through small
+/// buffers.
+fn write_delta(old: &Path, new: &Path, output: &Path) -> Result<()> {
+    let mut old_file = File::open(old)?;
+    let old_len = old_file.metadata()?.len();
+
+    // Build a hash table of old file blocks for quick lookup
+    let mut block_index: HashMap<u64, Vec<u64>> = HashMap::new();
+    let mut block = vec![0u8; BLOCK_SIZE];
+    {
+        let mut reader = BufReader::new(&old_file);
+        let mut offset = 0u64;
+        loop {
+            let n = read_up_to(&mut reader, &mut block)?;
+            if n == 0 {
+                break;
+            }
+            block_index.entry(hash_block(&block[..n])).or_default().push(offset);
+            offset += n as u64;
+        }
+    }
+
+    // Scan through new file looking for matching blocks
+    let mut ops = OpSpool::new()?;
+    let mut window = Window::new(File::open(new)?);
+    let mut pending_insert: Vec<u8> = Vec::new();
+
+    loop {
+        window.fill(BLOCK_SIZE)?;
+        let new_block = &window.data()[..window.data().len().min(BLOCK_SIZE)];
+        if new_block.is_empty() {
+            break;
+        }
+        let block_len = new_block.len();
+
+        // Try to find a matching block in old file
+        let mut found = None;
+        if let Some(positions) = block_index.get(&hash_block(new_block)) {
+            for &old_pos in positions {
+                // Verify the match (hash collision check)
+                if old_pos + block_len as u64 > old_len {
+                    continue;
+                }
+                old_file.seek(SeekFrom::Start(old_pos))?;
+                old_file.read_exact(&mut block[..block_len])?;
+                if block[..block_len] == *new_block {
+                    found = Some(old_pos);
+                    break;
+                }
+            }
+        }
+
+        let Some(old_pos) = found else {
+            // No match found, add to pending inserts
+            pending_insert.push(new_block[0]);
+            window.consume(1);
+            if pending_insert.len() >= MAX_INSERT_SIZE {
+                ops.insert(std::mem::take(&mut pending_insert))?;
+            }
+            continue;
+        };
+
+        // Found a match! First, flush any pending inserts
+        if !pending_insert.is_empty() {
+            ops.insert(std::mem::take(&mut pending_insert))?;
+        }
+        window.consume(block_len);
+        let mut match_len = block_len as u64;
+
+        // Try to extend the match forward
+        loop {
+            window.fill(BLOCK_SIZE)?;
+            let available = window.data().len().min((old_len - old_pos - match_len) as usize);
+            if available == 0 {
+                break;
+            }
+            let want = available.min(BLOCK_SIZE);
+            old_file.seek(SeekFrom::Start(old_pos + match_len))?;
+            old_file.read_exact(&mut block[..want])?;
+            let same = window.data()[..want]
+                .iter()
+                .zip(&block[..want])
+                .take_while(|(a, b)| a == b)
+                .count();
+            window.consume(same);
+            match_len += same as u64;
+            if same < want {
+                break;
+            }
+        }
+
+        ops.copy(old_pos, match_len)?;
+    }
+
+    // Flush any remaining pending inserts
+    if !pending_insert.is_empty() {
+        ops.insert(pending_insert)?;
+    }
+
+    // The header comes first, so the operations are copied in after it
+    let (mut spooled, op_count) = ops.finish()?;
+    let header = DeltaHeader {
+        output_size: fs::metadata(new)?.len(),
+        output_sha256: compute_sha256(new)?,
+        op_count,
+    };
+    let mut encoder = zstd::stream::Encoder::new(File::create(output)?, 19)?;
+    header.write_to(&mut encoder)?;
+    std::io::copy(&mut spooled, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Writer that hashes and counts what passes through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Apply a (decompressed) delta stream to `old`, an uncompressed data
+/// tarball, writing the new tarball to `output`
+///
+/// Operations are applied as they are read, so neither tarball nor the
+/// delta is held in memory.
+fn apply_delta(old: &Path, mut delta: impl Read, output: impl Write) -> Result<()> {
+    let header = DeltaHeader::read_from(&mut delta)?;
+    let mut old_file = File::open(old)?;
+    let old_len = old_file.metadata()?.len();
+    let mut out = HashingWriter { inner: output, hasher: Sha256::new(), written: 0 };
+
+    for _ in 0..header.op_count {
+        let mut marker = [0u8; 1];
+        delta.read_exact(&mut marker).context("Delta file truncated")?;
+        match marker[0] {
+            0x01 => {
+                // Copy
+                let offset = read_u64(&mut delta)?;
+                let length = read_u64(&mut delta)?;
+                match offset.checked_add(length) {
+                    Some(end) if end <= old_len => {}
+                    _ => bail!(
+                        "Delta copy operation out of bounds: {}+{} (old file size: {})",
+                        offset,
+                        length,
+                        old_len
+                    ),
+                }
+                old_file.seek(SeekFrom::Start(offset))?;
+                std::io::copy(&mut (&old_file).take(length), &mut out)?;
+            }
+            0x02 => {
+                // Insert
+                let length = read_u64(&mut delta)?;
+                if std::io::copy(&mut (&mut delta).take(length), &mut out)? != length {
+                    bail!("Delta file truncated");
+                }
+            }
+            op => bail!("Unknown delta operation: {}", op),
+        }
+    }
+    out.flush()?;
+
+    // Verify output size
+    if out.written != header.output_size {
+        bail!(
+            "Output size mismatch: expected {}, got {}",
+            header.output_size,
+            out.written
+        );
+    }
+
+    // Verify checksum
+    let actual_sha256 = hex::encode(out.hasher.finalize());
+    if actual_sha256 != header.output_sha256 {
+        bail!(
+            "Output checksum mismatch: expected {}, got {}",
+            header.output_sha256,
+            actual_sha256
+        );
+    }
+
+    Ok(())
 }
 
 /// Delta builder - creates delta packages from two package versions
@@ -190,9 +594,11 @@ impl DeltaBuilder {
 
         let temp_dir = tempfile::tempdir()?;
 
-        // Extract data.tar.zst from both packages
-        let old_data = self.extract_data_archive(&self.old_path, temp_dir.path(), "old")?;
-        let new_data = self.extract_data_archive(&self.new_path, temp_dir.path(), "new")?;
+        // Decompress the data tarballs of both packages
+        let old_data = temp_dir.path().join("old_data.tar");
+        extract_data_tar(&self.old_path, &old_data)?;
+        let new_data = temp_dir.path().join("new_data.tar");
+        extract_data_tar(&self.new_path, &new_data)?;
 
         // Compute checksums
         let old_sha256 = compute_sha256(&self.old_path)?;
@@ -200,13 +606,9 @@ impl DeltaBuilder {
         let old_size = fs::metadata(&self.old_path)?.len();
         let new_size = fs::metadata(&self.new_path)?.len();
 
-        // Generate the binary diff
-        let delta_data = self.generate_diff(&old_data, &new_data)?;
-
-        // Serialize and compress the delta
-        let delta_bytes = self.serialize_delta(&delta_data)?;
+        // Generate the binary diff, compressed
         let delta_zst_path = temp_dir.path().join("data.delta.zst");
-        self.compress_data(&delta_bytes, &delta_zst_path)?;
+        write_delta(&old_data, &new_data, &delta_zst_path)?;
 
         // Create delta info
         let delta_info = DeltaInfo {
@@ -266,205 +668,6 @@ impl DeltaBuilder {
         Ok(output_path)
     }
 
-    /// Extract data.tar.zst from a package
-    fn extract_data_archive(&self, package: &Path, temp_dir: &Path, prefix: &str) -> Result<PathBuf> {
-        let file = File::open(package)?;
-        let mut archive = tar::Archive::new(file);
-
-        let data_zst = temp_dir.join(format!("{}_data.tar.zst", prefix));
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
-
-            if path.to_string_lossy() == "data.tar.zst" {
-                let mut out = File::create(&data_zst)?;
-                std::io::copy(&mut entry, &mut out)?;
-                return Ok(data_zst);
-            }
-        }
-
-        bail!("Package does not contain data.tar.zst")
-    }
-
-    /// Generate binary diff between old and new data
-    fn generate_diff(&self, old_data: &Path, new_data: &Path) -> Result<DeltaData> {
-        let old_bytes = fs::read(old_data)?;
-        let new_bytes = fs::read(new_data)?;
-
-        // Compute output checksum
-        let mut hasher = Sha256::new();
-        hasher.update(&new_bytes);
-        let output_sha256 = hex::encode(hasher.finalize());
-
-        // Use block-based diffing algorithm
-        let ops = self.compute_block_diff(&old_bytes, &new_bytes)?;
-
-        Ok(DeltaData {
-            ops,
-            output_size: new_bytes.len() as u64,
-            output_sha256,
-        })
-    }
-
-    /// Compute block-based diff (simplified bsdiff-style algorithm)
-    fn compute_block_diff(&self, old: &[u8], new: &[u8]) -> Result<Vec<DeltaOp>> {
-        let mut ops = Vec::new();
-        let mut new_pos = 0;
-
-        // Build a hash table of old file blocks for quick lookup
-        let mut block_index: HashMap<u64, Vec<usize>> = HashMap::new();
-        for (i, chunk) in old.chunks(BLOCK_SIZE).enumerate() {
-            let hash = self.hash_block(chunk);
-            block_index.entry(hash).or_default().push(i * BLOCK_SIZE);
-        }
-
-        // Scan through new file looking for matching blocks
-        let mut pending_insert: Vec<u8> = Vec::new();
-
-        while new_pos < new.len() {
-            let remaining = new.len() - new_pos;
-            let block_len = remaining.min(BLOCK_SIZE);
-            let new_block = &new[new_pos..new_pos + block_len];
-            let block_hash = self.hash_block(new_block);
-
-            // Try to find a matching block in old file
-            let mut found_match = false;
-            if let Some(positions) = block_index.get(&block_hash) {
-                for &old_pos in positions {
-                    // Verify the match (hash collision check)
-                    let old_end = (old_pos + block_len).min(old.len());
-                    if old_end - old_pos == block_len && &old[old_pos..old_end] == new_block {
-                        // Found a match! First, flush any pending inserts
-                        if !pending_insert.is_empty() {
-                            ops.push(DeltaOp::Insert {
-                                data: std::mem::take(&mut pending_insert),
-                            });
-                        }
-
-                        // Try to extend the match forward
-                        let mut match_len = block_len;
-                        while new_pos + match_len < new.len()
-                            && old_pos + match_len < old.len()
-                            && new[new_pos + match_len] == old[old_pos + match_len]
-                        {
-                            match_len += 1;
-                        }
-
-                        ops.push(DeltaOp::Copy {
-                            offset: old_pos as u64,
-                            length: match_len as u64,
-                        });
-
-                        new_pos += match_len;
-                        found_match = true;
-                        break;
-                    }
-                }
-            }
-
-            if !found_match {
-                // No match found, add to pending inserts
-                pending_insert.push(new[new_pos]);
-                new_pos += 1;
-            }
-        }
-
-        // Flush any remaining pending inserts
-        if !pending_insert.is_empty() {
-            ops.push(DeltaOp::Insert { data: pending_insert });
-        }
-
-        // Merge adjacent copy operations
-        ops = self.merge_ops(ops);
-
-        Ok(ops)
-    }
-
-    /// Simple hash function for blocks
-    fn hash_block(&self, data: &[u8]) -> u64 {
-        // FNV-1a hash
-        let mut hash: u64 = 0xcbf29ce484222325;
-        for &byte in data {
-            hash ^= byte as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
-        }
-        hash
-    }
-
-    /// Merge adjacent operations where possible
-    fn merge_ops(&self, ops: Vec<DeltaOp>) -> Vec<DeltaOp> {
-        let mut merged = Vec::new();
-
-        for op in ops {
-            match (&mut merged.last_mut(), &op) {
-                // Merge adjacent inserts
-                (Some(DeltaOp::Insert { data: existing }), DeltaOp::Insert { data: new }) => {
-                    existing.extend(new);
-                }
-                // Merge adjacent copies if they're contiguous
-                (
-                    Some(DeltaOp::Copy {
-                        offset: existing_off,
-                        length: existing_len,
-                    }),
-                    DeltaOp::Copy { offset, length },
-                ) if *existing_off + *existing_len == *offset => {
-                    *existing_len += length;
-                }
-                _ => {
-                    merged.push(op);
-                }
-            }
-        }
-
-        merged
-    }
-
-    /// Serialize delta data to bytes
-    fn serialize_delta(&self, delta: &DeltaData) -> Result<Vec<u8>> {
-        // Use bincode for compact binary serialization
-        let mut output = Vec::new();
-
-        // Write header
-        output.extend_from_slice(b"ROOKDELTA\x01"); // Magic + version
-
-        // Write output info
-        output.extend_from_slice(&delta.output_size.to_le_bytes());
-        let sha_bytes = hex::decode(&delta.output_sha256)?;
-        output.extend_from_slice(&sha_bytes);
-
-        // Write number of operations
-        output.extend_from_slice(&(delta.ops.len() as u32).to_le_bytes());
-
-        // Write each operation
-        for op in &delta.ops {
-            match op {
-                DeltaOp::Copy { offset, length } => {
-                    output.push(0x01); // Copy marker
-                    output.extend_from_slice(&offset.to_le_bytes());
-                    output.extend_from_slice(&length.to_le_bytes());
-                }
-                DeltaOp::Insert { data } => {
-                    output.push(0x02); // Insert marker
-                    output.extend_from_slice(&(data.len() as u64).to_le_bytes());
-                    output.extend_from_slice(data);
-                }
-            }
-        }
-
-        Ok(output)
-    }
-
-    /// Compress data with zstd
-    fn compress_data(&self, data: &[u8], output: &Path) -> Result<()> {
-        let file = File::create(output)?;
-        let mut encoder = zstd::stream::Encoder::new(file, 19)?;
-        encoder.write_all(data)?;
-        encoder.finish()?;
-        Ok(())
-    }
-
     /// Create the final delta archive
     fn create_delta_archive(
         &self,
@@ -545,15 +748,13 @@ impl DeltaApplier {
 
         let temp_dir = tempfile::tempdir()?;
 
-        // Extract data.tar.zst from old package
-        let old_data = self.extract_data_archive(&self.old_path, temp_dir.path())?;
-
-        // Extract and decompress delta
-        let delta_data = self.extract_and_decompress_delta(temp_dir.path())?;
+        // Decompress the old data tarball
+        let old_data = temp_dir.path().join("old_data.tar");
+        extract_data_tar(&self.old_path, &old_data)?;
 
         // Apply delta to produce new data
         let new_data_path = temp_dir.path().join("new_data.tar.zst");
-        self.apply_delta(&old_data, &delta_data, &new_data_path)?;
+        self.apply_delta_member(&old_data, &new_data_path)?;
 
         // Reconstruct the new package
         let output_path = self.reconstruct_package(&new_data_path, output_dir)?;
@@ -577,198 +778,24 @@ impl DeltaApplier {
         Ok(output_path)
     }
 
-    /// Extract data.tar.zst from a package
-    fn extract_data_archive(&self, package: &Path, temp_dir: &Path) -> Result<PathBuf> {
-        let file = File::open(package)?;
-        let mut archive = tar::Archive::new(file);
-
-        let data_zst = temp_dir.join("old_data.tar.zst");
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
-
-            if path.to_string_lossy() == "data.tar.zst" {
-                let mut out = File::create(&data_zst)?;
-                std::io::copy(&mut entry, &mut out)?;
-                return Ok(data_zst);
-            }
-        }
-
-        bail!("Package does not contain data.tar.zst")
-    }
-
-    /// Extract and decompress the delta data
-    fn extract_and_decompress_delta(&self, temp_dir: &Path) -> Result<DeltaData> {
+    /// Stream the delta member through zstd into [`apply_delta`], and the
+    /// result back through zstd into `output`
+    fn apply_delta_member(&self, old_data: &Path, output: &Path) -> Result<()> {
         let file = File::open(&self.delta_path)?;
         let mut archive = tar::Archive::new(file);
 
-        let delta_zst = temp_dir.join("data.delta.zst");
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?;
-
-            if path.to_string_lossy() == "data.delta.zst" {
-                let mut out = File::create(&delta_zst)?;
-                std::io::copy(&mut entry, &mut out)?;
-                break;
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            if entry.path()?.to_string_lossy() == "data.delta.zst" {
+                let decoder = zstd::stream::Decoder::new(entry)?;
+                let mut encoder = zstd::stream::Encoder::new(File::create(output)?, 19)?;
+                apply_delta(old_data, decoder, &mut encoder)?;
+                encoder.finish()?;
+                return Ok(());
             }
         }
 
-        if !delta_zst.exists() {
-            bail!("Delta file does not contain data.delta.zst");
-        }
-
-        // Decompress
-        let zst_file = File::open(&delta_zst)?;
-        let mut decoder = zstd::stream::Decoder::new(zst_file)?;
-        let mut delta_bytes = Vec::new();
-        decoder.read_to_end(&mut delta_bytes)?;
-
-        // Parse delta data
-        self.parse_delta(&delta_bytes)
-    }
-
-    /// Parse serialized delta data
-    fn parse_delta(&self, data: &[u8]) -> Result<DeltaData> {
-        let mut pos = 0;
-
-        // Check magic
-        if data.len() < 10 || &data[0..10] != b"ROOKDELTA\x01" {
-            bail!("Invalid delta file format");
-        }
-        pos += 10;
-
-        // Read output size
-        if pos + 8 > data.len() {
-            bail!("Delta file truncated");
-        }
-        let output_size = u64::from_le_bytes(data[pos..pos + 8].try_into()?);
-        pos += 8;
-
-        // Read output SHA256
-        if pos + 32 > data.len() {
-            bail!("Delta file truncated");
-        }
-        let output_sha256 = hex::encode(&data[pos..pos + 32]);
-        pos += 32;
-
-        // Read operation count
-        if pos + 4 > data.len() {
-            bail!("Delta file truncated");
-        }
-        let op_count = u32::from_le_bytes(data[pos..pos + 4].try_into()?) as usize;
-        pos += 4;
-
-        // Read operations
-        let mut ops = Vec::with_capacity(op_count);
-        for _ in 0..op_count {
-            if pos >= data.len() {
-                bail!("Delta file truncated");
-            }
-
-            match data[pos] {
-                0x01 => {
-                    // Copy
-                    pos += 1;
-                    if pos + 16 > data.len() {
-                        bail!("Delta file truncated");
-                    }
-                    let offset = u64::from_le_bytes(data[pos..pos + 8].try_into()?);
-                    pos += 8;
-                    let length = u64::from_le_bytes(data[pos..pos + 8].try_into()?);
-                    pos += 8;
-                    ops.push(DeltaOp::Copy { offset, length });
-                }
-                0x02 => {
-                    // Insert
-                    pos += 1;
-                    if pos + 8 > data.len() {
-                        bail!("Delta file truncated");
-                    }
-                    let length = u64::from_le_bytes(data[pos..pos + 8].try_into()?) as usize;
-                    pos += 8;
-                    if pos + length > data.len() {
-                        bail!("Delta file truncated");
-                    }
-                    let insert_data = data[pos..pos + length].to_vec();
-                    pos += length;
-                    ops.push(DeltaOp::Insert { data: insert_data });
-                }
-                _ => {
-                    bail!("Unknown delta operation: {}", data[pos]);
-                }
-            }
-        }
-
-        Ok(DeltaData {
-            ops,
-            output_size,
-            output_sha256,
-        })
-    }
-
-    /// Apply delta operations to produce new data
-    fn apply_delta(&self, old_data_path: &Path, delta: &DeltaData, output: &Path) -> Result<()> {
-        // Read and decompress old data
-        let old_zst = File::open(old_data_path)?;
-        let mut decoder = zstd::stream::Decoder::new(old_zst)?;
-        let mut old_bytes = Vec::new();
-        decoder.read_to_end(&mut old_bytes)?;
-
-        // Apply operations
-        let mut new_bytes = Vec::with_capacity(delta.output_size as usize);
-
-        for op in &delta.ops {
-            match op {
-                DeltaOp::Copy { offset, length } => {
-                    let start = *offset as usize;
-                    let end = start + *length as usize;
-                    if end > old_bytes.len() {
-                        bail!(
-                            "Delta copy operation out of bounds: {}..{} (old file size: {})",
-                            start,
-                            end,
-                            old_bytes.len()
-                        );
-                    }
-                    new_bytes.extend_from_slice(&old_bytes[start..end]);
-                }
-                DeltaOp::Insert { data } => {
-                    new_bytes.extend_from_slice(data);
-                }
-            }
-        }
-
-        // Verify output size
-        if new_bytes.len() as u64 != delta.output_size {
-            bail!(
-                "Output size mismatch: expected {}, got {}",
-                delta.output_size,
-                new_bytes.len()
-            );
-        }
-
-        // Verify checksum
-        let mut hasher = Sha256::new();
-        hasher.update(&new_bytes);
-        let actual_sha256 = hex::encode(hasher.finalize());
-        if actual_sha256 != delta.output_sha256 {
-            bail!(
-                "Output checksum mismatch: expected {}, got {}",
-                delta.output_sha256,
-                actual_sha256
-            );
-        }
-
-        // Compress and write output
-        let file = File::create(output)?;
-        let mut encoder = zstd::stream::Encoder::new(file, 19)?;
-        encoder.write_all(&new_bytes)?;
-        encoder.finish()?;
-
-        Ok(())
+        bail!("Delta file does not contain data.delta.zst")
     }
 
     /// Reconstruct the new package from the delta result
@@ -896,9 +923,9 @@ impl PackageDeltaIndex {
     /// Find a delta for upgrading from a specific version
     pub fn find_delta(&self, from_version: &str, from_release: u32, to_version: &str, to_release: u32) -> Option<&DeltaEntry> {
         self.deltas.iter().find(|d| {
-            d.from_version == from_version
+            same_version(&d.from_version, from_version)
                 && d.from_release == from_release
-                && d.to_version == to_version
+                && same_version(&d.to_version, to_version)
                 && d.to_release == to_release
         })
     }
@@ -907,10 +934,15 @@ impl PackageDeltaIndex {
     pub fn find_delta_from(&self, from_version: &str, from_release: u32) -> Option<&DeltaEntry> {
         self.deltas
             .iter()
-            .find(|d| d.from_version == from_version && d.from_release == from_release)
+            .find(|d| same_version(&d.from_version, from_version) && d.from_release == from_release)
     }
 }
 
+/// Version strings are matched by version ordering, so "1.0" finds a delta from "1.0.0"
+fn same_version(a: &str, b: &str) -> bool {
+    compare_versions(a, b) == std::cmp::Ordering::Equal
+}
+
 /// Repository-wide delta index
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RepoDeltaIndex {
@@ -1041,6 +1073,10 @@ mod tests {
 
         let not_found = index.find_delta("1.0", 1, "2.0", 1);
         assert!(not_found.is_none());
+
+        // Equivalent version spellings match
+        assert!(index.find_delta("1.0.0", 1, "1.1.0", 1).is_some());
+        assert!(index.find_delta_from("1", 1).is_some());
     }
 
     #[test]
@@ -1078,84 +1114,67 @@ mod tests {
 
     #[test]
     fn test_delta_serialization() {
-        let delta = DeltaData {
-            ops: vec![
-                DeltaOp::Copy { offset: 0, length: 100 },
-                DeltaOp::Insert { data: vec![1, 2, 3, 4] },
-                DeltaOp::Copy { offset: 200, length: 50 },
-            ],
+        let header = DeltaHeader {
             output_size: 154,
             output_sha256: "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef".to_string(),
+            op_count: 3,
         };
-
-        // Create a temporary builder just to test serialization
-        let temp = tempdir().unwrap();
-        let builder = DeltaBuilder {
-            old_path: temp.path().join("old.rookpkg"),
-            new_path: temp.path().join("new.rookpkg"),
-            old_info: PackageInfo {
-                name: "test".to_string(),
-                version: "1.0".to_string(),
-                release: 1,
-                summary: String::new(),
-                description: String::new(),
-                license: String::new(),
-                url: String::new(),
-                maintainer: String::new(),
-                build_time: 0,
-                installed_size: 0,
-                depends: HashMap::new(),
-                build_depends: HashMap::new(),
-                optional_depends: HashMap::new(),
-                arch: "x86_64".to_string(),
-            },
-            new_info: PackageInfo {
-                name: "test".to_string(),
-                version: "1.1".to_string(),
-                release: 1,
-                summary: String::new(),
-                description: String::new(),
-                license: String::new(),
-                url: String::new(),
-                maintainer: String::new(),
-                build_time: 0,
-                installed_size: 0,
-                depends: HashMap::new(),
-                build_depends: HashMap::new(),
-                optional_depends: HashMap::new(),
-                arch: "x86_64".to_string(),
-            },
-        };
-
-        let serialized = builder.serialize_delta(&delta).unwrap();
+        let ops = vec![
+            DeltaOp::Copy { offset: 0, length: 100 },
+            DeltaOp::Insert { data: vec![1, 2, 3, 4] },
+            DeltaOp::Copy { offset: 200, length: 50 },
+        ];
+
+        let mut serialized = Vec::new();
+        header.write_to(&mut serialized).unwrap();
+        for op in &ops {
+            op.write_to(&mut serialized).unwrap();
+        }
 
         // Verify magic
         assert_eq!(&serialized[0..10], b"ROOKDELTA\x01");
 
-        // Parse it back using DeltaApplier's parse method
-        let applier = DeltaApplier {
-            old_path: temp.path().join("old.rookpkg"),
-            delta_path: temp.path().join("delta.rookdelta"),
-            delta_info: DeltaInfo {
-                name: "test".to_string(),
-                old_version: "1.0".to_string(),
-                old_release: 1,
-                new_version: "1.1".to_string(),
-                new_release: 1,
-                arch: "x86_64".to_string(),
-                old_sha256: String::new(),
-                new_sha256: String::new(),
-                old_size: 0,
-                new_size: 0,
-                delta_size: 0,
-                created: 0,
-                algorithm: DeltaAlgorithm::Bsdiff,
-            },
-        };
+        let parsed = DeltaHeader::read_from(&mut serialized.as_slice()).unwrap();
+        assert_eq!(parsed, header);
+        assert!(DeltaHeader::read_from(&mut &serialized[..20]).is_err());
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let temp = tempdir().unwrap();
 
-        let parsed = applier.parse_delta(&serialized).unwrap();
-        assert_eq!(parsed.output_size, delta.output_size);
-        assert_eq!(parsed.output_sha256, delta.output_sha256);
-        assert_eq!(parsed.ops.len(), delta.ops.len());
+        // Pseudo-random data, so only real matches are found
+        let mut state: u32 = 12345;
+        let old: Vec<u8> = (0..300_000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let mut new = old[..100_000].to_vec();
+        new.extend_from_slice(b"inserted in the middle");
+        new.extend_from_slice(&old[120_000..250_000]);
+        new.extend(std::iter::repeat_n(7u8, 5_000));
+        new.extend_from_slice(&old[..10_000]);
+
+        let old_path = temp.path().join("old.tar");
+        let new_path = temp.path().join("new.tar");
+        fs::write(&old_path, &old).unwrap();
+        fs::write(&new_path, &new).unwrap();
+
+        let delta_path = temp.path().join("data.delta.zst");
+        write_delta(&old_path, &new_path, &delta_path).unwrap();
+        assert!(fs::metadata(&delta_path).unwrap().len() < new.len() as u64 / 10);
+
+        let mut output = Vec::new();
+        let decoder = zstd::stream::Decoder::new(File::open(&delta_path).unwrap()).unwrap();
+        apply_delta(&old_path, decoder, &mut output).unwrap();
+        assert_eq!(output, new);
+
+        // A delta for other old data fails its checksum rather than
+        // producing a corrupt tarball
+        fs::write(&old_path, vec![0u8; old.len()]).unwrap();
+        let decoder = zstd::stream::Decoder::new(File::open(&delta_path).unwrap()).unwrap();
+        assert!(apply_delta(&old_path, decoder, std::io::sink()).is_err());
     }
 }
@@ -7,11 +7,11 @@ use std::io::{BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use anyhow::{bail, Context, Result};
 use reqwest::blocking::Client;
 use sha2::{Digest, Sha256};
 
 use crate::config::Config;
+use crate::error::{Context, Result, RookpkgError};
 
 /// Maximum number of download retries
 const MAX_RETRIES: u32 = 3;
@@ -135,7 +135,7 @@ impl Downloader {
 
         // Try each URL until one succeeds
         let urls = source.all_urls();
-        let mut last_error: Option<anyhow::Error> = None;
+        let mut last_error: Option<RookpkgError> = None;
 
         for url in &urls {
             tracing::info!("Downloading: {}", url);
@@ -149,11 +149,10 @@ impl Downloader {
                             return Ok(dest_path);
                         }
                         Ok(false) => {
-                            let err = anyhow::anyhow!(
+                            let err = RookpkgError::DownloadFailed(format!(
                                 "Checksum mismatch for {} (expected: {})",
-                                filename,
-                                source.sha256
-                            );
+                                filename, source.sha256
+                            ));
                             tracing::error!("{}", err);
                             fs::remove_file(&dest_path).ok();
                             last_error = Some(err);
@@ -172,12 +171,12 @@ impl Downloader {
             }
         }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No URLs available for download")))
+        Err(last_error.unwrap_or_else(|| RookpkgError::DownloadFailed("No URLs available for download".to_string())))
     }
 
     /// Download with retry logic
     fn download_with_retries(&self, url: &str, dest: &Path) -> Result<()> {
-        let mut last_error: Option<anyhow::Error> = None;
+        let mut last_error: Option<RookpkgError> = None;
 
         for attempt in 1..=MAX_RETRIES {
             if attempt > 1 {
@@ -194,7 +193,9 @@ impl Downloader {
             }
         }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Download failed after {} retries", MAX_RETRIES)))
+        Err(last_error.unwrap_or_else(|| {
+            RookpkgError::DownloadFailed(format!("Download failed after {} retries", MAX_RETRIES))
+        }))
     }
 
     /// Perform a single download attempt
@@ -206,7 +207,7 @@ impl Downloader {
             .with_context(|| format!("Failed to connect to: {}", url))?;
 
         if !response.status().is_success() {
-            bail!("HTTP error {}: {}", response.status(), url);
+            return Err(RookpkgError::DownloadFailed(format!("HTTP error {}: {}", response.status(), url)));
         }
 
         let total_size = response.content_length();
@@ -337,7 +338,7 @@ pub fn extract_tarball(archive: &Path, dest_dir: &Path) -> Result<()> {
 
     let archive_str = archive
         .to_str()
-        .ok_or_else(|| anyhow::anyhow!("Invalid archive path"))?;
+        .ok_or_else(|| RookpkgError::Extraction(format!("Invalid archive path: {}", archive.display())))?;
 
     // Determine decompression based on extension
     let tar_args: Vec<&str> = if archive_str.ends_with(".tar.gz") || archive_str.ends_with(".tgz") {
@@ -351,12 +352,12 @@ pub fn extract_tarball(archive: &Path, dest_dir: &Path) -> Result<()> {
     } else if archive_str.ends_with(".tar") {
         vec!["-xf", archive_str, "-C"]
     } else {
-        bail!("Unsupported archive format: {}", archive_str);
+        return Err(RookpkgError::Extraction(format!("Unsupported archive format: {}", archive_str)));
     };
 
     let dest_str = dest_dir
         .to_str()
-        .ok_or_else(|| anyhow::anyhow!("Invalid destination path"))?;
+        .ok_or_else(|| RookpkgError::Extraction(format!("Invalid destination path: {}", dest_dir.display())))?;
 
     let mut cmd_args = tar_args;
     cmd_args.push(dest_str);
@@ -368,7 +369,7 @@ pub fn extract_tarball(archive: &Path, dest_dir: &Path) -> Result<()> {
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("tar extraction failed: {}", stderr);
+        return Err(RookpkgError::Extraction(format!("tar failed: {}", stderr.trim())));
     }
 
     Ok(())
@@ -1,4 +1,11 @@
 //! Error types for rookpkg
+//!
+//! The library API reports failures as [`RookpkgError`]. What was being done
+//! when an error happened is added with the [`Context`] trait, which wraps
+//! the error in [`RookpkgError::Context`] and keeps it as the source, so
+//! callers can still match on the underlying variant.
+
+use std::fmt::Display;
 
 use thiserror::Error;
 
@@ -26,6 +33,12 @@ pub enum RookpkgError {
     #[error("Untrusted package signer: {0}")]
     UntrustedSigner(String),
 
+    #[error("Signing key revoked: {0}")]
+    KeyRevoked(String),
+
+    #[error("Signing agent error: {0}")]
+    SigningAgent(String),
+
     #[error("Build failed: {0}")]
     BuildFailed(String),
 
@@ -46,6 +59,121 @@ pub enum RookpkgError {
 
     #[error("Configuration error: {0}")]
     Config(String),
+
+    #[error("Package is not installed: {0}")]
+    NotInstalled(String),
+
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid metadata: {0}")]
+    Metadata(String),
+
+    #[error("Repository error: {0}")]
+    Repository(String),
+
+    #[error("Stale repository metadata: {0}")]
+    StaleMetadata(String),
+
+    #[error("Transaction failed: {0}")]
+    Transaction(String),
+
+    #[error("Transaction rolled back due to: {0}")]
+    RolledBack(Box<RookpkgError>),
+
+    #[error("{script} script failed for {package}: {message}")]
+    ScriptFailed { script: String, package: String, message: String },
+
+    #[error("Hook failed: {0}")]
+    HookFailed(String),
+
+    #[error("Snapshot error: {0}")]
+    Snapshot(String),
+
+    #[error("System account error: {0}")]
+    Account(String),
+
+    #[error("Invalid file attributes: {0}")]
+    FileAttributes(String),
+
+    #[error("Extraction failed: {0}")]
+    Extraction(String),
+
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<RookpkgError>,
+    },
+}
+
+impl RookpkgError {
+    /// The innermost error, below any added context
+    pub fn root_cause(&self) -> &RookpkgError {
+        match self {
+            RookpkgError::Context { source, .. } => source.root_cause(),
+            err => err,
+        }
+    }
+}
+
+/// Add context to a failed operation, like `anyhow::Context` for
+/// [`RookpkgError`]
+pub trait Context<T> {
+    /// Wrap the error with a description of what was being done
+    fn context<C: Display>(self, context: C) -> Result<T>;
+
+    /// Wrap the error with a lazily built description
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: Into<RookpkgError>> Context<T> for std::result::Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.map_err(|err| RookpkgError::Context {
+            context: context.to_string(),
+            source: Box::new(err.into()),
+        })
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T> {
+        self.map_err(|err| RookpkgError::Context {
+            context: f().to_string(),
+            source: Box::new(err.into()),
+        })
+    }
+}
+
+impl From<rusqlite::Error> for RookpkgError {
+    fn from(err: rusqlite::Error) -> Self {
+        RookpkgError::Database(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for RookpkgError {
+    fn from(err: reqwest::Error) -> Self {
+        RookpkgError::DownloadFailed(err.to_string())
+    }
+}
+
+impl From<toml::de::Error> for RookpkgError {
+    fn from(err: toml::de::Error) -> Self {
+        RookpkgError::Metadata(err.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for RookpkgError {
+    fn from(err: toml::ser::Error) -> Self {
+        RookpkgError::Metadata(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for RookpkgError {
+    fn from(err: serde_json::Error) -> Self {
+        RookpkgError::Metadata(err.to_string())
+    }
 }
 
 /// Result type alias for rookpkg operations
@@ -78,6 +206,12 @@ mod tests {
         let err = RookpkgError::UntrustedSigner("unknown@example.org".to_string());
         assert!(err.to_string().contains("Untrusted"));
 
+        let err = RookpkgError::KeyRevoked("HYBRID:SHA256:abc".to_string());
+        assert!(err.to_string().contains("revoked"));
+
+        let err = RookpkgError::SigningAgent("no such key".to_string());
+        assert!(err.to_string().contains("Signing agent"));
+
         let err = RookpkgError::BuildFailed("make failed".to_string());
         assert!(err.to_string().contains("Build failed"));
 
@@ -122,6 +256,22 @@ mod tests {
         assert!(matches!(result, Err(RookpkgError::SigningKeyNotFound)));
     }
 
+    #[test]
+    fn test_context() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::StorageFull, "disk full");
+        let err = Err::<(), _>(io_err).context("Failed to write /etc/passwd").unwrap_err();
+        assert_eq!(err.to_string(), "Failed to write /etc/passwd");
+        assert!(matches!(err.root_cause(), RookpkgError::Io(_)));
+
+        // The typed error stays reachable through the source chain
+        let err = Err::<(), _>(RookpkgError::NotInstalled("foo".to_string()))
+            .with_context(|| "Failed to remove foo")
+            .unwrap_err();
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "Package is not installed: foo");
+        assert!(matches!(err.root_cause(), RookpkgError::NotInstalled(name) if name == "foo"));
+    }
+
     #[test]
     fn test_io_error_conversion() {
         let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
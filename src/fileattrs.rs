@@ -0,0 +1,436 @@
+//! File ownership, modes, timestamps and extended attributes
+//!
+//! Packages name owners and groups rather than carrying numeric IDs, so the
+//! names are resolved against the passwd and group files of the root being
+//! installed to, never the host's. Extended attribute values are stored
+//! hex-encoded so binary values such as `security.capability` survive TOML
+//! and the database unchanged.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::archive::{FileEntry, FileType};
+use crate::error::{Context, Result, RookpkgError};
+
+/// Extended attributes: name -> hex-encoded value
+pub type Xattrs = BTreeMap<String, String>;
+
+/// Attributes that belong to the host rather than the package
+/// (SELinux labels are assigned by the target's policy)
+const HOST_XATTRS: &[&str] = &["security.selinux"];
+
+/// Name of the file capability attribute
+pub const CAPABILITY_XATTR: &str = "security.capability";
+
+/// Linux capability names, indexed by capability number
+const CAPABILITIES: &[&str] = &[
+    "chown", "dac_override", "dac_read_search", "fowner", "fsetid", "kill",
+    "setgid", "setuid", "setpcap", "linux_immutable", "net_bind_service",
+    "net_broadcast", "net_admin", "net_raw", "ipc_lock", "ipc_owner",
+    "sys_module", "sys_rawio", "sys_chroot", "sys_ptrace", "sys_pacct",
+    "sys_admin", "sys_boot", "sys_nice", "sys_resource", "sys_time",
+    "sys_tty_config", "mknod", "lease", "audit_write", "audit_control",
+    "setfcap", "mac_override", "mac_admin", "syslog", "wake_alarm",
+    "block_suspend", "audit_read", "perfmon", "bpf", "checkpoint_restore",
+];
+
+/// User and group IDs of a root filesystem
+#[derive(Debug, Clone, Default)]
+pub struct IdMap {
+    users: HashMap<String, u32>,
+    groups: HashMap<String, u32>,
+}
+
+impl IdMap {
+    /// Read `etc/passwd` and `etc/group` under `root`
+    ///
+    /// Missing files are treated as empty; `root` always maps to 0 so a
+    /// fresh sysroot can be populated before it has account files.
+    pub fn load(root: &Path) -> Result<Self> {
+        Ok(Self {
+            users: read_id_file(&root.join("etc/passwd"))?,
+            groups: read_id_file(&root.join("etc/group"))?,
+        })
+    }
+
+    /// Numeric ID of a user name
+    pub fn uid(&self, name: &str) -> Result<u32> {
+        match self.users.get(name) {
+            Some(&uid) => Ok(uid),
+            None if name == "root" => Ok(0),
+            None => Err(RookpkgError::FileAttributes(format!(
+                "User '{}' does not exist in the target root",
+                name
+            ))),
+        }
+    }
+
+    /// Numeric ID of a group name
+    pub fn gid(&self, name: &str) -> Result<u32> {
+        match self.groups.get(name) {
+            Some(&gid) => Ok(gid),
+            None if name == "root" => Ok(0),
+            None => Err(RookpkgError::FileAttributes(format!(
+                "Group '{}' does not exist in the target root",
+                name
+            ))),
+        }
+    }
+}
+
+/// Parse `name:x:id:...` lines into name -> id
+fn read_id_file(path: &Path) -> Result<HashMap<String, u32>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), id))
+        })
+        .collect())
+}
+
+/// Whether ownership and `security.*` attributes can be set
+fn privileged() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+fn c_path(path: &Path) -> Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| RookpkgError::FileAttributes(format!("Invalid path: {}", path.display())))
+}
+
+/// Read the extended attributes of a file (without following symlinks)
+///
+/// Returns nothing on filesystems without xattr support.
+pub fn read_xattrs(path: &Path) -> Result<Xattrs> {
+    let c_path = c_path(path)?;
+    let mut xattrs = Xattrs::new();
+
+    let size = unsafe { libc::llistxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOTSUP) {
+            return Ok(xattrs);
+        }
+        return Err(err).with_context(|| format!("Failed to list xattrs of {}", path.display()));
+    }
+
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe {
+        libc::llistxattr(c_path.as_ptr(), names.as_mut_ptr() as *mut libc::c_char, names.len())
+    };
+    if size < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to list xattrs of {}", path.display()));
+    }
+    names.truncate(size as usize);
+
+    for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+        let name_str = String::from_utf8_lossy(name).into_owned();
+        if HOST_XATTRS.contains(&name_str.as_str()) {
+            continue;
+        }
+        let c_name = std::ffi::CString::new(name)
+            .map_err(|_| RookpkgError::FileAttributes(format!("Invalid xattr name: {}", name_str)))?;
+
+        let len = unsafe {
+            libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), std::ptr::null_mut(), 0)
+        };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to read xattr {} of {}", name_str, path.display()));
+        }
+        let mut value = vec![0u8; len as usize];
+        let len = unsafe {
+            libc::lgetxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to read xattr {} of {}", name_str, path.display()));
+        }
+        value.truncate(len as usize);
+
+        xattrs.insert(name_str, hex::encode(value));
+    }
+
+    Ok(xattrs)
+}
+
+/// Set one extended attribute (without following symlinks)
+fn write_xattr(path: &Path, name: &str, hex_value: &str) -> Result<()> {
+    let value = hex::decode(hex_value).map_err(|_| {
+        RookpkgError::FileAttributes(format!("Invalid value for xattr {} of {}", name, path.display()))
+    })?;
+    let c_path = c_path(path)?;
+    let c_name = std::ffi::CString::new(name)
+        .map_err(|_| RookpkgError::FileAttributes(format!("Invalid xattr name: {}", name)))?;
+
+    let ret = unsafe {
+        libc::lsetxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr() as *const libc::c_void,
+            value.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to set xattr {} on {}", name, path.display()));
+    }
+    Ok(())
+}
+
+/// Modification time of a file as a Unix timestamp
+pub fn mtime(metadata: &fs::Metadata) -> i64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.mtime()
+}
+
+/// Give an installed (or staged) file the attributes recorded for it
+///
+/// Ownership goes first because `chown` clears setuid bits and file
+/// capabilities; the mtime goes last because everything else touches it.
+pub fn apply(path: &Path, entry: &FileEntry, ids: &IdMap) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let is_symlink = entry.file_type == FileType::Symlink;
+    let privileged = privileged();
+
+    if privileged {
+        let uid = ids.uid(&entry.owner)?;
+        let gid = ids.gid(&entry.group)?;
+        std::os::unix::fs::lchown(path, Some(uid), Some(gid))
+            .with_context(|| format!("Failed to set owner of {}", path.display()))?;
+    } else {
+        tracing::debug!("Not running as root, leaving ownership of {} unchanged", path.display());
+    }
+
+    if !is_symlink {
+        fs::set_permissions(path, fs::Permissions::from_mode(entry.mode & 0o7777))
+            .with_context(|| format!("Failed to set mode of {}", path.display()))?;
+
+        for (name, value) in &entry.xattrs {
+            if !privileged && !name.starts_with("user.") {
+                tracing::debug!("Not running as root, skipping xattr {} on {}", name, path.display());
+                continue;
+            }
+            write_xattr(path, name, value)?;
+        }
+    }
+
+    if entry.mtime != 0 {
+        let c_path = c_path(path)?;
+        let time = libc::timespec {
+            tv_sec: entry.mtime as libc::time_t,
+            tv_nsec: 0,
+        };
+        let times = [time, time];
+        let ret = unsafe {
+            libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), libc::AT_SYMLINK_NOFOLLOW)
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to set mtime of {}", path.display()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare a file on disk with its recorded attributes
+///
+/// Returns a description of each difference. Only attributes the package
+/// recorded are compared, plus file capabilities, which must never appear
+/// on a file the package did not give them to.
+pub fn differences(
+    path: &Path,
+    mode: u32,
+    owner: &str,
+    group: &str,
+    xattrs: &Xattrs,
+    ids: &IdMap,
+) -> Result<Vec<String>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    let mut diffs = Vec::new();
+
+    if let Ok(uid) = ids.uid(owner) {
+        if metadata.uid() != uid {
+            diffs.push(format!("owner {} (expected {})", metadata.uid(), owner));
+        }
+    }
+    if let Ok(gid) = ids.gid(group) {
+        if metadata.gid() != gid {
+            diffs.push(format!("group {} (expected {})", metadata.gid(), group));
+        }
+    }
+
+    if metadata.file_type().is_symlink() {
+        return Ok(diffs);
+    }
+
+    if metadata.mode() & 0o7777 != mode & 0o7777 {
+        diffs.push(format!(
+            "mode {:04o} (expected {:04o})",
+            metadata.mode() & 0o7777,
+            mode & 0o7777
+        ));
+    }
+
+    let actual = read_xattrs(path)?;
+    for (name, value) in xattrs {
+        match actual.get(name) {
+            Some(v) if v == value => {}
+            Some(_) => diffs.push(format!("xattr {} changed", name)),
+            None => diffs.push(format!("xattr {} missing", name)),
+        }
+    }
+    if actual.contains_key(CAPABILITY_XATTR) && !xattrs.contains_key(CAPABILITY_XATTR) {
+        diffs.push("unexpected file capabilities".to_string());
+    }
+
+    Ok(diffs)
+}
+
+/// Encode a capability set like "cap_net_raw+ep" as a `security.capability`
+/// value (VFS_CAP_REVISION_2), hex-encoded
+pub fn encode_capabilities(text: &str) -> Result<String> {
+    let (names, flags) = text
+        .split_once(['+', '='])
+        .ok_or_else(|| RookpkgError::FileAttributes(format!("Capabilities '{}' have no flags (e.g. +ep)", text)))?;
+
+    let mut set: u64 = 0;
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let bare = name.to_lowercase();
+        let bare = bare.strip_prefix("cap_").unwrap_or(&bare);
+        let bit = CAPABILITIES
+            .iter()
+            .position(|c| *c == bare)
+            .ok_or_else(|| RookpkgError::FileAttributes(format!("Unknown capability: {}", name)))?;
+        set |= 1 << bit;
+    }
+
+    let (mut permitted, mut inheritable, mut effective) = (0u64, 0u64, false);
+    for flag in flags.chars() {
+        match flag {
+            'p' => permitted = set,
+            'i' => inheritable = set,
+            'e' => effective = true,
+            _ => {
+                return Err(RookpkgError::FileAttributes(format!(
+                    "Unknown capability flag '{}' in '{}'",
+                    flag, text
+                )))
+            }
+        }
+    }
+
+    const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+    const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+    let magic = VFS_CAP_REVISION_2 | if effective { VFS_CAP_FLAGS_EFFECTIVE } else { 0 };
+
+    let mut value = Vec::with_capacity(20);
+    value.extend_from_slice(&magic.to_le_bytes());
+    value.extend_from_slice(&(permitted as u32).to_le_bytes());
+    value.extend_from_slice(&(inheritable as u32).to_le_bytes());
+    value.extend_from_slice(&((permitted >> 32) as u32).to_le_bytes());
+    value.extend_from_slice(&((inheritable >> 32) as u32).to_le_bytes());
+    Ok(hex::encode(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_id_map() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc")).unwrap();
+        fs::write(
+            dir.path().join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\nnobody:x:65534:65534::/:/sbin/nologin\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("etc/group"), "root:x:0:\nnet:x:42:alice\n").unwrap();
+
+        let ids = IdMap::load(dir.path()).unwrap();
+        assert_eq!(ids.uid("nobody").unwrap(), 65534);
+        assert_eq!(ids.gid("net").unwrap(), 42);
+        assert!(ids.uid("missing").is_err());
+
+        // A root without account files still knows root
+        let empty = IdMap::load(&dir.path().join("nowhere")).unwrap();
+        assert_eq!(empty.uid("root").unwrap(), 0);
+        assert_eq!(empty.gid("root").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_encode_capabilities() {
+        // cap_net_raw (13) permitted and effective
+        assert_eq!(
+            encode_capabilities("cap_net_raw+ep").unwrap(),
+            "0100000200200000000000000000000000000000"
+        );
+        // cap_setuid (7) and cap_setgid (6), permitted only
+        assert_eq!(
+            encode_capabilities("cap_setuid,cap_setgid=p").unwrap(),
+            "00000002c0000000000000000000000000000000"
+        );
+        assert!(encode_capabilities("cap_bogus+ep").is_err());
+        assert!(encode_capabilities("cap_net_raw").is_err());
+    }
+
+    #[test]
+    fn test_apply_and_differences() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tool");
+        fs::write(&path, "tool").unwrap();
+
+        let mut xattrs = Xattrs::new();
+        xattrs.insert("user.origin".to_string(), hex::encode("rookpkg"));
+        let entry = FileEntry {
+            path: "/usr/bin/tool".to_string(),
+            size: 4,
+            sha256: String::new(),
+            mode: 0o100750,
+            is_config: false,
+            file_type: FileType::Regular,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            mtime: 1_700_000_000,
+            xattrs: xattrs.clone(),
+        };
+        let ids = IdMap::default();
+
+        apply(&path, &entry, &ids).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(mtime(&metadata), 1_700_000_000);
+        assert_eq!(read_xattrs(&path).unwrap(), xattrs);
+        assert!(differences(&path, entry.mode, "root", "root", &xattrs, &ids)
+            .unwrap()
+            .iter()
+            .all(|d| d.starts_with("owner") || d.starts_with("group")));
+
+        let diffs = differences(&path, 0o4755, "root", "root", &xattrs, &ids).unwrap();
+        assert!(diffs.contains(&"mode 0750 (expected 4755)".to_string()));
+    }
+}
@@ -18,9 +18,10 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Context, Result, RookpkgError};
+
 /// Hook event types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -169,7 +170,7 @@ impl Hook {
         let filename = path
             .file_name()
             .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid hook path: {}", path.display()))?;
+            .ok_or_else(|| RookpkgError::HookFailed(format!("Invalid hook path: {}", path.display())))?;
 
         // Extract order from NN- prefix
         let (order, name) = if filename.len() > 3 && filename.chars().take(2).all(|c| c.is_ascii_digit()) && filename.chars().nth(2) == Some('-') {
@@ -385,12 +386,12 @@ impl HookManager {
 
             if !success && fail_fast {
                 let failed = results.last().unwrap();
-                bail!(
-                    "Hook '{}' failed with exit code {}: {}",
+                return Err(RookpkgError::HookFailed(format!(
+                    "'{}' exited with code {}: {}",
                     failed.name,
                     failed.exit_code.unwrap_or(-1),
                     failed.stderr.lines().next().unwrap_or("unknown error")
-                );
+                )));
             }
         }
 
@@ -0,0 +1,67 @@
+//! rookpkg - Rookery OS Package Manager
+//!
+//! The package manager as a library, for installers and provisioning tools
+//! that need to drive it without going through the CLI.
+//!
+//! The stable embedding API is what this crate root re-exports, plus the
+//! [`signing`] module; it follows [`API_VERSION`], which changes whenever
+//! one of those items changes in an incompatible way. Errors are reported
+//! as [`RookpkgError`]. The other modules are public so the `rookpkg`
+//! binary can be built on top of them, but their items may change between
+//! releases. The build tooling in [`build`], [`sandbox`], [`delta`],
+//! [`cve`] and [`convert`] is outside the stable API and reports errors as
+//! `anyhow::Error`; everything else reports [`RookpkgError`].
+//!
+//! ```no_run
+//! use rookpkg::{Config, Database, TransactionBuilder};
+//!
+//! # fn main() -> rookpkg::Result<()> {
+//! let config = Config::load(None)?.with_sysroot("/mnt/target".into());
+//! let db = Database::open(&config.database_path())?;
+//! TransactionBuilder::new(config.sysroot())
+//!     .remove("nano")
+//!     .execute_with_hooks(db, &config.hooks, &config.snapshot)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod accounts;
+pub mod agent;
+pub mod archive;
+pub mod autodeps;
+pub mod build;
+pub mod config;
+pub mod convert;
+pub mod cve;
+pub mod database;
+pub mod delta;
+pub mod download;
+pub mod error;
+pub mod fileattrs;
+pub mod hooks;
+pub mod package;
+pub mod repository;
+pub mod resolver;
+pub mod sandbox;
+pub mod signing;
+pub mod snapshot;
+pub mod spec;
+pub mod transaction;
+pub mod version;
+
+/// Version of the embedding API re-exported from the crate root
+///
+/// - 2: signing takes any [`signing::Signer`], such as a signing agent,
+///   instead of a loaded secret key
+/// - 3: [`RookeryDependencyProvider::add_installed`] takes the installed
+///   package's provides
+pub const API_VERSION: u32 = 3;
+
+pub use archive::{PackageArchiveReader, PackageInfo};
+pub use config::Config;
+pub use database::Database;
+pub use error::{Result, RookpkgError};
+pub use package::{InstalledPackage, InstallReason};
+pub use repository::RepoManager;
+pub use resolver::{resolve_upgrade, PackageRequest, ResolutionReport, RookeryDependencyProvider};
+pub use transaction::{Transaction, TransactionBuilder};
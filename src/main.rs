@@ -7,28 +7,9 @@ use anyhow::Result;
 use clap::Parser;
 use tracing_subscriber::EnvFilter;
 
-mod archive;
-mod build;
 mod cli;
-mod config;
-mod convert;
-mod cve;
-mod database;
-mod delta;
-mod download;
-mod error;
-mod hooks;
-mod package;
-mod repository;
-mod resolver;
-mod signing;
-mod spec;
-mod transaction;
 
-use cli::Commands;
-
-// Re-export error types for library users
-pub use error::{Result as RookpkgResult, RookpkgError};
+use cli::{Commands, OutputFormat};
 
 /// Rookery OS Package Manager
 #[derive(Parser)]
@@ -58,6 +39,10 @@ struct Cli {
     #[arg(long, global = true)]
     root: Option<std::path::PathBuf>,
 
+    /// Output format of query commands and dry runs
+    #[arg(long = "output", value_enum, global = true, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -77,10 +62,11 @@ fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| filter.into()))
         .with_target(false)
+        .with_writer(std::io::stderr)
         .init();
 
     // Load configuration
-    let mut config = config::Config::load(cli.config.as_deref())?;
+    let mut config = rookpkg::Config::load(cli.config.as_deref())?;
 
     // Apply --root if specified
     if let Some(root) = cli.root {
@@ -88,5 +74,5 @@ fn main() -> Result<()> {
     }
 
     // Execute command
-    cli::execute(cli.command, &config)
+    cli::execute(cli.command, cli.format, &config)
 }
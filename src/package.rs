@@ -5,6 +5,8 @@ use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::fileattrs::Xattrs;
+
 /// Reason why a package was installed
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
@@ -114,6 +116,14 @@ pub struct PackageFile {
 
     /// Is this a config file (preserved on upgrade)?
     pub is_config: bool,
+
+    /// Modification time (Unix epoch, 0 = unknown)
+    #[serde(default)]
+    pub mtime: i64,
+
+    /// Extended attributes: name -> hex-encoded value
+    #[serde(default)]
+    pub xattrs: Xattrs,
 }
 
 /// A dependency relationship
@@ -6,8 +6,11 @@
 //!
 //! A repository is a directory (local or remote) containing:
 //! - `repo.toml` - Repository metadata (name, description, signing key)
+//! - `repo.toml.sig` - Signature of the repository metadata
 //! - `packages.json` - Package index (all available packages)
 //! - `packages.json.sig` - Signature of the package index
+//! - `revocations.json` - Signed list of revoked keys (optional)
+//! - `rotations.json` - Key rotation statements (optional)
 //! - `packages/` - Directory containing .rookpkg files
 //!
 //! ## Repository Format
@@ -18,16 +21,54 @@
 //! name = "rookery-core"
 //! description = "Core packages for Rookery OS"
 //! version = 1
+//! index_valid_days = 7    # packages.json expires unless re-signed in time
 //!
 //! [signing]
 //! fingerprint = "HYBRID:SHA256:..."
 //! public_key = "path/to/key.pub or inline base64"
+//! fingerprints = ["HYBRID:SHA256:...", "HYBRID:SHA256:..."]  # further release keys
+//! threshold = 2           # signatures required from distinct keys
 //!
 //! [[mirrors]]
 //! url = "https://packages.rookery.org/core"
 //! priority = 1
 //! ```
-
+//!
+//! ## Signing Key
+//!
+//! repo.toml is signed like the index, since clients act on its mirror
+//! list. The keys it names are only checked, never trusted: repo.toml must
+//! be signed under the keys and threshold accepted by the previous update
+//! (or the first one, which must include the pinned fingerprint if there is
+//! one), and only then do its own keys and threshold apply to the index.
+//! Signature files are envelopes that collect one signature per key, so a
+//! threshold of 2 means no single stolen key can publish an update. Mirrors
+//! of unsigned metadata (with `allow_untrusted`) are dropped.
+//!
+//! ## Freshness
+//!
+//! A valid signature alone does not stop a mirror from serving an old
+//! index forever, or swapping in an older one. Like TUF's timestamp and
+//! snapshot roles, the signed index carries `generated` and an optional
+//! `valid_until`, and an update refuses an index that has expired, that is
+//! older than the cached one, or that exceeds the repository's configured
+//! `max_metadata_age_days`. A cached index that has expired is not used to
+//! find packages until an update replaces it.
+//!
+//! ## Revocation
+//!
+//! An update applies the repository's key rotations whose old key is one
+//! of the repository's own keys and trusted locally, retiring the old key.
+//! The new key is trusted for that repository only: it is kept with the
+//! repository's cache, never in the master or packager key directories.
+//! The revocation list is recorded in the database; signed by a master key
+//! it may revoke any key, signed by a repository key only the repository's
+//! own keys. Revocations are never
+//! dropped, so serving an older list changes nothing. Signatures made by a
+//! key after its revocation, or after its certification expired, are
+//! refused from then on.
+
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{BufReader, Read, Write};
 use std::path::{Path, PathBuf};
@@ -35,15 +76,19 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use crate::error::{Context, Result, RookpkgError};
+use crate::archive::{detached_signature_path, PackageArchiveReader, PKG_EXTENSION};
 use crate::config::{Config, DownloadConfig};
+use crate::database::{Database, RevokedKey};
 use crate::delta::RepoDeltaIndex;
-use crate::signing::{self, HybridSignature, LoadedPublicKey};
+use crate::resolver::{format_dep, parse_version};
+use crate::signing::{self, HybridSignature, KeyRotation, LoadedPublicKey, RevocationList, SignatureEnvelope};
+use crate::version::Version;
 
 /// Repository metadata from repo.toml
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +115,9 @@ pub struct RepositoryInfo {
     /// Last update timestamp
     #[serde(default)]
     pub updated: Option<DateTime<Utc>>,
+    /// Days a newly generated or re-signed index stays valid (no expiry if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_valid_days: Option<u32>,
 }
 
 fn default_version() -> u32 {
@@ -84,6 +132,60 @@ pub struct RepoSigningInfo {
     /// Public key (path or inline base64)
     #[serde(default)]
     pub public_key: Option<String>,
+    /// Further keys that may sign the repository
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fingerprints: Vec<String>,
+    /// Signatures required from distinct keys (default 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u32>,
+}
+
+impl RepoSigningInfo {
+    /// Policy of a single key
+    pub fn single(fingerprint: &str) -> Self {
+        Self {
+            fingerprint: fingerprint.to_string(),
+            public_key: None,
+            fingerprints: Vec::new(),
+            threshold: None,
+        }
+    }
+
+    /// All keys that may sign the repository
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys = vec![self.fingerprint.as_str()];
+        for fingerprint in &self.fingerprints {
+            if !keys.contains(&fingerprint.as_str()) {
+                keys.push(fingerprint);
+            }
+        }
+        keys
+    }
+
+    /// Whether the key with this full fingerprint may sign the repository
+    ///
+    /// Fingerprints in signatures are chosen by the signer, so they must
+    /// match exactly; an abbreviated one names no key.
+    pub fn allows(&self, fingerprint: &str) -> bool {
+        self.keys().contains(&fingerprint)
+    }
+
+    /// Number of distinct keys whose signatures are required
+    pub fn required_signatures(&self) -> usize {
+        self.threshold.unwrap_or(1).max(1) as usize
+    }
+
+    /// Replace a rotated key with its successor
+    pub fn rotate(&mut self, old_key: &str, new_key: &str) {
+        if self.fingerprint == old_key {
+            self.fingerprint = new_key.to_string();
+        }
+        for fingerprint in &mut self.fingerprints {
+            if fingerprint == old_key {
+                *fingerprint = new_key.to_string();
+            }
+        }
+    }
 }
 
 /// A repository mirror
@@ -214,6 +316,58 @@ pub struct PackageEntry {
     pub build_date: Option<DateTime<Utc>>,
 }
 
+impl PackageEntry {
+    /// Build an index entry from a package archive on disk.
+    ///
+    /// `filename` is the path recorded in the index (relative to the
+    /// repository root, or just the file name for cached archives).
+    pub fn from_archive(path: &Path, filename: String) -> Result<Self> {
+        let reader = PackageArchiveReader::open(path)?;
+        let info = reader.read_info()?;
+
+        // Relation tables become index strings ("name" or "name >= 1.0")
+        let to_list = |map: &HashMap<String, String>| -> Vec<String> {
+            let mut list: Vec<String> = map.iter().map(|(n, c)| format_dep(n, c)).collect();
+            list.sort();
+            list
+        };
+
+        Ok(Self {
+            depends: to_list(&info.depends),
+            build_depends: to_list(&info.build_depends),
+            provides: to_list(&info.provides),
+            conflicts: to_list(&info.conflicts),
+            replaces: to_list(&info.replaces),
+            license: Some(info.license).filter(|s| !s.is_empty()),
+            homepage: Some(info.url).filter(|s| !s.is_empty()),
+            maintainer: Some(info.maintainer).filter(|s| !s.is_empty()),
+            build_date: DateTime::from_timestamp(info.build_time, 0),
+            name: info.name,
+            version: info.version,
+            release: info.release,
+            description: info.description,
+            arch: info.arch,
+            size: fs::metadata(path)?.len(),
+            sha256: compute_sha256(path)?,
+            filename,
+        })
+    }
+}
+
+/// Pick the entry for a resolved version, preferring the highest release
+/// unless a specific release is requested
+pub fn find_candidate<'a>(
+    candidates: &'a [SearchResult],
+    version: &Version,
+    release: Option<u32>,
+) -> Option<&'a SearchResult> {
+    candidates
+        .iter()
+        .filter(|c| &parse_version(&c.package.version) == version)
+        .filter(|c| release.is_none_or(|r| c.package.release == r))
+        .max_by_key(|c| c.package.release)
+}
+
 fn default_release() -> u32 {
     1
 }
@@ -229,6 +383,9 @@ pub struct PackageIndex {
     pub version: u32,
     /// When the index was generated
     pub generated: DateTime<Utc>,
+    /// When clients stop accepting the index (covered by its signature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<DateTime<Utc>>,
     /// Repository name
     pub repository: String,
     /// Package count
@@ -249,6 +406,7 @@ impl PackageIndex {
         Self {
             version: 1,
             generated: Utc::now(),
+            valid_until: None,
             repository: repository.to_string(),
             count: 0,
             packages: Vec::new(),
@@ -280,6 +438,58 @@ impl PackageIndex {
         self.groups.iter().find(|g| g.name == name)
     }
 
+    /// Mark the index as generated now, valid for `valid_days` if given
+    ///
+    /// Repositories with an expiry re-sign the index this way before it
+    /// runs out, even when no package changed.
+    pub fn stamp(&mut self, valid_days: Option<u32>) {
+        self.generated = Utc::now();
+        self.valid_until = valid_days.map(|days| self.generated + chrono::Duration::days(days.into()));
+    }
+
+    /// Whether the index is past its `valid_until`
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.valid_until.is_some_and(|until| until <= now)
+    }
+
+    /// Check a freshly fetched index against its expiry, the maximum age
+    /// allowed for the repository, and the index cached from the last update
+    pub fn check_freshness(
+        &self,
+        cached: Option<&PackageIndex>,
+        max_age_days: Option<u32>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        if let Some(until) = self.valid_until {
+            if until <= now {
+                return Err(RookpkgError::StaleMetadata(format!(
+                    "index of '{}' expired at {}",
+                    self.repository, until
+                )));
+            }
+        }
+
+        if let Some(days) = max_age_days {
+            if now - self.generated > chrono::Duration::days(days.into()) {
+                return Err(RookpkgError::StaleMetadata(format!(
+                    "index of '{}' was generated at {}, more than {} day(s) ago",
+                    self.repository, self.generated, days
+                )));
+            }
+        }
+
+        if let Some(cached) = cached {
+            if self.generated < cached.generated {
+                return Err(RookpkgError::StaleMetadata(format!(
+                    "index of '{}' was generated at {}, before the cached index ({}); refusing to roll back",
+                    self.repository, self.generated, cached.generated
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Find all versions of a package
     pub fn find_all_versions(&self, name: &str) -> Vec<&PackageEntry> {
         self.packages.iter().filter(|p| p.name == name).collect()
@@ -401,13 +611,22 @@ impl Repository {
         }
 
         if index_path.exists() {
-            let content = fs::read_to_string(&index_path)?;
-            self.index = Some(serde_json::from_str(&content)?);
+            self.index = self.cached_index()?;
         }
 
         Ok(())
     }
 
+    /// The package index cached by the last update, read from disk
+    fn cached_index(&self) -> Result<Option<PackageIndex>> {
+        let index_path = self.cache_dir.join("packages.json");
+        if !index_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&index_path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
     /// Save metadata to cache
     pub fn save_cache(&self) -> Result<()> {
         fs::create_dir_all(&self.cache_dir)?;
@@ -435,6 +654,11 @@ impl Repository {
         self.file_url("repo.toml")
     }
 
+    /// Get the URL for the repository metadata signature
+    pub fn metadata_sig_url(&self) -> String {
+        self.file_url("repo.toml.sig")
+    }
+
     /// Get the URL for the package index
     pub fn index_url(&self) -> String {
         self.file_url("packages.json")
@@ -445,6 +669,21 @@ impl Repository {
         self.file_url("packages.json.sig")
     }
 
+    /// Get the URL for the revocation list
+    pub fn revocations_url(&self) -> String {
+        self.file_url("revocations.json")
+    }
+
+    /// Get the URL for the key rotation statements
+    pub fn rotations_url(&self) -> String {
+        self.file_url("rotations.json")
+    }
+
+    /// Keys the repository rotated to, trusted for this repository only
+    pub fn keys_dir(&self) -> PathBuf {
+        self.cache_dir.join("keys")
+    }
+
     /// Get the URL for a package file
     pub fn package_url(&self, entry: &PackageEntry) -> String {
         self.file_url(&entry.filename)
@@ -569,72 +808,157 @@ impl RepoManager {
         let repo = &self.repos[idx];
         let name = repo.name.clone();
         let metadata_url = repo.metadata_url();
+        let metadata_sig_url = repo.metadata_sig_url();
         let index_url = repo.index_url();
         let sig_url = repo.index_sig_url();
+        let revocations_url = repo.revocations_url();
+        let rotations_url = repo.rotations_url();
+        let keys_dir = repo.keys_dir();
+        let repo_config = config.repositories.iter().find(|r| r.name == name);
 
         tracing::info!("Updating repository: {}", name);
 
-        // Fetch repository metadata
-        let metadata_response = self.client.get(&metadata_url).send()?;
-
-        if !metadata_response.status().is_success() {
-            bail!(
-                "Failed to fetch repository metadata: HTTP {}",
-                metadata_response.status()
-            );
+        // What the last successful update accepted: the signing key, unless
+        // one is pinned, and the index freshness is compared against
+        let repo = &mut self.repos[idx];
+        if (repo.metadata.is_none() || repo.index.is_none()) && repo.has_cache() {
+            if let Err(e) = repo.load_cache() {
+                tracing::warn!("Ignoring unreadable cache of '{}': {}", name, e);
+            }
         }
 
-        let metadata_content = metadata_response.text()?;
-        let metadata: RepoMetadata = toml::from_str(&metadata_content)?;
-
-        // Fetch package index
-        let index_response = self.client.get(&index_url).send()?;
+        // Fetch repository metadata
+        let metadata_content = self.fetch_text(&metadata_url)?.ok_or_else(|| {
+            RookpkgError::Repository(format!("Failed to fetch repository metadata: {}", metadata_url))
+        })?;
+        let mut metadata: RepoMetadata = toml::from_str(&metadata_content)?;
+
+        let accepted = self.repos[idx].metadata.as_ref().map(|m| &m.signing);
+        let pinned = repo_config.and_then(|r| r.fingerprint.clone());
+        let mut policy = signing_policy(&name, accepted, pinned.as_deref(), &metadata.signing)?;
+
+        // Rotations by keys trusted here take effect first, so the
+        // repository can move to a new key without being re-pinned
+        let known_revoked = load_revoked_keys(config)?;
+        let mut revoked = Vec::new();
+        if let Some(content) = self.fetch_text(&rotations_url)? {
+            let rotations: Vec<KeyRotation> = serde_json::from_str(&content)?;
+            for rotation in &rotations {
+                // A key revoked by someone else may have been stolen
+                if known_revoked
+                    .iter()
+                    .any(|k| k.fingerprint == rotation.old_key && k.revoked_by != rotation.old_key)
+                {
+                    tracing::warn!("Ignoring rotation of revoked key {}", rotation.old_key);
+                    continue;
+                }
+                if self.apply_rotation(rotation, &policy, &keys_dir, config, &mut revoked)? {
+                    policy.rotate(&rotation.old_key, &rotation.new_key);
+                }
+            }
+            if let Some(pinned) = pinned.as_deref().filter(|p| !policy.allows(p)) {
+                tracing::warn!(
+                    "Repository '{}' rotated key {} to {}; pin the new fingerprint in its configuration",
+                    name,
+                    pinned,
+                    policy.keys().join(", ")
+                );
+            }
+        }
 
-        if !index_response.status().is_success() {
-            bail!(
-                "Failed to fetch package index: HTTP {}",
-                index_response.status()
-            );
+        // Revocations apply before any signature below is counted
+        if let Some(content) = self.fetch_text(&revocations_url)? {
+            let list: RevocationList = serde_json::from_str(&content)?;
+            revoked.extend(self.accept_revocations(&list, &name, &policy, &keys_dir, config)?);
+        }
+        let revoked = record_revocations(&revoked, known_revoked, config)?;
+        let find_key = |fingerprint: &str| self.find_repo_key(fingerprint, &keys_dir, config);
+
+        // Verify repo.toml; mirrors are only used from signed metadata
+        let mut public_key = None;
+        match self.fetch_text(&metadata_sig_url)? {
+            Some(sig_content) => {
+                let envelope = SignatureEnvelope::parse(&sig_content)?;
+                let key = verify_threshold(
+                    "Repository metadata",
+                    metadata_content.as_bytes(),
+                    &envelope,
+                    &policy,
+                    find_key,
+                    &revoked,
+                    config,
+                )?;
+                public_key = Some(key);
+
+                // Signed metadata may change the keys and threshold for what
+                // follows, now that it has been accepted
+                policy = metadata.signing.clone();
+            }
+            None if !config.signing.allow_untrusted => {
+                return Err(RookpkgError::SignatureVerificationFailed(
+                    "Repository metadata signature not found and untrusted repositories are not allowed".to_string(),
+                ));
+            }
+            None => {
+                tracing::warn!("Repository metadata signature not found, proceeding without verification");
+                if !metadata.mirrors.is_empty() {
+                    tracing::warn!("Ignoring the mirrors of unsigned repository metadata");
+                    metadata.mirrors.clear();
+                }
+            }
         }
 
-        let index_content = index_response.text()?;
+        // Fetch package index
+        let index_content = self.fetch_text(&index_url)?.ok_or_else(|| {
+            RookpkgError::Repository(format!("Failed to fetch package index: {}", index_url))
+        })?;
 
         // Fetch and verify signature
-        let sig_response = self.client.get(&sig_url).send()?;
-
-        let public_key = if sig_response.status().is_success() {
-            let sig_content = sig_response.text()?;
-            let signature: HybridSignature = serde_json::from_str(&sig_content)?;
-
-            // Find the public key
-            let public_key = self.find_repo_key(&metadata.signing.fingerprint, config)?;
-
-            // Verify the signature
-            signing::verify_signature(&public_key, index_content.as_bytes(), &signature)
-                .context("Package index signature verification failed")?;
-
-            tracing::info!("Package index signature verified");
-            Some(public_key)
-        } else if !config.signing.allow_untrusted {
-            bail!("Package index signature not found and untrusted repositories are not allowed");
-        } else {
-            tracing::warn!("Package index signature not found, proceeding without verification");
-            None
-        };
+        match self.fetch_text(&sig_url)? {
+            Some(sig_content) => {
+                let envelope = SignatureEnvelope::parse(&sig_content)?;
+                let key = verify_threshold(
+                    "Package index",
+                    index_content.as_bytes(),
+                    &envelope,
+                    &policy,
+                    find_key,
+                    &revoked,
+                    config,
+                )?;
+                public_key.get_or_insert(key);
+            }
+            None if !config.signing.allow_untrusted => {
+                return Err(RookpkgError::SignatureVerificationFailed(
+                    "Package index signature not found and untrusted repositories are not allowed".to_string(),
+                ));
+            }
+            None => {
+                tracing::warn!("Package index signature not found, proceeding without verification");
+            }
+        }
 
         // Parse the index
         let index: PackageIndex = serde_json::from_str(&index_content)?;
 
+        // Refuse frozen, expired or rolled back indexes
+        let repo = &mut self.repos[idx];
+        let max_age_days = repo_config.and_then(|r| r.max_metadata_age_days);
+        // An expired index is not loaded, but still guards against rollback
+        let on_disk;
+        let cached = match &repo.index {
+            Some(cached) => Some(cached),
+            None => {
+                on_disk = repo.cached_index().ok().flatten();
+                on_disk.as_ref()
+            }
+        };
+        index.check_freshness(cached, max_age_days, Utc::now())?;
+
         // Check if anything changed
-        let repo = &self.repos[idx];
-        let changed = repo
-            .index
-            .as_ref()
-            .map(|i| i.generated != index.generated)
-            .unwrap_or(true);
+        let changed = cached.map(|i| i.generated != index.generated).unwrap_or(true);
 
         // Update repo state
-        let repo = &mut self.repos[idx];
         repo.metadata = Some(metadata);
         repo.index = Some(index);
         repo.public_key = public_key;
@@ -645,8 +969,109 @@ impl RepoManager {
         Ok(changed)
     }
 
-    /// Find a repository signing key
-    fn find_repo_key(&self, fingerprint: &str, config: &Config) -> Result<LoadedPublicKey> {
+    /// Apply a key rotation if its old key signs for the repository and
+    /// is trusted here
+    ///
+    /// The new key goes into the repository's `keys_dir`, and the old key
+    /// is recorded as revoked from the effective time on. Returns whether
+    /// the rotation applied.
+    fn apply_rotation(
+        &self,
+        rotation: &KeyRotation,
+        policy: &RepoSigningInfo,
+        keys_dir: &Path,
+        config: &Config,
+        revoked: &mut Vec<RevokedKey>,
+    ) -> Result<bool> {
+        if !policy.allows(&rotation.old_key) {
+            tracing::warn!("Ignoring rotation of {}, which does not sign this repository", rotation.old_key);
+            return Ok(false);
+        }
+        let Ok(old_key) = self.find_repo_key(&rotation.old_key, keys_dir, config) else {
+            tracing::debug!("Ignoring rotation of untrusted key {}", rotation.old_key);
+            return Ok(false);
+        };
+        let new_key = signing::verify_rotation(rotation, &old_key)?;
+
+        if self.search_key_in_dir(keys_dir, &new_key.fingerprint)?.is_none() {
+            fs::create_dir_all(keys_dir)?;
+            let path = keys_dir.join(format!("{}.pub", new_key.fingerprint.replace([':', '/'], "-")));
+            fs::write(&path, &rotation.new_public_key)?;
+            tracing::warn!(
+                "Key {} rotated to {}, installed {}",
+                old_key.fingerprint,
+                new_key.fingerprint,
+                path.display()
+            );
+        }
+
+        revoked.push(RevokedKey {
+            fingerprint: old_key.fingerprint.clone(),
+            revoked_date: rotation.effective_time()?.timestamp(),
+            reason: format!("Rotated to {}", new_key.fingerprint),
+            revoked_by: old_key.fingerprint,
+        });
+        Ok(true)
+    }
+
+    /// The revocations of a repository's list that its issuer may make
+    ///
+    /// A master key may revoke any key. A key of the repository may only
+    /// revoke the repository's own keys, so one repository cannot revoke
+    /// the keys of another, or the master keys.
+    fn accept_revocations(
+        &self,
+        list: &RevocationList,
+        name: &str,
+        policy: &RepoSigningInfo,
+        keys_dir: &Path,
+        config: &Config,
+    ) -> Result<Vec<RevokedKey>> {
+        let (issuer, master) = match self.search_key_in_dir(&config.signing.master_keys_dir, &list.issuer)? {
+            Some(key) => (key, true),
+            None if policy.allows(&list.issuer) => (self.find_repo_key(&list.issuer, keys_dir, config)?, false),
+            None => {
+                return Err(RookpkgError::UntrustedSigner(format!(
+                    "{} (revocation list of '{}')",
+                    list.issuer, name
+                )))
+            }
+        };
+        signing::verify_revocation_list(list, &issuer)?;
+
+        let mut accepted = Vec::new();
+        for entry in &list.entries {
+            if !master && !policy.allows(&entry.fingerprint) {
+                tracing::warn!(
+                    "Ignoring revocation of {} by {}: not a key of '{}'",
+                    entry.fingerprint,
+                    list.issuer,
+                    name
+                );
+                continue;
+            }
+            accepted.push(RevokedKey {
+                fingerprint: entry.fingerprint.clone(),
+                revoked_date: entry.revocation_time()?.timestamp(),
+                reason: entry.reason.clone(),
+                revoked_by: list.issuer.clone(),
+            });
+        }
+        Ok(accepted)
+    }
+
+    /// Fetch a text file, or None if the server does not have it
+    fn fetch_text(&self, url: &str) -> Result<Option<String>> {
+        let response = self.client.get(url).send()?;
+        if !response.status().is_success() {
+            tracing::debug!("{}: HTTP {}", url, response.status());
+            return Ok(None);
+        }
+        Ok(Some(response.text()?))
+    }
+
+    /// Find a repository signing key by its full fingerprint
+    fn find_repo_key(&self, fingerprint: &str, keys_dir: &Path, config: &Config) -> Result<LoadedPublicKey> {
         // Search in master keys
         let master_dir = &config.signing.master_keys_dir;
         if let Some(key) = self.search_key_in_dir(master_dir, fingerprint)? {
@@ -659,14 +1084,19 @@ impl RepoManager {
             return Ok(key);
         }
 
-        bail!(
-            "Repository signing key not found: {}\n\
+        // Search in the keys the repository rotated to
+        if let Some(key) = self.search_key_in_dir(keys_dir, fingerprint)? {
+            return Ok(key);
+        }
+
+        Err(RookpkgError::UntrustedSigner(format!(
+            "{}\n\
             Add the repository's public key with: rookpkg keytrust <key.pub>",
             fingerprint
-        );
+        )))
     }
 
-    /// Search for a key in a directory
+    /// Search for a key in a directory by its full fingerprint
     fn search_key_in_dir(
         &self,
         dir: &Path,
@@ -681,10 +1111,7 @@ impl RepoManager {
             let path = entry.path();
             if path.extension().map(|e| e == "pub").unwrap_or(false) {
                 if let Ok(key) = signing::load_public_key(&path) {
-                    if key.fingerprint == fingerprint
-                        || key.fingerprint.ends_with(fingerprint)
-                        || fingerprint.ends_with(&key.fingerprint)
-                    {
+                    if key.fingerprint == fingerprint {
                         return Ok(Some(key));
                     }
                 }
@@ -735,6 +1162,33 @@ impl RepoManager {
         None
     }
 
+    /// Every available version of every package, grouped by name.
+    ///
+    /// Each name is taken only from the highest-priority repository that
+    /// carries it, so a lower-priority repository cannot shadow it with a
+    /// newer version.
+    pub fn package_candidates(&self) -> HashMap<String, Vec<SearchResult>> {
+        let mut candidates: HashMap<String, Vec<SearchResult>> = HashMap::new();
+
+        for repo in self.enabled_repos() {
+            let Some(ref index) = repo.index else {
+                continue;
+            };
+            for entry in &index.packages {
+                let versions = candidates.entry(entry.name.clone()).or_default();
+                if versions.first().is_some_and(|r| r.repository != repo.name) {
+                    continue;
+                }
+                versions.push(SearchResult {
+                    repository: repo.name.clone(),
+                    package: entry.clone(),
+                });
+            }
+        }
+
+        candidates
+    }
+
     /// Find a package group by name across all enabled repositories
     pub fn find_group(&self, name: &str) -> Option<GroupSearchResult> {
         for repo in self.enabled_repos() {
@@ -784,11 +1238,22 @@ impl RepoManager {
     }
 
     /// Load cached data for all repositories
+    ///
+    /// An index past its `valid_until` is dropped, so its packages are not
+    /// offered until `rookpkg update` fetches a fresh one.
     pub fn load_caches(&mut self) -> Result<()> {
+        let now = Utc::now();
         for repo in &mut self.repos {
             if repo.has_cache() {
                 let _ = repo.load_cache(); // Ignore errors, just use what we can
             }
+            if repo.index.as_ref().is_some_and(|index| index.is_expired(now)) {
+                tracing::warn!(
+                    "Cached index of '{}' has expired and is ignored; run `rookpkg update` to refresh it",
+                    repo.name
+                );
+                repo.index = None;
+            }
         }
         Ok(())
     }
@@ -807,104 +1272,168 @@ impl RepoManager {
             .repos
             .iter()
             .find(|r| r.name == repo_name)
-            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", repo_name))?;
+            .ok_or_else(|| RookpkgError::Repository(format!("Repository not found: {}", repo_name)))?;
 
         // Download the package file
         let pkg_path = self.download_package(package, repo_name)?;
 
-        // Determine signature file path
-        let pkg_filename = Path::new(&package.filename)
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_else(|| format!("{}-{}-{}.rookpkg", package.name, package.version, package.release));
-        let sig_filename = format!("{}.sig", pkg_filename);
-        let sig_cache_path = self.pkg_cache_dir.join(&sig_filename);
-
-        // Try to download the signature file
-        let sig_url = format!("{}.sig", repo.package_url(package));
-
-        let signature_result = match self.download_with_retries(&sig_url, &sig_cache_path) {
-            Ok(()) => {
-                // Parse the signature
-                let sig_content = fs::read_to_string(&sig_cache_path)
-                    .context("Failed to read signature file")?;
-                let signature: HybridSignature = serde_json::from_str(&sig_content)
-                    .context("Failed to parse signature file")?;
-
-                // Find the signing key
-                match self.find_signing_key(&signature.fingerprint, config) {
-                    Ok(public_key) => {
-                        // Read package content for verification
-                        let pkg_content = fs::read(&pkg_path)
-                            .context("Failed to read package for verification")?;
-
-                        // Verify the signature
-                        match signing::verify_signature(&public_key, &pkg_content, &signature) {
-                            Ok(()) => {
-                                tracing::info!("Package signature verified: {}", pkg_filename);
-                                SignatureStatus::Verified {
-                                    fingerprint: signature.fingerprint.clone(),
-                                    signer: format!("{} <{}>", public_key.name, public_key.email),
-                                    trust_level: public_key.trust_level,
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("Signature verification failed: {}", e);
-                                SignatureStatus::Invalid(e.to_string())
-                            }
+        // Packages built before signatures were embedded have a detached .sig
+        if PackageArchiveReader::open(&pkg_path)?.signature().is_none() {
+            let sig_url = format!("{}.sig", repo.package_url(package));
+            if let Err(e) = self.download_with_retries(&sig_url, &detached_signature_path(&pkg_path)) {
+                tracing::warn!("No signature found for {}: {}", package.filename, e);
+            }
+        }
+        let signature_result = self.check_signature(&pkg_path, config)?;
+
+        // Reject unsigned, unknown key, or invalid packages - signing is MANDATORY
+        require_verified(&signature_result, &package.name)?;
+
+        Ok(VerifiedPackage {
+            path: pkg_path,
+            package: package.clone(),
+            signature_status: signature_result,
+        })
+    }
+
+    /// Verify the signature of a cached package archive.
+    ///
+    /// Used for archives that are no longer listed in any repository index,
+    /// such as older builds kept for downgrades.
+    pub fn verify_cached_package(&self, package: &PackageEntry, config: &Config) -> Result<VerifiedPackage> {
+        let pkg_path = self.pkg_cache_dir.join(&package.filename);
+        if !verify_sha256(&pkg_path, &package.sha256)? {
+            return Err(RookpkgError::InvalidPackage(format!(
+                "Cached package {} changed on disk",
+                pkg_path.display()
+            )));
+        }
+
+        let signature_status = self.check_signature(&pkg_path, config)?;
+        require_verified(&signature_status, &package.name)?;
+
+        Ok(VerifiedPackage {
+            path: pkg_path,
+            package: package.clone(),
+            signature_status,
+        })
+    }
+
+    /// Locate a specific build of a package.
+    ///
+    /// Repositories are searched in priority order, then archives left in
+    /// the package cache.
+    pub fn find_build(&self, name: &str, version: &str, release: u32) -> Option<(PackageEntry, BuildSource)> {
+        let wanted = parse_version(version);
+        for repo in self.enabled_repos() {
+            let Some(ref index) = repo.index else {
+                continue;
+            };
+            let found = index
+                .find_all_versions(name)
+                .into_iter()
+                .find(|p| p.release == release && parse_version(&p.version) == wanted);
+            if let Some(entry) = found {
+                return Some((entry.clone(), BuildSource::Repository(repo.name.clone())));
+            }
+        }
+
+        self.cached_versions(name)
+            .into_iter()
+            .find(|p| p.release == release && parse_version(&p.version) == wanted)
+            .map(|entry| (entry, BuildSource::Cache))
+    }
+
+    /// Download (or reuse from the cache) and verify a located build
+    pub fn fetch_build(
+        &self,
+        package: &PackageEntry,
+        source: &BuildSource,
+        config: &Config,
+    ) -> Result<VerifiedPackage> {
+        match source {
+            BuildSource::Repository(repo) => self.download_and_verify_package(package, repo, config),
+            BuildSource::Cache => self.verify_cached_package(package, config),
+        }
+    }
+
+    /// Versions of a package available as archives in the package cache
+    pub fn cached_versions(&self, name: &str) -> Vec<PackageEntry> {
+        let Ok(dir) = fs::read_dir(&self.pkg_cache_dir) else {
+            return Vec::new();
+        };
+
+        let prefix = format!("{}-", name);
+        let mut entries = Vec::new();
+        for file in dir.flatten() {
+            let filename = file.file_name().to_string_lossy().to_string();
+            if !filename.starts_with(&prefix) || !filename.ends_with(PKG_EXTENSION) {
+                continue;
+            }
+            match PackageEntry::from_archive(&file.path(), filename.clone()) {
+                // The prefix also matches packages such as "name-libs"
+                Ok(entry) if entry.name == name => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => tracing::debug!("Skipping cached archive {}: {}", filename, e),
+            }
+        }
+        entries
+    }
+
+    /// Verify the signature of a package file from outside any repository
+    ///
+    /// Fails unless the package is signed by a known key, like packages
+    /// installed from a repository.
+    pub fn verify_local_package(&self, pkg_path: &Path, config: &Config) -> Result<SignatureStatus> {
+        let status = self.check_signature(pkg_path, config)?;
+        require_verified(&status, &pkg_path.display().to_string())?;
+        Ok(status)
+    }
+
+    /// Check a package file against its embedded (or detached) signature
+    fn check_signature(&self, pkg_path: &Path, config: &Config) -> Result<SignatureStatus> {
+        let Some(package_signature) = PackageArchiveReader::open(pkg_path)?.find_signature()? else {
+            return Ok(SignatureStatus::Unsigned);
+        };
+        let signature = &package_signature.signature;
+
+        // Find the signing key
+        let signed_at = signing::signature_time(signature)?;
+        let status = match self.find_signing_key(&signature.fingerprint, signed_at, config) {
+            Ok(public_key) => {
+                // Verify the signature, then that the key could still sign
+                match package_signature.verify(&public_key) {
+                    Ok(()) => {
+                        check_key_validity(signature, &load_revoked_keys(config)?, config)?;
+                        tracing::info!("Package signature verified: {}", pkg_path.display());
+                        SignatureStatus::Verified {
+                            fingerprint: signature.fingerprint.clone(),
+                            signer: format!("{} <{}>", public_key.name, public_key.email),
+                            trust_level: public_key.trust_level,
                         }
                     }
                     Err(e) => {
-                        tracing::warn!("Signing key not found: {}", e);
-                        SignatureStatus::UnknownKey(signature.fingerprint.clone())
+                        tracing::error!("Signature verification failed: {}", e);
+                        SignatureStatus::Invalid(e.to_string())
                     }
                 }
             }
             Err(e) => {
-                tracing::warn!("No signature file found for {}: {}", pkg_filename, e);
-                SignatureStatus::Unsigned
+                tracing::warn!("Signing key not found: {}", e);
+                SignatureStatus::UnknownKey(signature.fingerprint.clone())
             }
         };
 
-        // Reject unsigned, unknown key, or invalid packages - signing is MANDATORY
-        match &signature_result {
-            SignatureStatus::Invalid(reason) => {
-                bail!(
-                    "Package signature is INVALID: {}\n\
-                    DO NOT INSTALL - package may be tampered!",
-                    reason
-                );
-            }
-            SignatureStatus::Unsigned => {
-                bail!(
-                    "Package {} is unsigned.\n\
-                    All packages MUST be signed with a trusted key.\n\
-                    Contact the package maintainer to sign this package.",
-                    package.name
-                );
-            }
-            SignatureStatus::UnknownKey(fingerprint) => {
-                bail!(
-                    "Package {} is signed with unknown key: {}\n\
-                    Trust the key with: rookpkg keytrust <key.pub>",
-                    package.name,
-                    fingerprint
-                );
-            }
-            SignatureStatus::Verified { .. } => {
-                // Valid signature - proceed
-            }
-        }
-
-        Ok(VerifiedPackage {
-            path: pkg_path,
-            package: package.clone(),
-            signature_status: signature_result,
-        })
+        Ok(status)
     }
 
-    /// Find a signing key by fingerprint
-    fn find_signing_key(&self, fingerprint: &str, config: &Config) -> Result<signing::LoadedPublicKey> {
+    /// Find a signing key by fingerprint, judging certifications as of `signed_at`
+    fn find_signing_key(
+        &self,
+        fingerprint: &str,
+        signed_at: DateTime<Utc>,
+        config: &Config,
+    ) -> Result<signing::LoadedPublicKey> {
         // Search in master keys (full trust - these are the root of trust)
         if let Some(mut key) = self.search_key_in_dir(&config.signing.master_keys_dir, fingerprint)? {
             key.trust_level = signing::TrustLevel::Full;
@@ -919,7 +1448,7 @@ impl RepoManager {
                 // Try to find the certifying master key
                 if let Some(master_key) = self.search_key_in_dir(&config.signing.master_keys_dir, &cert.certifier_key)? {
                     // Verify the certification
-                    if signing::verify_certification(&cert, &key, &master_key).is_ok() {
+                    if signing::verify_certification_at(&cert, &key, &master_key, signed_at).is_ok() {
                         tracing::debug!(
                             "Key {} certified by master key {} for purpose '{}'",
                             key.fingerprint,
@@ -946,17 +1475,14 @@ impl RepoManager {
 
         if user_pub_path.exists() {
             if let Ok(mut key) = signing::load_public_key(&user_pub_path) {
-                if key.fingerprint == fingerprint
-                    || key.fingerprint.ends_with(fingerprint)
-                    || fingerprint.ends_with(&key.fingerprint)
-                {
+                if key.fingerprint == fingerprint {
                     key.trust_level = signing::TrustLevel::Ultimate;
                     return Ok(key);
                 }
             }
         }
 
-        bail!("Signing key not found: {}", fingerprint)
+        Err(RookpkgError::UntrustedSigner(fingerprint.to_string()))
     }
 
     /// Download a package from a repository with mirror fallback
@@ -969,7 +1495,7 @@ impl RepoManager {
             .repos
             .iter()
             .find(|r| r.name == repo_name)
-            .ok_or_else(|| anyhow::anyhow!("Repository not found: {}", repo_name))?;
+            .ok_or_else(|| RookpkgError::Repository(format!("Repository not found: {}", repo_name)))?;
 
         // Determine the local cache path for this package
         let pkg_filename = Path::new(&package.filename)
@@ -1039,7 +1565,7 @@ impl RepoManager {
         }
 
         // Try each URL until one succeeds
-        let mut last_error: Option<anyhow::Error> = None;
+        let mut last_error: Option<RookpkgError> = None;
 
         for url in &urls {
             tracing::info!("Downloading package from: {}", url);
@@ -1053,11 +1579,10 @@ impl RepoManager {
                             return Ok(cache_path);
                         }
                         Ok(false) => {
-                            let err = anyhow::anyhow!(
+                            let err = RookpkgError::DownloadFailed(format!(
                                 "Checksum mismatch for {} (expected: {}, got different hash)",
-                                pkg_filename,
-                                package.sha256
-                            );
+                                pkg_filename, package.sha256
+                            ));
                             tracing::error!("{}", err);
                             fs::remove_file(&cache_path).ok();
                             last_error = Some(err);
@@ -1076,7 +1601,9 @@ impl RepoManager {
             }
         }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No URLs available for package download")))
+        Err(last_error.unwrap_or_else(|| {
+            RookpkgError::DownloadFailed("No URLs available for package download".to_string())
+        }))
     }
 
     /// Download with retry logic
@@ -1092,7 +1619,7 @@ impl RepoManager {
         progress: Option<&ProgressBar>,
     ) -> Result<()> {
         let max_retries = self.download_config.retries;
-        let mut last_error: Option<anyhow::Error> = None;
+        let mut last_error: Option<RookpkgError> = None;
 
         for attempt in 1..=max_retries {
             if attempt > 1 {
@@ -1113,7 +1640,7 @@ impl RepoManager {
         }
 
         Err(last_error.unwrap_or_else(|| {
-            anyhow::anyhow!("Download failed after {} retries", max_retries)
+            RookpkgError::DownloadFailed(format!("Download failed after {} retries", max_retries))
         }))
     }
 
@@ -1131,7 +1658,7 @@ impl RepoManager {
             .with_context(|| format!("Failed to connect to: {}", url))?;
 
         if !response.status().is_success() {
-            bail!("HTTP error {}: {}", response.status(), url);
+            return Err(RookpkgError::DownloadFailed(format!("HTTP error {}: {}", response.status(), url)));
         }
 
         let total_size = response.content_length();
@@ -1392,7 +1919,10 @@ impl RepoManager {
         let errors = errors_mutex.lock().unwrap();
         if !errors.is_empty() {
             let error_msgs: Vec<_> = errors.iter().map(|(_, msg)| msg.as_str()).collect();
-            bail!("Download errors:\n  {}", error_msgs.join("\n  "));
+            return Err(RookpkgError::DownloadFailed(format!(
+                "Download errors:\n  {}",
+                error_msgs.join("\n  ")
+            )));
         }
         drop(errors);
 
@@ -1401,11 +1931,11 @@ impl RepoManager {
         final_results.sort_by_key(|(idx, _)| *idx);
 
         if final_results.len() != packages.len() {
-            bail!(
+            return Err(RookpkgError::DownloadFailed(format!(
                 "Download incomplete: expected {} packages, got {}",
                 packages.len(),
                 final_results.len()
-            );
+            )));
         }
 
         Ok(final_results.into_iter().map(|(_, path)| path).collect())
@@ -1519,6 +2049,215 @@ impl RepoManager {
     }
 }
 
+/// The keys that must sign a repository's metadata before it is accepted
+///
+/// The keys are never taken from the metadata being checked: those accepted
+/// before count, unless the pinned fingerprint is not among them, in which
+/// case the pinned key alone must sign. Without either, the metadata's keys
+/// are trusted on first use.
+fn signing_policy(
+    name: &str,
+    accepted: Option<&RepoSigningInfo>,
+    pinned: Option<&str>,
+    fetched: &RepoSigningInfo,
+) -> Result<RepoSigningInfo> {
+    match (accepted, pinned) {
+        (Some(accepted), None) => Ok(accepted.clone()),
+        (Some(accepted), Some(pinned)) if accepted.allows(pinned) => Ok(accepted.clone()),
+        (_, Some(pinned)) => {
+            if !fetched.allows(pinned) {
+                return Err(RookpkgError::SignatureVerificationFailed(format!(
+                    "repo.toml of '{}' names signing key(s) {}, but {} is pinned\n\
+                    If the repository changed its key, pin the new fingerprint in its configuration.",
+                    name,
+                    fetched.keys().join(", "),
+                    pinned
+                )));
+            }
+            Ok(RepoSigningInfo::single(pinned))
+        }
+        (None, None) => {
+            tracing::warn!(
+                "No fingerprint pinned for '{}', trusting {} on first use",
+                name,
+                fetched.keys().join(", ")
+            );
+            Ok(fetched.clone())
+        }
+    }
+}
+
+/// Check that enough of a repository's keys signed `content`
+///
+/// A signature counts only if the key it verifies with has exactly one of
+/// the policy's full fingerprints, is in the local keyring and could still
+/// sign at the time. Each such key counts once. Returns the first key that
+/// counted.
+fn verify_threshold(
+    what: &str,
+    content: &[u8],
+    envelope: &SignatureEnvelope,
+    signing: &RepoSigningInfo,
+    find_key: impl Fn(&str) -> Result<LoadedPublicKey>,
+    revoked: &[RevokedKey],
+    config: &Config,
+) -> Result<LoadedPublicKey> {
+    let required = signing.required_signatures();
+    let mut counted: Vec<LoadedPublicKey> = Vec::new();
+    let mut failures = Vec::new();
+
+    for signature in &envelope.signatures {
+        let result = if signing.allows(&signature.fingerprint) {
+            find_key(&signature.fingerprint).and_then(|key| {
+                if !signing.allows(&key.fingerprint) {
+                    return Err(RookpkgError::UntrustedSigner(format!(
+                        "{} is not a signing key of the repository",
+                        key.fingerprint
+                    )));
+                }
+                signing::verify_signature(&key, content, signature)?;
+                check_key_validity(signature, revoked, config)?;
+                Ok(key)
+            })
+        } else {
+            Err(RookpkgError::UntrustedSigner(format!(
+                "{} is not a signing key of the repository",
+                signature.fingerprint
+            )))
+        };
+        match result {
+            Ok(key) if counted.iter().any(|k| k.fingerprint == key.fingerprint) => {}
+            Ok(key) => counted.push(key),
+            Err(e) => failures.push(e),
+        }
+    }
+
+    if counted.len() >= required {
+        tracing::info!("{} signed by {} key(s), {} required", what, counted.len(), required);
+        return Ok(counted.swap_remove(0));
+    }
+
+    // With a single key, its own error says best what went wrong
+    if required == 1 && failures.len() == 1 {
+        return Err(match failures.swap_remove(0) {
+            RookpkgError::SignatureVerificationFailed(e) => {
+                RookpkgError::SignatureVerificationFailed(format!("{}: {}", what, e))
+            }
+            e => e,
+        });
+    }
+    let mut message = format!(
+        "{} has {} valid signature(s) from {}, {} required",
+        what,
+        counted.len(),
+        signing.keys().join(", "),
+        required
+    );
+    for failure in &failures {
+        message.push_str(&format!("\n  {}", failure));
+    }
+    Err(RookpkgError::SignatureVerificationFailed(message))
+}
+
+/// Refuse a signature made when its key could no longer sign
+///
+/// The key must not have been revoked or rotated out by the time in the
+/// signature, and a packager key's certification must not have expired by
+/// then. That time is chosen by the signer, so a stolen key has to be
+/// revoked from before it was stolen.
+pub fn check_key_validity(signature: &HybridSignature, revoked: &[RevokedKey], config: &Config) -> Result<()> {
+    let signed_at = signing::signature_time(signature)?;
+
+    if let Some(revocation) = revoked
+        .iter()
+        .find(|r| r.fingerprint == signature.fingerprint)
+    {
+        if signed_at.timestamp() >= revocation.revoked_date {
+            let since = DateTime::from_timestamp(revocation.revoked_date, 0)
+                .map(|d| d.to_rfc3339())
+                .unwrap_or_else(|| revocation.revoked_date.to_string());
+            return Err(RookpkgError::KeyRevoked(format!(
+                "{} since {} ({}), but the signature is from {}",
+                revocation.fingerprint, since, revocation.reason, signature.timestamp
+            )));
+        }
+    }
+
+    let cert_dir = config.signing.packager_keys_dir.join("certs");
+    if let Some(cert) = signing::find_certification_for_key(&signature.fingerprint, &cert_dir)? {
+        if let Some(expires) = cert.expires_at()? {
+            if signed_at > expires {
+                return Err(RookpkgError::SignatureVerificationFailed(format!(
+                    "Certification of key {} expired on {}, before the signature was made ({})",
+                    cert.certified_key, cert.expires, signature.timestamp
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Revoked keys recorded by updates (none before the database exists)
+pub fn load_revoked_keys(config: &Config) -> Result<Vec<RevokedKey>> {
+    let db_path = config.database_path();
+    if !db_path.exists() {
+        return Ok(Vec::new());
+    }
+    Database::open(&db_path)?.list_revoked_keys()
+}
+
+/// Record revocations that are not known yet, returning all revoked keys
+fn record_revocations(revoked: &[RevokedKey], known: Vec<RevokedKey>, config: &Config) -> Result<Vec<RevokedKey>> {
+    let new: Vec<_> = revoked
+        .iter()
+        .filter(|r| {
+            !known
+                .iter()
+                .any(|k| k.fingerprint == r.fingerprint && k.revoked_date <= r.revoked_date)
+        })
+        .collect();
+    if new.is_empty() {
+        return Ok(known);
+    }
+
+    let db = Database::open(&config.database_path())?;
+    for revocation in new {
+        tracing::warn!("Key {} revoked: {}", revocation.fingerprint, revocation.reason);
+        db.revoke_key(revocation)?;
+    }
+    db.list_revoked_keys()
+}
+
+/// Refuse anything but a verified signature - signing is MANDATORY
+fn require_verified(status: &SignatureStatus, package: &str) -> Result<()> {
+    match status {
+        SignatureStatus::Invalid(reason) => {
+            Err(RookpkgError::SignatureVerificationFailed(format!(
+                "Package signature is INVALID: {}\n\
+                DO NOT INSTALL - package may be tampered!",
+                reason
+            )))
+        }
+        SignatureStatus::Unsigned => {
+            Err(RookpkgError::SignatureVerificationFailed(format!(
+                "Package {} is unsigned.\n\
+                All packages MUST be signed with a trusted key.\n\
+                Contact the package maintainer to sign this package.",
+                package
+            )))
+        }
+        SignatureStatus::UnknownKey(fingerprint) => {
+            Err(RookpkgError::UntrustedSigner(format!(
+                "{} (signing {})\n\
+                Trust the key with: rookpkg keytrust <key.pub>",
+                fingerprint, package
+            )))
+        }
+        SignatureStatus::Verified { .. } => Ok(()),
+    }
+}
+
 /// Result of a repository update operation
 #[derive(Debug, Default)]
 pub struct UpdateResult {
@@ -1614,7 +2353,7 @@ fn download_file_with_retries(
     max_retries: u32,
     progress: Option<&ProgressBar>,
 ) -> Result<()> {
-    let mut last_error: Option<anyhow::Error> = None;
+    let mut last_error: Option<RookpkgError> = None;
 
     for attempt in 1..=max_retries {
         if attempt > 1 {
@@ -1635,7 +2374,7 @@ fn download_file_with_retries(
     }
 
     Err(last_error.unwrap_or_else(|| {
-        anyhow::anyhow!("Download failed after {} retries", max_retries)
+        RookpkgError::DownloadFailed(format!("Download failed after {} retries", max_retries))
     }))
 }
 
@@ -1652,7 +2391,7 @@ fn download_file_single(
         .with_context(|| format!("Failed to connect to: {}", url))?;
 
     if !response.status().is_success() {
-        bail!("HTTP error {}: {}", response.status(), url);
+        return Err(RookpkgError::DownloadFailed(format!("HTTP error {}: {}", response.status(), url)));
     }
 
     let total_size = response.content_length();
@@ -1720,6 +2459,24 @@ impl UpdateResult {
     }
 }
 
+/// Where a specific package build can be fetched from
+#[derive(Debug, Clone)]
+pub enum BuildSource {
+    /// Listed in the index of this repository
+    Repository(String),
+    /// Archive in the local package cache, no longer in any index
+    Cache,
+}
+
+impl std::fmt::Display for BuildSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildSource::Repository(name) => write!(f, "{}", name),
+            BuildSource::Cache => write!(f, "cache"),
+        }
+    }
+}
+
 /// A search result from repository search
 #[derive(Debug, Clone)]
 pub struct SearchResult {
@@ -1819,6 +2576,7 @@ impl VerifiedPackage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::signing::{LoadedSigningKey, RevocationEntry};
 
     #[test]
     fn test_package_index_search() {
@@ -2061,4 +2819,414 @@ mod tests {
         let missing = index.find_group("nonexistent");
         assert!(missing.is_none());
     }
+
+    #[test]
+    fn test_find_candidate() {
+        let build = |version: &str, release: u32| SearchResult {
+            repository: "core".to_string(),
+            package: PackageEntry {
+                name: "openssl".to_string(),
+                version: version.to_string(),
+                release,
+                description: String::new(),
+                arch: "x86_64".to_string(),
+                size: 0,
+                sha256: String::new(),
+                filename: String::new(),
+                depends: vec![],
+                build_depends: vec![],
+                provides: vec![],
+                conflicts: vec![],
+                replaces: vec![],
+                license: None,
+                homepage: None,
+                maintainer: None,
+                build_date: None,
+            },
+        };
+        let candidates = vec![build("3.0", 1), build("3.0.0", 2), build("3.1", 1)];
+        let version = parse_version("3.0");
+
+        // "3.0" and "3.0.0" are the same version; the highest release wins
+        let found = find_candidate(&candidates, &version, None).unwrap();
+        assert_eq!(found.package.release, 2);
+
+        let found = find_candidate(&candidates, &version, Some(1)).unwrap();
+        assert_eq!(found.package.version, "3.0");
+
+        assert!(find_candidate(&candidates, &version, Some(3)).is_none());
+        assert!(find_candidate(&candidates, &parse_version("2.9"), None).is_none());
+    }
+
+    #[test]
+    fn test_index_freshness() {
+        let now = Utc::now();
+        let mut cached = PackageIndex::new("core");
+        cached.generated = now - chrono::Duration::days(3);
+
+        let mut index = PackageIndex::new("core");
+        index.stamp(Some(7));
+        assert!(!index.is_expired(now));
+        assert!(index.check_freshness(Some(&cached), Some(1), now).is_ok());
+
+        // Expired
+        assert!(index.is_expired(now + chrono::Duration::days(8)));
+        let err = index
+            .check_freshness(Some(&cached), None, now + chrono::Duration::days(8))
+            .unwrap_err();
+        assert!(matches!(err, RookpkgError::StaleMetadata(_)));
+
+        // Older than the configured maximum age
+        index.generated = now - chrono::Duration::days(2);
+        assert!(index.check_freshness(None, Some(1), now).is_err());
+        assert!(index.check_freshness(None, Some(2), now).is_ok());
+
+        // Rolled back to before the cached index
+        index.generated = now - chrono::Duration::days(4);
+        let err = index.check_freshness(Some(&cached), None, now).unwrap_err();
+        assert!(err.to_string().contains("roll back"));
+
+        // Re-fetching the cached index itself is fine
+        assert!(cached.check_freshness(Some(&cached), None, now).is_ok());
+    }
+
+    #[test]
+    fn test_load_caches_drops_expired_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.paths.cache_dir = dir.path().to_path_buf();
+        config.repositories = vec![crate::config::RepositoryConfig {
+            name: "core".to_string(),
+            url: "https://example.org/core".to_string(),
+            enabled: true,
+            priority: 100,
+            fingerprint: None,
+            max_metadata_age_days: None,
+        }];
+
+        let mut manager = RepoManager::new(&config).unwrap();
+        let mut index = PackageIndex::new("core");
+        index.packages.push(PackageEntry {
+            name: "openssl".to_string(),
+            version: "3.2.1".to_string(),
+            release: 1,
+            description: String::new(),
+            arch: "x86_64".to_string(),
+            size: 0,
+            sha256: String::new(),
+            filename: "openssl-3.2.1-1.x86_64.rookpkg".to_string(),
+            depends: vec![],
+            build_depends: vec![],
+            provides: vec![],
+            conflicts: vec![],
+            replaces: vec![],
+            license: None,
+            homepage: None,
+            maintainer: None,
+            build_date: None,
+        });
+        index.generated = Utc::now() - chrono::Duration::days(10);
+        index.valid_until = Some(Utc::now() + chrono::Duration::days(1));
+
+        let repo = manager.get_repo_mut("core").unwrap();
+        fs::create_dir_all(&repo.cache_dir).unwrap();
+        fs::write(
+            repo.cache_dir.join("repo.toml"),
+            "[repository]\nname = \"core\"\ndescription = \"\"\n\n[signing]\nfingerprint = \"HYBRID:SHA256:00\"\n",
+        )
+        .unwrap();
+        repo.index = Some(index.clone());
+        repo.save_cache().unwrap();
+
+        manager.load_caches().unwrap();
+        assert!(manager.find_package("openssl").is_some());
+
+        // Once it has expired, nothing is resolved from it
+        index.valid_until = Some(Utc::now() - chrono::Duration::days(1));
+        let repo = manager.get_repo_mut("core").unwrap();
+        repo.index = Some(index);
+        repo.save_cache().unwrap();
+        repo.index = None;
+
+        manager.load_caches().unwrap();
+        assert!(manager.find_package("openssl").is_none());
+        assert!(manager.get_repo_mut("core").unwrap().index.is_none());
+
+        // The expired index on disk still refuses a rollback
+        let cached = manager.get_repo_mut("core").unwrap().cached_index().unwrap().unwrap();
+        let mut older = PackageIndex::new("core");
+        older.generated = cached.generated - chrono::Duration::days(1);
+        assert!(older.check_freshness(Some(&cached), None, Utc::now()).is_err());
+    }
+
+    /// Generate a key into `dir`
+    ///
+    /// Boxed, and made outside the tests: a few ML-DSA keys on one frame
+    /// overflow a test thread's stack in debug builds.
+    fn boxed_key(name: &str, dir: &Path) -> Box<LoadedSigningKey> {
+        let email = format!("{}@example.org", name.to_lowercase());
+        Box::new(signing::generate_key(name, &email, dir).unwrap().0)
+    }
+
+    /// A manager with one master key and one packager key that signs "core"
+    fn key_setup(dir: &Path) -> (RepoManager, Config, Box<LoadedSigningKey>, Box<LoadedSigningKey>) {
+        let mut config = Config::default();
+        config.paths.cache_dir = dir.join("cache");
+        config.signing.master_keys_dir = dir.join("master");
+        config.signing.packager_keys_dir = dir.join("packagers");
+
+        let master = boxed_key("Master", &dir.join("m"));
+        let repo_key = boxed_key("Core", &dir.join("r"));
+        fs::create_dir_all(&config.signing.master_keys_dir).unwrap();
+        fs::create_dir_all(&config.signing.packager_keys_dir).unwrap();
+        fs::copy(dir.join("m/signing-key.pub"), config.signing.master_keys_dir.join("master.pub")).unwrap();
+        fs::copy(dir.join("r/signing-key.pub"), config.signing.packager_keys_dir.join("core.pub")).unwrap();
+
+        (RepoManager::new(&config).unwrap(), config, master, repo_key)
+    }
+
+    #[test]
+    fn test_repository_revocations_are_scoped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, config, master, repo_key) = key_setup(dir.path());
+        let keys_dir = dir.path().join("cache/repos/core/keys");
+        let policy = RepoSigningInfo::single(&repo_key.fingerprint);
+
+        let entries: Vec<RevocationEntry> = [&master.fingerprint, &repo_key.fingerprint]
+            .into_iter()
+            .map(|fingerprint| RevocationEntry {
+                fingerprint: fingerprint.clone(),
+                revoked_at: "2025-06-01T00:00:00Z".to_string(),
+                reason: "Key compromised".to_string(),
+            })
+            .collect();
+        let revoked = |list: &RevocationList, policy: &RepoSigningInfo| -> Result<Vec<String>> {
+            Ok(manager
+                .accept_revocations(list, "core", policy, &keys_dir, &config)?
+                .into_iter()
+                .map(|k| k.fingerprint)
+                .collect())
+        };
+
+        // The repository key cannot revoke the master key, only itself
+        let list = signing::sign_revocation_list(repo_key.as_ref(), entries.clone()).unwrap();
+        assert_eq!(revoked(&list, &policy).unwrap(), vec![repo_key.fingerprint.clone()]);
+
+        // Nor can it sign the list of a repository it does not sign for
+        let err = revoked(&list, &RepoSigningInfo::single("HYBRID:SHA256:ffff")).unwrap_err();
+        assert!(matches!(err, RookpkgError::UntrustedSigner(_)));
+
+        // A master key can revoke anything
+        let list = signing::sign_revocation_list(master.as_ref(), entries).unwrap();
+        assert_eq!(revoked(&list, &policy).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_rotation_stays_with_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, config, master, repo_key) = key_setup(dir.path());
+        let keys_dir = dir.path().join("cache/repos/core/keys");
+        let new_key = boxed_key("New", &dir.path().join("n"));
+        let new_pub = fs::read_to_string(dir.path().join("n/signing-key.pub")).unwrap();
+        let mut revoked = Vec::new();
+
+        // A trusted key that does not sign the repository cannot rotate
+        let rotation = signing::rotate_key(master.as_ref(), new_key.as_ref(), &new_pub).unwrap();
+        let policy = RepoSigningInfo::single(&repo_key.fingerprint);
+        assert!(!manager.apply_rotation(&rotation, &policy, &keys_dir, &config, &mut revoked).unwrap());
+        assert!(revoked.is_empty());
+
+        // The repository's key can, but its successor is only trusted here
+        let rotation = signing::rotate_key(repo_key.as_ref(), new_key.as_ref(), &new_pub).unwrap();
+        assert!(manager.apply_rotation(&rotation, &policy, &keys_dir, &config, &mut revoked).unwrap());
+        assert!(manager.search_key_in_dir(&keys_dir, &new_key.fingerprint).unwrap().is_some());
+        assert!(manager
+            .search_key_in_dir(&config.signing.packager_keys_dir, &new_key.fingerprint)
+            .unwrap()
+            .is_none());
+        assert_eq!(revoked[0].fingerprint, repo_key.fingerprint);
+
+        // A master key that signs the repository does not hand on its role
+        let rotation = signing::rotate_key(master.as_ref(), new_key.as_ref(), &new_pub).unwrap();
+        let policy = RepoSigningInfo::single(&master.fingerprint);
+        assert!(manager.apply_rotation(&rotation, &policy, &keys_dir, &config, &mut revoked).unwrap());
+        assert!(manager
+            .search_key_in_dir(&config.signing.master_keys_dir, &new_key.fingerprint)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_signing_policy_matches_full_fingerprints() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, config, master, repo_key) = key_setup(dir.path());
+        let keys_dir = dir.path().join("cache/repos/core/keys");
+        let policy = RepoSigningInfo::single(&repo_key.fingerprint);
+        let tail = &repo_key.fingerprint[repo_key.fingerprint.len() - 1..];
+
+        assert!(policy.allows(&repo_key.fingerprint));
+        assert!(!policy.allows(tail));
+        assert!(!policy.allows(""));
+
+        // A trailing part does not find a trusted key either, whichever
+        // key it would have matched
+        assert!(manager.find_repo_key(tail, &keys_dir, &config).is_err());
+        let key = manager.find_repo_key(&master.fingerprint, &keys_dir, &config).unwrap();
+        assert_eq!(key.fingerprint, master.fingerprint);
+    }
+
+    #[test]
+    fn test_signing_policy() {
+        let fetched = RepoSigningInfo {
+            fingerprint: "HYBRID:SHA256:aaaa".to_string(),
+            public_key: None,
+            fingerprints: vec!["HYBRID:SHA256:bbbb".to_string()],
+            threshold: Some(2),
+        };
+        let policy = |accepted, pinned| signing_policy("core", accepted, pinned, &fetched);
+
+        // A pinned key that was not accepted before must sign by itself;
+        // the fetched metadata's other keys and threshold do not count yet
+        let pinned = policy(None, Some("HYBRID:SHA256:bbbb")).unwrap();
+        assert_eq!(pinned.keys(), vec!["HYBRID:SHA256:bbbb"]);
+        assert_eq!(pinned.required_signatures(), 1);
+        let accepted = RepoSigningInfo::single("HYBRID:SHA256:cccc");
+        let pinned = policy(Some(&accepted), Some("HYBRID:SHA256:bbbb")).unwrap();
+        assert_eq!(pinned.keys(), vec!["HYBRID:SHA256:bbbb"]);
+
+        // Metadata that does not name the pinned key, even by its tail
+        assert!(policy(None, Some("bbbb")).is_err());
+        assert!(policy(None, Some("HYBRID:SHA256:dddd")).is_err());
+
+        // What was accepted before stays, pinned or not
+        let accepted = fetched.clone();
+        assert_eq!(policy(Some(&accepted), Some("HYBRID:SHA256:aaaa")).unwrap().required_signatures(), 2);
+        assert_eq!(policy(Some(&accepted), None).unwrap().required_signatures(), 2);
+        assert_eq!(policy(None, None).unwrap().keys().len(), 2);
+    }
+
+    #[test]
+    fn test_check_key_validity() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.signing.packager_keys_dir = dir.path().join("packagers");
+        let (master, _) = signing::generate_key("Master", "master@example.org", &dir.path().join("master")).unwrap();
+        let (packager, _) = signing::generate_key("Packager", "packager@example.org", &config.signing.packager_keys_dir).unwrap();
+        let packager_pub = signing::load_public_key(&config.signing.packager_keys_dir.join("signing-key.pub")).unwrap();
+
+        let mut signature = signing::sign_message(&packager, b"package").unwrap();
+        signature.timestamp = "2026-03-01T00:00:00Z".to_string();
+        assert!(check_key_validity(&signature, &[], &config).is_ok());
+
+        // Revoked after the signature was made, then before
+        let mut revoked = RevokedKey {
+            fingerprint: packager.fingerprint.clone(),
+            revoked_date: DateTime::parse_from_rfc3339("2026-04-01T00:00:00Z").unwrap().timestamp(),
+            reason: "Key compromised".to_string(),
+            revoked_by: master.fingerprint.clone(),
+        };
+        assert!(check_key_validity(&signature, std::slice::from_ref(&revoked), &config).is_ok());
+        revoked.revoked_date = DateTime::parse_from_rfc3339("2026-02-01T00:00:00Z").unwrap().timestamp();
+        assert!(matches!(
+            check_key_validity(&signature, &[revoked], &config),
+            Err(RookpkgError::KeyRevoked(_))
+        ));
+
+        // A certification that expired before the signature
+        let cert_dir = config.signing.packager_keys_dir.join("certs");
+        fs::create_dir_all(&cert_dir).unwrap();
+        let cert = signing::certify_key(&master, &packager_pub, "packager", Some("2026-02-15T00:00:00Z")).unwrap();
+        signing::save_certification(&cert, &cert_dir.join("packager.cert")).unwrap();
+        assert!(check_key_validity(&signature, &[], &config).is_err());
+        signature.timestamp = "2026-02-14T00:00:00Z".to_string();
+        assert!(check_key_validity(&signature, &[], &config).is_ok());
+    }
+
+    #[test]
+    fn test_verify_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::default();
+        let keys: Vec<_> = ["a", "b", "c", "outsider"]
+            .iter()
+            .map(|name| {
+                let key_dir = dir.path().join(name);
+                let (key, _) = signing::generate_key(name, "release@example.org", &key_dir).unwrap();
+                let public_key = signing::load_public_key(&key_dir.join("signing-key.pub")).unwrap();
+                (key, public_key)
+            })
+            .collect();
+        let find_key = |fingerprint: &str| {
+            keys.iter()
+                .map(|(_, public_key)| public_key.clone())
+                .find(|k| k.fingerprint == fingerprint)
+                .ok_or_else(|| RookpkgError::UntrustedSigner(fingerprint.to_string()))
+        };
+        let policy = RepoSigningInfo {
+            fingerprint: keys[0].0.fingerprint.clone(),
+            public_key: None,
+            fingerprints: keys[..3].iter().map(|(k, _)| k.fingerprint.clone()).collect(),
+            threshold: Some(2),
+        };
+        assert_eq!(policy.keys().len(), 3);
+
+        let content = b"packages.json";
+        let signed_by = |signers: &[usize]| {
+            let mut envelope = SignatureEnvelope::default();
+            for &i in signers {
+                envelope.sign(&keys[i].0, content).unwrap();
+            }
+            envelope
+        };
+        let verify = |envelope: &SignatureEnvelope, revoked: &[RevokedKey]| {
+            verify_threshold("Package index", content, envelope, &policy, find_key, revoked, &config)
+        };
+
+        assert!(verify(&signed_by(&[0, 2]), &[]).is_ok());
+        assert!(verify(&signed_by(&[0]), &[]).is_err());
+        // Keys outside the repository's set do not count
+        assert!(verify(&signed_by(&[1, 3]), &[]).is_err());
+
+        // Nor does one key signing twice
+        let mut twice = signed_by(&[1]);
+        twice.signatures.push(twice.signatures[0].clone());
+        assert!(verify(&twice, &[]).is_err());
+
+        // Nor a signature naming its key by a trailing part, not even with a
+        // key finder that would resolve it, so it cannot count twice either
+        let by_suffix = |fingerprint: &str| {
+            keys.iter()
+                .map(|(_, public_key)| public_key.clone())
+                .find(|k| !fingerprint.is_empty() && k.fingerprint.ends_with(fingerprint))
+                .ok_or_else(|| RookpkgError::UntrustedSigner(fingerprint.to_string()))
+        };
+        let mut shortened = signed_by(&[1]);
+        let mut short = shortened.signatures[0].clone();
+        short.fingerprint = short.fingerprint[short.fingerprint.len() - 8..].to_string();
+        shortened.signatures.push(short);
+        let Err(err) = verify_threshold("Package index", content, &shortened, &policy, by_suffix, &[], &config) else {
+            panic!("a shortened fingerprint counted");
+        };
+        assert!(err.to_string().contains("1 valid signature"), "{}", err);
+
+        // A policy entry that is only the tail of an outside key's
+        // fingerprint does not let that key count
+        let tail = &keys[3].0.fingerprint[keys[3].0.fingerprint.len() - 8..];
+        let colliding = RepoSigningInfo {
+            fingerprints: vec![keys[1].0.fingerprint.clone(), tail.to_string()],
+            ..policy.clone()
+        };
+        let mut outsider = signed_by(&[1, 3]);
+        outsider.signatures[1].fingerprint = tail.to_string();
+        assert!(verify_threshold("Package index", content, &outsider, &colliding, by_suffix, &[], &config).is_err());
+
+        // Nor a revoked key
+        let revoked = RevokedKey {
+            fingerprint: keys[1].0.fingerprint.clone(),
+            revoked_date: 0,
+            reason: "Key compromised".to_string(),
+            revoked_by: keys[0].0.fingerprint.clone(),
+        };
+        assert!(verify(&signed_by(&[0, 1]), &[]).is_ok());
+        assert!(verify(&signed_by(&[0, 1]), &[revoked]).is_err());
+    }
 }
@@ -3,27 +3,57 @@
 //! This module implements a dependency provider for the PubGrub algorithm.
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{self, Display};
 
+use pubgrub::error::PubGrubError;
 use pubgrub::range::Range;
-use pubgrub::solver::{Dependencies, DependencyConstraints, DependencyProvider};
-use pubgrub::version::{SemanticVersion, Version};
+use pubgrub::report::{DefaultStringReporter, DerivationTree, External, Reporter};
+use pubgrub::solver::{resolve, Dependencies, DependencyConstraints, DependencyProvider};
+use pubgrub::version::Version as _;
+use serde::Serialize;
 
-/// A package identifier
+use crate::package::{Dependency, DependencyType, InstalledPackage};
+use crate::repository::PackageEntry;
+use crate::version::Version;
+
+/// A package identifier as seen by the solver
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct Package(pub String);
+pub enum Package {
+    /// A real package, or the root
+    Named(String),
+    /// A virtual name. Each of its versions stands for one provider version,
+    /// so the solver can move to another provider when one does not fit.
+    Virtual(String),
+    /// Marker shared by a package version declaring a conflict and every
+    /// package version the conflict matches. The declarer needs version 1
+    /// and the others version 2, so no solution contains both sides.
+    Conflict {
+        package: String,
+        version: Version,
+        target: String,
+    },
+}
 
-impl Display for Package {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+impl Package {
+    /// Name of the package or virtual name (the declaring package for conflict markers)
+    pub fn name(&self) -> &str {
+        match self {
+            Package::Named(name) | Package::Virtual(name) => name,
+            Package::Conflict { package, .. } => package,
+        }
     }
 }
 
-impl Borrow<str> for Package {
-    fn borrow(&self) -> &str {
-        &self.0
+impl Display for Package {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Package::Named(name) | Package::Virtual(name) => write!(f, "{}", name),
+            Package::Conflict { package, version, target } => {
+                write!(f, "{} {} conflicting with {}", package, version, target)
+            }
+        }
     }
 }
 
@@ -31,13 +61,61 @@ impl Borrow<str> for Package {
 pub struct RookeryDependencyProvider {
     /// Available packages and their versions
     packages: HashMap<String, Vec<PackageVersion>>,
+
+    /// Packages to favour when several provide the same virtual name
+    /// (typically installed or explicitly requested packages)
+    preferred: HashSet<String>,
+
+    /// Versions to try before newer ones (installed packages that should
+    /// only move when something needs it)
+    favoured: HashMap<String, Version>,
 }
 
 /// A specific version of a package with its dependencies
 #[derive(Debug, Clone)]
 pub struct PackageVersion {
-    pub version: SemanticVersion,
-    pub dependencies: HashMap<String, Range<SemanticVersion>>,
+    pub version: Version,
+    pub dependencies: HashMap<String, Range<Version>>,
+    /// Virtual names provided by this version (None = unversioned provide)
+    pub provides: HashMap<String, Option<Version>>,
+    /// Packages (or virtual names) this version cannot coexist with
+    pub conflicts: HashMap<String, Range<Version>>,
+    /// Dependency constraints as written in the metadata, for reports
+    pub constraint_text: HashMap<String, String>,
+}
+
+impl PackageVersion {
+    /// Whether this version provides `name` in a way that satisfies `range`.
+    ///
+    /// Unversioned provides only satisfy unconstrained dependencies.
+    fn provides_within(&self, name: &str, range: &Range<Version>) -> bool {
+        match self.provides.get(name) {
+            Some(Some(provided)) => range.contains(provided),
+            Some(None) => *range == Range::any(),
+            None => false,
+        }
+    }
+
+    /// Whether a conflict on `target` within `range` hits this version of `name`.
+    ///
+    /// Conflicts may name a package directly or a virtual name it provides;
+    /// an unversioned provide matches any range.
+    fn conflicts_with(&self, name: &str, target: &str, range: &Range<Version>) -> bool {
+        if name == target {
+            return range.contains(&self.version);
+        }
+        match self.provides.get(target) {
+            Some(Some(provided)) => range.contains(provided),
+            Some(None) => true,
+            None => false,
+        }
+    }
+}
+
+/// Solver version standing for the n-th (0-based) provider of a virtual name
+/// or side of a conflict marker
+fn slot(index: usize) -> Version {
+    parse_version(&(index + 1).to_string())
 }
 
 impl RookeryDependencyProvider {
@@ -45,19 +123,23 @@ impl RookeryDependencyProvider {
     pub fn new() -> Self {
         Self {
             packages: HashMap::new(),
+            preferred: HashSet::new(),
+            favoured: HashMap::new(),
         }
     }
-
     /// Add a package version to the provider
     pub fn add_package(
         &mut self,
         name: &str,
-        version: SemanticVersion,
-        dependencies: HashMap<String, Range<SemanticVersion>>,
+        version: Version,
+        dependencies: HashMap<String, Range<Version>>,
     ) {
         let pkg_version = PackageVersion {
             version,
             dependencies,
+            provides: HashMap::new(),
+            conflicts: HashMap::new(),
+            constraint_text: HashMap::new(),
         };
 
         self.packages
@@ -66,10 +148,346 @@ impl RookeryDependencyProvider {
             .push(pkg_version);
     }
 
+    /// Add a repository index entry, including its provides and conflicts.
+    ///
+    /// Returns the version the entry was registered under.
+    pub fn add_entry(&mut self, entry: &PackageEntry) -> Version {
+        let version = parse_version(&entry.version);
+
+        let mut deps = HashMap::new();
+        let mut constraint_text = HashMap::new();
+        for dep_str in &entry.depends {
+            let (dep_name, constraint) = parse_dep_string(dep_str);
+            match parse_constraint(&constraint) {
+                Ok(range) => {
+                    deps.insert(dep_name.to_string(), range);
+                    constraint_text.insert(dep_name.to_string(), constraint);
+                }
+                Err(e) => tracing::warn!("{}-{}: ignoring dependency '{}': {}", entry.name, entry.version, dep_str, e),
+            }
+        }
+
+        let mut provides = HashMap::new();
+        for prov_str in &entry.provides {
+            let (prov_name, constraint) = parse_dep_string(prov_str);
+            let provided = constraint.trim_start_matches('=').trim();
+            let provided = if provided == "*" {
+                None
+            } else {
+                Version::parse(provided).ok()
+            };
+            provides.insert(prov_name.to_string(), provided);
+        }
+
+        let mut conflicts = HashMap::new();
+        for conflict_str in &entry.conflicts {
+            let (conflict_name, constraint) = parse_dep_string(conflict_str);
+            match parse_constraint(&constraint) {
+                Ok(range) => {
+                    conflicts.insert(conflict_name.to_string(), range);
+                }
+                Err(e) => tracing::warn!("{}-{}: ignoring conflict '{}': {}", entry.name, entry.version, conflict_str, e),
+            }
+        }
+
+        self.add_package(&entry.name, version.clone(), deps);
+        self.add_relations(&entry.name, version.clone(), provides, conflicts);
+        if let Some(pkg_version) = self.find_version_mut(&entry.name, &version) {
+            pkg_version.constraint_text = constraint_text;
+        }
+        version
+    }
+
+    /// Add an installed package using the dependencies and provides recorded
+    /// in the database.
+    ///
+    /// Skipped when a repository already supplied the same version, so local
+    /// installs that are not in any repository can still satisfy dependencies.
+    pub fn add_installed(
+        &mut self,
+        package: &InstalledPackage,
+        dependencies: &[Dependency],
+        provides: &HashMap<String, String>,
+    ) -> Version {
+        let version = parse_version(&package.version);
+        if self.find_version(&package.name, &version).is_some() {
+            return version;
+        }
+
+        let mut deps = HashMap::new();
+        let mut constraint_text = HashMap::new();
+        for dep in dependencies.iter().filter(|d| d.dep_type == DependencyType::Runtime) {
+            let constraint = if dep.constraint.trim().is_empty() {
+                "*".to_string()
+            } else {
+                dep.constraint.clone()
+            };
+            if let Ok(range) = parse_constraint(&constraint) {
+                deps.insert(dep.depends_on.clone(), range);
+                constraint_text.insert(dep.depends_on.clone(), constraint);
+            }
+        }
+
+        let provides = provides
+            .iter()
+            .map(|(name, provided)| (name.clone(), Version::parse(provided.trim()).ok()))
+            .collect();
+
+        self.add_package(&package.name, version.clone(), deps);
+        self.add_relations(&package.name, version.clone(), provides, HashMap::new());
+        if let Some(pkg_version) = self.find_version_mut(&package.name, &version) {
+            pkg_version.constraint_text = constraint_text;
+        }
+        version
+    }
+
+    /// Record the provides and conflicts of an already added package version
+    pub fn add_relations(
+        &mut self,
+        name: &str,
+        version: Version,
+        provides: HashMap<String, Option<Version>>,
+        conflicts: HashMap<String, Range<Version>>,
+    ) {
+        if let Some(pkg_version) = self.find_version_mut(name, &version) {
+            pkg_version.provides.extend(provides);
+            pkg_version.conflicts.extend(conflicts);
+        }
+    }
+
+    /// Favour this package when it is one of several providers of a virtual name
+    pub fn prefer(&mut self, name: &str) {
+        self.preferred.insert(name.to_string());
+    }
+
+    /// Try this version of a package before newer ones whenever it is allowed
+    pub fn favour(&mut self, name: &str, version: Version) {
+        self.favoured.insert(name.to_string(), version);
+    }
+
+    /// Replace the dependencies of the virtual root package.
+    ///
+    /// Returns the root version to hand to the solver.
+    pub fn set_root(&mut self, dependencies: HashMap<String, Range<Version>>) -> Version {
+        let version = parse_version("1");
+        self.packages.remove(ROOT_PACKAGE);
+        self.add_package(ROOT_PACKAGE, version.clone(), dependencies);
+        version
+    }
+
+    /// Highest known version of a package
+    pub fn newest_version(&self, name: &str) -> Option<&Version> {
+        self.packages.get(name)?.iter().map(|v| &v.version).max()
+    }
+
     /// Get all versions of a package
     pub fn get_versions(&self, name: &str) -> Option<&Vec<PackageVersion>> {
         self.packages.get(name)
     }
+
+    /// Solve for the root package registered with [`set_root`](Self::set_root).
+    ///
+    /// Returns the version chosen for every real package, without the root
+    /// and the solver's virtual and conflict packages, or a report of why
+    /// there is no solution.
+    pub fn solve(
+        &self,
+        root_version: Version,
+        context: &ResolutionContext,
+    ) -> Result<HashMap<String, Version>, ResolutionReport> {
+        let solution = resolve(self, Package::Named(ROOT_PACKAGE.to_string()), root_version)
+            .map_err(|e| ResolutionReport::from_error(&e, self, context))?;
+        Ok(solution
+            .into_iter()
+            .filter_map(|(package, version)| match package {
+                Package::Named(name) if name != ROOT_PACKAGE => Some((name, version)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Check whether a name is a real package or a virtual name someone provides
+    pub fn is_known(&self, name: &str) -> bool {
+        self.packages.contains_key(name) || !self.providers(name).is_empty()
+    }
+
+    /// The selected package that satisfies a dependency on `name`.
+    ///
+    /// That is the package itself, or for a virtual name the provider the
+    /// solver settled on among the `selected` packages.
+    pub fn satisfied_by(
+        &self,
+        name: &str,
+        range: &Range<Version>,
+        selected: &HashMap<String, Version>,
+    ) -> Option<String> {
+        if self.packages.contains_key(name) {
+            return selected.contains_key(name).then(|| name.to_string());
+        }
+        self.providers(name)
+            .into_iter()
+            .rev()
+            .find(|(provider, v)| {
+                selected.get(*provider) == Some(&v.version) && v.provides_within(name, range)
+            })
+            .map(|(provider, _)| provider.to_string())
+    }
+
+    /// Every package version providing a virtual name, least favoured first.
+    ///
+    /// A provider's position in this list is its version of the virtual name
+    /// (see [`slot`]), and the solver tries the highest first: preferred
+    /// packages, then the alphabetically first provider, newest version first.
+    fn providers(&self, name: &str) -> Vec<(&str, &PackageVersion)> {
+        let mut providers: Vec<(&str, &PackageVersion)> = self
+            .packages
+            .iter()
+            .flat_map(|(provider, versions)| {
+                versions
+                    .iter()
+                    .filter(|v| v.provides.contains_key(name))
+                    .map(move |v| (provider.as_str(), v))
+            })
+            .collect();
+        providers.sort_by(|a, b| {
+            self.preferred
+                .contains(a.0)
+                .cmp(&self.preferred.contains(b.0))
+                .then_with(|| b.0.cmp(a.0))
+                .then_with(|| a.1.version.cmp(&b.1.version))
+        });
+        providers
+    }
+
+    /// The solver package and range standing for a dependency as written.
+    ///
+    /// A dependency on a virtual name becomes one on the provider slots whose
+    /// provided version satisfies the constraint. Names nobody knows stay as
+    /// they are so the failure report can say they are missing.
+    fn dependency_target(&self, name: &str, range: &Range<Version>) -> (Package, Range<Version>) {
+        if !self.packages.contains_key(name) {
+            let mut slots = Range::none();
+            for (i, (_, v)) in self.providers(name).iter().enumerate() {
+                if v.provides_within(name, range) {
+                    slots = slots.union(&Range::exact(slot(i)));
+                }
+            }
+            if slots != Range::none() {
+                return (Package::Virtual(name.to_string()), slots);
+            }
+        }
+        (Package::Named(name.to_string()), range.clone())
+    }
+
+    /// Conflict markers a package version depends on: one per conflict it
+    /// declares, and one per conflict declared by another package that hits it
+    fn conflict_markers(&self, name: &str, pkg_version: &PackageVersion) -> Vec<(Package, Range<Version>)> {
+        let mut markers = Vec::new();
+        for target in pkg_version.conflicts.keys() {
+            let marker = Package::Conflict {
+                package: name.to_string(),
+                version: pkg_version.version.clone(),
+                target: target.clone(),
+            };
+            markers.push((marker, Range::exact(slot(0))));
+        }
+
+        for (other, versions) in &self.packages {
+            if other == name {
+                continue;
+            }
+            for other_version in versions {
+                for (target, range) in &other_version.conflicts {
+                    if pkg_version.conflicts_with(name, target, range) {
+                        let marker = Package::Conflict {
+                            package: other.clone(),
+                            version: other_version.version.clone(),
+                            target: target.clone(),
+                        };
+                        markers.push((marker, Range::exact(slot(1))));
+                    }
+                }
+            }
+        }
+        markers
+    }
+
+    /// Solver versions of a package, virtual name or conflict marker
+    fn solver_versions(&self, package: &Package) -> Vec<Version> {
+        match package {
+            Package::Named(name) => self
+                .packages
+                .get(name)
+                .map(|versions| versions.iter().map(|v| v.version.clone()).collect())
+                .unwrap_or_default(),
+            Package::Virtual(name) => (0..self.providers(name).len()).map(slot).collect(),
+            Package::Conflict { .. } => vec![slot(0), slot(1)],
+        }
+    }
+
+    fn find_version(&self, name: &str, version: &Version) -> Option<&PackageVersion> {
+        self.packages
+            .get(name)
+            .and_then(|versions| versions.iter().find(|v| &v.version == version))
+    }
+
+    fn find_version_mut(&mut self, name: &str, version: &Version) -> Option<&mut PackageVersion> {
+        self.packages
+            .get_mut(name)
+            .and_then(|versions| versions.iter_mut().rev().find(|v| &v.version == version))
+    }
+
+    /// Describe a dependency edge from the solver the way the metadata wrote it.
+    ///
+    /// Returns the constraint on `dependency` (a package or virtual name)
+    /// declared by the versions of `package` in `versions`.
+    fn describe_dependency(
+        &self,
+        package: &str,
+        versions: &Range<Version>,
+        dependency: &str,
+        range: &Range<Version>,
+    ) -> String {
+        let candidates = self
+            .packages
+            .get(package)
+            .into_iter()
+            .flatten()
+            .filter(|v| versions.contains(&v.version));
+
+        for pkg_version in candidates {
+            if let Some(text) = pkg_version.constraint_text.get(dependency) {
+                return text.clone();
+            }
+            if let Some(written) = pkg_version.dependencies.get(dependency) {
+                return format_range(written);
+            }
+        }
+
+        format_range(range)
+    }
+
+    /// The single known version of a package inside a range, if there is exactly one
+    fn single_version(&self, package: &str, versions: &Range<Version>) -> Option<String> {
+        let mut matching = self
+            .packages
+            .get(package)?
+            .iter()
+            .filter(|v| versions.contains(&v.version))
+            .map(|v| v.version.to_string());
+        let first = matching.next()?;
+        match matching.next() {
+            Some(_) => None,
+            None => Some(first),
+        }
+    }
+
+    /// The versions of a package to show in a report: the single known
+    /// version inside the range, otherwise the range (None when unconstrained)
+    fn describe_version(&self, package: &str, versions: &Range<Version>) -> Option<String> {
+        self.single_version(package, versions)
+            .or_else(|| Some(format_range(versions)).filter(|r| r != "*"))
+    }
 }
 
 impl Default for RookeryDependencyProvider {
@@ -78,31 +496,31 @@ impl Default for RookeryDependencyProvider {
     }
 }
 
-impl DependencyProvider<Package, SemanticVersion> for RookeryDependencyProvider {
-    fn choose_package_version<T: Borrow<Package>, U: Borrow<Range<SemanticVersion>>>(
+impl DependencyProvider<Package, Version> for RookeryDependencyProvider {
+    fn choose_package_version<T: Borrow<Package>, U: Borrow<Range<Version>>>(
         &self,
         potential_packages: impl Iterator<Item = (T, U)>,
-    ) -> Result<(T, Option<SemanticVersion>), Box<dyn Error>> {
+    ) -> Result<(T, Option<Version>), Box<dyn Error>> {
         // Choose the package with the fewest matching versions (most constrained)
-        // Then choose the highest matching version for that package
-        let mut best_package: Option<(T, Option<SemanticVersion>, usize)> = None;
+        // Then choose its favoured version if allowed, otherwise the highest
+        let mut best_package: Option<(T, Option<Version>, usize)> = None;
 
         for (package, range) in potential_packages {
-            let pkg_name: &Package = package.borrow();
-            let version_range: &Range<SemanticVersion> = range.borrow();
+            let pkg: &Package = package.borrow();
+            let version_range: &Range<Version> = range.borrow();
 
-            let (version, count) = match self.packages.get(&pkg_name.0) {
-                Some(versions) => {
-                    let mut matching: Vec<_> = versions
-                        .iter()
-                        .filter(|v| version_range.contains(&v.version))
-                        .map(|v| v.version)
-                        .collect();
-                    matching.sort();
-                    (matching.pop(), matching.len() + 1) // +1 because we popped
-                }
-                None => (None, 0),
+            let mut matching: Vec<Version> = self
+                .solver_versions(pkg)
+                .into_iter()
+                .filter(|v| version_range.contains(v))
+                .collect();
+            matching.sort();
+            let count = matching.len();
+            let favoured = match pkg {
+                Package::Named(name) => self.favoured.get(name).filter(|v| matching.contains(v)).cloned(),
+                _ => None,
             };
+            let version = favoured.or_else(|| matching.pop());
 
             match &best_package {
                 Some((_, _, best_count)) if count >= *best_count => continue,
@@ -119,57 +537,568 @@ impl DependencyProvider<Package, SemanticVersion> for RookeryDependencyProvider
     fn get_dependencies(
         &self,
         package: &Package,
-        version: &SemanticVersion,
-    ) -> Result<Dependencies<Package, SemanticVersion>, Box<dyn Error>> {
-        match self.packages.get(&package.0) {
-            Some(versions) => {
-                for pkg_version in versions {
-                    if &pkg_version.version == version {
-                        let mut deps: DependencyConstraints<Package, SemanticVersion> =
-                            DependencyConstraints::default();
-                        for (name, range) in &pkg_version.dependencies {
-                            deps.insert(Package(name.clone()), range.clone());
+        version: &Version,
+    ) -> Result<Dependencies<Package, Version>, Box<dyn Error>> {
+        let mut deps: DependencyConstraints<Package, Version> = DependencyConstraints::default();
+        match package {
+            Package::Named(name) => {
+                if !self.packages.contains_key(name) {
+                    return Err(format!("Package {} not found", package).into());
+                }
+                let pkg_version = self.find_version(name, version).ok_or_else(|| {
+                    format!("Version {} not found for package {}", version, package)
+                })?;
+                for (dep_name, range) in &pkg_version.dependencies {
+                    let (target, range) = self.dependency_target(dep_name, range);
+                    deps.insert(target, range);
+                }
+                for (marker, side) in self.conflict_markers(name, pkg_version) {
+                    deps.insert(marker, side);
+                }
+            }
+            Package::Virtual(name) => {
+                let providers = self.providers(name);
+                let (provider, provider_version) = (0..providers.len())
+                    .find(|i| slot(*i) == *version)
+                    .map(|i| providers[i])
+                    .ok_or_else(|| format!("Provider {} not found for {}", version, name))?;
+                deps.insert(
+                    Package::Named(provider.to_string()),
+                    Range::exact(provider_version.version.clone()),
+                );
+            }
+            Package::Conflict { .. } => {}
+        }
+        Ok(Dependencies::Known(deps))
+    }
+}
+/// Name of the virtual package whose dependencies are the top-level requests
+pub const ROOT_PACKAGE: &str = "__root__";
+
+/// What the caller knows about the packages in a resolution
+///
+/// Used to explain where a failing constraint came from.
+#[derive(Debug, Clone, Default)]
+pub struct ResolutionContext {
+    /// Packages the user asked for on the command line
+    pub requested: HashSet<String>,
+    /// Installed packages: name -> installed version
+    pub installed: HashMap<String, String>,
+    /// Installed packages held at their current version
+    pub held: HashSet<String>,
+    /// Installed packages whose top-level constraint is an upgrade
+    pub upgrading: HashSet<String>,
+}
+
+impl ResolutionContext {
+    fn tags(&self, package: &str) -> Vec<String> {
+        let mut tags = Vec::new();
+        if self.requested.contains(package) {
+            tags.push("requested".to_string());
+        }
+        if self.installed.contains_key(package) {
+            tags.push("installed".to_string());
+        }
+        if self.held.contains(package) {
+            tags.push("held".to_string());
+        }
+        tags
+    }
+}
+
+/// Where a constraint in a failure report came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConstraintOrigin {
+    /// Requested explicitly by the user
+    Request,
+    /// Pin on a held package
+    Held,
+    /// Pin keeping an installed package at its version
+    Installed,
+    /// Installed package being moved to a newer version
+    Upgrade,
+    /// Dependency declared in package metadata
+    Repository,
+    /// A provider of a virtual name, as tried by the solver
+    Provider,
+}
+
+/// A single constraint involved in a resolution failure
+#[derive(Debug, Clone, Serialize)]
+pub struct Requirement {
+    /// Package declaring the constraint (None for top-level constraints,
+    /// the virtual name for provider constraints)
+    pub package: Option<String>,
+    /// Version of the declaring package, when it is a single known version
+    pub version: Option<String>,
+    /// Markers for the declaring package: requested, installed, held
+    pub tags: Vec<String>,
+    pub origin: ConstraintOrigin,
+    /// Package (or virtual name) being constrained
+    pub dependency: String,
+    pub constraint: String,
+    /// Package the solver actually constrained
+    #[serde(skip)]
+    target: Package,
+    #[serde(skip)]
+    range: Range<Version>,
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let constraint = if self.constraint == "*" {
+            String::new()
+        } else {
+            format!(" {}", self.constraint)
+        };
+        match self.origin {
+            ConstraintOrigin::Request => {
+                write!(f, "you requested {}{}", self.dependency, constraint)
+            }
+            ConstraintOrigin::Held => {
+                let target = self.constraint.strip_prefix("= ").unwrap_or(&self.constraint);
+                write!(f, "{} (installed, held) is pinned to {}", self.dependency, target)
+            }
+            ConstraintOrigin::Installed => {
+                write!(f, "{} (installed) is kept at{}", self.dependency, constraint)
+            }
+            ConstraintOrigin::Upgrade => {
+                let target = self.constraint.strip_prefix("= ").unwrap_or(&self.constraint);
+                write!(f, "upgrading {} to {}", self.dependency, target)
+            }
+            ConstraintOrigin::Repository => {
+                write!(f, "{}", self.package.as_deref().unwrap_or("?"))?;
+                if let Some(ref version) = self.version {
+                    write!(f, " {}", version)?;
+                }
+                if !self.tags.is_empty() {
+                    write!(f, " ({})", self.tags.join(", "))?;
+                }
+                write!(f, " needs {}{}", self.dependency, constraint)
+            }
+            ConstraintOrigin::Provider => {
+                write!(f, "{}{} provides {}", self.dependency, constraint, self.package.as_deref().unwrap_or("?"))
+            }
+        }
+    }
+}
+
+/// Human-readable explanation of a failed resolution
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolutionReport {
+    /// One sentence per incompatibility, e.g.
+    /// "foo 2.0 needs bar >= 3, but baz 1.0 (installed, held) needs bar < 3"
+    pub explanation: Vec<String>,
+    /// Every constraint that took part in the failure
+    pub requirements: Vec<Requirement>,
+    /// Packages for which no acceptable version exists: name -> wanted range
+    pub unavailable: Vec<(String, String)>,
+}
+
+impl ResolutionReport {
+    /// Build a report from a solver error
+    pub fn from_error(
+        error: &PubGrubError<Package, Version>,
+        provider: &RookeryDependencyProvider,
+        context: &ResolutionContext,
+    ) -> Self {
+        match error {
+            PubGrubError::NoSolution(tree) => Self::from_tree(tree, provider, context),
+            other => Self {
+                explanation: vec![other.to_string()],
+                requirements: Vec::new(),
+                unavailable: Vec::new(),
+            },
+        }
+    }
+
+    fn from_tree(
+        tree: &DerivationTree<Package, Version>,
+        provider: &RookeryDependencyProvider,
+        context: &ResolutionContext,
+    ) -> Self {
+        let mut externals = Vec::new();
+        collect_externals(tree, &mut externals);
+
+        let mut requirements: Vec<Requirement> = Vec::new();
+        let mut no_versions: Vec<(String, Range<Version>)> = Vec::new();
+        // Package versions on each side of a conflict marker
+        let mut conflict_sides: Vec<(&Package, &Package, &Range<Version>, &Range<Version>)> = Vec::new();
+        let mut explanation = Vec::new();
+
+        for external in externals {
+            match external {
+                External::FromDependencyOf(pkg, pkg_range, dep, dep_range) => {
+                    if matches!(dep, Package::Conflict { .. }) {
+                        conflict_sides.push((dep, pkg, pkg_range, dep_range));
+                        continue;
+                    }
+                    let requirement = match pkg {
+                        Package::Virtual(name) => Requirement {
+                            package: Some(name.clone()),
+                            version: None,
+                            tags: Vec::new(),
+                            origin: ConstraintOrigin::Provider,
+                            dependency: dep.name().to_string(),
+                            constraint: format_range(dep_range),
+                            target: dep.clone(),
+                            range: dep_range.clone(),
+                        },
+                        _ if pkg.name() == ROOT_PACKAGE => {
+                            let name = dep.name();
+                            let origin = if context.held.contains(name) {
+                                ConstraintOrigin::Held
+                            } else if context.upgrading.contains(name) {
+                                ConstraintOrigin::Upgrade
+                            } else if context.installed.contains_key(name) && !context.requested.contains(name) {
+                                ConstraintOrigin::Installed
+                            } else {
+                                ConstraintOrigin::Request
+                            };
+                            Requirement {
+                                package: None,
+                                version: None,
+                                tags: Vec::new(),
+                                origin,
+                                dependency: name.to_string(),
+                                constraint: provider.describe_dependency(ROOT_PACKAGE, pkg_range, name, dep_range),
+                                target: dep.clone(),
+                                range: dep_range.clone(),
+                            }
                         }
-                        return Ok(Dependencies::Known(deps));
+                        _ => Requirement {
+                            package: Some(pkg.name().to_string()),
+                            version: provider.describe_version(pkg.name(), pkg_range),
+                            tags: context.tags(pkg.name()),
+                            origin: ConstraintOrigin::Repository,
+                            dependency: dep.name().to_string(),
+                            constraint: provider.describe_dependency(pkg.name(), pkg_range, dep.name(), dep_range),
+                            target: dep.clone(),
+                            range: dep_range.clone(),
+                        },
+                    };
+                    if !requirements
+                        .iter()
+                        .any(|r| r.to_string() == requirement.to_string())
+                    {
+                        requirements.push(requirement);
                     }
                 }
-                Err(format!(
-                    "Version {} not found for package {}",
-                    version, package
-                ).into())
+                External::NoVersions(Package::Named(name), range) => {
+                    if name != ROOT_PACKAGE && !no_versions.iter().any(|(n, r)| n == name && r == range) {
+                        no_versions.push((name.clone(), range.clone()));
+                    }
+                }
+                External::NoVersions(..) => {}
+                External::UnavailableDependencies(pkg, range) => {
+                    explanation.push(format!(
+                        "the dependencies of {} {} could not be determined",
+                        pkg,
+                        format_range(range)
+                    ));
+                }
+                External::NotRoot(..) => {}
+            }
+        }
+
+        // Pairs of constraints on the same package that no version can satisfy together
+        for (i, a) in requirements.iter().enumerate() {
+            for b in requirements.iter().skip(i + 1) {
+                if a.target == b.target && a.range.intersection(&b.range) == Range::none() {
+                    explanation.push(format!("{}, but {}", a, b));
+                }
+            }
+        }
+
+        // Declared conflicts: the declaring version takes the first side of
+        // its marker, every version it conflicts with the second
+        for (marker, declarer, _, side) in &conflict_sides {
+            let Package::Conflict { package, version, target } = marker else {
+                continue;
+            };
+            if !side.contains(&slot(0)) {
+                continue;
+            }
+            for (other_marker, other, other_range, other_side) in &conflict_sides {
+                if other_marker != marker || !other_side.contains(&slot(1)) {
+                    continue;
+                }
+                let mut line = format!("{} {}", package, version);
+                let tags = context.tags(declarer.name());
+                if !tags.is_empty() {
+                    line.push_str(&format!(" ({})", tags.join(", ")));
+                }
+                let mut with = other.name().to_string();
+                if let Some(version) = provider.describe_version(other.name(), other_range) {
+                    with.push_str(&format!(" {}", version));
+                }
+                let tags = context.tags(other.name());
+                if !tags.is_empty() {
+                    with.push_str(&format!(" ({})", tags.join(", ")));
+                }
+                if other.name() == target {
+                    line.push_str(&format!(" conflicts with {}", with));
+                } else {
+                    line.push_str(&format!(" conflicts with {}, which {} provides", target, with));
+                }
+                explanation.push(line);
             }
-            None => Err(format!("Package {} not found", package).into()),
+        }
+
+        // Constraints that no available version satisfies. The solver also
+        // records leftover ranges of packages it already picked a version
+        // for; only ranges covering a whole requirement are real causes.
+        let mut unavailable = Vec::new();
+        for (name, range) in &no_versions {
+            let available = provider
+                .get_versions(name)
+                .map(|versions| {
+                    let mut list: Vec<&Version> = versions.iter().map(|v| &v.version).collect();
+                    list.sort();
+                    list.dedup();
+                    list.iter().map(|v| v.to_string()).collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            let reason = if available.is_empty() {
+                format!("{} is not available in any repository", name)
+            } else {
+                format!("available versions of {} are {}", name, available.join(", "))
+            };
+
+            let mut matched = false;
+            let target = Package::Named(name.clone());
+            for requirement in requirements.iter().filter(|r| r.target == target) {
+                if requirement.range.intersection(range) == requirement.range {
+                    explanation.push(format!("{}, but {}", requirement, reason));
+                    matched = true;
+                }
+            }
+            if !matched && available.is_empty() {
+                explanation.push(format!("no version of {} matches {} ({})", name, format_range(range), reason));
+                matched = true;
+            }
+            if matched {
+                unavailable.push((name.clone(), format_range(range)));
+            }
+        }
+
+        if explanation.is_empty() {
+            explanation.push(DefaultStringReporter::report(tree));
+        }
+        explanation.dedup();
+
+        Self {
+            explanation,
+            requirements,
+            unavailable,
         }
     }
 }
 
+impl Display for ResolutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.explanation.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// An installed package the upgrade leaves below its newest known version
+#[derive(Debug, Clone)]
+pub struct HeldBack {
+    pub name: String,
+    /// Version the solver picked
+    pub selected: Version,
+    /// Newest version any repository offers
+    pub newest: Version,
+    /// Why the newest version cannot be used
+    pub reason: String,
+}
+
+/// Outcome of resolving a full-system upgrade
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeResolution {
+    /// Version chosen for every package in the solution (root excluded)
+    pub selected: HashMap<String, Version>,
+    /// Installed packages that stay below their newest version
+    pub held_back: Vec<HeldBack>,
+}
+
+/// Resolve a full-system upgrade as a single problem.
+///
+/// `root_deps` holds one top-level constraint per package that should be
+/// present afterwards: held packages pinned to their installed version, other
+/// installed packages bounded below by it. Since the solver always tries the
+/// highest matching version first, this yields the newest consistent set.
+///
+/// Every installed package left below its newest version is then resolved
+/// again with that version forced, so the report can say what holds it back.
+pub fn resolve_upgrade(
+    provider: &mut RookeryDependencyProvider,
+    root_deps: HashMap<String, Range<Version>>,
+    context: &ResolutionContext,
+) -> Result<UpgradeResolution, ResolutionReport> {
+    let root_version = provider.set_root(root_deps.clone());
+    let selected = provider.solve(root_version, context)?;
+
+    let mut behind: Vec<(&String, &Version, Version)> = selected
+        .iter()
+        .filter(|(name, _)| context.installed.contains_key(*name) && root_deps.contains_key(*name))
+        .filter_map(|(name, version)| {
+            let newest = provider.newest_version(name)?;
+            (newest > version).then(|| (name, version, newest.clone()))
+        })
+        .collect();
+    behind.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut held_back = Vec::new();
+    for (name, version, newest) in behind {
+        let reason = if context.held.contains(name) {
+            "held".to_string()
+        } else {
+            let mut probe = root_deps.clone();
+            probe.insert(name.clone(), Range::exact(newest.clone()));
+            let root_version = provider.set_root(probe);
+            match provider.solve(root_version, context) {
+                Err(report) => report.explanation.join("; "),
+                Ok(_) => format!("upgrading {} to {} would hold back other packages", name, newest),
+            }
+        };
+        held_back.push(HeldBack {
+            name: name.clone(),
+            selected: version.clone(),
+            newest,
+            reason,
+        });
+    }
+    provider.set_root(root_deps);
+
+    Ok(UpgradeResolution { selected, held_back })
+}
+
+/// Gather the external (leaf) incompatibilities of a derivation tree
+fn collect_externals<'a>(
+    tree: &'a DerivationTree<Package, Version>,
+    out: &mut Vec<&'a External<Package, Version>>,
+) {
+    match tree {
+        DerivationTree::External(external) => out.push(external),
+        DerivationTree::Derived(derived) => {
+            collect_externals(&derived.cause1, out);
+            collect_externals(&derived.cause2, out);
+        }
+    }
+}
+
+/// Format a version range as a constraint string (">= 1.0, < 2.0")
+pub fn format_range(range: &Range<Version>) -> String {
+    // Bumped versions display with a trailing '+': "1.0+" is "just above 1.0"
+    fn lower(v: &str) -> Option<String> {
+        match v.strip_suffix('+') {
+            Some(base) => Some(format!("> {}", base)),
+            None if v == "0" => None,
+            None => Some(format!(">= {}", v)),
+        }
+    }
+    fn upper(v: &str) -> String {
+        match v.strip_suffix('+') {
+            Some(base) => format!("<= {}", base),
+            None => format!("< {}", v),
+        }
+    }
+    fn interval(lo: &str, hi: Option<&str>) -> String {
+        if hi.and_then(|h| h.strip_suffix('+')) == Some(lo) {
+            return format!("= {}", lo);
+        }
+        match (lower(lo), hi) {
+            (Some(l), Some(h)) => format!("{}, {}", l, upper(h)),
+            (Some(l), None) => l,
+            (None, Some(h)) => upper(h),
+            (None, None) => "*".to_string(),
+        }
+    }
+
+    let shown = range.to_string();
+    if shown == "∗" {
+        return "*".to_string();
+    }
+    if shown == "∅" {
+        return "(no version)".to_string();
+    }
+    if let Some((lo, hi)) = shown.split_once(" <= v < ") {
+        return interval(lo, Some(hi));
+    }
+    if let Some(lo) = shown.strip_suffix(" <= v") {
+        return interval(lo, None);
+    }
+    if let Some(hi) = shown.strip_prefix("v < ") {
+        return upper(hi);
+    }
+    if shown.starts_with("[ ") {
+        return shown
+            .split("  ")
+            .filter_map(|part| {
+                let inner = part.trim().strip_prefix("[ ")?.strip_suffix(" [")?;
+                let (lo, hi) = inner.split_once(", ")?;
+                Some(interval(lo, if hi == "∞" { None } else { Some(hi) }))
+            })
+            .collect::<Vec<_>>()
+            .join(" or ");
+    }
+    format!("= {}", shown)
+}
+
 /// Parse a version constraint string (e.g., ">= 1.0", "= 2.0")
-pub fn parse_constraint(constraint: &str) -> Result<Range<SemanticVersion>, String> {
+///
+/// Comma-separated constraints must all hold: ">= 1.0, < 2.0".
+pub fn parse_constraint(constraint: &str) -> Result<Range<Version>, String> {
     let constraint = constraint.trim();
 
+    if constraint.contains(',') {
+        let mut range = Range::any();
+        for part in constraint.split(',') {
+            if part.trim().is_empty() {
+                return Err(format!("Empty constraint in: {}", constraint));
+            }
+            range = range.intersection(&parse_constraint(part)?);
+        }
+        return Ok(range);
+    }
+
     // Handle empty constraint (any version)
     if constraint.is_empty() || constraint == "*" {
         return Ok(Range::any());
     }
 
+    // Handle exclusion
+    if let Some(version_str) = constraint.strip_prefix("!=") {
+        let version = Version::parse(version_str.trim())?;
+        return Ok(Range::exact(version).negate());
+    }
+
     // Handle exact version
-    if constraint.starts_with("= ") || constraint.starts_with("==") {
-        let version_str = constraint.trim_start_matches("= ").trim_start_matches("==").trim();
-        let version = parse_semver(version_str)?;
+    if constraint.starts_with('=') {
+        let version_str = constraint.trim_start_matches('=').trim();
+        let version = Version::parse(version_str)?;
         return Ok(Range::exact(version));
     }
 
     // Handle >= constraint
     if constraint.starts_with(">=") {
         let version_str = constraint.trim_start_matches(">=").trim();
-        let version = parse_semver(version_str)?;
+        let version = Version::parse(version_str)?;
         return Ok(Range::higher_than(version));
     }
 
     // Handle > constraint (strictly greater)
     if constraint.starts_with(">") && !constraint.starts_with(">=") {
         let version_str = constraint.trim_start_matches(">").trim();
-        let version = parse_semver(version_str)?;
+        let version = Version::parse(version_str)?;
         // Strictly higher than = higher than the next version
         let bumped = version.bump();
         return Ok(Range::higher_than(bumped));
@@ -178,41 +1107,116 @@ pub fn parse_constraint(constraint: &str) -> Result<Range<SemanticVersion>, Stri
     // Handle < constraint
     if constraint.starts_with("<") && !constraint.starts_with("<=") {
         let version_str = constraint.trim_start_matches("<").trim();
-        let version = parse_semver(version_str)?;
+        let version = Version::parse(version_str)?;
         return Ok(Range::strictly_lower_than(version));
     }
 
     // Handle <= constraint
     if constraint.starts_with("<=") {
         let version_str = constraint.trim_start_matches("<=").trim();
-        let version = parse_semver(version_str)?;
+        let version = Version::parse(version_str)?;
         // Lower than or equal = strictly lower than the next version
         let bumped = version.bump();
         return Ok(Range::strictly_lower_than(bumped));
     }
 
     // Try parsing as a bare version (exact match)
-    let version = parse_semver(constraint)?;
+    let version = Version::parse(constraint)?;
     Ok(Range::exact(version))
 }
 
-/// Parse a version string to SemanticVersion
-pub fn parse_semver(s: &str) -> Result<SemanticVersion, String> {
-    let parts: Vec<&str> = s.split('.').collect();
+/// Parse a dependency string like "name" or "name >= 1.0"
+///
+/// Returns the name and the constraint ("*" when none is given).
+pub fn parse_dep_string(dep: &str) -> (&str, String) {
+    // The name ends at the first operator, so "foo < 2, >= 1" keeps both parts
+    if let Some(pos) = dep.find(['<', '>', '=', '!']) {
+        let name = dep[..pos].trim();
+        let constraint = dep[pos..].trim();
+        return (name, constraint.to_string());
+    }
+    // No operator found - name only, any version
+    (dep.trim(), "*".to_string())
+}
+
+/// A package named on the command line, optionally with a version constraint
+///
+/// Accepts `foo`, `foo<2`, `foo >= 1.0`, `foo=1.2.3` and `foo=1.2.3-4`. In an
+/// exact match a trailing `-N` is the package release, not part of the version.
+#[derive(Debug, Clone)]
+pub struct PackageRequest {
+    pub name: String,
+    /// Version constraint ("*" when none was given)
+    pub constraint: String,
+    pub range: Range<Version>,
+    /// Release pinned by `name=version-release`
+    pub release: Option<u32>,
+}
+
+impl PackageRequest {
+    /// Parse a command-line package argument
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, constraint) = parse_dep_string(s);
+        if name.is_empty() {
+            return Err(format!("Missing package name in '{}'", s.trim()));
+        }
+
+        let mut constraint = constraint;
+        let mut release = None;
+        let exact = constraint
+            .strip_prefix("==")
+            .or_else(|| constraint.strip_prefix('='))
+            .map(str::trim);
+        if let Some((version, rel)) = exact.and_then(|e| e.rsplit_once('-')) {
+            if let Ok(rel) = rel.parse::<u32>() {
+                release = Some(rel);
+                constraint = format!("= {}", version);
+            }
+        }
 
-    let major: u32 = parts
-        .first()
-        .and_then(|s| s.parse().ok())
-        .ok_or_else(|| format!("Invalid major version in: {}", s))?;
+        let range = parse_constraint(&constraint)
+            .map_err(|e| format!("Invalid version constraint in '{}': {}", s.trim(), e))?;
 
-    let minor: u32 = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+        Ok(Self {
+            name: name.to_string(),
+            constraint,
+            range,
+            release,
+        })
+    }
+}
+
+impl Display for PackageRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_dep(&self.name, &self.constraint))?;
+        if let Some(release) = self.release {
+            write!(f, "-{}", release)?;
+        }
+        Ok(())
+    }
+}
 
-    let patch: u32 = parts.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+/// Format a name and constraint as a dependency string (inverse of `parse_dep_string`)
+///
+/// A bare version is treated as an exact match, so provides entries such as
+/// `libssl = "3.0.0"` become "libssl = 3.0.0".
+pub fn format_dep(name: &str, constraint: &str) -> String {
+    let constraint = constraint.trim();
+    if constraint.is_empty() || constraint == "*" {
+        name.to_string()
+    } else if constraint.starts_with(['=', '<', '>', '!']) {
+        format!("{} {}", name, constraint)
+    } else {
+        format!("{} = {}", name, constraint)
+    }
+}
 
-    Ok(SemanticVersion::new(major, minor, patch))
+/// Parse a version string, treating unparseable (empty) versions as the lowest version
+pub fn parse_version(s: &str) -> Version {
+    Version::parse(s).unwrap_or_else(|_| Version::lowest())
 }
 
-/// Compare two version strings using semantic versioning rules.
+/// Compare two version strings using distribution version ordering.
 /// Returns:
 /// - `Ordering::Greater` if version_a > version_b
 /// - `Ordering::Less` if version_a < version_b
@@ -220,7 +1224,7 @@ pub fn parse_semver(s: &str) -> Result<SemanticVersion, String> {
 ///
 /// Falls back to string comparison if parsing fails.
 pub fn compare_versions(version_a: &str, version_b: &str) -> std::cmp::Ordering {
-    match (parse_semver(version_a), parse_semver(version_b)) {
+    match (Version::parse(version_a), Version::parse(version_b)) {
         (Ok(a), Ok(b)) => a.cmp(&b),
         // Fall back to string comparison if parsing fails
         _ => version_a.cmp(version_b),
@@ -230,7 +1234,7 @@ pub fn compare_versions(version_a: &str, version_b: &str) -> std::cmp::Ordering
 /// Check if a package needs upgrading based on version and release numbers.
 ///
 /// Returns true if the available version is newer than the installed version.
-/// Uses proper semantic versioning for version comparison, and numeric
+/// Uses distribution version ordering for version comparison, and numeric
 /// comparison for release numbers when versions are equal.
 pub fn needs_upgrade(
     installed_version: &str,
@@ -251,12 +1255,41 @@ pub fn needs_upgrade(
 mod tests {
     use super::*;
 
+    fn v(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
     #[test]
     fn test_parse_constraint() {
         let range = parse_constraint(">= 1.0").unwrap();
-        assert!(range.contains(&SemanticVersion::new(1, 0, 0)));
-        assert!(range.contains(&SemanticVersion::new(2, 0, 0)));
-        assert!(!range.contains(&SemanticVersion::new(0, 9, 0)));
+        assert!(range.contains(&v("1.0.0")));
+        assert!(range.contains(&v("2.0.0")));
+        assert!(!range.contains(&v("0.9.0")));
+
+        let range = parse_constraint("< 2.38").unwrap();
+        assert!(range.contains(&v("2.38~rc1")));
+        assert!(!range.contains(&v("2.38")));
+
+        let range = parse_constraint("<= 1.1.1").unwrap();
+        assert!(range.contains(&v("1.1.1")));
+        assert!(!range.contains(&v("1.1.1a")));
+
+        let range = parse_constraint("!= 1.0").unwrap();
+        assert!(!range.contains(&v("1.0.0")));
+        assert!(range.contains(&v("1.0.1")));
+
+        assert!(parse_constraint("=1.0").unwrap().contains(&v("1.0")));
+
+        let range = parse_constraint(">= 1.0, < 2.0").unwrap();
+        assert!(range.contains(&v("1.5")));
+        assert!(!range.contains(&v("2.0")));
+        assert!(!range.contains(&v("0.9")));
+        assert_eq!(range, parse_constraint("<2.0,>=1.0").unwrap());
+        assert_eq!(format_range(&range), ">= 1.0, < 2.0");
+
+        for bad in [">= 1.0,", ", < 2.0", ">= 1.0,, < 2.0", ">= 1.0 < 2.0", ">= >= 1.0", "~> 1.0"] {
+            assert!(parse_constraint(bad).is_err(), "{} should not parse", bad);
+        }
     }
 
     #[test]
@@ -264,21 +1297,359 @@ mod tests {
         let mut provider = RookeryDependencyProvider::new();
 
         // Add package A version 1.0.0 with no dependencies
-        provider.add_package("a", SemanticVersion::new(1, 0, 0), HashMap::new());
+        provider.add_package("a", v("1.0.0"), HashMap::new());
 
         // Add package B version 1.0.0 depending on A >= 1.0
         let mut b_deps = HashMap::new();
         b_deps.insert(
             "a".to_string(),
-            Range::higher_than(SemanticVersion::new(1, 0, 0)),
+            Range::higher_than(v("1.0.0")),
         );
-        provider.add_package("b", SemanticVersion::new(1, 0, 0), b_deps);
+        provider.add_package("b", v("1.0.0"), b_deps);
 
         // Check that we can find versions
         assert!(provider.get_versions("a").is_some());
         assert!(provider.get_versions("b").is_some());
     }
 
+    #[test]
+    fn test_dep_string_roundtrip() {
+        assert_eq!(parse_dep_string("glibc >= 2.39"), ("glibc", ">= 2.39".to_string()));
+        assert_eq!(parse_dep_string("zlib"), ("zlib", "*".to_string()));
+        assert_eq!(parse_dep_string("foo < 2, >= 1"), ("foo", "< 2, >= 1".to_string()));
+        assert_eq!(format_dep("glibc", ">= 2.39"), "glibc >= 2.39");
+        assert_eq!(format_dep("libssl", "3.0.0"), "libssl = 3.0.0");
+        assert_eq!(format_dep("zlib", ""), "zlib");
+        assert_eq!(format_dep("zlib", "*"), "zlib");
+    }
+
+    fn provider_with_ssl() -> RookeryDependencyProvider {
+        let mut provider = RookeryDependencyProvider::new();
+        let v1 = v("1.0.0");
+
+        for name in ["openssl", "libressl"] {
+            provider.add_package(name, v1.clone(), HashMap::new());
+            let mut provides = HashMap::new();
+            provides.insert("libssl".to_string(), Some(v("3.0.0")));
+            let mut conflicts = HashMap::new();
+            conflicts.insert("libssl".to_string(), Range::any());
+            provider.add_relations(name, v1.clone(), provides, conflicts);
+        }
+
+        let mut deps = HashMap::new();
+        deps.insert("libssl".to_string(), parse_constraint(">= 3.0").unwrap());
+        provider.add_package("curl", v1, deps);
+        provider
+    }
+
+    /// Solve for `root_deps`, returning the real packages chosen
+    fn solve(
+        provider: &mut RookeryDependencyProvider,
+        root_deps: &[(&str, &str)],
+    ) -> Result<HashMap<String, Version>, ResolutionReport> {
+        let root_deps = root_deps
+            .iter()
+            .map(|(name, constraint)| (name.to_string(), parse_constraint(constraint).unwrap()))
+            .collect();
+        let root_version = provider.set_root(root_deps);
+        provider.solve(root_version, &ResolutionContext::default())
+    }
+
+    #[test]
+    fn test_virtual_provides() {
+        let mut provider = provider_with_ssl();
+        assert!(provider.is_known("libssl"));
+        assert!(!provider.is_known("libtls"));
+
+        // Alphabetical fallback, then preference
+        let solution = solve(&mut provider, &[("curl", "*")]).unwrap();
+        assert!(solution.contains_key("libressl"));
+        assert!(!solution.contains_key("openssl"));
+        assert!(!solution.contains_key("libssl"));
+        assert_eq!(
+            provider.satisfied_by("libssl", &Range::any(), &solution).as_deref(),
+            Some("libressl")
+        );
+        provider.prefer("openssl");
+        let solution = solve(&mut provider, &[("curl", "*")]).unwrap();
+        assert!(solution.contains_key("openssl"));
+        assert!(!solution.contains_key("libressl"));
+
+        // Version constraint on the virtual name is honoured
+        let deps = HashMap::from([("libssl".to_string(), parse_constraint(">= 4.0").unwrap())]);
+        provider.add_package("wget", v("1.0.0"), deps);
+        assert!(solve(&mut provider, &[("wget", "*")]).is_err());
+    }
+
+    #[test]
+    fn test_virtual_provider_backtracking() {
+        // The preferred provider cannot be used, so the solver moves on
+        let mut provider = provider_with_ssl();
+        provider.prefer("libressl");
+        provider.add_package("app", v("1.0.0"), HashMap::new());
+        provider.add_relations(
+            "app",
+            v("1.0.0"),
+            HashMap::new(),
+            HashMap::from([("libressl".to_string(), Range::any())]),
+        );
+        let solution = solve(&mut provider, &[("curl", "*"), ("app", "*")]).unwrap();
+        assert!(solution.contains_key("openssl"));
+        assert!(!solution.contains_key("libressl"));
+
+        // Only an older version of the first provider satisfies the constraint
+        let mut provider = RookeryDependencyProvider::new();
+        let mut tls = entry("libressl", "3.8", &[]);
+        tls.provides = vec!["libssl = 1.1".to_string()];
+        provider.add_entry(&tls);
+        let mut ssl = entry("openssl", "3.0", &[]);
+        ssl.provides = vec!["libssl = 3.0".to_string()];
+        provider.add_entry(&ssl);
+        provider.add_entry(&entry("curl", "8.0", &["libssl >= 3"]));
+        let solution = solve(&mut provider, &[("curl", "*")]).unwrap();
+        assert_eq!(solution.get("openssl"), Some(&v("3.0")));
+        assert!(!solution.contains_key("libressl"));
+    }
+
+    #[test]
+    fn test_installed_provides() {
+        let mut provider = RookeryDependencyProvider::new();
+        let openssl = InstalledPackage {
+            name: "openssl".to_string(),
+            version: "3.0.0".to_string(),
+            release: 1,
+            install_date: 0,
+            size_bytes: 0,
+            checksum: String::new(),
+            spec: String::new(),
+            install_reason: Default::default(),
+        };
+        let provides = HashMap::from([
+            ("so:libssl.so.3".to_string(), String::new()),
+            ("pc:openssl".to_string(), "3.0.0".to_string()),
+        ]);
+        provider.add_installed(&openssl, &[], &provides);
+
+        let mut deps = HashMap::new();
+        deps.insert("so:libssl.so.3".to_string(), Range::any());
+        deps.insert("pc:openssl".to_string(), parse_constraint(">= 1.1").unwrap());
+        provider.add_package("curl", v("8.0.0"), deps);
+
+        let solution = solve(&mut provider, &[("curl", "*")]).unwrap();
+        assert!(solution.contains_key("openssl"));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut provider = provider_with_ssl();
+
+        // A package providing and conflicting with the same name is fine alone
+        let solution = solve(&mut provider, &[("curl", "*"), ("openssl", "*")]).unwrap();
+        assert!(solution.contains_key("openssl"));
+
+        // Two providers of libssl cannot be installed together
+        let mut context = ResolutionContext::default();
+        context.requested.insert("libressl".to_string());
+        context.installed.insert("openssl".to_string(), "1.0.0".to_string());
+        let root_deps = HashMap::from([
+            ("openssl".to_string(), Range::any()),
+            ("libressl".to_string(), Range::any()),
+        ]);
+        let text = failure_report(&mut provider, root_deps, &context).to_string();
+        assert!(
+            text.contains("openssl 1.0.0 (installed) conflicts with libssl, which libressl 1.0.0 (requested) provides")
+                || text.contains("libressl 1.0.0 (requested) conflicts with libssl, which openssl 1.0.0 (installed) provides"),
+            "unexpected report: {}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_conflict_avoided_by_older_version() {
+        // The newest app conflicts with the requested tool, an older one does not
+        let mut provider = RookeryDependencyProvider::new();
+        provider.add_entry(&entry("app", "1.0", &[]));
+        let mut app = entry("app", "2.0", &[]);
+        app.conflicts = vec!["tool < 2".to_string()];
+        provider.add_entry(&app);
+        provider.add_entry(&entry("tool", "1.0", &[]));
+
+        let solution = solve(&mut provider, &[("app", "*"), ("tool", "*")]).unwrap();
+        assert_eq!(solution.get("app"), Some(&v("1.0")));
+        assert_eq!(solution.get("tool"), Some(&v("1.0")));
+    }
+
+    fn entry(name: &str, version: &str, depends: &[&str]) -> PackageEntry {
+        PackageEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            release: 1,
+            description: String::new(),
+            arch: "x86_64".to_string(),
+            size: 0,
+            sha256: String::new(),
+            filename: String::new(),
+            depends: depends.iter().map(|d| d.to_string()).collect(),
+            build_depends: Vec::new(),
+            provides: Vec::new(),
+            conflicts: Vec::new(),
+            replaces: Vec::new(),
+            license: None,
+            homepage: None,
+            maintainer: None,
+            build_date: None,
+        }
+    }
+
+    /// Resolve `root_deps` (which must fail) and return the failure report
+    fn failure_report(
+        provider: &mut RookeryDependencyProvider,
+        root_deps: HashMap<String, Range<Version>>,
+        context: &ResolutionContext,
+    ) -> ResolutionReport {
+        let root_version = provider.set_root(root_deps);
+        let Err(report) = provider.solve(root_version, context) else {
+            panic!("resolution succeeded");
+        };
+        report
+    }
+
+    #[test]
+    fn test_resolution_report_held_conflict() {
+        let mut provider = RookeryDependencyProvider::new();
+        provider.add_entry(&entry("foo", "2.0", &["bar >= 3"]));
+        provider.add_entry(&entry("baz", "1.0", &["bar < 3"]));
+        provider.add_entry(&entry("bar", "2.0", &[]));
+        provider.add_entry(&entry("bar", "3.0", &[]));
+
+        let mut context = ResolutionContext::default();
+        context.requested.insert("foo".to_string());
+        context.installed.insert("baz".to_string(), "1.0".to_string());
+        context.held.insert("baz".to_string());
+
+        let mut root_deps = HashMap::new();
+        root_deps.insert("foo".to_string(), Range::any());
+        root_deps.insert("baz".to_string(), Range::exact(v("1.0")));
+        let report = failure_report(&mut provider, root_deps, &context);
+        let text = report.to_string();
+        assert!(
+            text.contains("foo 2.0 (requested) needs bar >= 3, but baz 1.0 (installed, held) needs bar < 3")
+                || text.contains("baz 1.0 (installed, held) needs bar < 3, but foo 2.0 (requested) needs bar >= 3"),
+            "unexpected report: {}",
+            text
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        let origins: Vec<&str> = json["requirements"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["origin"].as_str().unwrap())
+            .collect();
+        assert!(origins.contains(&"repository"));
+    }
+
+    #[test]
+    fn test_resolution_report_missing_package() {
+        let mut provider = RookeryDependencyProvider::new();
+        provider.add_entry(&entry("foo", "1.0", &["libmissing >= 2"]));
+
+        let mut context = ResolutionContext::default();
+        context.requested.insert("foo".to_string());
+        let mut root_deps = HashMap::new();
+        root_deps.insert("foo".to_string(), Range::any());
+        let text = failure_report(&mut provider, root_deps, &context).to_string();
+        assert!(text.contains("libmissing is not available in any repository"), "{}", text);
+
+        let mut provider = RookeryDependencyProvider::new();
+        provider.add_entry(&entry("libold", "1.0", &[]));
+        provider.add_entry(&entry("app", "1.0", &["libold >= 2"]));
+        let mut root_deps = HashMap::new();
+        root_deps.insert("app".to_string(), Range::any());
+        let text = failure_report(&mut provider, root_deps, &context).to_string();
+        assert!(text.contains("app 1.0 needs libold >= 2, but available versions of libold are 1.0"), "{}", text);
+    }
+
+    #[test]
+    fn test_resolve_upgrade() {
+        let mut provider = RookeryDependencyProvider::new();
+        provider.add_entry(&entry("app", "1.0", &["lib"]));
+        provider.add_entry(&entry("app", "2.0", &["lib >= 2", "newdep"]));
+        provider.add_entry(&entry("lib", "1.0", &[]));
+        provider.add_entry(&entry("lib", "2.0", &[]));
+        provider.add_entry(&entry("newdep", "1.0", &[]));
+        provider.add_entry(&entry("tool", "1.0", &[]));
+        provider.add_entry(&entry("tool", "2.0", &[]));
+        provider.add_entry(&entry("plugin", "1.0", &["tool"]));
+        provider.add_entry(&entry("plugin", "2.0", &["tool >= 2"]));
+
+        let mut context = ResolutionContext::default();
+        let mut root_deps = HashMap::new();
+        for name in ["app", "lib", "tool", "plugin"] {
+            context.installed.insert(name.to_string(), "1.0".to_string());
+            if name == "tool" {
+                context.held.insert(name.to_string());
+                root_deps.insert(name.to_string(), Range::exact(v("1.0")));
+            } else {
+                context.upgrading.insert(name.to_string());
+                root_deps.insert(name.to_string(), Range::higher_than(v("1.0")));
+            }
+        }
+
+        let resolution = resolve_upgrade(&mut provider, root_deps, &context).unwrap();
+        assert_eq!(resolution.selected["app"], v("2.0"));
+        assert_eq!(resolution.selected["lib"], v("2.0"));
+        assert_eq!(resolution.selected["newdep"], v("1.0"));
+        assert_eq!(resolution.selected["tool"], v("1.0"));
+        assert_eq!(resolution.selected["plugin"], v("1.0"));
+
+        let held_back: Vec<&str> = resolution.held_back.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(held_back, vec!["plugin", "tool"]);
+        let plugin = &resolution.held_back[0];
+        assert_eq!(plugin.newest, v("2.0"));
+        assert!(plugin.reason.contains("plugin 2.0"), "{}", plugin.reason);
+        assert!(plugin.reason.contains("tool (installed, held) is pinned to 1.0"), "{}", plugin.reason);
+        assert_eq!(resolution.held_back[1].reason, "held");
+    }
+
+    #[test]
+    fn test_package_request() {
+        let request = PackageRequest::parse("foo").unwrap();
+        assert_eq!(request.name, "foo");
+        assert_eq!(request.range, Range::any());
+
+        let request = PackageRequest::parse("foo<2").unwrap();
+        assert_eq!(request.name, "foo");
+        assert!(request.range.contains(&v("1.9")));
+        assert!(!request.range.contains(&v("2.0")));
+        assert_eq!(request.release, None);
+
+        let request = PackageRequest::parse("foo=1.2.3-4").unwrap();
+        assert_eq!(request.range, Range::exact(v("1.2.3")));
+        assert_eq!(request.release, Some(4));
+        assert_eq!(request.to_string(), "foo = 1.2.3-4");
+
+        let request = PackageRequest::parse("foo=2.38-rc1").unwrap();
+        assert_eq!(request.range, Range::exact(v("2.38-rc1")));
+        assert_eq!(request.release, None);
+
+        assert!(PackageRequest::parse(">= 1.0").is_err());
+        assert!(PackageRequest::parse("foo>=").is_err());
+    }
+
+    #[test]
+    fn test_format_range() {
+        for constraint in [">= 1.0", "< 2.0", "<= 1.1.1", "> 1.0", "= 2.38"] {
+            assert_eq!(format_range(&parse_constraint(constraint).unwrap()), constraint);
+        }
+        assert_eq!(format_range(&Range::any()), "*");
+        assert_eq!(
+            format_range(&parse_constraint(">= 1.0").unwrap().intersection(&parse_constraint("< 2").unwrap())),
+            ">= 1.0, < 2"
+        );
+        assert_eq!(format_range(&parse_constraint("!= 1.0").unwrap()), "< 1.0 or > 1.0");
+    }
+
     #[test]
     fn test_compare_versions() {
         use std::cmp::Ordering;
@@ -304,6 +1675,12 @@ mod tests {
         assert_eq!(compare_versions("1", "1.0.0"), Ordering::Equal);
         assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
         assert_eq!(compare_versions("2", "1.9.9"), Ordering::Greater);
+
+        // Suffixes, pre-releases and date versions
+        assert_eq!(compare_versions("1.1.1w", "1.1.1v"), Ordering::Greater);
+        assert_eq!(compare_versions("2.38~rc1", "2.38"), Ordering::Less);
+        assert_eq!(compare_versions("20240115", "20231231"), Ordering::Greater);
+        assert_eq!(compare_versions("9.0p1", "9.0"), Ordering::Greater);
     }
 
     #[test]
@@ -324,5 +1701,8 @@ mod tests {
 
         // Downgrade cases (should return false)
         assert!(!needs_upgrade("2.0.0", 1, "1.0.0", 5));
+
+        // Letter suffixes are real upgrades
+        assert!(needs_upgrade("1.1.1v", 1, "1.1.1w", 1));
     }
 }
@@ -0,0 +1,332 @@
+//! Build sandbox
+//!
+//! Build phases run in their own user, mount, network and PID namespaces.
+//! The sandbox sees the host filesystem read-only, except for the
+//! directories the build writes to and a private `/tmp`; it has only a
+//! loopback interface, so sources must have been fetched before any phase
+//! runs, and everything the build started dies with it.
+//!
+//! The invoking user is mapped to root inside the sandbox, and the
+//! capability to change mounts is dropped before the build script runs, so
+//! it cannot make the root writable again.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+/// `CAP_SYS_ADMIN` from `linux/capability.h`
+const CAP_SYS_ADMIN: libc::c_ulong = 21;
+
+/// Namespaces a sandboxed command runs in
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    /// Empty directory the sandbox root is mounted on
+    root: PathBuf,
+
+    /// Host directories mounted over the read-only root, in order, and
+    /// whether they are writable
+    binds: Vec<(PathBuf, bool)>,
+}
+
+impl Sandbox {
+    /// Create a sandbox whose root is mounted on `root`
+    pub fn new(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root)
+            .with_context(|| format!("Failed to create sandbox root: {}", root.display()))?;
+        let root = root.canonicalize()?;
+        Ok(Self { root, binds: Vec::new() })
+    }
+
+    /// Keep `dir` visible even if it is below the private `/tmp`
+    pub fn read_only(self, dir: &Path) -> Result<Self> {
+        self.bind(dir, false)
+    }
+
+    /// Let the sandbox write to `dir`
+    pub fn writable(self, dir: &Path) -> Result<Self> {
+        self.bind(dir, true)
+    }
+
+    fn bind(mut self, dir: &Path, writable: bool) -> Result<Self> {
+        // Mount targets are looked up inside the sandbox root, where
+        // symlinks in the host path would point elsewhere
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+        self.binds.push((dir, writable));
+        Ok(self)
+    }
+
+    /// Make `command` run inside the sandbox
+    ///
+    /// `work_dir` is entered again inside the sandbox, so it must be
+    /// visible there.
+    pub fn apply(&self, command: &mut Command, work_dir: &Path) -> Result<()> {
+        let setup = Setup::new(self, work_dir)?;
+        unsafe {
+            command.pre_exec(move || setup.enter());
+        }
+        Ok(())
+    }
+}
+
+/// A bind mount, with the directories leading to its target (which may
+/// have to be created on the private `/tmp`)
+struct Bind {
+    source: CString,
+    target: Vec<CString>,
+    writable: bool,
+}
+
+/// Everything the child needs, prepared before forking: between fork and
+/// exec nothing may allocate
+struct Setup {
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    root: CString,
+    tmp: CString,
+    proc: CString,
+    binds: Vec<Bind>,
+    work_dir: CString,
+}
+
+impl Setup {
+    fn new(sandbox: &Sandbox, work_dir: &Path) -> Result<Self> {
+        let inside = |path: &Path| sandbox.root.join(path.strip_prefix("/").unwrap_or(path));
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        let mut binds = Vec::new();
+        for (dir, writable) in &sandbox.binds {
+            let mut target = dir
+                .ancestors()
+                .filter(|a| a.parent().is_some())
+                .map(|a| c_path(&inside(a)))
+                .collect::<Result<Vec<_>>>()?;
+            target.reverse();
+            binds.push(Bind { source: c_path(dir)?, target, writable: *writable });
+        }
+
+        Ok(Self {
+            uid_map: format!("0 {} 1", uid).into_bytes(),
+            gid_map: format!("0 {} 1", gid).into_bytes(),
+            root: c_path(&sandbox.root)?,
+            tmp: c_path(&inside(Path::new("/tmp")))?,
+            proc: c_path(&inside(Path::new("/proc")))?,
+            binds,
+            work_dir: c_path(work_dir)?,
+        })
+    }
+
+    /// Runs in the forked child, before exec
+    fn enter(&self) -> io::Result<()> {
+        check(unsafe {
+            libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET | libc::CLONE_NEWPID)
+        })?;
+        write_proc(c"/proc/self/setgroups", b"deny")?;
+        write_proc(c"/proc/self/uid_map", &self.uid_map)?;
+        write_proc(c"/proc/self/gid_map", &self.gid_map)?;
+
+        // Only children join the new PID namespace: this process stays
+        // behind to wait for the build and pass its exit status on
+        match check(unsafe { libc::fork() })? {
+            0 => {}
+            pid => wait_and_exit(pid),
+        }
+
+        let none = std::ptr::null::<libc::c_char>();
+        check(unsafe { libc::mount(none, c"/".as_ptr(), none, libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()) })?;
+        check(unsafe {
+            libc::mount(c"/".as_ptr(), self.root.as_ptr(), none, libc::MS_BIND | libc::MS_REC, std::ptr::null())
+        })?;
+        make_read_only(&self.root)?;
+        check(unsafe {
+            libc::mount(
+                c"tmpfs".as_ptr(),
+                self.tmp.as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                c"mode=1777".as_ptr().cast(),
+            )
+        })?;
+
+        for bind in &self.binds {
+            for dir in &bind.target {
+                if unsafe { libc::mkdir(dir.as_ptr(), 0o755) } == -1
+                    && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            let target = &bind.target[bind.target.len() - 1];
+            check(unsafe {
+                libc::mount(bind.source.as_ptr(), target.as_ptr(), none, libc::MS_BIND | libc::MS_REC, std::ptr::null())
+            })?;
+            if !bind.writable {
+                make_read_only(target)?;
+            }
+        }
+        // Containers that mask parts of /proc refuse a fresh one; the
+        // host's then stays visible (read-only)
+        unsafe {
+            libc::mount(
+                c"proc".as_ptr(),
+                self.proc.as_ptr(),
+                c"proc".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                std::ptr::null(),
+            );
+        }
+
+        // Swap roots and detach the old one, leaving no way back to it
+        check(unsafe { libc::chdir(self.root.as_ptr()) })?;
+        check(unsafe { libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int })?;
+        check(unsafe { libc::umount2(c".".as_ptr(), libc::MNT_DETACH) })?;
+        check(unsafe { libc::chdir(self.work_dir.as_ptr()) })?;
+
+        loopback_up()?;
+        check(unsafe { libc::prctl(libc::PR_CAPBSET_DROP, CAP_SYS_ADMIN, 0, 0, 0) })?;
+        Ok(())
+    }
+}
+
+/// Make the mount at `path` and everything below it read-only
+fn make_read_only(path: &CStr) -> io::Result<()> {
+    let attr = libc::mount_attr {
+        attr_set: libc::MOUNT_ATTR_RDONLY,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    check(unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::AT_RECURSIVE,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        ) as libc::c_int
+    })?;
+    Ok(())
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Path contains a NUL byte: {}", path.display()))
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn write_proc(path: &CStr, contents: &[u8]) -> io::Result<()> {
+    let fd = check(unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) })?;
+    let written = unsafe { libc::write(fd, contents.as_ptr().cast(), contents.len()) };
+    unsafe { libc::close(fd) };
+    if written != contents.len() as isize {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Wait for the sandboxed process and exit with its status
+fn wait_and_exit(pid: libc::pid_t) -> ! {
+    // The descriptor std uses to report a failed exec would otherwise stay
+    // open here, and the parent would wait for this process to close it
+    unsafe { libc::close_range(3, libc::c_uint::MAX, 0) };
+
+    let mut status = 0;
+    loop {
+        let ret = unsafe { libc::waitpid(pid, &mut status, 0) };
+        if ret == pid {
+            break;
+        }
+        if ret == -1 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            unsafe { libc::_exit(127) };
+        }
+    }
+
+    let code = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        128 + libc::WTERMSIG(status)
+    };
+    unsafe { libc::_exit(code) }
+}
+
+/// Bring up `lo` so builds can still talk to themselves
+fn loopback_up() -> io::Result<()> {
+    let fd = check(unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) })?;
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    ifr.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+    let ret = unsafe { libc::ioctl(fd, libc::SIOCSIFFLAGS, &ifr) };
+    unsafe { libc::close(fd) };
+    check(ret).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sandboxed_command() {
+        let dir = tempdir().unwrap();
+        let work = dir.path().join("work");
+        let data = dir.path().join("data");
+        fs::create_dir(&work).unwrap();
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("input"), "input\n").unwrap();
+        // Outside /tmp, so only the read-only root protects it: in the
+        // target directory (target/debug), next to the test binary
+        let exe = std::env::current_exe().unwrap();
+        let host = tempfile::tempdir_in(exe.parent().unwrap().parent().unwrap()).unwrap();
+
+        let sandbox = Sandbox::new(&dir.path().join("sandbox"))
+            .unwrap()
+            .read_only(&data)
+            .unwrap()
+            .writable(&work)
+            .unwrap();
+        let mut command = Command::new("/bin/sh");
+        command
+            .arg("-c")
+            .arg(format!(
+                "echo $$; touch ok && echo writable; cat {data}/input; touch {data}/x || echo read-only; \
+                touch {host}/escaped || echo read-only; touch /tmp/private && echo tmp; grep -c : /proc/net/dev",
+                data = data.display(),
+                host = host.path().display()
+            ))
+            .current_dir(&work);
+        sandbox.apply(&mut command, &work).unwrap();
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(e) => {
+                // Kernels or containers without unprivileged namespaces
+                eprintln!("Skipping sandbox test: {}", e);
+                return;
+            }
+        };
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines, ["1", "writable", "input", "read-only", "read-only", "tmp", "1"]);
+        assert!(work.join("ok").exists());
+        assert!(!data.join("x").exists());
+        assert!(!host.path().join("escaped").exists());
+    }
+}
@@ -11,22 +11,32 @@
 //!
 //! Both signatures must verify for a package to be considered valid.
 //! This provides security against both classical and quantum adversaries.
+//!
+//! ## Revocation and Rotation
+//!
+//! A key is only good for signatures made while it was valid: before its
+//! certification expired and before it was revoked. Revocations travel as
+//! a [`RevocationList`] signed by a master or repository key. A key is
+//! handed over with a [`KeyRotation`], which the old key signs (and the new
+//! key countersigns); it carries the new public key and retires the old one
+//! from its effective time on.
 
 use std::fs;
 use std::path::Path;
 
-use anyhow::{bail, Context, Result};
 use base64::prelude::*;
 use ed25519_dalek::{Signature as Ed25519Signature, Signer as Ed25519Signer, SigningKey, Verifier as Ed25519Verifier, VerifyingKey};
 use ml_dsa::MlDsa65;
 use ml_dsa::signature::{SignatureEncoding, Signer as MlDsaSigner, Verifier as MlDsaVerifier};
 use rand::rngs::OsRng;
 use rand::RngCore;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use zeroize::Zeroizing;
 
 use crate::config::Config;
+use crate::error::{Context, Result, RookpkgError};
 
 /// Key algorithm type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -62,6 +72,61 @@ pub struct HybridSignature {
     pub timestamp: String,
 }
 
+/// Signatures by several keys over the same content
+///
+/// Detached repository `.sig` files hold an envelope; one holding a single
+/// bare signature (the format before envelopes) reads as an envelope of
+/// one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SignatureEnvelope {
+    /// SHA-256 of the signed content (hex), to tell whether a new signature
+    /// joins the others or starts over
+    pub digest: String,
+    pub signatures: Vec<HybridSignature>,
+}
+
+impl SignatureEnvelope {
+    /// Parse an envelope or a single signature
+    pub fn parse(content: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Envelope(SignatureEnvelope),
+            Single(HybridSignature),
+        }
+
+        Ok(match serde_json::from_str(content)? {
+            Stored::Envelope(envelope) => envelope,
+            Stored::Single(signature) => SignatureEnvelope {
+                digest: String::new(),
+                signatures: vec![signature],
+            },
+        })
+    }
+
+    /// Whether the signatures are over `message`
+    pub fn covers(&self, message: &[u8]) -> bool {
+        self.digest == hex::encode(Sha256::digest(message))
+    }
+
+    /// Whether `fingerprint` has signed already
+    pub fn is_signed_by(&self, fingerprint: &str) -> bool {
+        self.signatures.iter().any(|s| s.fingerprint == fingerprint)
+    }
+
+    /// Sign `message`, replacing the key's earlier signature, and dropping
+    /// all signatures if they were over different content
+    pub fn sign(&mut self, key: &(impl Signer + ?Sized), message: &[u8]) -> Result<()> {
+        if !self.covers(message) {
+            self.digest = hex::encode(Sha256::digest(message));
+            self.signatures.clear();
+        }
+        self.signatures.retain(|s| s.fingerprint != key.fingerprint());
+        self.signatures.push(sign_message(key, message)?);
+        Ok(())
+    }
+}
+
 /// A key certification - a signature on a public key by another key (typically master key)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyCertification {
@@ -79,6 +144,86 @@ pub struct KeyCertification {
     pub signature: HybridSignature,
 }
 
+impl KeyCertification {
+    /// When the certification expires, or None if it never does
+    pub fn expires_at(&self) -> Result<Option<DateTime<Utc>>> {
+        if self.expires.is_empty() {
+            return Ok(None);
+        }
+        let expires = parse_time(&self.expires).map_err(|_| {
+            RookpkgError::SignatureVerificationFailed(format!(
+                "Key certification has an invalid expiry: {}",
+                self.expires
+            ))
+        })?;
+        Ok(Some(expires))
+    }
+}
+
+/// A key withdrawn from use, as listed in a revocation list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationEntry {
+    /// Fingerprint of the revoked key
+    pub fingerprint: String,
+    /// Signatures made from this time on (RFC 3339) are refused
+    pub revoked_at: String,
+    /// Why the key was revoked
+    pub reason: String,
+}
+
+impl RevocationEntry {
+    /// When the revocation takes effect
+    pub fn revocation_time(&self) -> Result<DateTime<Utc>> {
+        parse_time(&self.revoked_at).map_err(|_| {
+            RookpkgError::SignatureVerificationFailed(format!(
+                "Revocation of {} has an invalid time: {}",
+                self.fingerprint, self.revoked_at
+            ))
+        })
+    }
+}
+
+/// A signed list of revoked keys, published with a repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationList {
+    /// Fingerprint of the key that issued the list
+    pub issuer: String,
+    /// When the list was issued (RFC 3339)
+    pub issued: String,
+    pub entries: Vec<RevocationEntry>,
+    /// The issuer's signature over the list
+    pub signature: HybridSignature,
+}
+
+/// A key handing over to its successor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// Fingerprint of the retiring key
+    pub old_key: String,
+    /// Fingerprint of the key taking over
+    pub new_key: String,
+    /// Contents of the new key's .pub file
+    pub new_public_key: String,
+    /// When the old key stops being valid (RFC 3339)
+    pub effective: String,
+    /// Signature by the old key
+    pub signature: HybridSignature,
+    /// Signature by the new key, showing it is held by the same party
+    pub new_signature: HybridSignature,
+}
+
+impl KeyRotation {
+    /// When the old key stops being valid
+    pub fn effective_time(&self) -> Result<DateTime<Utc>> {
+        parse_time(&self.effective).map_err(|_| {
+            RookpkgError::SignatureVerificationFailed(format!(
+                "Key rotation has an invalid effective time: {}",
+                self.effective
+            ))
+        })
+    }
+}
+
 /// A loaded signing key pair with metadata
 pub struct LoadedSigningKey {
     /// Ed25519 signing key
@@ -95,6 +240,72 @@ pub struct LoadedSigningKey {
     pub algorithm: KeyAlgorithm,
 }
 
+/// Something that makes hybrid signatures with one key
+///
+/// A [`LoadedSigningKey`] signs in this process; an
+/// [`AgentSigner`](crate::agent::AgentSigner) asks a signing agent, so the
+/// secret key never enters it. All signing functions take either.
+pub trait Signer {
+    /// Fingerprint of the key
+    fn fingerprint(&self) -> &str;
+
+    /// Key owner name
+    fn name(&self) -> &str;
+
+    /// Key owner email
+    fn email(&self) -> &str;
+
+    /// Sign the SHA-256 digest of a message
+    fn sign_digest(&self, message_hash: &[u8]) -> Result<HybridSignature>;
+}
+
+impl Signer for LoadedSigningKey {
+    fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+
+    fn sign_digest(&self, message_hash: &[u8]) -> Result<HybridSignature> {
+        // Create Ed25519 signature
+        let ed25519_sig = self.ed25519_key.sign(message_hash);
+
+        // Create ML-DSA signature
+        let ml_dsa_sig = self.ml_dsa_key.sign(message_hash);
+
+        Ok(HybridSignature {
+            ed25519: BASE64_STANDARD.encode(ed25519_sig.to_bytes()),
+            ml_dsa: BASE64_STANDARD.encode(ml_dsa_sig.to_bytes().as_slice()),
+            fingerprint: self.fingerprint.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+impl<S: Signer + ?Sized> Signer for Box<S> {
+    fn fingerprint(&self) -> &str {
+        (**self).fingerprint()
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn email(&self) -> &str {
+        (**self).email()
+    }
+
+    fn sign_digest(&self, message_hash: &[u8]) -> Result<HybridSignature> {
+        (**self).sign_digest(message_hash)
+    }
+}
+
 /// A loaded public key for verification
 #[derive(Clone)]
 pub struct LoadedPublicKey {
@@ -250,16 +461,30 @@ algorithm = "hybrid-ed25519-ml-dsa-65"
     Ok((loaded_key, fingerprint))
 }
 
+/// Report a malformed key file
+fn invalid_key(err: impl std::fmt::Display) -> RookpkgError {
+    RookpkgError::InvalidKey(err.to_string())
+}
+
 /// Load an existing signing key from the config-specified location
 pub fn load_signing_key(config: &Config) -> Result<LoadedSigningKey> {
     let key_path = &config.signing.user_signing_key;
     load_signing_key_from_path(key_path)
 }
 
+/// Load the configured signer: the signing agent if `agent_socket` is set,
+/// else the secret key at `user_signing_key`
+pub fn load_signer(config: &Config) -> Result<Box<dyn Signer>> {
+    match &config.signing.agent_socket {
+        Some(socket) => Ok(Box::new(crate::agent::AgentSigner::connect(socket)?)),
+        None => Ok(Box::new(load_signing_key(config)?)),
+    }
+}
+
 /// Load a signing key from a specific path
 pub fn load_signing_key_from_path(key_path: &Path) -> Result<LoadedSigningKey> {
     if !key_path.exists() {
-        bail!("Signing key not found at: {}", key_path.display());
+        return Err(RookpkgError::SigningKeyNotFound);
     }
 
     // Check permissions on Unix
@@ -269,17 +494,13 @@ pub fn load_signing_key_from_path(key_path: &Path) -> Result<LoadedSigningKey> {
         let metadata = fs::metadata(key_path)?;
         let mode = metadata.mode() & 0o777;
         if mode != 0o600 {
-            bail!(
-                "Signing key has insecure permissions: {:o} (expected 0600). Fix with: chmod 600 {}",
-                mode,
-                key_path.display()
-            );
+            return Err(RookpkgError::InsecureKeyPermissions(mode));
         }
     }
 
     // Read and parse key file
     let content = Zeroizing::new(fs::read_to_string(key_path)?);
-    let parsed: toml::Value = toml::from_str(&content)?;
+    let parsed: toml::Value = toml::from_str(&content).map_err(invalid_key)?;
 
     // Determine key type
     let key_type = parsed
@@ -290,7 +511,7 @@ pub fn load_signing_key_from_path(key_path: &Path) -> Result<LoadedSigningKey> {
     match key_type {
         "hybrid-ed25519-ml-dsa-65" => load_hybrid_secret_key(&parsed),
         "ed25519" => load_legacy_ed25519_key(&parsed),
-        _ => bail!("Unknown key type: {}", key_type),
+        _ => Err(RookpkgError::InvalidKey(format!("Unknown key type: {}", key_type))),
     }
 }
 
@@ -298,17 +519,17 @@ pub fn load_signing_key_from_path(key_path: &Path) -> Result<LoadedSigningKey> {
 fn load_hybrid_secret_key(parsed: &toml::Value) -> Result<LoadedSigningKey> {
     let keys = parsed
         .get("keys")
-        .ok_or_else(|| anyhow::anyhow!("Missing [keys] section in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing [keys] section in key file".to_string()))?;
 
     // Load Ed25519 key
     let ed25519_secret_b64 = keys
         .get("ed25519-secret")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing ed25519-secret in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing ed25519-secret in key file".to_string()))?;
 
-    let ed25519_bytes = BASE64_STANDARD.decode(ed25519_secret_b64)?;
+    let ed25519_bytes = BASE64_STANDARD.decode(ed25519_secret_b64).map_err(invalid_key)?;
     if ed25519_bytes.len() != 32 {
-        bail!("Invalid Ed25519 secret key length");
+        return Err(RookpkgError::InvalidKey("Invalid Ed25519 secret key length".to_string()));
     }
 
     let mut ed25519_key_bytes = [0u8; 32];
@@ -320,12 +541,12 @@ fn load_hybrid_secret_key(parsed: &toml::Value) -> Result<LoadedSigningKey> {
     let ml_dsa_secret_b64 = keys
         .get("ml-dsa-65-secret")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing ml-dsa-65-secret in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing ml-dsa-65-secret in key file".to_string()))?;
 
-    let ml_dsa_bytes = BASE64_STANDARD.decode(ml_dsa_secret_b64)?;
+    let ml_dsa_bytes = BASE64_STANDARD.decode(ml_dsa_secret_b64).map_err(invalid_key)?;
     let ml_dsa_key = ml_dsa::SigningKey::<MlDsa65>::decode(
         ml_dsa_bytes.as_slice().try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid ML-DSA-65 secret key length"))?
+            .map_err(|_| RookpkgError::InvalidKey("Invalid ML-DSA-65 secret key length".to_string()))?
     );
 
     // Get metadata
@@ -353,7 +574,7 @@ fn load_hybrid_secret_key(parsed: &toml::Value) -> Result<LoadedSigningKey> {
     ed25519_key
         .verifying_key()
         .verify(test_msg, &ed25519_sig)
-        .map_err(|_| anyhow::anyhow!("Ed25519 key verification failed"))?;
+        .map_err(|_| RookpkgError::InvalidKey("Ed25519 key verification failed".to_string()))?;
 
     Ok(LoadedSigningKey {
         ed25519_key,
@@ -370,11 +591,11 @@ fn load_legacy_ed25519_key(parsed: &toml::Value) -> Result<LoadedSigningKey> {
     let secret_key_b64 = parsed
         .get("secret-key")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing secret-key in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing secret-key in key file".to_string()))?;
 
-    let secret_bytes = BASE64_STANDARD.decode(secret_key_b64)?;
+    let secret_bytes = BASE64_STANDARD.decode(secret_key_b64).map_err(invalid_key)?;
     if secret_bytes.len() != 32 {
-        bail!("Invalid secret key length");
+        return Err(RookpkgError::InvalidKey("Invalid secret key length".to_string()));
     }
 
     let mut key_bytes = [0u8; 32];
@@ -429,7 +650,12 @@ fn load_legacy_ed25519_key(parsed: &toml::Value) -> Result<LoadedSigningKey> {
 /// Load a public key from a file
 pub fn load_public_key(path: &Path) -> Result<LoadedPublicKey> {
     let content = fs::read_to_string(path)?;
-    let parsed: toml::Value = toml::from_str(&content)?;
+    parse_public_key(&content)
+}
+
+/// Parse the contents of a public key file
+pub fn parse_public_key(content: &str) -> Result<LoadedPublicKey> {
+    let parsed: toml::Value = toml::from_str(content).map_err(invalid_key)?;
 
     let key_type = parsed
         .get("type")
@@ -439,7 +665,7 @@ pub fn load_public_key(path: &Path) -> Result<LoadedPublicKey> {
     match key_type {
         "hybrid-ed25519-ml-dsa-65" => load_hybrid_public_key(&parsed),
         "ed25519" => load_legacy_public_key(&parsed),
-        _ => bail!("Unknown key type: {}", key_type),
+        _ => Err(RookpkgError::InvalidKey(format!("Unknown key type: {}", key_type))),
     }
 }
 
@@ -447,33 +673,33 @@ pub fn load_public_key(path: &Path) -> Result<LoadedPublicKey> {
 fn load_hybrid_public_key(parsed: &toml::Value) -> Result<LoadedPublicKey> {
     let keys = parsed
         .get("keys")
-        .ok_or_else(|| anyhow::anyhow!("Missing [keys] section in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing [keys] section in key file".to_string()))?;
 
     // Load Ed25519 public key
     let ed25519_pub_b64 = keys
         .get("ed25519-public")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing ed25519-public in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing ed25519-public in key file".to_string()))?;
 
-    let ed25519_bytes = BASE64_STANDARD.decode(ed25519_pub_b64)?;
+    let ed25519_bytes = BASE64_STANDARD.decode(ed25519_pub_b64).map_err(invalid_key)?;
     let ed25519_key = VerifyingKey::from_bytes(
         ed25519_bytes
             .as_slice()
             .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid Ed25519 public key length"))?,
+            .map_err(|_| RookpkgError::InvalidKey("Invalid Ed25519 public key length".to_string()))?,
     )
-    .map_err(|_| anyhow::anyhow!("Invalid Ed25519 public key"))?;
+    .map_err(|_| RookpkgError::InvalidKey("Invalid Ed25519 public key".to_string()))?;
 
     // Load ML-DSA public key
     let ml_dsa_pub_b64 = keys
         .get("ml-dsa-65-public")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing ml-dsa-65-public in key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing ml-dsa-65-public in key file".to_string()))?;
 
-    let ml_dsa_bytes = BASE64_STANDARD.decode(ml_dsa_pub_b64)?;
+    let ml_dsa_bytes = BASE64_STANDARD.decode(ml_dsa_pub_b64).map_err(invalid_key)?;
     let ml_dsa_key = ml_dsa::VerifyingKey::<MlDsa65>::decode(
         ml_dsa_bytes.as_slice().try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid ML-DSA-65 public key length"))?
+            .map_err(|_| RookpkgError::InvalidKey("Invalid ML-DSA-65 public key length".to_string()))?
     );
 
     let fingerprint = parsed
@@ -510,16 +736,16 @@ fn load_legacy_public_key(parsed: &toml::Value) -> Result<LoadedPublicKey> {
     let pub_key_b64 = parsed
         .get("key")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing key in public key file"))?;
+        .ok_or_else(|| RookpkgError::InvalidKey("Missing key in public key file".to_string()))?;
 
-    let pub_bytes = BASE64_STANDARD.decode(pub_key_b64)?;
+    let pub_bytes = BASE64_STANDARD.decode(pub_key_b64).map_err(invalid_key)?;
     let ed25519_key = VerifyingKey::from_bytes(
         pub_bytes
             .as_slice()
             .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid Ed25519 public key length"))?,
+            .map_err(|_| RookpkgError::InvalidKey("Invalid Ed25519 public key length".to_string()))?,
     )
-    .map_err(|_| anyhow::anyhow!("Invalid Ed25519 public key"))?;
+    .map_err(|_| RookpkgError::InvalidKey("Invalid Ed25519 public key".to_string()))?;
 
     // Derive ML-DSA public key from Ed25519 public key (deterministic)
     let mut seed_hasher = Sha256::new();
@@ -581,22 +807,16 @@ pub fn calculate_fingerprint(key: &VerifyingKey) -> String {
 }
 
 /// Sign a message with hybrid Ed25519 + ML-DSA signatures
-pub fn sign_message(key: &LoadedSigningKey, message: &[u8]) -> Result<HybridSignature> {
-    // Hash the message first
-    let message_hash = Sha256::digest(message);
-
-    // Create Ed25519 signature
-    let ed25519_sig = key.ed25519_key.sign(&message_hash);
-
-    // Create ML-DSA signature
-    let ml_dsa_sig = key.ml_dsa_key.sign(&message_hash);
+pub fn sign_message(key: &(impl Signer + ?Sized), message: &[u8]) -> Result<HybridSignature> {
+    key.sign_digest(&Sha256::digest(message))
+}
 
-    Ok(HybridSignature {
-        ed25519: BASE64_STANDARD.encode(ed25519_sig.to_bytes()),
-        ml_dsa: BASE64_STANDARD.encode(ml_dsa_sig.to_bytes().as_slice()),
-        fingerprint: key.fingerprint.clone(),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    })
+/// Sign the SHA-256 digest of a message
+///
+/// Both algorithms sign the digest rather than the message, so this
+/// produces the same signature as [`sign_message`] on the full message.
+pub fn sign_digest(key: &(impl Signer + ?Sized), message_hash: &[u8]) -> Result<HybridSignature> {
+    key.sign_digest(message_hash)
 }
 
 /// Verify a hybrid signature
@@ -605,43 +825,58 @@ pub fn verify_signature(
     message: &[u8],
     signature: &HybridSignature,
 ) -> Result<()> {
-    // Hash the message
-    let message_hash = Sha256::digest(message);
+    verify_digest(public_key, &Sha256::digest(message), signature)
+}
 
+/// Verify a hybrid signature against the SHA-256 digest of a message
+pub fn verify_digest(
+    public_key: &LoadedPublicKey,
+    message_hash: &[u8],
+    signature: &HybridSignature,
+) -> Result<()> {
     // Verify Ed25519 signature
-    let ed25519_sig_bytes = BASE64_STANDARD.decode(&signature.ed25519)?;
+    let ed25519_sig_bytes = BASE64_STANDARD.decode(&signature.ed25519).map_err(|e| RookpkgError::SignatureVerificationFailed(e.to_string()))?;
     let ed25519_sig = Ed25519Signature::from_bytes(
         ed25519_sig_bytes
             .as_slice()
             .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid Ed25519 signature length"))?,
+            .map_err(|_| RookpkgError::SignatureVerificationFailed("Invalid Ed25519 signature length".to_string()))?,
     );
 
     public_key
         .ed25519_key
-        .verify(&message_hash, &ed25519_sig)
-        .map_err(|_| anyhow::anyhow!("Ed25519 signature verification failed"))?;
+        .verify(message_hash, &ed25519_sig)
+        .map_err(|_| RookpkgError::SignatureVerificationFailed("Ed25519 signature verification failed".to_string()))?;
 
     // Verify ML-DSA signature
-    let ml_dsa_sig_bytes = BASE64_STANDARD.decode(&signature.ml_dsa)?;
+    let ml_dsa_sig_bytes = BASE64_STANDARD.decode(&signature.ml_dsa).map_err(|e| RookpkgError::SignatureVerificationFailed(e.to_string()))?;
     let ml_dsa_sig = ml_dsa::Signature::<MlDsa65>::decode(
         ml_dsa_sig_bytes.as_slice().try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid ML-DSA signature length"))?
-    ).ok_or_else(|| anyhow::anyhow!("Invalid ML-DSA signature"))?;
+            .map_err(|_| RookpkgError::SignatureVerificationFailed("Invalid ML-DSA signature length".to_string()))?
+    ).ok_or_else(|| RookpkgError::SignatureVerificationFailed("Invalid ML-DSA signature".to_string()))?;
 
     public_key
         .ml_dsa_key
-        .verify(&message_hash, &ml_dsa_sig)
-        .map_err(|_| anyhow::anyhow!("ML-DSA signature verification failed"))?;
+        .verify(message_hash, &ml_dsa_sig)
+        .map_err(|_| RookpkgError::SignatureVerificationFailed("ML-DSA signature verification failed".to_string()))?;
 
     tracing::debug!("Hybrid signature verified successfully");
     Ok(())
 }
 
+/// SHA-256 digest of a file, read in chunks so large packages are never
+/// held in memory
+fn file_digest(file_path: &Path) -> Result<Vec<u8>> {
+    let file = fs::File::open(file_path)
+        .with_context(|| format!("Failed to open {}", file_path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::io::BufReader::with_capacity(1024 * 1024, file), &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
 /// Sign a file and return the signature
-pub fn sign_file(key: &LoadedSigningKey, file_path: &Path) -> Result<HybridSignature> {
-    let content = fs::read(file_path)?;
-    sign_message(key, &content)
+pub fn sign_file(key: &(impl Signer + ?Sized), file_path: &Path) -> Result<HybridSignature> {
+    sign_digest(key, &file_digest(file_path)?)
 }
 
 /// Verify a file signature
@@ -650,13 +885,12 @@ pub fn verify_file(
     file_path: &Path,
     signature: &HybridSignature,
 ) -> Result<()> {
-    let content = fs::read(file_path)?;
-    verify_signature(public_key, &content, signature)
+    verify_digest(public_key, &file_digest(file_path)?, signature)
 }
 
 /// Get the fingerprint of a loaded signing key
-pub fn get_fingerprint(key: &LoadedSigningKey) -> &str {
-    &key.fingerprint
+pub fn get_fingerprint(key: &(impl Signer + ?Sized)) -> &str {
+    key.fingerprint()
 }
 
 /// Certify (sign) a public key with a master key
@@ -664,7 +898,7 @@ pub fn get_fingerprint(key: &LoadedSigningKey) -> &str {
 /// This creates a certification that attests the public key is authorized
 /// for a specific purpose (e.g., "packager").
 pub fn certify_key(
-    master_key: &LoadedSigningKey,
+    master_key: &(impl Signer + ?Sized),
     public_key: &LoadedPublicKey,
     purpose: &str,
     expires: Option<&str>,
@@ -675,7 +909,7 @@ pub fn certify_key(
     let cert_data = format!(
         "ROOKERY-KEY-CERTIFICATION-V1|{}|{}|{}|{}",
         public_key.fingerprint,
-        master_key.fingerprint,
+        master_key.fingerprint(),
         purpose,
         expires_str
     );
@@ -685,8 +919,8 @@ pub fn certify_key(
 
     Ok(KeyCertification {
         certified_key: public_key.fingerprint.clone(),
-        certifier_key: master_key.fingerprint.clone(),
-        certifier_name: format!("{} <{}>", master_key.name, master_key.email),
+        certifier_key: master_key.fingerprint().to_string(),
+        certifier_name: format!("{} <{}>", master_key.name(), master_key.email()),
         purpose: purpose.to_string(),
         expires: expires_str.to_string(),
         signature,
@@ -698,30 +932,39 @@ pub fn verify_certification(
     certification: &KeyCertification,
     certified_key: &LoadedPublicKey,
     certifier_key: &LoadedPublicKey,
+) -> Result<()> {
+    verify_certification_at(certification, certified_key, certifier_key, Utc::now())
+}
+
+/// Verify a key certification as of `at`, e.g. when a signature was made
+pub fn verify_certification_at(
+    certification: &KeyCertification,
+    certified_key: &LoadedPublicKey,
+    certifier_key: &LoadedPublicKey,
+    at: DateTime<Utc>,
 ) -> Result<()> {
     // Check fingerprints match
     if certification.certified_key != certified_key.fingerprint {
-        bail!(
+        return Err(RookpkgError::SignatureVerificationFailed(format!(
             "Certification is for key {} but got {}",
-            certification.certified_key,
-            certified_key.fingerprint
-        );
+            certification.certified_key, certified_key.fingerprint
+        )));
     }
 
     if certification.certifier_key != certifier_key.fingerprint {
-        bail!(
+        return Err(RookpkgError::SignatureVerificationFailed(format!(
             "Certification is from key {} but verifying with {}",
-            certification.certifier_key,
-            certifier_key.fingerprint
-        );
+            certification.certifier_key, certifier_key.fingerprint
+        )));
     }
 
     // Check expiration
-    if !certification.expires.is_empty() {
-        if let Ok(expires) = chrono::DateTime::parse_from_rfc3339(&certification.expires) {
-            if expires < chrono::Utc::now() {
-                bail!("Key certification has expired ({})", certification.expires);
-            }
+    if let Some(expires) = certification.expires_at()? {
+        if expires < at {
+            return Err(RookpkgError::SignatureVerificationFailed(format!(
+                "Key certification has expired ({})",
+                certification.expires
+            )));
         }
     }
 
@@ -735,8 +978,9 @@ pub fn verify_certification(
     );
 
     // Verify the signature
-    verify_signature(certifier_key, cert_data.as_bytes(), &certification.signature)
-        .context("Key certification signature verification failed")?;
+    verify_signature(certifier_key, cert_data.as_bytes(), &certification.signature).map_err(|e| {
+        RookpkgError::SignatureVerificationFailed(format!("Key certification signature: {}", e))
+    })?;
 
     tracing::debug!(
         "Key {} certified by {} for purpose '{}'",
@@ -789,6 +1033,137 @@ pub fn find_certification_for_key(
     Ok(None)
 }
 
+/// When a signature was made, as recorded by its signer
+pub fn signature_time(signature: &HybridSignature) -> Result<DateTime<Utc>> {
+    parse_time(&signature.timestamp).map_err(|_| {
+        RookpkgError::SignatureVerificationFailed(format!(
+            "Signature has an invalid timestamp: {}",
+            signature.timestamp
+        ))
+    })
+}
+
+fn parse_time(value: &str) -> std::result::Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(value).map(|t| t.with_timezone(&Utc))
+}
+
+/// The data a revocation list signature covers
+fn revocation_list_data(issuer: &str, issued: &str, entries: &[RevocationEntry]) -> Result<String> {
+    Ok(format!(
+        "ROOKERY-REVOCATION-LIST-V1|{}|{}|{}",
+        issuer,
+        issued,
+        serde_json::to_string(entries)?
+    ))
+}
+
+/// Sign a list of revoked keys
+pub fn sign_revocation_list(key: &(impl Signer + ?Sized), entries: Vec<RevocationEntry>) -> Result<RevocationList> {
+    for entry in &entries {
+        entry.revocation_time()?;
+    }
+
+    let issued = Utc::now().to_rfc3339();
+    let data = revocation_list_data(key.fingerprint(), &issued, &entries)?;
+    let signature = sign_message(key, data.as_bytes())?;
+
+    Ok(RevocationList {
+        issuer: key.fingerprint().to_string(),
+        issued,
+        entries,
+        signature,
+    })
+}
+
+/// Verify a revocation list against the key that issued it
+pub fn verify_revocation_list(list: &RevocationList, issuer_key: &LoadedPublicKey) -> Result<()> {
+    if list.issuer != issuer_key.fingerprint {
+        return Err(RookpkgError::SignatureVerificationFailed(format!(
+            "Revocation list is from key {} but verifying with {}",
+            list.issuer, issuer_key.fingerprint
+        )));
+    }
+
+    let data = revocation_list_data(&list.issuer, &list.issued, &list.entries)?;
+    verify_signature(issuer_key, data.as_bytes(), &list.signature).map_err(|e| {
+        RookpkgError::SignatureVerificationFailed(format!("Revocation list signature: {}", e))
+    })?;
+
+    for entry in &list.entries {
+        entry.revocation_time()?;
+    }
+
+    Ok(())
+}
+
+/// The data both key rotation signatures cover
+fn rotation_data(old_key: &str, new_key: &str, new_public_key: &str, effective: &str) -> String {
+    format!(
+        "ROOKERY-KEY-ROTATION-V1|{}|{}|{}|{}",
+        old_key,
+        new_key,
+        hex::encode(Sha256::digest(new_public_key.as_bytes())),
+        effective
+    )
+}
+
+/// Hand over from `old_key` to `new_key`, retiring the old key now
+///
+/// `new_public_key` is the contents of the new key's .pub file.
+pub fn rotate_key(
+    old_key: &(impl Signer + ?Sized),
+    new_key: &(impl Signer + ?Sized),
+    new_public_key: &str,
+) -> Result<KeyRotation> {
+    if parse_public_key(new_public_key)?.fingerprint != new_key.fingerprint() {
+        return Err(RookpkgError::InvalidKey(format!(
+            "Public key does not belong to {}",
+            new_key.fingerprint()
+        )));
+    }
+
+    let effective = Utc::now().to_rfc3339();
+    let data = rotation_data(old_key.fingerprint(), new_key.fingerprint(), new_public_key, &effective);
+
+    Ok(KeyRotation {
+        old_key: old_key.fingerprint().to_string(),
+        new_key: new_key.fingerprint().to_string(),
+        new_public_key: new_public_key.to_string(),
+        effective,
+        signature: sign_message(old_key, data.as_bytes())?,
+        new_signature: sign_message(new_key, data.as_bytes())?,
+    })
+}
+
+/// Verify a key rotation against the retiring key, returning the new key
+pub fn verify_rotation(rotation: &KeyRotation, old_key: &LoadedPublicKey) -> Result<LoadedPublicKey> {
+    if rotation.old_key != old_key.fingerprint {
+        return Err(RookpkgError::SignatureVerificationFailed(format!(
+            "Key rotation is from key {} but verifying with {}",
+            rotation.old_key, old_key.fingerprint
+        )));
+    }
+
+    let new_key = parse_public_key(&rotation.new_public_key)?;
+    if new_key.fingerprint != rotation.new_key {
+        return Err(RookpkgError::SignatureVerificationFailed(format!(
+            "Key rotation names key {} but carries {}",
+            rotation.new_key, new_key.fingerprint
+        )));
+    }
+    rotation.effective_time()?;
+
+    let data = rotation_data(&rotation.old_key, &rotation.new_key, &rotation.new_public_key, &rotation.effective);
+    verify_signature(old_key, data.as_bytes(), &rotation.signature).map_err(|e| {
+        RookpkgError::SignatureVerificationFailed(format!("Key rotation signature: {}", e))
+    })?;
+    verify_signature(&new_key, data.as_bytes(), &rotation.new_signature).map_err(|e| {
+        RookpkgError::SignatureVerificationFailed(format!("Key rotation countersignature: {}", e))
+    })?;
+
+    Ok(new_key)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -819,6 +1194,26 @@ mod tests {
         verify_signature(&pub_key, message, &signature).unwrap();
     }
 
+    #[test]
+    fn test_file_signature() {
+        let dir = tempdir().unwrap();
+        let (key, _) = generate_key("Test User", "test@example.org", dir.path()).unwrap();
+        let pub_key = load_public_key(&dir.path().join("signing-key.pub")).unwrap();
+
+        let path = dir.path().join("payload");
+        let content: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &content).unwrap();
+
+        // A streamed file signature is the signature of its whole content
+        let signature = sign_file(&key, &path).unwrap();
+        verify_signature(&pub_key, &content, &signature).unwrap();
+        verify_file(&pub_key, &path, &sign_message(&key, &content).unwrap()).unwrap();
+        verify_digest(&pub_key, &Sha256::digest(&content), &signature).unwrap();
+
+        fs::write(&path, b"other").unwrap();
+        assert!(verify_file(&pub_key, &path, &signature).is_err());
+    }
+
     #[test]
     fn test_signature_tamper_detection() {
         let dir = tempdir().unwrap();
@@ -846,4 +1241,106 @@ mod tests {
         let hash_part = fingerprint.strip_prefix("HYBRID:SHA256:").unwrap();
         assert_eq!(hash_part.len(), 32);
     }
+
+    #[test]
+    fn test_certification_expiry() {
+        let dir = tempdir().unwrap();
+        let (master, _) = generate_key("Master", "master@example.org", &dir.path().join("master")).unwrap();
+        generate_key("Packager", "packager@example.org", &dir.path().join("packager")).unwrap();
+        let master_pub = load_public_key(&dir.path().join("master/signing-key.pub")).unwrap();
+        let packager_pub = load_public_key(&dir.path().join("packager/signing-key.pub")).unwrap();
+
+        let expires = "2030-01-01T00:00:00+00:00";
+        let cert = certify_key(&master, &packager_pub, "packager", Some(expires)).unwrap();
+        let before = parse_time("2029-12-31T00:00:00Z").unwrap();
+        let after = parse_time("2030-01-02T00:00:00Z").unwrap();
+        assert!(verify_certification_at(&cert, &packager_pub, &master_pub, before).is_ok());
+        assert!(verify_certification_at(&cert, &packager_pub, &master_pub, after).is_err());
+        assert_eq!(cert.expires_at().unwrap(), Some(parse_time(expires).unwrap()));
+
+        // An expiry that cannot be read does not mean "never"
+        let mut garbled = cert.clone();
+        garbled.expires = "next year".to_string();
+        assert!(garbled.expires_at().is_err());
+    }
+
+    #[test]
+    fn test_revocation_list() {
+        let dir = tempdir().unwrap();
+        let (key, _) = generate_key("Master", "master@example.org", dir.path()).unwrap();
+        let public_key = load_public_key(&dir.path().join("signing-key.pub")).unwrap();
+
+        let entries = vec![RevocationEntry {
+            fingerprint: "HYBRID:SHA256:0123".to_string(),
+            revoked_at: "2025-06-01T00:00:00Z".to_string(),
+            reason: "Key compromised".to_string(),
+        }];
+        let list = sign_revocation_list(&key, entries).unwrap();
+        assert!(verify_revocation_list(&list, &public_key).is_ok());
+
+        // Dropping an entry invalidates the list
+        let mut tampered = list.clone();
+        tampered.entries.clear();
+        assert!(verify_revocation_list(&tampered, &public_key).is_err());
+
+        let bad_time = vec![RevocationEntry {
+            fingerprint: "HYBRID:SHA256:0123".to_string(),
+            revoked_at: "yesterday".to_string(),
+            reason: String::new(),
+        }];
+        assert!(sign_revocation_list(&key, bad_time).is_err());
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let dir = tempdir().unwrap();
+        let (old, _) = generate_key("Old", "old@example.org", &dir.path().join("old")).unwrap();
+        let (new, _) = generate_key("New", "new@example.org", &dir.path().join("new")).unwrap();
+        let old_pub = load_public_key(&dir.path().join("old/signing-key.pub")).unwrap();
+        let new_pub_content = fs::read_to_string(dir.path().join("new/signing-key.pub")).unwrap();
+
+        let rotation = rotate_key(&old, &new, &new_pub_content).unwrap();
+        let new_pub = verify_rotation(&rotation, &old_pub).unwrap();
+        assert_eq!(new_pub.fingerprint, new.fingerprint);
+        assert!(signature_time(&rotation.signature).is_ok());
+
+        // The statement cannot be redirected to another key
+        let (other, _) = generate_key("Other", "other@example.org", &dir.path().join("other")).unwrap();
+        let mut redirected = rotation.clone();
+        redirected.new_key = other.fingerprint.clone();
+        redirected.new_public_key = fs::read_to_string(dir.path().join("other/signing-key.pub")).unwrap();
+        assert!(verify_rotation(&redirected, &old_pub).is_err());
+
+        // Nor be made without the new key
+        assert!(rotate_key(&old, &new, &redirected.new_public_key).is_err());
+    }
+
+    #[test]
+    fn test_signature_envelope() {
+        let dir = tempdir().unwrap();
+        let (first, _) = generate_key("First", "first@example.org", &dir.path().join("first")).unwrap();
+        let (second, _) = generate_key("Second", "second@example.org", &dir.path().join("second")).unwrap();
+
+        let mut envelope = SignatureEnvelope::default();
+        envelope.sign(&first, b"index v1").unwrap();
+        envelope.sign(&second, b"index v1").unwrap();
+        envelope.sign(&second, b"index v1").unwrap();
+        assert!(envelope.covers(b"index v1"));
+        assert_eq!(envelope.signatures.len(), 2);
+        assert!(envelope.is_signed_by(&first.fingerprint));
+
+        // New content starts a new round of signatures
+        envelope.sign(&second, b"index v2").unwrap();
+        assert_eq!(envelope.signatures.len(), 1);
+        assert!(!envelope.is_signed_by(&first.fingerprint));
+
+        let parsed = SignatureEnvelope::parse(&serde_json::to_string(&envelope).unwrap()).unwrap();
+        assert_eq!(parsed.signatures.len(), 1);
+
+        // A bare signature is an envelope of one that covers nothing yet
+        let single = sign_message(&first, b"index v1").unwrap();
+        let parsed = SignatureEnvelope::parse(&serde_json::to_string(&single).unwrap()).unwrap();
+        assert_eq!(parsed.signatures.len(), 1);
+        assert!(!parsed.covers(b"index v1"));
+    }
 }
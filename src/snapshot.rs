@@ -0,0 +1,324 @@
+//! Filesystem snapshots around transactions
+//!
+//! When enabled, `Transaction::execute_with_hooks` takes a snapshot before a
+//! transaction changes anything and another once it has completed. Each
+//! snapshot is recorded in the database under the ID of its transaction, so
+//! a bad upgrade can be undone at the filesystem level with
+//! `rookpkg snapshot rollback`, not only through the transaction journal.
+//!
+//! Backends:
+//! - `btrfs`: read-only subvolume snapshots. Rolling back makes a writable
+//!   copy of the snapshot the default subvolume, effective on the next boot.
+//! - `archive`: a copy of the database plus a tarball of the paths the
+//!   transaction touches. Works on any filesystem, which makes it suitable
+//!   for testing and CI.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::SnapshotConfig;
+use crate::database::Database;
+use crate::error::{Context, Result, RookpkgError};
+
+/// Snapshot backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotBackend {
+    /// Btrfs subvolume snapshots
+    Btrfs,
+    /// Database copy plus a tarball of the changed paths
+    #[default]
+    Archive,
+}
+
+impl fmt::Display for SnapshotBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotBackend::Btrfs => write!(f, "btrfs"),
+            SnapshotBackend::Archive => write!(f, "archive"),
+        }
+    }
+}
+
+impl FromStr for SnapshotBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "btrfs" => Ok(SnapshotBackend::Btrfs),
+            "archive" => Ok(SnapshotBackend::Archive),
+            _ => Err(format!("Unknown snapshot backend: {}", s)),
+        }
+    }
+}
+
+/// Whether a snapshot was taken before or after its transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    Pre,
+    Post,
+}
+
+impl fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotKind::Pre => write!(f, "pre"),
+            SnapshotKind::Post => write!(f, "post"),
+        }
+    }
+}
+
+impl FromStr for SnapshotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pre" => Ok(SnapshotKind::Pre),
+            "post" => Ok(SnapshotKind::Post),
+            _ => Err(format!("Unknown snapshot kind: {}", s)),
+        }
+    }
+}
+
+/// What a rollback achieved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackOutcome {
+    /// The snapshot has been restored in place
+    Applied,
+    /// The snapshot becomes active on the next boot
+    RebootRequired,
+}
+
+/// A way of taking and restoring snapshots
+pub trait SnapshotProvider {
+    /// Take a snapshot named `id`
+    ///
+    /// `paths` are the root-relative paths the transaction may create,
+    /// change or remove; whole-filesystem backends ignore them.
+    fn create(&self, id: &str, db: &Database, paths: &[PathBuf]) -> Result<()>;
+
+    /// Return the system to snapshot `id`
+    ///
+    /// The caller must not hold a connection to the database at `db_path`.
+    fn rollback(&self, id: &str, db_path: &Path) -> Result<RollbackOutcome>;
+}
+
+/// Create the provider for `backend`
+///
+/// The backend is passed separately from the configuration so a snapshot is
+/// always restored with the backend that took it.
+pub fn provider(backend: SnapshotBackend, config: &SnapshotConfig, root: &Path) -> Box<dyn SnapshotProvider> {
+    let dir = root.join(config.dir.strip_prefix("/").unwrap_or(&config.dir));
+    match backend {
+        SnapshotBackend::Btrfs => Box::new(BtrfsSnapshots {
+            subvolume: root.join(config.subvolume.strip_prefix("/").unwrap_or(&config.subvolume)),
+            dir,
+        }),
+        SnapshotBackend::Archive => Box::new(ArchiveSnapshots {
+            root: root.to_path_buf(),
+            dir,
+        }),
+    }
+}
+
+/// Btrfs subvolume snapshots
+struct BtrfsSnapshots {
+    /// Subvolume holding the system
+    subvolume: PathBuf,
+    /// Where snapshots are created
+    dir: PathBuf,
+}
+
+impl BtrfsSnapshots {
+    fn btrfs(args: &[&Path]) -> Result<()> {
+        let output = Command::new("btrfs")
+            .args(args)
+            .output()
+            .context("Failed to run btrfs")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RookpkgError::Snapshot(format!(
+                "btrfs failed: {}",
+                stderr.lines().next().unwrap_or("unknown error")
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotProvider for BtrfsSnapshots {
+    fn create(&self, id: &str, _db: &Database, _paths: &[PathBuf]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let target = self.dir.join(id);
+        Self::btrfs(&[
+            Path::new("subvolume"),
+            Path::new("snapshot"),
+            Path::new("-r"),
+            &self.subvolume,
+            &target,
+        ])
+        .with_context(|| format!("Failed to snapshot {}", self.subvolume.display()))
+    }
+
+    fn rollback(&self, id: &str, _db_path: &Path) -> Result<RollbackOutcome> {
+        let snapshot = self.dir.join(id);
+        if !snapshot.exists() {
+            return Err(RookpkgError::Snapshot(format!("Snapshot {} not found in {}", id, self.dir.display())));
+        }
+
+        // The snapshot itself is read-only; boot into a writable copy of it
+        let writable = self.dir.join(format!("{}-rollback", id));
+        Self::btrfs(&[Path::new("subvolume"), Path::new("snapshot"), &snapshot, &writable])?;
+        Self::btrfs(&[Path::new("subvolume"), Path::new("set-default"), &writable])?;
+
+        Ok(RollbackOutcome::RebootRequired)
+    }
+}
+
+/// Paths the archive snapshot must delete on rollback
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArchiveManifest {
+    /// Paths that did not exist when the snapshot was taken
+    absent: Vec<PathBuf>,
+}
+
+/// Database copy plus a tarball of the paths a transaction touches
+struct ArchiveSnapshots {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl SnapshotProvider for ArchiveSnapshots {
+    fn create(&self, id: &str, db: &Database, paths: &[PathBuf]) -> Result<()> {
+        let snapshot = self.dir.join(id);
+        fs::create_dir_all(&snapshot)?;
+
+        db.backup_to(&snapshot.join("packages.db"))?;
+
+        let mut manifest = ArchiveManifest::default();
+        let tarball = File::create(snapshot.join("files.tar"))?;
+        let mut builder = tar::Builder::new(tarball);
+        builder.follow_symlinks(false);
+        for path in paths {
+            let full_path = self.root.join(path);
+            match fs::symlink_metadata(&full_path) {
+                Ok(meta) if meta.is_dir() => {}
+                Ok(_) => builder
+                    .append_path_with_name(&full_path, path)
+                    .with_context(|| format!("Failed to archive {}", full_path.display()))?,
+                Err(_) => manifest.absent.push(path.clone()),
+            }
+        }
+        builder.finish()?;
+
+        fs::write(snapshot.join("manifest.toml"), toml::to_string(&manifest)?)?;
+        Ok(())
+    }
+
+    fn rollback(&self, id: &str, db_path: &Path) -> Result<RollbackOutcome> {
+        let snapshot = self.dir.join(id);
+        if !snapshot.exists() {
+            return Err(RookpkgError::Snapshot(format!("Snapshot {} not found in {}", id, self.dir.display())));
+        }
+
+        let manifest: ArchiveManifest = toml::from_str(&fs::read_to_string(snapshot.join("manifest.toml"))?)
+            .context("Invalid snapshot manifest")?;
+        for path in &manifest.absent {
+            let full_path = self.root.join(path);
+            if fs::symlink_metadata(&full_path).is_ok_and(|meta| !meta.is_dir()) {
+                fs::remove_file(&full_path)
+                    .with_context(|| format!("Failed to remove {}", full_path.display()))?;
+            }
+        }
+
+        let mut archive = tar::Archive::new(File::open(snapshot.join("files.tar"))?);
+        archive.set_preserve_permissions(true);
+        archive.set_overwrite(true);
+        archive.unpack(&self.root)
+            .with_context(|| format!("Failed to restore files from snapshot {}", id))?;
+
+        // Only the package tables go back; history and snapshot records
+        // made since stay, so the rollback can itself be rolled back
+        Database::open(db_path)?
+            .restore_packages_from(&snapshot.join("packages.db"))
+            .with_context(|| format!("Failed to restore database {}", db_path.display()))?;
+
+        Ok(RollbackOutcome::Applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::{AccountKind, RequestedAccount};
+    use crate::database::SnapshotRecord;
+    use crate::package::{InstallReason, InstalledPackage};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_snapshot_rollback() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let db_path = root.join("var/lib/rookpkg/packages.db");
+        let config = SnapshotConfig::default();
+        let snapshots = provider(SnapshotBackend::Archive, &config, root);
+
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::write(root.join("usr/bin/tool"), "old").unwrap();
+        let db = Database::open(&db_path).unwrap();
+
+        let paths = vec![PathBuf::from("usr/bin/tool"), PathBuf::from("usr/bin/extra")];
+        snapshots.create("tx-pre", &db, &paths).unwrap();
+
+        // The transaction changes one file, adds another and a package
+        fs::write(root.join("usr/bin/tool"), "new").unwrap();
+        fs::write(root.join("usr/bin/extra"), "extra").unwrap();
+        db.add_package(&InstalledPackage {
+            name: "tool".to_string(),
+            version: "2.0".to_string(),
+            release: 1,
+            install_date: 0,
+            size_bytes: 0,
+            checksum: String::new(),
+            spec: String::new(),
+            install_reason: InstallReason::Explicit,
+        })
+        .unwrap();
+        db.add_account("tool", &RequestedAccount {
+            kind: AccountKind::User,
+            name: "tool".to_string(),
+            created: true,
+        })
+        .unwrap();
+        db.record_transaction("tx", "root", "rookpkg install tool", "completed", &[]).unwrap();
+        db.add_snapshot(&SnapshotRecord {
+            snapshot_id: "tx-post".to_string(),
+            transaction_id: "tx".to_string(),
+            kind: SnapshotKind::Post,
+            backend: SnapshotBackend::Archive,
+            created: 0,
+            history_id: None,
+            command_line: None,
+        })
+        .unwrap();
+        drop(db);
+
+        let outcome = snapshots.rollback("tx-pre", &db_path).unwrap();
+        assert_eq!(outcome, RollbackOutcome::Applied);
+        assert_eq!(fs::read_to_string(root.join("usr/bin/tool")).unwrap(), "old");
+        assert!(!root.join("usr/bin/extra").exists());
+        let db = Database::open(&db_path).unwrap();
+        assert!(db.get_package("tool").unwrap().is_none());
+        assert!(db.get_accounts("tool").unwrap().is_empty());
+        // What happened after the snapshot is still on record
+        assert_eq!(db.list_history(None).unwrap().len(), 1);
+        assert!(db.get_snapshot("tx-post").unwrap().is_some());
+
+        assert!(snapshots.rollback("missing", &db_path).is_err());
+    }
+}
@@ -3,10 +3,13 @@
 //! Parses TOML spec files that define packages.
 
 use std::collections::HashMap;
+use std::fmt;
 use std::path::Path;
 
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::{Context, Result, RookpkgError};
 
 /// A complete package specification
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,9 +21,9 @@ pub struct PackageSpec {
     #[serde(default)]
     pub sources: HashMap<String, Source>,
 
-    /// Patches to apply
-    #[serde(default)]
-    pub patches: HashMap<String, Patch>,
+    /// Patches to apply, in order
+    #[serde(default, deserialize_with = "deserialize_patches")]
+    pub patches: Vec<Patch>,
 
     /// Build-time dependencies
     #[serde(default, rename = "build-depends")]
@@ -34,6 +37,26 @@ pub struct PackageSpec {
     #[serde(default, rename = "optional-depends")]
     pub optional_depends: HashMap<String, Vec<String>>,
 
+    /// Virtual names this package provides: name -> provided version ("" = unversioned)
+    #[serde(default)]
+    pub provides: HashMap<String, String>,
+
+    /// Packages that cannot be installed alongside this one: name -> version constraint
+    #[serde(default)]
+    pub conflicts: HashMap<String, String>,
+
+    /// Packages this one supersedes on upgrade: name -> version constraint
+    #[serde(default)]
+    pub replaces: HashMap<String, String>,
+
+    /// System users the package needs: name -> account settings
+    #[serde(default)]
+    pub users: HashMap<String, SystemUser>,
+
+    /// System groups the package needs: name -> group settings
+    #[serde(default)]
+    pub groups: HashMap<String, SystemGroup>,
+
     /// Environment variables for build
     #[serde(default)]
     pub environment: HashMap<String, String>,
@@ -65,6 +88,10 @@ pub struct PackageSpec {
     /// Security information
     #[serde(default)]
     pub security: Security,
+
+    /// The spec file as written, recorded in built packages for rebuilds
+    #[serde(skip)]
+    pub text: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,12 +129,21 @@ pub struct PackageMetadata {
     /// Categories
     #[serde(default)]
     pub categories: Vec<String>,
+
+    /// Add the shared libraries, interpreters and pkg-config modules the
+    /// installed files need and offer to `depends` and `provides`
+    #[serde(default = "default_auto_depends")]
+    pub auto_depends: bool,
 }
 
 fn default_release() -> u32 {
     1
 }
 
+fn default_auto_depends() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Source {
     /// Download URL
@@ -128,17 +164,173 @@ pub struct Source {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Patch {
     /// Patch file path (relative to spec file)
-    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    /// Upstream URL; the patch is downloaded from here when there is no `file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// SHA256 checksum (required for downloaded patches)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 
     /// Strip level for patch -p
     #[serde(default = "default_strip")]
     pub strip: u32,
+
+    /// Only apply when this holds ("arch:aarch64", "feature:nls", "!arch:i686")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<PatchCondition>,
+
+    /// CVE fixed by this patch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cve: Option<String>,
+
+    /// What the patch does
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
 }
 
 fn default_strip() -> u32 {
     1
 }
 
+impl Patch {
+    /// Name for messages: the file name, else the last part of the URL
+    pub fn name(&self) -> &str {
+        match (&self.file, &self.url) {
+            (Some(file), _) => file,
+            (None, Some(url)) => url.rsplit('/').next().unwrap_or(url),
+            (None, None) => "<unnamed patch>",
+        }
+    }
+
+    /// Whether the patch applies when building for `arch` with `features` enabled
+    pub fn applies(&self, arch: &str, features: &[String]) -> bool {
+        self.condition
+            .as_ref()
+            .is_none_or(|condition| condition.holds(arch, features))
+    }
+}
+
+/// Condition under which a patch is applied
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PatchCondition {
+    /// What is tested
+    pub kind: ConditionKind,
+    /// Architecture or feature name
+    pub value: String,
+    /// Apply when the test fails instead
+    pub negated: bool,
+}
+
+/// What a [`PatchCondition`] tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionKind {
+    /// Target architecture (as in `std::env::consts::ARCH`)
+    Arch,
+    /// Build feature enabled in `[build] features` of the config
+    Feature,
+}
+
+impl PatchCondition {
+    /// Evaluate the condition
+    pub fn holds(&self, arch: &str, features: &[String]) -> bool {
+        let matched = match self.kind {
+            ConditionKind::Arch => self.value == arch,
+            ConditionKind::Feature => features.iter().any(|f| f == &self.value),
+        };
+        matched != self.negated
+    }
+}
+
+impl TryFrom<String> for PatchCondition {
+    type Error = String;
+
+    fn try_from(condition: String) -> std::result::Result<Self, String> {
+        let (negated, rest) = match condition.trim().strip_prefix('!') {
+            Some(rest) => (true, rest.trim()),
+            None => (false, condition.trim()),
+        };
+        let (kind, value) = rest
+            .split_once(':')
+            .ok_or_else(|| format!("invalid patch condition '{}' (expected arch:NAME or feature:NAME)", condition))?;
+        let kind = match kind.trim() {
+            "arch" => ConditionKind::Arch,
+            "feature" => ConditionKind::Feature,
+            other => return Err(format!("unknown patch condition '{}' (expected arch or feature)", other)),
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("patch condition '{}' has no value", condition));
+        }
+        Ok(Self { kind, value: value.to_string(), negated })
+    }
+}
+
+impl From<PatchCondition> for String {
+    fn from(condition: PatchCondition) -> Self {
+        condition.to_string()
+    }
+}
+
+impl fmt::Display for PatchCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ConditionKind::Arch => "arch",
+            ConditionKind::Feature => "feature",
+        };
+        write!(f, "{}{}:{}", if self.negated { "!" } else { "" }, kind, self.value)
+    }
+}
+
+/// Order of the keys of an older `[patches]` table: by the number they end
+/// in (patch2 before patch10), then by name
+pub(crate) fn legacy_patch_order(a: &str, b: &str) -> std::cmp::Ordering {
+    let number = |name: &str| {
+        let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        name[name.len() - digits..].parse::<u64>().ok()
+    };
+    number(a).cmp(&number(b)).then_with(|| a.cmp(b))
+}
+
+/// Accept the `[[patches]]` list, or the older `[patches]` table ordered by key
+fn deserialize_patches<'de, D>(deserializer: D) -> std::result::Result<Vec<Patch>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct PatchesVisitor;
+
+    impl<'de> Visitor<'de> for PatchesVisitor {
+        type Value = Vec<Patch>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a list of patches")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Vec<Patch>, A::Error> {
+            let mut patches = Vec::new();
+            while let Some(patch) = seq.next_element()? {
+                patches.push(patch);
+            }
+            Ok(patches)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Vec<Patch>, A::Error> {
+            let mut named = Vec::new();
+            while let Some((name, patch)) = map.next_entry::<String, Patch>()? {
+                named.push((name, patch));
+            }
+            named.sort_by(|a, b| legacy_patch_order(&a.0, &b.0));
+            Ok(named.into_iter().map(|(_, patch)| patch).collect())
+        }
+    }
+
+    deserializer.deserialize_any(PatchesVisitor)
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BuildInstructions {
     /// Preparation phase (unpack, patch)
@@ -193,6 +385,67 @@ pub struct FileConfig {
     /// Group
     #[serde(default)]
     pub group: Option<String>,
+
+    /// File capabilities (e.g., "cap_net_raw+ep")
+    #[serde(default)]
+    pub capabilities: Option<String>,
+}
+
+/// A system user created on install when missing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemUser {
+    /// Preferred user ID (a free system ID is picked if taken or unset)
+    #[serde(default)]
+    pub uid: Option<u32>,
+
+    /// Primary group (defaults to a group named after the user)
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// Supplementary groups
+    #[serde(default)]
+    pub groups: Vec<String>,
+
+    /// GECOS comment
+    #[serde(default)]
+    pub comment: String,
+
+    /// Home directory
+    #[serde(default = "default_home")]
+    pub home: String,
+
+    /// Login shell
+    #[serde(default = "default_shell")]
+    pub shell: String,
+}
+
+impl Default for SystemUser {
+    fn default() -> Self {
+        Self {
+            uid: None,
+            group: None,
+            groups: Vec::new(),
+            comment: String::new(),
+            home: default_home(),
+            shell: default_shell(),
+        }
+    }
+}
+
+fn default_home() -> String {
+    "/".to_string()
+}
+
+fn default_shell() -> String {
+    "/sbin/nologin".to_string()
+}
+
+/// A system group created on install when missing
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemGroup {
+    /// Preferred group ID (a free system ID is picked if taken or unset)
+    #[serde(default)]
+    pub gid: Option<u32>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -280,8 +533,32 @@ impl PackageSpec {
     }
 
     /// Parse a spec file from a string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(content: &str) -> Result<Self> {
-        toml::from_str(content).context("Failed to parse spec file as TOML")
+        let mut spec: Self = toml::from_str(content)
+            .map_err(|e| RookpkgError::InvalidSpec(format!("Failed to parse spec file as TOML: {}", e)))?;
+        spec.text = content.to_string();
+
+        for (i, patch) in spec.patches.iter().enumerate() {
+            match (&patch.file, &patch.url, &patch.sha256) {
+                (None, None, _) => {
+                    return Err(RookpkgError::InvalidSpec(format!(
+                        "Patch {} has neither a file nor a url",
+                        i + 1
+                    )))
+                }
+                (None, Some(url), None) => {
+                    return Err(RookpkgError::InvalidSpec(format!(
+                        "Patch {} is downloaded from {} but has no sha256",
+                        i + 1,
+                        url
+                    )))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(spec)
     }
 
     /// Get the full version string (version-release)
@@ -289,6 +566,23 @@ impl PackageSpec {
         format!("{}-{}", self.package.version, self.package.release)
     }
 
+    /// SOURCE_DATE_EPOCH for reproducible builds: the newest changelog date
+    ///
+    /// Dates are `YYYY-MM-DD` (midnight UTC) or RFC 3339. None when the
+    /// changelog has no usable date.
+    pub fn source_date_epoch(&self) -> Option<i64> {
+        self.changelog
+            .iter()
+            .filter_map(|entry| {
+                let date = entry.date.trim();
+                match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                    Ok(day) => day.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp()),
+                    Err(_) => chrono::DateTime::parse_from_rfc3339(date).ok().map(|t| t.timestamp()),
+                }
+            })
+            .max()
+    }
+
     /// Get all sources as a vec
     pub fn sources_list(&self) -> Vec<(&str, &Source)> {
         self.sources.iter().map(|(k, v)| (k.as_str(), v)).collect()
@@ -341,5 +635,175 @@ include = ["/usr/bin/hello", "/usr/share/man/man1/hello.1"]
         assert_eq!(parsed.package.version, "2.12");
         assert_eq!(parsed.package.release, 1);
         assert_eq!(parsed.depends.get("glibc"), Some(&">= 2.39".to_string()));
+        assert!(parsed.provides.is_empty());
+    }
+
+    #[test]
+    fn test_parse_relations() {
+        let spec = r#"
+[package]
+name = "libressl"
+version = "3.9.2"
+
+[provides]
+libssl = "3.0.0"
+libcrypto = ""
+
+[conflicts]
+openssl = "*"
+
+[replaces]
+libressl-compat = "< 3.0"
+"#;
+
+        let parsed = PackageSpec::from_str(spec).unwrap();
+        assert_eq!(parsed.provides.get("libssl"), Some(&"3.0.0".to_string()));
+        assert_eq!(parsed.provides.get("libcrypto"), Some(&String::new()));
+        assert_eq!(parsed.conflicts.get("openssl"), Some(&"*".to_string()));
+        assert_eq!(parsed.replaces.get("libressl-compat"), Some(&"< 3.0".to_string()));
+    }
+
+    #[test]
+    fn test_parse_patches() {
+        let spec = r#"
+[package]
+name = "openssl"
+version = "3.3.1"
+
+[[patches]]
+file = "openssl-3.3.1-zz-last-by-name.patch"
+
+[[patches]]
+url = "https://github.com/openssl/openssl/commit/abc123.patch"
+sha256 = "def456"
+cve = "CVE-2024-5535"
+strip = 0
+
+[[patches]]
+file = "aarch64-asm.patch"
+condition = "!arch:x86_64"
+"#;
+
+        let parsed = PackageSpec::from_str(spec).unwrap();
+        let names: Vec<_> = parsed.patches.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["openssl-3.3.1-zz-last-by-name.patch", "abc123.patch", "aarch64-asm.patch"]);
+        assert_eq!(parsed.patches[1].cve.as_deref(), Some("CVE-2024-5535"));
+        assert_eq!(parsed.patches[1].strip, 0);
+        assert_eq!(parsed.patches[0].strip, 1);
+
+        let features = vec!["nls".to_string()];
+        assert!(parsed.patches[0].applies("x86_64", &features));
+        assert!(!parsed.patches[2].applies("x86_64", &features));
+        assert!(parsed.patches[2].applies("aarch64", &features));
+        assert_eq!(parsed.patches[2].condition.as_ref().unwrap().to_string(), "!arch:x86_64");
+    }
+
+    #[test]
+    fn test_parse_patches_legacy_table() {
+        let spec = r#"
+[package]
+name = "test"
+version = "1.0"
+
+[patches]
+patch1 = { file = "second.patch" }
+patch0 = { file = "first.patch", strip = 2 }
+"#;
+
+        let parsed = PackageSpec::from_str(spec).unwrap();
+        let names: Vec<_> = parsed.patches.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["first.patch", "second.patch"]);
+        assert_eq!(parsed.patches[0].strip, 2);
+
+        // Keys are ordered by number, not as text
+        let table: String = [10, 2, 0, 11, 1, 9, 3, 8, 4, 7, 5, 6]
+            .iter()
+            .map(|n| format!("patch{} = {{ file = \"{:02}.patch\" }}\n", n, n))
+            .collect();
+        let parsed = PackageSpec::from_str(&format!(
+            "[package]\nname = \"test\"\nversion = \"1.0\"\n\n[patches]\n{}",
+            table
+        ))
+        .unwrap();
+        let names: Vec<_> = parsed.patches.iter().map(|p| p.name()).collect();
+        let expected: Vec<_> = (0..12).map(|n| format!("{:02}.patch", n)).collect();
+        assert_eq!(names, expected);
+
+        assert_eq!(legacy_patch_order("patch2", "patch10"), std::cmp::Ordering::Less);
+        assert_eq!(legacy_patch_order("fix", "patch0"), std::cmp::Ordering::Less);
+        assert_eq!(legacy_patch_order("a1", "b1"), std::cmp::Ordering::Less);
+    }
+
+    #[test]
+    fn test_parse_patches_invalid() {
+        let base = "[package]\nname = \"test\"\nversion = \"1.0\"\n\n[[patches]]\n";
+
+        // A downloaded patch must be pinned
+        let err = PackageSpec::from_str(&format!("{}url = \"https://example.org/fix.patch\"\n", base));
+        assert!(err.unwrap_err().to_string().contains("sha256"));
+
+        assert!(PackageSpec::from_str(&format!("{}strip = 1\n", base)).is_err());
+        let err = PackageSpec::from_str(&format!("{}file = \"a.patch\"\ncondition = \"os:linux\"\n", base));
+        assert!(format!("{:#}", err.unwrap_err()).contains("unknown patch condition"));
+        assert!(PackageSpec::from_str(&format!("{}file = \"a.patch\"\ncondition = \"feature:\"\n", base)).is_err());
+    }
+
+    #[test]
+    fn test_source_date_epoch() {
+        let spec = r#"
+[package]
+name = "hello"
+version = "2.12"
+
+[[changelog]]
+date = "2024-03-01"
+version = "2.12"
+author = "a@example.org"
+changes = ["Update"]
+
+[[changelog]]
+date = "2024-06-15T12:00:00Z"
+version = "2.12"
+author = "a@example.org"
+changes = ["Rebuild"]
+
+[[changelog]]
+date = "sometime"
+version = "2.11"
+author = "a@example.org"
+changes = ["Initial"]
+"#;
+
+        let parsed = PackageSpec::from_str(spec).unwrap();
+        assert_eq!(parsed.source_date_epoch(), Some(1718452800));
+        assert_eq!(parsed.text, spec);
+
+        let parsed = PackageSpec::from_str("[package]\nname = \"x\"\nversion = \"1\"\n").unwrap();
+        assert_eq!(parsed.source_date_epoch(), None);
+    }
+
+    #[test]
+    fn test_parse_accounts() {
+        let spec = r#"
+[package]
+name = "nginx"
+version = "1.26.0"
+
+[users.nginx]
+uid = 60
+groups = ["www"]
+home = "/var/lib/nginx"
+
+[groups.www]
+"#;
+
+        let parsed = PackageSpec::from_str(spec).unwrap();
+        let user = &parsed.users["nginx"];
+        assert_eq!(user.uid, Some(60));
+        assert_eq!(user.group, None);
+        assert_eq!(user.groups, vec!["www".to_string()]);
+        assert_eq!(user.home, "/var/lib/nginx");
+        assert_eq!(user.shell, "/sbin/nologin");
+        assert_eq!(parsed.groups["www"], SystemGroup::default());
     }
 }
@@ -3,21 +3,24 @@
 //! Ensures package installations, removals, and upgrades are atomic.
 //! Uses a journal-based approach to allow rollback on failure.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 #[cfg(unix)]
 use std::os::unix::fs as unix_fs;
 
-use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::archive::{PackageArchiveReader, FileType};
-use crate::config::{HooksConfig, OptionsConfig};
-use crate::database::Database;
+use crate::accounts::{AccountFiles, RequestedAccount, ACCOUNT_FILES};
+use crate::archive::{FileEntry, FileType, PackageArchiveReader, PackageInfo};
+use crate::config::{HooksConfig, OptionsConfig, SnapshotConfig};
+use crate::database::{Database, HistoryAction, HistoryOperation, SnapshotRecord};
 use crate::download::compute_sha256;
+use crate::error::{Context, Result, RookpkgError};
+use crate::fileattrs::{self, IdMap};
 use crate::hooks::{HookContext, HookEvent, HookManager, HookOperation, HookResult};
 use crate::package::{InstalledPackage, InstallReason, PackageFile};
+use crate::snapshot::{self, SnapshotKind, SnapshotProvider};
 
 /// A file conflict detected during pre-installation check
 #[derive(Debug, Clone)]
@@ -113,11 +116,27 @@ impl Operation {
     }
 }
 
+/// Install scripts saved per package under var/lib/rookpkg/scripts
+const SCRIPT_NAMES: [&str; 6] = [
+    "pre_install",
+    "post_install",
+    "pre_remove",
+    "post_remove",
+    "pre_upgrade",
+    "post_upgrade",
+];
+
 /// Journal entry for tracking file operations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum JournalEntry {
     /// A file was created
     FileCreated { path: PathBuf },
+    /// A file was written to a staging path next to its destination,
+    /// waiting to be renamed into place
+    FileStaged { staged: PathBuf, path: PathBuf },
+    /// A file was renamed over an existing one; the old inode is kept
+    /// as a hard link at `backup` until the transaction completes
+    FileReplaced { path: PathBuf, backup: PathBuf },
     /// A file was removed (with backup path)
     FileRemoved { path: PathBuf, backup: PathBuf },
     /// A file was modified (with backup path)
@@ -202,7 +221,7 @@ impl Transaction {
     pub fn resume(root: &Path, tx_id: &str, db: Database) -> Result<Self> {
         let tx_dir = root.join("var/lib/rookpkg/transactions").join(tx_id);
         if !tx_dir.exists() {
-            bail!("Transaction {} not found", tx_id);
+            return Err(RookpkgError::Transaction(format!("Transaction {} not found", tx_id)));
         }
 
         let state_file = tx_dir.join("state.toml");
@@ -386,13 +405,34 @@ impl Transaction {
     /// Execute the transaction
     pub fn execute(&mut self) -> Result<()> {
         if self.state != TransactionState::Pending {
-            bail!("Transaction already executed (state: {:?})", self.state);
+            return Err(RookpkgError::Transaction(format!(
+                "Transaction already executed (state: {:?})",
+                self.state
+            )));
         }
 
+        // Capture versions and install reasons before anything changes
+        let history = self.history_operations()?;
+
         self.state = TransactionState::InProgress;
         self.save_state()?;
 
-        // Execute each operation
+        let outcome = self.run_operations();
+
+        // The history is a record, not part of the transaction; failing to
+        // write it must not undo a change that already happened
+        if let Err(e) = self.record_history(&history) {
+            tracing::warn!("Failed to record transaction history: {}", e);
+        }
+
+        outcome?;
+        self.cleanup()?;
+
+        Ok(())
+    }
+
+    /// Run every operation, rolling back on the first failure
+    fn run_operations(&mut self) -> Result<()> {
         for i in 0..self.operations.len() {
             let op = self.operations[i].clone();
             if let Err(e) = self.execute_operation(&op) {
@@ -401,38 +441,92 @@ impl Transaction {
                     tracing::error!("Rollback failed: {}", rollback_err);
                     self.state = TransactionState::Failed;
                     self.save_state()?;
-                    bail!(
-                        "Transaction failed and rollback failed: {} (rollback: {})",
-                        e,
-                        rollback_err
-                    );
+                    return Err(RookpkgError::Transaction(format!(
+                        "{} (rollback failed: {})",
+                        e, rollback_err
+                    )));
                 }
                 self.state = TransactionState::RolledBack;
                 self.save_state()?;
-                bail!("Transaction rolled back due to: {}", e);
+                return Err(RookpkgError::RolledBack(Box::new(e)));
             }
         }
 
         self.state = TransactionState::Completed;
         self.save_state()?;
-        self.cleanup()?;
+        Ok(())
+    }
+
+    /// Describe the operations for the history, using the current database state
+    fn history_operations(&self) -> Result<Vec<HistoryOperation>> {
+        let mut history = Vec::new();
+        for op in &self.operations {
+            let installed = self.db.get_package(op.package_name())?;
+            let current_reason = installed.as_ref().map(|p| p.install_reason).unwrap_or_default();
+            let entry = match op {
+                Operation::Install { package, version, install_reason, .. } => HistoryOperation {
+                    action: HistoryAction::Install,
+                    package: package.clone(),
+                    old_version: installed.as_ref().map(|p| p.full_version()),
+                    new_version: Some(version.clone()),
+                    install_reason: *install_reason,
+                },
+                Operation::Remove { package } => HistoryOperation {
+                    action: HistoryAction::Remove,
+                    package: package.clone(),
+                    old_version: installed.as_ref().map(|p| p.full_version()),
+                    new_version: None,
+                    install_reason: current_reason,
+                },
+                Operation::Upgrade { package, old_version, new_version, .. } => HistoryOperation {
+                    action: HistoryAction::Upgrade,
+                    package: package.clone(),
+                    old_version: Some(old_version.clone()),
+                    new_version: Some(new_version.clone()),
+                    install_reason: current_reason,
+                },
+            };
+            history.push(entry);
+        }
+        Ok(history)
+    }
+
+    /// Write the finished transaction to the history table
+    fn record_history(&self, operations: &[HistoryOperation]) -> Result<()> {
+        let result = match self.state {
+            TransactionState::Completed => "completed",
+            TransactionState::RolledBack => "rolled_back",
+            TransactionState::Failed => "failed",
+            TransactionState::Pending | TransactionState::InProgress => "interrupted",
+        };
 
+        // Under sudo, record who actually asked for the change
+        let user = std::env::var("SUDO_USER")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let command_line = std::env::args().collect::<Vec<_>>().join(" ");
+
+        self.db
+            .record_transaction(&self.id, &user, &command_line, result, operations)?;
         Ok(())
     }
 
     /// Execute the transaction with system-wide hooks
     ///
     /// This wraps `execute()` with pre-transaction and post-transaction hooks.
-    /// Hooks are run from /etc/rookpkg/hooks.d/ (or as configured).
+    /// Hooks are run from /etc/rookpkg/hooks.d/ (or as configured). If
+    /// snapshots are enabled, they are taken right before and after the
+    /// transaction itself, inside the hooks.
     ///
     /// Returns a tuple of (pre_hook_results, post_hook_results) on success.
     pub fn execute_with_hooks(
         &mut self,
         hooks_config: &HooksConfig,
+        snapshot_config: &SnapshotConfig,
     ) -> Result<(Vec<HookResult>, Vec<HookResult>)> {
         if !hooks_config.enabled {
             // Hooks disabled, just execute normally
-            self.execute()?;
+            self.execute_with_snapshots(snapshot_config)?;
             return Ok((Vec::new(), Vec::new()));
         }
 
@@ -459,7 +553,7 @@ impl Transaction {
         }
 
         // Execute the transaction
-        let tx_result = self.execute();
+        let tx_result = self.execute_with_snapshots(snapshot_config);
 
         // Determine which post-hooks to run based on transaction result
         let (post_event, post_context) = if tx_result.is_ok() {
@@ -494,6 +588,91 @@ impl Transaction {
         Ok((pre_results, post_results))
     }
 
+    /// Execute the transaction between a pre- and a post-transaction snapshot
+    ///
+    /// Failing to take the first snapshot aborts before anything changes;
+    /// failing to take the second only warns, as the transaction is done.
+    fn execute_with_snapshots(&mut self, snapshot_config: &SnapshotConfig) -> Result<()> {
+        if !snapshot_config.enabled {
+            return self.execute();
+        }
+
+        let provider = snapshot::provider(snapshot_config.backend, snapshot_config, &self.root);
+        let paths = self.touched_paths()?;
+
+        self.take_snapshot(provider.as_ref(), snapshot_config, SnapshotKind::Pre, &paths)
+            .context("Failed to take pre-transaction snapshot")?;
+
+        self.execute()?;
+
+        if let Err(e) = self.take_snapshot(provider.as_ref(), snapshot_config, SnapshotKind::Post, &paths) {
+            tracing::warn!("Failed to take post-transaction snapshot: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Take a snapshot and link it to this transaction in the database
+    fn take_snapshot(
+        &self,
+        provider: &dyn SnapshotProvider,
+        snapshot_config: &SnapshotConfig,
+        kind: SnapshotKind,
+        paths: &[PathBuf],
+    ) -> Result<()> {
+        let snapshot_id = format!("{}-{}", self.id, kind);
+        tracing::info!("Taking {} snapshot {}", snapshot_config.backend, snapshot_id);
+        provider.create(&snapshot_id, &self.db, paths)?;
+        self.db.add_snapshot(&SnapshotRecord {
+            snapshot_id,
+            transaction_id: self.id.clone(),
+            kind,
+            backend: snapshot_config.backend,
+            created: chrono::Utc::now().timestamp(),
+            history_id: None,
+            command_line: None,
+        })
+    }
+
+    /// Root-relative paths the transaction may create, change or remove
+    fn touched_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = BTreeSet::new();
+
+        for op in &self.operations {
+            let package = op.package_name();
+
+            if let Operation::Remove { .. } | Operation::Upgrade { .. } = op {
+                for file in self.db.get_files(package)? {
+                    paths.insert(PathBuf::from(file.path.trim_start_matches('/')));
+                }
+            }
+
+            if let Operation::Install { archive_path, .. } | Operation::Upgrade { archive_path, .. } = op {
+                let reader = PackageArchiveReader::open(archive_path)?;
+                for file_entry in reader.read_files()? {
+                    if file_entry.file_type != FileType::Directory {
+                        paths.insert(PathBuf::from(file_entry.path.trim_start_matches('/')));
+                    }
+                }
+
+                let info = reader.read_info()?;
+                if !info.users.is_empty() || !info.groups.is_empty() {
+                    paths.extend(ACCOUNT_FILES.iter().map(PathBuf::from));
+                }
+            }
+
+            // Saved install scripts are replaced along with the package
+            for script in SCRIPT_NAMES {
+                paths.insert(
+                    Path::new("var/lib/rookpkg/scripts")
+                        .join(package)
+                        .join(format!("{}.sh", script)),
+                );
+            }
+        }
+
+        Ok(paths.into_iter().collect())
+    }
+
     /// Build a hook context from current transaction state
     fn build_hook_context(&self, event: HookEvent) -> HookContext {
         let mut context = HookContext::new(event, &self.id, &self.root);
@@ -546,6 +725,9 @@ impl Transaction {
         let files = reader.read_files()?;
         let scripts = reader.read_scripts()?;
 
+        // Accounts come first so scripts and file ownership can use them
+        let accounts = self.create_accounts(&info)?;
+
         // Run pre_install script if present
         if let Some(ref scripts) = scripts {
             if !scripts.pre_install.is_empty() {
@@ -560,89 +742,11 @@ impl Transaction {
         // No conflict check needed - the database schema allows multiple packages
         // to own the same path (UNIQUE constraint is on (package_id, path) not just path).
 
-        // Create backup directory for this package
-        let backup_dir = self.tx_dir.join("backup").join(&info.name);
-        fs::create_dir_all(&backup_dir)?;
-
         // Extract files
         let extract_dir = self.tx_dir.join("extract").join(&info.name);
         reader.extract_data(&extract_dir)?;
 
-        // Install files to root
-        for file_entry in &files {
-            // Skip files matching no_extract patterns
-            if self.options.should_skip_extract(&file_entry.path) {
-                tracing::debug!("Skipping extraction of {}: matches no_extract pattern", file_entry.path);
-                continue;
-            }
-
-            let src = extract_dir.join(file_entry.path.trim_start_matches('/'));
-            let dest = self.root.join(file_entry.path.trim_start_matches('/'));
-
-            // Backup existing file if it exists (only for regular files, not directories)
-            if dest.exists() && dest.is_file() {
-                let backup = backup_dir.join(file_entry.path.trim_start_matches('/'));
-                if let Some(parent) = backup.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::copy(&dest, &backup)?;
-                self.journal.push(JournalEntry::FileModified {
-                    path: dest.clone(),
-                    backup,
-                });
-            }
-
-            // Create parent directories
-            if let Some(parent) = dest.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)?;
-                    self.journal.push(JournalEntry::DirCreated {
-                        path: parent.to_path_buf(),
-                    });
-                }
-            }
-
-            // Copy file or symlink
-            if src.is_dir() {
-                if !dest.exists() {
-                    fs::create_dir_all(&dest)?;
-                    self.journal.push(JournalEntry::DirCreated { path: dest });
-                }
-            } else if src.exists() || src.symlink_metadata().is_ok() {
-                // Check if source is a symlink (use symlink_metadata to not follow symlinks)
-                let metadata = fs::symlink_metadata(&src);
-                if let Ok(meta) = metadata {
-                    if meta.file_type().is_symlink() {
-                        // Handle symlink: read target and recreate symlink
-                        let target = fs::read_link(&src).with_context(|| {
-                            format!("Failed to read symlink {}", src.display())
-                        })?;
-                        // Remove existing file/symlink at destination if present
-                        if dest.exists() || dest.symlink_metadata().is_ok() {
-                            fs::remove_file(&dest).ok();
-                        }
-                        #[cfg(unix)]
-                        unix_fs::symlink(&target, &dest).with_context(|| {
-                            format!("Failed to create symlink {} -> {}", dest.display(), target.display())
-                        })?;
-                        #[cfg(not(unix))]
-                        {
-                            // On non-unix, fall back to copy (shouldn't happen for this project)
-                            fs::copy(&src, &dest).with_context(|| {
-                                format!("Failed to copy {} to {}", src.display(), dest.display())
-                            })?;
-                        }
-                        self.journal.push(JournalEntry::FileCreated { path: dest });
-                    } else {
-                        // Regular file: use fs::copy
-                        fs::copy(&src, &dest).with_context(|| {
-                            format!("Failed to copy {} to {}", src.display(), dest.display())
-                        })?;
-                        self.journal.push(JournalEntry::FileCreated { path: dest });
-                    }
-                }
-            }
-        }
+        self.install_files(&files, &extract_dir, false)?;
 
         // Compute package checksum
         let checksum = compute_sha256(archive_path)
@@ -668,6 +772,9 @@ impl Transaction {
         self.journal.push(JournalEntry::DbPackageAdded {
             package: info.name.clone(),
         });
+        for account in &accounts {
+            self.db.add_account(&info.name, account)?;
+        }
 
         // Add files to database (skip directories and no_extract files)
         for file_entry in &files {
@@ -678,16 +785,7 @@ impl Transaction {
             if self.options.should_skip_extract(&file_entry.path) {
                 continue;
             }
-            let pkg_file = PackageFile {
-                path: file_entry.path.clone(),
-                mode: file_entry.mode,
-                owner: "root".to_string(),
-                group: "root".to_string(),
-                size_bytes: file_entry.size,
-                checksum: file_entry.sha256.clone(),
-                is_config: file_entry.is_config,
-            };
-            self.db.add_file(pkg_id, &pkg_file)?;
+            self.db.add_file(pkg_id, &PackageFile::from(file_entry))?;
         }
 
         // Add dependencies to database
@@ -700,6 +798,9 @@ impl Transaction {
             };
             self.db.add_dependency(&dep)?;
         }
+        for (name, version) in &info.provides {
+            self.db.add_provide(pkg_id, name, version)?;
+        }
 
         // Save scripts for later use (removal, upgrade)
         if let Some(ref scripts) = scripts {
@@ -718,6 +819,157 @@ impl Transaction {
         Ok(())
     }
 
+    /// Create the system users and groups a package declares
+    ///
+    /// The account files are backed up before they are rewritten, so a
+    /// rollback also removes the accounts this transaction added.
+    fn create_accounts(&mut self, info: &PackageInfo) -> Result<Vec<RequestedAccount>> {
+        if info.users.is_empty() && info.groups.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut accounts = AccountFiles::load(&self.root)?;
+        let requested = accounts
+            .ensure(&info.users, &info.groups)
+            .with_context(|| format!("Failed to create accounts for {}", info.name))?;
+        if !accounts.is_changed() {
+            return Ok(requested);
+        }
+
+        let backup_dir = self.tx_dir.join("backup").join(&info.name);
+        for path in accounts.changed_paths() {
+            if path.exists() {
+                let backup = backup_dir.join(path.strip_prefix(&self.root).unwrap_or(&path));
+                if let Some(parent) = backup.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&path, &backup)?;
+                self.journal.push(JournalEntry::FileModified { path, backup });
+            } else {
+                self.journal.push(JournalEntry::FileCreated { path });
+            }
+        }
+        self.save_journal()?;
+        accounts.save()?;
+
+        for account in requested.iter().filter(|a| a.created) {
+            tracing::info!("Created {} {} for {}", account.kind, account.name, info.name);
+        }
+        Ok(requested)
+    }
+
+    /// Place extracted files into the root
+    ///
+    /// Every file is first staged as a hidden sibling of its destination and
+    /// fsynced, then committed with `rename(2)`, so a destination always holds
+    /// either the old or the complete new file. A replaced file keeps its
+    /// inode as a hard link until the transaction completes, which makes
+    /// rollback a matter of renames and unlinks rather than copies.
+    fn install_files(&mut self, files: &[FileEntry], extract_dir: &Path, upgrading: bool) -> Result<()> {
+        // Owner and group names are resolved against the target root
+        let ids = IdMap::load(&self.root)?;
+        let mut staged = Vec::new();
+
+        for file_entry in files {
+            // Skip files matching no_extract patterns
+            if self.options.should_skip_extract(&file_entry.path) {
+                tracing::debug!("Skipping extraction of {}: matches no_extract pattern", file_entry.path);
+                continue;
+            }
+
+            // Check if file should be preserved (no_upgrade)
+            let dest = self.root.join(file_entry.path.trim_start_matches('/'));
+            if upgrading && self.options.should_preserve(&file_entry.path) && dest.exists() {
+                tracing::debug!("Preserving {}: matches no_upgrade pattern", file_entry.path);
+                continue;
+            }
+
+            let src = extract_dir.join(file_entry.path.trim_start_matches('/'));
+
+            // Create parent directories
+            if let Some(parent) = dest.parent() {
+                if !parent.exists() {
+                    fs::create_dir_all(parent)?;
+                    self.journal.push(JournalEntry::DirCreated {
+                        path: parent.to_path_buf(),
+                    });
+                }
+            }
+
+            if src.is_dir() {
+                if !dest.exists() {
+                    fs::create_dir_all(&dest)?;
+                    fileattrs::apply(&dest, file_entry, &ids)?;
+                    self.journal.push(JournalEntry::DirCreated { path: dest });
+                }
+            } else if src.symlink_metadata().is_ok() {
+                let staged_path = self.sibling_path(&dest, "new");
+                self.journal.push(JournalEntry::FileStaged {
+                    staged: staged_path.clone(),
+                    path: dest.clone(),
+                });
+                staged.push((file_entry, src, staged_path, dest));
+            }
+        }
+
+        // Record the staged paths before writing them, so an interrupted
+        // transaction knows what to clean up
+        self.save_journal()?;
+
+        // Ownership, mode, xattrs and mtime are set before the rename, so
+        // the file never appears with the wrong privileges
+        for (file_entry, src, staged_path, _) in &staged {
+            stage_file(src, staged_path, file_entry, &ids)?;
+        }
+
+        let mut dirs: HashSet<PathBuf> = HashSet::new();
+        for (_, _, staged_path, dest) in staged {
+            let replaced = fs::symlink_metadata(&dest).is_ok_and(|meta| !meta.is_dir());
+            if replaced {
+                let backup = self.sibling_path(&dest, "old");
+                fs::hard_link(&dest, &backup).with_context(|| {
+                    format!("Failed to keep {} for rollback", dest.display())
+                })?;
+                self.journal.push(JournalEntry::FileReplaced {
+                    path: dest.clone(),
+                    backup,
+                });
+            }
+
+            fs::rename(&staged_path, &dest).with_context(|| {
+                format!("Failed to move {} into place", dest.display())
+            })?;
+            if !replaced {
+                self.journal.push(JournalEntry::FileCreated { path: dest.clone() });
+            }
+
+            if let Some(parent) = dest.parent() {
+                dirs.insert(parent.to_path_buf());
+            }
+        }
+
+        // Make the renames themselves durable
+        for dir in dirs {
+            if let Ok(handle) = fs::File::open(&dir) {
+                handle.sync_all().ok();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hidden path next to `dest`, unique within this transaction
+    ///
+    /// Staged and replaced files live in the destination directory so that
+    /// renaming them never crosses a filesystem boundary.
+    fn sibling_path(&self, dest: &Path, kind: &str) -> PathBuf {
+        let name = dest
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        dest.with_file_name(format!(".{}.rookpkg-{}-{}.{}", name, kind, self.id, self.journal.len()))
+    }
+
     /// Save package scripts to persistent storage for removal/upgrade
     fn save_package_scripts(&self, package: &str, scripts: &crate::archive::InstallScripts) -> Result<()> {
         let scripts_dir = self.root.join("var/lib/rookpkg/scripts").join(package);
@@ -783,15 +1035,13 @@ impl Transaction {
             }
         }
 
-        // Get the new package's scripts before removal
+        // Get the new package's scripts before the old ones are replaced
         let reader = PackageArchiveReader::open(archive_path)?;
         let new_scripts = reader.read_scripts()?;
 
-        // Remove old package (but don't run pre_remove/post_remove - we run upgrade scripts instead)
-        self.do_remove_for_upgrade(package)?;
-
-        // Install new package (but don't run pre_install/post_install - we run upgrade scripts instead)
-        self.do_install_for_upgrade(archive_path)?;
+        // Install the new package over the old one (but don't run the
+        // install or remove scripts - we run upgrade scripts instead)
+        self.do_install_for_upgrade(package, archive_path)?;
 
         // Run post_upgrade script if present (from NEW package)
         if let Some(ref scripts) = new_scripts {
@@ -804,14 +1054,17 @@ impl Transaction {
         Ok(())
     }
 
-    /// Remove package for upgrade (skips pre_remove/post_remove scripts)
-    fn do_remove_for_upgrade(&mut self, package: &str) -> Result<()> {
+    /// Remove the old package of an upgrade (skips pre_remove/post_remove
+    /// scripts)
+    ///
+    /// Only files the new package no longer ships are removed; the others
+    /// have already been replaced in place.
+    fn do_remove_for_upgrade(&mut self, package: &str, kept: &HashSet<&str>) -> Result<()> {
         // Get package info from database
-        let pkg = self.db.get_package(package)?;
-        if pkg.is_none() {
-            bail!("Package {} is not installed", package);
-        }
-        let pkg = pkg.unwrap();
+        let pkg = self
+            .db
+            .get_package(package)?
+            .ok_or_else(|| RookpkgError::NotInstalled(package.to_string()))?;
 
         // Create backup
         let backup_dir = self.tx_dir.join("backup").join(package);
@@ -829,7 +1082,11 @@ impl Transaction {
         let mut dirs_to_check: HashSet<PathBuf> = HashSet::new();
 
         // Remove files (in reverse order to handle nested paths)
-        let mut file_paths: Vec<_> = files.iter().map(|f| f.path.clone()).collect();
+        let mut file_paths: Vec<_> = files
+            .iter()
+            .map(|f| f.path.clone())
+            .filter(|path| !kept.contains(path.as_str()))
+            .collect();
         file_paths.sort();
         file_paths.reverse();
 
@@ -837,15 +1094,12 @@ impl Transaction {
             let full_path = self.root.join(path.trim_start_matches('/'));
 
             if full_path.is_file() {
-                // Backup the file
+                // Move the file into the backup area
                 let backup = backup_dir.join(path.trim_start_matches('/'));
                 if let Some(parent) = backup.parent() {
                     fs::create_dir_all(parent)?;
                 }
-                fs::copy(&full_path, &backup)?;
-
-                // Remove the file
-                fs::remove_file(&full_path)?;
+                set_aside(&full_path, &backup)?;
                 self.journal.push(JournalEntry::FileRemoved {
                     path: full_path.clone(),
                     backup,
@@ -889,105 +1143,38 @@ impl Transaction {
     }
 
     /// Install package for upgrade (skips pre_install/post_install scripts)
-    fn do_install_for_upgrade(&mut self, archive_path: &Path) -> Result<()> {
+    ///
+    /// The new files are staged and renamed over the old ones, so commands
+    /// and libraries the package ships never go missing; only then are the
+    /// old files the new package dropped removed.
+    fn do_install_for_upgrade(&mut self, package: &str, archive_path: &Path) -> Result<()> {
         let reader = PackageArchiveReader::open(archive_path)?;
         let info = reader.read_info()?;
         let files = reader.read_files()?;
         let scripts = reader.read_scripts()?;
 
+        // Upgrades keep the original install reason
+        let old_reason = self
+            .db
+            .get_package(package)?
+            .ok_or_else(|| RookpkgError::NotInstalled(package.to_string()))?
+            .install_reason;
+
         // RPM-style shared file ownership:
         // Multiple packages can own the same file. No conflict check needed for upgrades.
         // The database schema allows multiple packages to own the same path.
 
-        // Create backup directory for this package
-        let backup_dir = self.tx_dir.join("backup").join(&info.name);
-        fs::create_dir_all(&backup_dir)?;
-
         // Extract files
+        let accounts = self.create_accounts(&info)?;
+
         let extract_dir = self.tx_dir.join("extract").join(&info.name);
         reader.extract_data(&extract_dir)?;
 
-        // Install files to root
-        for file_entry in &files {
-            // Skip files matching no_extract patterns
-            if self.options.should_skip_extract(&file_entry.path) {
-                tracing::debug!("Skipping extraction of {}: matches no_extract pattern", file_entry.path);
-                continue;
-            }
-
-            // Check if file should be preserved (no_upgrade)
-            let dest = self.root.join(file_entry.path.trim_start_matches('/'));
-            if self.options.should_preserve(&file_entry.path) && dest.exists() {
-                tracing::debug!("Preserving {}: matches no_upgrade pattern", file_entry.path);
-                continue;
-            }
+        self.install_files(&files, &extract_dir, true)?;
 
-            let src = extract_dir.join(file_entry.path.trim_start_matches('/'));
-
-            // Backup existing file if it exists (only for regular files, not directories)
-            if dest.exists() && dest.is_file() {
-                let backup = backup_dir.join(file_entry.path.trim_start_matches('/'));
-                if let Some(parent) = backup.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::copy(&dest, &backup)?;
-                self.journal.push(JournalEntry::FileModified {
-                    path: dest.clone(),
-                    backup,
-                });
-            }
-
-            // Create parent directories
-            if let Some(parent) = dest.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)?;
-                    self.journal.push(JournalEntry::DirCreated {
-                        path: parent.to_path_buf(),
-                    });
-                }
-            }
-
-            // Copy file or symlink
-            if src.is_dir() {
-                if !dest.exists() {
-                    fs::create_dir_all(&dest)?;
-                    self.journal.push(JournalEntry::DirCreated { path: dest });
-                }
-            } else if src.exists() || src.symlink_metadata().is_ok() {
-                // Check if source is a symlink (use symlink_metadata to not follow symlinks)
-                let metadata = fs::symlink_metadata(&src);
-                if let Ok(meta) = metadata {
-                    if meta.file_type().is_symlink() {
-                        // Handle symlink: read target and recreate symlink
-                        let target = fs::read_link(&src).with_context(|| {
-                            format!("Failed to read symlink {}", src.display())
-                        })?;
-                        // Remove existing file/symlink at destination if present
-                        if dest.exists() || dest.symlink_metadata().is_ok() {
-                            fs::remove_file(&dest).ok();
-                        }
-                        #[cfg(unix)]
-                        unix_fs::symlink(&target, &dest).with_context(|| {
-                            format!("Failed to create symlink {} -> {}", dest.display(), target.display())
-                        })?;
-                        #[cfg(not(unix))]
-                        {
-                            // On non-unix, fall back to copy (shouldn't happen for this project)
-                            fs::copy(&src, &dest).with_context(|| {
-                                format!("Failed to copy {} to {}", src.display(), dest.display())
-                            })?;
-                        }
-                        self.journal.push(JournalEntry::FileCreated { path: dest });
-                    } else {
-                        // Regular file: use fs::copy
-                        fs::copy(&src, &dest).with_context(|| {
-                            format!("Failed to copy {} to {}", src.display(), dest.display())
-                        })?;
-                        self.journal.push(JournalEntry::FileCreated { path: dest });
-                    }
-                }
-            }
-        }
+        // Remove what is left of the old package
+        let kept: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        self.do_remove_for_upgrade(package, &kept)?;
 
         // Compute package checksum
         let checksum = compute_sha256(archive_path)
@@ -996,11 +1183,7 @@ impl Transaction {
                 String::new()
             });
 
-        // Add to database (upgrades keep the original install reason)
-        // Get the old install reason if upgrading
-        let old_reason = self.db.get_package(&info.name)?
-            .map(|p| p.install_reason)
-            .unwrap_or(InstallReason::Explicit);
+        // Add to database
         let pkg = InstalledPackage {
             name: info.name.clone(),
             version: info.version.clone(),
@@ -1015,6 +1198,9 @@ impl Transaction {
         self.journal.push(JournalEntry::DbPackageAdded {
             package: info.name.clone(),
         });
+        for account in &accounts {
+            self.db.add_account(&info.name, account)?;
+        }
 
         // Add files to database (skip directories and no_extract files)
         for file_entry in &files {
@@ -1025,16 +1211,7 @@ impl Transaction {
             if self.options.should_skip_extract(&file_entry.path) {
                 continue;
             }
-            let pkg_file = PackageFile {
-                path: file_entry.path.clone(),
-                mode: file_entry.mode,
-                owner: "root".to_string(),
-                group: "root".to_string(),
-                size_bytes: file_entry.size,
-                checksum: file_entry.sha256.clone(),
-                is_config: file_entry.is_config,
-            };
-            self.db.add_file(pkg_id, &pkg_file)?;
+            self.db.add_file(pkg_id, &PackageFile::from(file_entry))?;
         }
 
         // Add dependencies to database
@@ -1047,6 +1224,9 @@ impl Transaction {
             };
             self.db.add_dependency(&dep)?;
         }
+        for (name, version) in &info.provides {
+            self.db.add_provide(pkg_id, name, version)?;
+        }
 
         // Save scripts for later use (removal, upgrade)
         if let Some(ref scripts) = scripts {
@@ -1060,11 +1240,10 @@ impl Transaction {
     /// Perform package removal
     fn do_remove(&mut self, package: &str) -> Result<()> {
         // Get package info from database
-        let pkg = self.db.get_package(package)?;
-        if pkg.is_none() {
-            bail!("Package {} is not installed", package);
-        }
-        let pkg = pkg.unwrap();
+        let pkg = self
+            .db
+            .get_package(package)?
+            .ok_or_else(|| RookpkgError::NotInstalled(package.to_string()))?;
 
         // Run pre_remove script if present
         if let Some(script) = self.load_package_script(package, "pre_remove") {
@@ -1098,15 +1277,12 @@ impl Transaction {
             let full_path = self.root.join(path.trim_start_matches('/'));
 
             if full_path.is_file() {
-                // Backup the file
+                // Move the file into the backup area
                 let backup = backup_dir.join(path.trim_start_matches('/'));
                 if let Some(parent) = backup.parent() {
                     fs::create_dir_all(parent)?;
                 }
-                fs::copy(&full_path, &backup)?;
-
-                // Remove the file
-                fs::remove_file(&full_path)?;
+                set_aside(&full_path, &backup)?;
                 self.journal.push(JournalEntry::FileRemoved {
                     path: full_path.clone(),
                     backup,
@@ -1169,12 +1345,23 @@ impl Transaction {
                         fs::remove_file(path).ok();
                     }
                 }
+                JournalEntry::FileStaged { staged, .. } => {
+                    // Only still present if it was never renamed into place
+                    if staged.symlink_metadata().is_ok() {
+                        fs::remove_file(staged).ok();
+                    }
+                }
+                JournalEntry::FileReplaced { path, backup } => {
+                    if backup.symlink_metadata().is_ok() {
+                        fs::rename(backup, path).ok();
+                    }
+                }
                 JournalEntry::FileRemoved { path, backup } => {
                     if backup.exists() {
                         if let Some(parent) = path.parent() {
                             fs::create_dir_all(parent).ok();
                         }
-                        fs::copy(backup, path).ok();
+                        restore_aside(backup, path).ok();
                     }
                 }
                 JournalEntry::FileModified { path, backup } => {
@@ -1235,6 +1422,12 @@ impl Transaction {
 
     /// Clean up transaction files after success
     fn cleanup(&self) -> Result<()> {
+        // Replaced files were kept next to their destination, outside tx_dir
+        for entry in &self.journal {
+            if let JournalEntry::FileReplaced { backup, .. } = entry {
+                fs::remove_file(backup).ok();
+            }
+        }
         fs::remove_dir_all(&self.tx_dir).ok();
         Ok(())
     }
@@ -1290,12 +1483,11 @@ impl Transaction {
             tracing::error!("{} script failed for {}", script_name, package);
             tracing::error!("stdout: {}", stdout);
             tracing::error!("stderr: {}", stderr);
-            bail!(
-                "{} script failed for {}: {}",
-                script_name,
-                package,
-                stderr.lines().next().unwrap_or("unknown error")
-            );
+            return Err(RookpkgError::ScriptFailed {
+                script: script_name.to_string(),
+                package: package.to_string(),
+                message: stderr.lines().next().unwrap_or("unknown error").to_string(),
+            });
         }
 
         tracing::info!("{} script completed successfully for {}", script_name, package);
@@ -1339,18 +1531,81 @@ impl Transaction {
     }
 }
 
+/// Move an extracted file (or symlink) to its staging path, give it its
+/// recorded attributes and flush it to disk
+///
+/// The extract directory lives under the root, so this is normally a
+/// rename and the payload is written only once; it is copied only when the
+/// destination is on another filesystem.
+fn stage_file(src: &Path, staged: &Path, file_entry: &FileEntry, ids: &IdMap) -> Result<()> {
+    // A leftover from an interrupted attempt would make symlink() fail
+    if staged.symlink_metadata().is_ok() {
+        fs::remove_file(staged)?;
+    }
+
+    let meta = fs::symlink_metadata(src)?;
+    if fs::rename(src, staged).is_err() {
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(src).with_context(|| {
+                format!("Failed to read symlink {}", src.display())
+            })?;
+            #[cfg(unix)]
+            unix_fs::symlink(&target, staged).with_context(|| {
+                format!("Failed to create symlink {} -> {}", staged.display(), target.display())
+            })?;
+            #[cfg(not(unix))]
+            {
+                // On non-unix, fall back to copy (shouldn't happen for this project)
+                fs::copy(src, staged).with_context(|| {
+                    format!("Failed to copy {} to {}", src.display(), staged.display())
+                })?;
+            }
+        } else {
+            fs::copy(src, staged).with_context(|| {
+                format!("Failed to copy {} to {}", src.display(), staged.display())
+            })?;
+        }
+    }
+
+    fileattrs::apply(staged, file_entry, ids)?;
+    if !meta.file_type().is_symlink() {
+        fs::File::open(staged)?
+            .sync_all()
+            .with_context(|| format!("Failed to sync {}", staged.display()))?;
+    }
+    Ok(())
+}
+
+/// Move a file being removed into the transaction's backup area
+///
+/// A rename keeps the original inode; copying is only needed when the
+/// backup area is on another filesystem.
+fn set_aside(path: &Path, backup: &Path) -> Result<()> {
+    if fs::rename(path, backup).is_err() {
+        fs::copy(path, backup)?;
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Put a file set aside by `set_aside` back in place
+fn restore_aside(backup: &Path, path: &Path) -> Result<()> {
+    if fs::rename(backup, path).is_err() {
+        fs::copy(backup, path)?;
+    }
+    Ok(())
+}
+
 /// Transaction builder for convenient transaction creation
 ///
 /// Note: For pre-flight conflict checking, use `Transaction` directly
 /// and call `check_conflicts()` before `execute()`.
-#[allow(dead_code)]
 pub struct TransactionBuilder {
     root: PathBuf,
     operations: Vec<Operation>,
     options: Option<OptionsConfig>,
 }
 
-#[allow(dead_code)]
 impl TransactionBuilder {
     /// Create a new transaction builder
     pub fn new(root: &Path) -> Self {
@@ -1436,6 +1691,7 @@ impl TransactionBuilder {
         self,
         db: Database,
         hooks_config: &HooksConfig,
+        snapshot_config: &SnapshotConfig,
     ) -> Result<(Vec<HookResult>, Vec<HookResult>)> {
         let mut tx = match self.options {
             Some(opts) => Transaction::with_options(&self.root, db, opts)?,
@@ -1464,7 +1720,7 @@ impl TransactionBuilder {
                 }
             }
         }
-        tx.execute_with_hooks(hooks_config)
+        tx.execute_with_hooks(hooks_config, snapshot_config)
     }
 }
 
@@ -1524,4 +1780,317 @@ mod tests {
         assert_eq!(builder.operations[2].package_name(), "baz");
         assert_eq!(builder.operations[3].package_name(), "qux");
     }
+
+    #[test]
+    fn test_execute_records_history() {
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+
+        let db = Database::open_in_memory().unwrap();
+        db.add_package(&InstalledPackage {
+            name: "foo".to_string(),
+            version: "1.0".to_string(),
+            release: 2,
+            install_date: 0,
+            size_bytes: 0,
+            checksum: String::new(),
+            spec: String::new(),
+            install_reason: InstallReason::Dependency,
+        })
+        .unwrap();
+
+        let mut tx = Transaction::new(root, db).unwrap();
+        tx.remove("foo");
+        tx.execute().unwrap();
+
+        // A failing transaction is recorded too, with its outcome
+        let mut failed = Transaction::new(root, tx.db).unwrap();
+        failed.remove("missing");
+        assert!(failed.execute().is_err());
+
+        let history = failed.db.list_history(None).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].result, "rolled_back");
+        assert_eq!(history[1].result, "completed");
+
+        let op = &history[1].operations[0];
+        assert_eq!(op.action, HistoryAction::Remove);
+        assert_eq!(op.package, "foo");
+        assert_eq!(op.old_version.as_deref(), Some("1.0-2"));
+        assert_eq!(op.new_version, None);
+        assert_eq!(op.install_reason, InstallReason::Dependency);
+    }
+
+    fn regular_file(path: &str) -> FileEntry {
+        FileEntry {
+            path: path.to_string(),
+            size: 0,
+            sha256: String::new(),
+            mode: 0o644,
+            is_config: false,
+            file_type: FileType::Regular,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            mtime: 0,
+            xattrs: Default::default(),
+        }
+    }
+
+    /// Hidden staging or backup files left in a directory
+    fn leftovers(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".rookpkg-"))
+            .collect()
+    }
+
+    #[test]
+    fn test_install_files_replaces_atomically() {
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let extract = root.join("extract");
+        let populate = || {
+            fs::create_dir_all(extract.join("usr/bin")).unwrap();
+            fs::write(extract.join("usr/bin/tool"), "new tool").unwrap();
+            fs::write(extract.join("usr/bin/helper"), "helper").unwrap();
+        };
+        populate();
+        let extracted_inode = fs::metadata(extract.join("usr/bin/tool")).unwrap().ino();
+
+        let bin = root.join("usr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("tool"), "old tool").unwrap();
+        let old_inode = fs::metadata(bin.join("tool")).unwrap().ino();
+
+        let files = [regular_file("/usr/bin/tool"), regular_file("/usr/bin/helper")];
+
+        // Rolled back: the replaced inode is back and nothing is left over
+        let mut tx = Transaction::new(root, Database::open_in_memory().unwrap()).unwrap();
+        tx.install_files(&files, &extract, false).unwrap();
+        assert_eq!(fs::read_to_string(bin.join("tool")).unwrap(), "new tool");
+        // Moved from the extract directory, not copied
+        assert_eq!(fs::metadata(bin.join("tool")).unwrap().ino(), extracted_inode);
+        assert!(!extract.join("usr/bin/tool").exists());
+        assert!(tx.journal.iter().any(|e| matches!(e, JournalEntry::FileStaged { .. })));
+        tx.rollback().unwrap();
+        assert_eq!(fs::read_to_string(bin.join("tool")).unwrap(), "old tool");
+        assert_eq!(fs::metadata(bin.join("tool")).unwrap().ino(), old_inode);
+        assert!(!bin.join("helper").exists());
+        assert!(leftovers(&bin).is_empty());
+
+        // Completed: the kept inode is released by cleanup
+        populate();
+        let mut tx = Transaction::new(root, tx.db).unwrap();
+        tx.install_files(&files, &extract, false).unwrap();
+        assert_eq!(leftovers(&bin).len(), 1);
+        tx.cleanup().unwrap();
+        assert!(leftovers(&bin).is_empty());
+        assert_eq!(fs::read_to_string(bin.join("helper")).unwrap(), "helper");
+    }
+
+    #[test]
+    fn test_install_files_unlinks_staged_on_failure() {
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let extract = root.join("extract");
+        fs::create_dir_all(extract.join("etc")).unwrap();
+        fs::write(extract.join("etc/a.conf"), "a").unwrap();
+        fs::write(extract.join("etc/b.conf"), "b").unwrap();
+
+        // A non-empty directory where a file should go makes the commit fail
+        let etc = root.join("etc");
+        fs::create_dir_all(etc.join("b.conf/occupied")).unwrap();
+
+        let files = [regular_file("/etc/a.conf"), regular_file("/etc/b.conf")];
+        let mut tx = Transaction::new(root, Database::open_in_memory().unwrap()).unwrap();
+        assert!(tx.install_files(&files, &extract, false).is_err());
+        tx.rollback().unwrap();
+
+        assert!(!etc.join("a.conf").exists());
+        assert!(etc.join("b.conf/occupied").is_dir());
+        assert!(leftovers(&etc).is_empty());
+    }
+
+    #[test]
+    fn test_snapshots_linked_to_transaction() {
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::write(root.join("usr/bin/foo"), "foo").unwrap();
+
+        let db = Database::open_in_memory().unwrap();
+        let pkg_id = db
+            .add_package(&InstalledPackage {
+                name: "foo".to_string(),
+                version: "1.0".to_string(),
+                release: 1,
+                install_date: 0,
+                size_bytes: 0,
+                checksum: String::new(),
+                spec: String::new(),
+                install_reason: InstallReason::Explicit,
+            })
+            .unwrap();
+        db.add_file(pkg_id, &PackageFile {
+            path: "/usr/bin/foo".to_string(),
+            mode: 0o755,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            size_bytes: 3,
+            checksum: String::new(),
+            is_config: false,
+            mtime: 0,
+            xattrs: Default::default(),
+        })
+        .unwrap();
+
+        let hooks = HooksConfig {
+            enabled: false,
+            ..HooksConfig::default()
+        };
+        let snapshots = SnapshotConfig {
+            enabled: true,
+            ..SnapshotConfig::default()
+        };
+
+        let mut tx = Transaction::new(root, db).unwrap();
+        tx.remove("foo");
+        tx.execute_with_hooks(&hooks, &snapshots).unwrap();
+        assert!(!root.join("usr/bin/foo").exists());
+
+        let records = tx.db.list_snapshots().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, SnapshotKind::Post);
+        assert_eq!(records[1].kind, SnapshotKind::Pre);
+        let history = tx.db.list_history(None).unwrap();
+        for record in &records {
+            assert_eq!(record.transaction_id, tx.id());
+            assert_eq!(record.history_id, Some(history[0].id));
+        }
+
+        // The pre-transaction snapshot holds the removed file
+        let tarball = root
+            .join("var/lib/rookpkg/snapshots")
+            .join(&records[1].snapshot_id)
+            .join("files.tar");
+        let mut archive = tar::Archive::new(fs::File::open(tarball).unwrap());
+        let names: Vec<PathBuf> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().into_owned())
+            .collect();
+        assert_eq!(names, vec![PathBuf::from("usr/bin/foo")]);
+    }
+
+    /// Build an unsigned package of `files` (path, content) into `dir`
+    fn build_package(version: &str, files: &[(&str, &str)], dir: &Path) -> PathBuf {
+        use crate::archive::PackageArchiveBuilder;
+        use crate::spec::PackageSpec;
+
+        let spec = PackageSpec::from_str(&format!("[package]\nname = \"tool\"\nversion = \"{}\"\n", version)).unwrap();
+        let dest = dir.join(format!("dest-{}", version));
+        for (path, content) in files {
+            let path = dest.join(path.trim_start_matches('/'));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+        builder.scan_files().unwrap();
+        builder.build(&dir.join("out")).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade_replaces_files_in_place() {
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().join("root");
+        let v1 = build_package("1.0", &[("/usr/bin/tool", "v1"), ("/usr/bin/old", "old")], temp_dir.path());
+        let v2 = build_package("2.0", &[("/usr/bin/tool", "v2"), ("/usr/bin/new", "new")], temp_dir.path());
+
+        let mut tx = Transaction::new(&root, Database::open_in_memory().unwrap()).unwrap();
+        tx.install("tool", "1.0", &v1, InstallReason::Dependency);
+        tx.execute().unwrap();
+        let bin = root.join("usr/bin");
+        let old_inode = fs::metadata(bin.join("tool")).unwrap().ino();
+
+        // The shipped file is renamed over the old one and kept by a hard
+        // link; it is never moved out of the way
+        let mut tx = Transaction::new(&root, tx.db).unwrap();
+        tx.do_upgrade("tool", &v2).unwrap();
+        assert_eq!(fs::read_to_string(bin.join("tool")).unwrap(), "v2");
+        let tool = bin.join("tool");
+        assert!(!tx
+            .journal
+            .iter()
+            .any(|e| matches!(e, JournalEntry::FileRemoved { path, .. } if *path == tool)));
+        assert!(tx.journal.iter().any(|e| matches!(e, JournalEntry::FileReplaced { path, .. } if *path == tool)));
+        assert!(!bin.join("old").exists());
+        assert_eq!(fs::read_to_string(bin.join("new")).unwrap(), "new");
+
+        let package = tx.db.get_package("tool").unwrap().unwrap();
+        assert_eq!(package.version, "2.0");
+        assert_eq!(package.install_reason, InstallReason::Dependency);
+        let mut files: Vec<_> = tx.db.get_files("tool").unwrap().into_iter().map(|f| f.path).collect();
+        files.sort();
+        assert_eq!(files, vec!["/usr/bin/new", "/usr/bin/tool"]);
+
+        // Rolled back, the old files are back as they were
+        tx.rollback().unwrap();
+        assert_eq!(fs::read_to_string(bin.join("tool")).unwrap(), "v1");
+        assert_eq!(fs::metadata(bin.join("tool")).unwrap().ino(), old_inode);
+        assert_eq!(fs::read_to_string(bin.join("old")).unwrap(), "old");
+        assert!(!bin.join("new").exists());
+        assert!(leftovers(&bin).is_empty());
+    }
+
+    #[test]
+    fn test_create_accounts_rolls_back() {
+        use crate::spec::PackageSpec;
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        let passwd = "root:x:0:0:root:/root:/bin/sh\n";
+        fs::write(root.join("etc/passwd"), passwd).unwrap();
+
+        let spec = PackageSpec::from_str(
+            r#"
+[package]
+name = "nginx"
+version = "1.26.0"
+
+[users.nginx]
+uid = 60
+"#,
+        )
+        .unwrap();
+        let info = PackageInfo::from_spec(&spec);
+
+        let db = Database::open_in_memory().unwrap();
+        let mut tx = Transaction::new(root, db).unwrap();
+        let requested = tx.create_accounts(&info).unwrap();
+        assert_eq!(requested.len(), 2);
+        assert!(requested.iter().all(|a| a.created));
+        assert!(fs::read_to_string(root.join("etc/passwd")).unwrap().contains("nginx:x:60:60:"));
+        assert!(root.join("etc/group").exists());
+
+        // Rollback restores the old passwd and removes the files it created
+        tx.rollback().unwrap();
+        assert_eq!(fs::read_to_string(root.join("etc/passwd")).unwrap(), passwd);
+        assert!(!root.join("etc/group").exists());
+        assert!(!root.join("etc/shadow").exists());
+    }
 }
@@ -0,0 +1,303 @@
+//! Distribution package versions
+//!
+//! Versions are compared the way rpm and dpkg do it rather than as semver
+//! triples, so real-world strings like "1.1.1w", "2.38~rc1", "20240115" or
+//! "1:9.0p1" order correctly:
+//!
+//! - An optional numeric epoch (`epoch:version`) is compared first.
+//! - The rest is split into runs of digits and runs of letters; `.`, `-`,
+//!   `_` and `+` only separate segments. Any other character is an error.
+//! - Numeric segments compare numerically and sort above alphabetic ones.
+//! - A `~` sorts below everything, even the end of the string, so
+//!   "2.38~rc1" < "2.38".
+//! - A segment missing on one side makes that side older ("1.0" < "1.0a"),
+//!   except that trailing zeros are insignificant ("1" == "1.0.0").
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// One comparable piece of a version string
+///
+/// Variant order is the sort order between kinds of segments; the end of a
+/// version sits between `Tilde` and `Bump`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    /// Sentinel below every real version (used by `Version::lowest`)
+    Min,
+    /// Pre-release marker `~`
+    Tilde,
+    /// Sentinel just above the end of a version (used by `Version::bump`)
+    Bump,
+    /// Run of letters
+    Alpha(String),
+    /// Run of digits, leading zeros stripped
+    Num(String),
+}
+
+impl Segment {
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Min => 0,
+            Segment::Tilde => 1,
+            Segment::Bump => 3,
+            Segment::Alpha(_) => 4,
+            Segment::Num(_) => 5,
+        }
+    }
+
+    /// Rank of the end of a version string
+    const END_RANK: u8 = 2;
+
+    fn is_zero(&self) -> bool {
+        matches!(self, Segment::Num(n) if n == "0")
+    }
+}
+
+impl Ord for Segment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Segment::Num(a), Segment::Num(b)) => a.len().cmp(&b.len()).then_with(|| a.cmp(b)),
+            (Segment::Alpha(a), Segment::Alpha(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Segment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A package version with epoch, free-form segments and `~` pre-releases
+///
+/// Equality and ordering use the normalized form, so "1.0" == "1.0.0";
+/// `Display` prints the original string.
+#[derive(Debug, Clone)]
+pub struct Version {
+    epoch: u64,
+    segments: Vec<Segment>,
+    raw: String,
+}
+
+impl Version {
+    /// Parse a version string.
+    ///
+    /// Fails on an empty string, a bad epoch or a character that is not part
+    /// of the version syntax, such as a stray operator or comma.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let raw = s.trim();
+        if raw.is_empty() {
+            return Err("Empty version string".to_string());
+        }
+
+        let (epoch, rest) = match raw.split_once(':') {
+            Some((epoch, rest)) => {
+                let epoch = epoch
+                    .parse()
+                    .map_err(|_| format!("Invalid epoch in version: {}", raw))?;
+                (epoch, rest)
+            }
+            None => (0, raw),
+        };
+
+        let mut segments = Vec::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c == '~' {
+                chars.next();
+                segments.push(Segment::Tilde);
+            } else if c.is_ascii_digit() {
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                let trimmed = digits.trim_start_matches('0');
+                let value = if trimmed.is_empty() { "0" } else { trimmed };
+                segments.push(Segment::Num(value.to_string()));
+            } else if c.is_ascii_alphabetic() {
+                let mut letters = String::new();
+                while let Some(&a) = chars.peek().filter(|a| a.is_ascii_alphabetic()) {
+                    letters.push(a);
+                    chars.next();
+                }
+                segments.push(Segment::Alpha(letters));
+            } else if matches!(c, '.' | '-' | '_' | '+') {
+                chars.next();
+            } else {
+                return Err(format!("Invalid character '{}' in version: {}", c, raw));
+            }
+        }
+
+        Ok(Self {
+            epoch,
+            segments: normalize(segments),
+            raw: raw.to_string(),
+        })
+    }
+}
+
+/// Drop zero segments that are followed only by a `~` or the end of the
+/// version, so "1.0.0" == "1" and "1.0~rc1" == "1~rc1".
+fn normalize(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut out: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if segment == Segment::Tilde {
+            while out.last().is_some_and(Segment::is_zero) {
+                out.pop();
+            }
+        }
+        out.push(segment);
+    }
+    while out.last().is_some_and(Segment::is_zero) {
+        out.pop();
+    }
+    out
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch.cmp(&other.epoch).then_with(|| {
+            let mut a = self.segments.iter();
+            let mut b = other.segments.iter();
+            loop {
+                match (a.next(), b.next()) {
+                    (None, None) => return Ordering::Equal,
+                    (Some(x), None) => return x.rank().cmp(&Segment::END_RANK),
+                    (None, Some(y)) => return Segment::END_RANK.cmp(&y.rank()),
+                    (Some(x), Some(y)) => match x.cmp(y) {
+                        Ordering::Equal => continue,
+                        ord => return ord,
+                    },
+                }
+            }
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.epoch.hash(state);
+        self.segments.hash(state);
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.segments.last() {
+            Some(Segment::Min) => write!(f, "0"),
+            Some(Segment::Bump) => write!(f, "{}+", self.raw),
+            _ => write!(f, "{}", self.raw),
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl pubgrub::version::Version for Version {
+    fn lowest() -> Self {
+        Self {
+            epoch: 0,
+            segments: vec![Segment::Min],
+            raw: "0".to_string(),
+        }
+    }
+
+    /// The smallest version strictly greater than this one
+    fn bump(&self) -> Self {
+        let mut segments = self.segments.clone();
+        segments.push(Segment::Bump);
+        Self {
+            epoch: self.epoch,
+            segments,
+            raw: self.raw.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pubgrub::version::Version as _;
+
+    fn v(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_alpha_suffixes() {
+        assert!(v("1.1.1w") > v("1.1.1v"));
+        assert!(v("1.1.1a") > v("1.1.1"));
+        assert!(v("9.0p1") > v("9.0"));
+        assert!(v("9.1") > v("9.0p1"));
+        assert!(v("1.0.1") > v("1.0a"));
+    }
+
+    #[test]
+    fn test_tilde_and_epoch() {
+        assert!(v("2.38~rc1") < v("2.38"));
+        assert!(v("2.38~rc1") < v("2.38~rc2"));
+        assert!(v("2.38~rc2") > v("2.37.1"));
+        assert!(v("2.38.0~rc1") < v("2.38"));
+        assert!(v("1:1.0") > v("2.0"));
+        assert_eq!(v("1:1.0").epoch, 1);
+        assert!(Version::parse("x:1.0").is_err());
+        assert!(Version::parse("").is_err());
+    }
+
+    #[test]
+    fn test_invalid_characters() {
+        assert_eq!(v("1.0_beta+git-2").to_string(), "1.0_beta+git-2");
+        for s in ["1.0, < 2.0", ">= 1.0", "1.0 2", "1:2:3", "1.0/2", "1.0é"] {
+            assert!(Version::parse(s).is_err(), "{} should not parse", s);
+        }
+    }
+
+    #[test]
+    fn test_numeric_and_equality() {
+        assert_eq!(v("1"), v("1.0.0"));
+        assert_eq!(v("1.01"), v("1.1"));
+        assert!(v("1.10") > v("1.9"));
+        assert!(v("20240115") > v("20231231"));
+        assert!(v("123456789012345678901234567890") > v("99999999999999999999"));
+        assert_eq!(v("2.38-rc1").to_string(), "2.38-rc1");
+    }
+
+    #[test]
+    fn test_lowest_and_bump() {
+        let lowest = Version::lowest();
+        for s in ["0", "~1", "0.0.1", "1.0"] {
+            assert!(lowest < v(s));
+        }
+
+        let bumped = v("1.0").bump();
+        assert!(bumped > v("1.0"));
+        assert!(bumped > v("1"));
+        for s in ["1.0.0.1", "1.0a", "1.1", "1.0.1~rc1"] {
+            assert!(bumped < v(s), "{} should be above bump(1.0)", s);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Signing a package through the `rookpkg-agent` binary
+
+use std::fs;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rookpkg::agent::AgentSigner;
+use rookpkg::archive::{PackageArchiveBuilder, PackageArchiveReader};
+use rookpkg::signing::{self, Signer};
+use rookpkg::spec::PackageSpec;
+
+/// The agent process, killed when the test ends
+struct Agent(Child);
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_sign_through_agent() {
+    let dir = tempfile::tempdir().unwrap();
+    signing::generate_key("Release Key", "release@example.org", &dir.path().join("key")).unwrap();
+    signing::generate_key("Other Key", "other@example.org", &dir.path().join("other")).unwrap();
+    let public_key = signing::load_public_key(&dir.path().join("key/signing-key.pub")).unwrap();
+    let other_key = signing::load_public_key(&dir.path().join("other/signing-key.pub")).unwrap();
+
+    let socket = dir.path().join("agent.sock");
+    let _agent = Agent(
+        Command::new(env!("CARGO_BIN_EXE_rookpkg-agent"))
+            .arg("--key")
+            .arg(dir.path().join("key/signing-key.secret"))
+            .arg("--socket")
+            .arg(&socket)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !socket.exists() {
+        assert!(Instant::now() < deadline, "rookpkg-agent did not create its socket");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let signer = AgentSigner::connect(&socket).unwrap();
+    assert_eq!(signer.fingerprint(), public_key.fingerprint);
+    assert_eq!(signer.name(), "Release Key");
+
+    let spec = PackageSpec::from_str("[package]\nname = \"hello\"\nversion = \"1.0\"\n").unwrap();
+    let dest = dir.path().join("dest");
+    fs::create_dir_all(dest.join("usr/bin")).unwrap();
+    fs::write(dest.join("usr/bin/hello"), "hello").unwrap();
+    let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+    builder.scan_files().unwrap();
+    let package = builder.build_signed(&dir.path().join("out"), &signer).unwrap();
+
+    // The agent's signature is embedded and verifies against its public key
+    let signature = PackageArchiveReader::open(&package)
+        .unwrap()
+        .find_signature()
+        .unwrap()
+        .expect("package is signed");
+    assert!(signature.is_embedded());
+    signature.verify(&public_key).unwrap();
+    assert!(signature.verify(&other_key).is_err());
+}
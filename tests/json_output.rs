@@ -0,0 +1,254 @@
+//! The `--output json` documents of the `rookpkg` binary
+//!
+//! Each command runs against a throwaway root, and its whole stdout must
+//! parse as one JSON document with the fields the README lists.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rookpkg::archive::PackageArchiveBuilder;
+use rookpkg::config::RepositoryConfig;
+use rookpkg::delta::DeltaBuilder;
+use rookpkg::spec::PackageSpec;
+use rookpkg::{signing, Config, Database, InstallReason, TransactionBuilder};
+use serde_json::Value;
+
+/// A root with one installed and held package, a cached repository and a
+/// trusted master key
+struct Fixture {
+    dir: tempfile::TempDir,
+    config: PathBuf,
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let keys = dir.path().join("keys");
+
+        let master_dir = keys.join("master");
+        fs::create_dir_all(&master_dir).unwrap();
+        signing::generate_key("Master", "master@example.org", &dir.path().join("generated")).unwrap();
+        fs::copy(dir.path().join("generated/signing-key.pub"), master_dir.join("master.pub")).unwrap();
+
+        let mut settings = Config::default();
+        settings.signing.master_keys_dir = master_dir;
+        settings.signing.packager_keys_dir = keys.join("packagers");
+        settings.signing.user_signing_key = keys.join("user/signing-key.secret");
+        settings.paths.cache_dir = dir.path().join("cache");
+        settings.repositories.push(RepositoryConfig {
+            name: "core".to_string(),
+            url: "http://127.0.0.1:9/core".to_string(),
+            enabled: true,
+            priority: 100,
+            fingerprint: None,
+            max_metadata_age_days: None,
+        });
+        let config = dir.path().join("rookpkg.conf");
+        fs::write(&config, toml::to_string(&settings).unwrap()).unwrap();
+
+        let repo_cache = dir.path().join("cache/repos/core");
+        fs::create_dir_all(&repo_cache).unwrap();
+        fs::write(
+            repo_cache.join("repo.toml"),
+            "[repository]\nname = \"core\"\ndescription = \"Core\"\n\n[signing]\nfingerprint = \"HYBRID:SHA256:0000\"\n",
+        )
+        .unwrap();
+        let index = serde_json::json!({
+            "version": 1,
+            "generated": "2026-01-01T00:00:00Z",
+            "repository": "core",
+            "count": 1,
+            "packages": [{
+                "name": "hello",
+                "version": "1.1",
+                "release": 1,
+                "description": "Greets",
+                "size": 1024,
+                "sha256": "00",
+                "filename": "hello-1.1-1.x86_64.rookpkg",
+            }],
+            "groups": [{
+                "name": "base",
+                "description": "Base system",
+                "packages": ["hello"],
+            }],
+        });
+        fs::write(repo_cache.join("packages.json"), index.to_string()).unwrap();
+
+        let fixture = Self { config, root, dir };
+        let package = fixture.package("1.0");
+        let db_path = settings.with_sysroot(fixture.root.clone()).database_path();
+        fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+        TransactionBuilder::new(&fixture.root)
+            .install("hello", "1.0", &package, InstallReason::Explicit)
+            .execute(Database::open(&db_path).unwrap())
+            .unwrap();
+        Database::open(&db_path).unwrap().hold_package("hello", None, "Testing").unwrap();
+        fixture
+    }
+
+    /// The spec of a hello package
+    fn spec(&self, version: &str) -> PathBuf {
+        let path = self.dir.path().join(format!("hello-{}.rook", version));
+        fs::write(
+            &path,
+            format!(
+                "[package]\nname = \"hello\"\nversion = \"{}\"\nsummary = \"Greets\"\n\n[depends]\nglibc = \">= 2.39\"\n",
+                version
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    /// An unsigned archive of a hello package
+    fn package(&self, version: &str) -> PathBuf {
+        let spec = PackageSpec::from_file(&self.spec(version)).unwrap();
+        let dest = self.dir.path().join(format!("dest-{}", version));
+        fs::create_dir_all(dest.join("usr/bin")).unwrap();
+        fs::write(dest.join("usr/bin/hello"), format!("hello {}", version)).unwrap();
+        let mut builder = PackageArchiveBuilder::new(&spec, &dest);
+        builder.scan_files().unwrap();
+        builder.build(&self.dir.path().join("out")).unwrap()
+    }
+
+    /// Run rookpkg with `--output json` and parse all of its stdout
+    fn json(&self, args: &[&str]) -> Value {
+        let output = Command::new(env!("CARGO_BIN_EXE_rookpkg"))
+            .arg("--config")
+            .arg(&self.config)
+            .arg("--root")
+            .arg(&self.root)
+            .args(["--output", "json"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            output.status.success(),
+            "rookpkg {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_json::from_str(&stdout)
+            .unwrap_or_else(|e| panic!("stdout of rookpkg {:?} is not one JSON document ({}):\n{}", args, e, stdout))
+    }
+}
+
+/// The keys of a JSON object
+fn keys(value: &Value) -> Vec<&str> {
+    let mut keys: Vec<_> = value
+        .as_object()
+        .unwrap_or_else(|| panic!("not an object: {}", value))
+        .keys()
+        .map(String::as_str)
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// The first element of an array field
+fn first<'a>(value: &'a Value, field: &str) -> &'a Value {
+    value[field]
+        .get(0)
+        .unwrap_or_else(|| panic!("{} is not a non-empty array: {}", field, value))
+}
+
+fn path_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_query_documents() {
+    let fixture = Fixture::new();
+
+    let list = fixture.json(&["list"]);
+    assert_eq!(
+        keys(first(&list, "packages")),
+        ["install_date", "install_reason", "name", "release", "size_bytes", "version"]
+    );
+    let available = fixture.json(&["list", "--available"]);
+    assert_eq!(
+        keys(first(&available, "packages")),
+        ["arch", "description", "name", "release", "repository", "size", "version"]
+    );
+
+    let info = fixture.json(&["info", "hello"]);
+    assert_eq!(info["status"], "installed");
+    assert_eq!(
+        keys(&info),
+        [
+            "accounts", "checksum", "dependencies", "files", "install_date", "install_reason", "name",
+            "release", "required_by", "size_bytes", "status", "version"
+        ]
+    );
+    assert_eq!(keys(first(&info, "dependencies")), ["constraint", "name"]);
+
+    let search = fixture.json(&["search", "hello"]);
+    assert_eq!(keys(&search), ["available", "groups", "installed"]);
+    assert_eq!(keys(first(&search, "installed")), ["description", "name", "release", "repository", "version"]);
+
+    let depends = fixture.json(&["depends", "hello"]);
+    assert_eq!(keys(&depends), ["dependencies", "package", "repository", "source"]);
+    assert_eq!(keys(first(&depends, "dependencies")), ["constraint", "name", "satisfied", "type"]);
+    let reverse = fixture.json(&["depends", "--reverse", "hello"]);
+    assert_eq!(keys(&reverse), ["package", "required_by"]);
+
+    let holds = fixture.json(&["holds"]);
+    assert_eq!(keys(first(&holds, "holds")), ["held_date", "name", "reason", "version"]);
+    assert_eq!(fixture.json(&["holds", "hello"])["name"], "hello");
+
+    let groups = fixture.json(&["groups"]);
+    assert_eq!(
+        keys(first(&groups, "groups")),
+        ["description", "essential", "name", "optional", "packages", "repository"]
+    );
+    assert_eq!(fixture.json(&["groups", "base"])["name"], "base");
+
+    let check = fixture.json(&["check"]);
+    assert_eq!(keys(first(&check, "packages")), ["files", "intact", "issues", "name", "version"]);
+    assert_eq!(first(&check, "packages")["intact"], true);
+
+    let keylist = fixture.json(&["keylist"]);
+    assert_eq!(keys(&keylist), ["keys", "revoked"]);
+    assert_eq!(
+        keys(first(&keylist, "keys")),
+        ["algorithm", "email", "fingerprint", "name", "path", "role", "trust"]
+    );
+}
+
+#[test]
+fn test_file_documents() {
+    let fixture = Fixture::new();
+    let old = fixture.package("1.0");
+    let new = fixture.package("1.1");
+
+    let archive = fixture.json(&["inspect", path_arg(&new)]);
+    assert_eq!(archive["kind"], "archive");
+    assert_eq!(keys(&archive), ["files", "info", "kind", "scripts"]);
+    let spec = fixture.json(&["inspect", path_arg(&fixture.spec("1.1"))]);
+    assert_eq!(keys(&spec), ["kind", "spec"]);
+
+    let delta = DeltaBuilder::new(&old, &new).unwrap().build(&fixture.dir.path().join("deltas")).unwrap();
+    let info = fixture.json(&["delta", "info", path_arg(&delta)]);
+    for field in ["file_size", "savings_percent", "worthwhile"] {
+        assert!(info.get(field).is_some(), "delta info has no {}: {}", field, info);
+    }
+}
+
+#[test]
+fn test_dry_run_plan() {
+    let fixture = Fixture::new();
+
+    let plan = fixture.json(&["remove", "hello", "--dry-run"]);
+    assert_eq!(keys(&plan), ["download_size", "held_back", "operations"]);
+    let operation = first(&plan, "operations");
+    assert_eq!(
+        keys(operation),
+        ["action", "install_reason", "new_version", "old_version", "package", "repository"]
+    );
+    assert_eq!(operation["action"], "remove");
+}